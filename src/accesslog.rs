@@ -0,0 +1,278 @@
+use std::convert::Infallible;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use log::info;
+use warp::http::{HeaderMap, Method};
+use warp::hyper::body::HttpBody;
+use warp::path::FullPath;
+use warp::reply::Response;
+use warp::{Filter, Reply};
+
+use crate::config::AccessLog;
+
+/// Log target of the access log lines. Allows filtering them with e.g.
+/// RUST_LOG=warn,access=info.
+pub const ACCESS_LOG_TARGET: &str = "access";
+
+// The client IP address of a request. The X-Forwarded-For header is followed
+// from the right for as long as the address it was received from is a trusted
+// proxy.
+fn client_ip(
+    remote: Option<SocketAddr>,
+    forwarded_for: Option<&str>,
+    trusted_proxies: &[IpAddr],
+) -> Option<IpAddr> {
+    let mut client = remote?.ip();
+    if let Some(forwarded_for) = forwarded_for {
+        for hop in forwarded_for.rsplit(',') {
+            if !trusted_proxies.contains(&client) {
+                break;
+            }
+            match hop.trim().parse::<IpAddr>() {
+                Ok(ip) => client = ip,
+                Err(_) => break,
+            }
+        }
+    }
+    Some(client)
+}
+
+fn format_ip(client: Option<IpAddr>) -> String {
+    client.map_or_else(|| "-".to_string(), |ip| ip.to_string())
+}
+
+// Formats an access log line like
+// `10.0.0.1 "GET /api/1/data.json" 200 5321 1.2ms`. The response size is
+// "-" if it isn't known, e.g. for streams.
+fn format_line(
+    client: Option<IpAddr>,
+    method: &Method,
+    path: &str,
+    status: u16,
+    size: Option<u64>,
+    latency: Duration,
+) -> String {
+    format!(
+        "{} \"{} {}\" {} {} {:.1}ms",
+        format_ip(client),
+        method,
+        path,
+        status,
+        size.map_or_else(|| "-".to_string(), |size| size.to_string()),
+        latency.as_secs_f64() * 1000.0,
+    )
+}
+
+/// Extracts the client IP address, honoring X-Forwarded-For headers set by
+/// the trusted proxies.
+pub fn client(
+    trusted_proxies: Vec<IpAddr>,
+) -> impl Filter<Extract = (Option<IpAddr>,), Error = Infallible> + Clone {
+    let trusted_proxies = Arc::new(trusted_proxies);
+    warp::addr::remote()
+        .and(warp::header::headers_cloned())
+        .map(move |remote: Option<SocketAddr>, headers: HeaderMap| {
+            let forwarded_for = headers
+                .get_all("x-forwarded-for")
+                .iter()
+                .filter_map(|value| value.to_str().ok())
+                .collect::<Vec<&str>>()
+                .join(",");
+            client_ip(
+                remote,
+                Some(forwarded_for.as_str()).filter(|f| !f.is_empty()),
+                &trusted_proxies,
+            )
+        })
+}
+
+/// Logs a line for each response of the routes if the access log is
+/// enabled. Server-sent event streams are logged when they are opened.
+pub fn wrap<F, R>(
+    routes: F,
+    access_log: AccessLog,
+) -> impl Filter<Extract = (Response,), Error = Infallible> + Clone
+where
+    F: Filter<Extract = (R,), Error = Infallible> + Clone + Send + Sync + 'static,
+    R: Reply,
+{
+    let enabled = access_log.enable;
+    warp::any()
+        .map(Instant::now)
+        .and(warp::method())
+        .and(warp::path::full())
+        .and(client(access_log.trusted_proxies))
+        .and(routes)
+        .map(
+            move |start: Instant,
+                  method: Method,
+                  path: FullPath,
+                  client: Option<IpAddr>,
+                  reply: R| {
+                let response = reply.into_response();
+                if enabled {
+                    info!(
+                        target: ACCESS_LOG_TARGET,
+                        "{}",
+                        format_line(
+                            client,
+                            &method,
+                            path.as_str(),
+                            response.status().as_u16(),
+                            response.body().size_hint().exact(),
+                            start.elapsed(),
+                        )
+                    );
+                }
+                response
+            },
+        )
+}
+
+/// Logs the duration of a server-sent event connection when it's dropped.
+pub struct SseSession {
+    client: Option<IpAddr>,
+    opened: Instant,
+}
+
+impl SseSession {
+    pub fn open(client: Option<IpAddr>) -> Self {
+        SseSession {
+            client,
+            opened: Instant::now(),
+        }
+    }
+}
+
+fn format_sse_close(client: Option<IpAddr>, duration: Duration) -> String {
+    format!(
+        "{} SSE connection closed after {:.1}s",
+        format_ip(client),
+        duration.as_secs_f64()
+    )
+}
+
+impl Drop for SseSession {
+    fn drop(&mut self) {
+        info!(
+            target: ACCESS_LOG_TARGET,
+            "{}",
+            format_sse_close(self.client, self.opened.elapsed())
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use warp::http::StatusCode;
+
+    #[test]
+    fn test_client_ip() {
+        let remote = Some(SocketAddr::from(([127, 0, 0, 1], 1234)));
+        let proxy = IpAddr::from([127, 0, 0, 1]);
+        let client = IpAddr::from([203, 0, 113, 7]);
+
+        assert_eq!(client_ip(None, Some("203.0.113.7"), &[proxy]), None);
+        assert_eq!(client_ip(remote, None, &[proxy]), Some(proxy));
+        // the header is ignored if the remote isn't a trusted proxy
+        assert_eq!(client_ip(remote, Some("203.0.113.7"), &[]), Some(proxy));
+        assert_eq!(
+            client_ip(remote, Some("203.0.113.7"), &[proxy]),
+            Some(client)
+        );
+        // spoofed entries left of the last untrusted hop are ignored
+        assert_eq!(
+            client_ip(remote, Some("198.51.100.1, 203.0.113.7"), &[proxy]),
+            Some(client)
+        );
+        // chained trusted proxies are skipped
+        assert_eq!(
+            client_ip(
+                remote,
+                Some("203.0.113.7, 10.0.0.1"),
+                &[proxy, IpAddr::from([10, 0, 0, 1])]
+            ),
+            Some(client)
+        );
+        // an unparsable hop stops at the last trusted address
+        assert_eq!(
+            client_ip(remote, Some("203.0.113.7, unknown"), &[proxy]),
+            Some(proxy)
+        );
+        assert_eq!(client_ip(remote, Some("garbage"), &[proxy]), Some(proxy));
+        assert_eq!(
+            client_ip(remote, Some("2001:db8::1"), &[proxy]),
+            Some("2001:db8::1".parse().unwrap())
+        );
+    }
+
+    #[tokio::test]
+    async fn test_client_filter() {
+        let filter = client(vec![IpAddr::from([127, 0, 0, 1])]);
+        let client = warp::test::request()
+            .remote_addr(SocketAddr::from(([127, 0, 0, 1], 1234)))
+            .header("x-forwarded-for", "203.0.113.7")
+            .filter(&filter)
+            .await
+            .unwrap();
+        assert_eq!(client, Some(IpAddr::from([203, 0, 113, 7])));
+
+        let client = warp::test::request()
+            .remote_addr(SocketAddr::from(([127, 0, 0, 1], 1234)))
+            .header("x-forwarded-for", "203.0.113.7")
+            .filter(&super::client(vec![]))
+            .await
+            .unwrap();
+        assert_eq!(client, Some(IpAddr::from([127, 0, 0, 1])));
+    }
+
+    #[test]
+    fn test_format_line() {
+        assert_eq!(
+            format_line(
+                Some(IpAddr::from([10, 0, 0, 1])),
+                &Method::GET,
+                "/api/1/data.json",
+                200,
+                Some(5321),
+                Duration::from_micros(1300),
+            ),
+            "10.0.0.1 \"GET /api/1/data.json\" 200 5321 1.3ms"
+        );
+        assert_eq!(
+            format_line(
+                None,
+                &Method::GET,
+                "/api/changes",
+                200,
+                None,
+                Duration::from_millis(3),
+            ),
+            "- \"GET /api/changes\" 200 - 3.0ms"
+        );
+        assert_eq!(
+            format_sse_close(Some(IpAddr::from([10, 0, 0, 1])), Duration::from_secs(90)),
+            "10.0.0.1 SSE connection closed after 90.0s"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_wrap_passes_responses() {
+        let routes = warp::path!("missing")
+            .map(|| warp::reply::with_status("gone", StatusCode::NOT_FOUND))
+            .recover(|_: warp::Rejection| async { Ok::<_, Infallible>("recovered") });
+        for enable in [false, true] {
+            let access_log = AccessLog {
+                enable,
+                trusted_proxies: vec![],
+            };
+            let wrapped = wrap(routes, access_log);
+            let res = warp::test::request().path("/missing").reply(&wrapped).await;
+            assert_eq!(res.status(), StatusCode::NOT_FOUND);
+            assert_eq!(res.body(), "gone");
+        }
+    }
+}
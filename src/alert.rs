@@ -0,0 +1,104 @@
+use std::str::FromStr;
+
+use bitcoincore_rpc::bitcoin::BlockHash;
+
+use crate::api;
+use crate::headertree;
+use crate::rss::THREASHOLD_NODE_LAGGING;
+use crate::types::{unix_timestamp, Alert, AlertKind, Caches, ConsensusTipJson, NodeData, Tree};
+
+// Number of alerts kept per network in the cache (e.g. for the RSS feed).
+pub const MAX_ALERTS_IN_CACHE: usize = 50;
+
+// Detects if reachable and non-lagging nodes have active tips on different
+// branches. A node being behind another node on the same chain isn't a chain
+// split. Returns the active tips of the nodes if there is a chain split.
+pub async fn detect_chain_split(
+    node_data: &NodeData,
+    tree: &Tree,
+) -> Option<Vec<ConsensusTipJson>> {
+    let tips = api::active_tip_groups(node_data);
+    let highest = tips.first()?;
+    let highest_hash = BlockHash::from_str(&highest.hash).ok()?;
+
+    for tip in tips.iter().skip(1) {
+        // Lagging nodes are reported in the lagging nodes feed.
+        if tip.height + THREASHOLD_NODE_LAGGING < highest.height {
+            continue;
+        }
+        let hash = match BlockHash::from_str(&tip.hash) {
+            Ok(hash) => hash,
+            Err(_) => continue,
+        };
+        match headertree::common_ancestor(tree, &highest_hash, &hash).await {
+            // The tip is on the same chain as the highest tip, just behind.
+            Some(common) if common == hash => continue,
+            Some(_) => return Some(tips),
+            // We don't know about one of the tips or their common ancestor
+            // yet. This might resolve with the next tree update.
+            None => continue,
+        }
+    }
+    None
+}
+
+// Checks the nodes of the network for a chain split and updates the chain
+// split state in the cache. Returns an alert when a chain split starts or
+// is resolved. A chain split is only alerted once, not for every update
+// while it's ongoing.
+pub async fn check_chain_split(network_id: u32, caches: &Caches, tree: &Tree) -> Option<Alert> {
+    // The detection looks at the tree and can't hold the cache lock. If the
+    // cache changed in the meantime, e.g. by a concurrent check after the
+    // tips of another node changed, the detection is repeated on the new
+    // node data. Otherwise, an outdated result could overwrite the chain
+    // split state.
+    let mut snapshot = {
+        let locked_caches = caches.lock().await;
+        let cache = locked_caches.get(&network_id)?;
+        (cache.journal.generation, cache.node_data.clone())
+    };
+    let (mut locked_caches, node_data, split) = loop {
+        let (generation, node_data) = snapshot;
+        let split = detect_chain_split(&node_data, tree).await;
+        let locked_caches = caches.lock().await;
+        let cache = locked_caches.get(&network_id)?;
+        if cache.journal.generation == generation {
+            break (locked_caches, node_data, split);
+        }
+        snapshot = (cache.journal.generation, cache.node_data.clone());
+    };
+    let cache = locked_caches.get_mut(&network_id)?;
+    let alert = match (&cache.chain_split, &split) {
+        (None, Some(tips)) => Some(Alert {
+            network_id,
+            kind: AlertKind::ChainSplit,
+            timestamp: unix_timestamp(),
+            tips: tips.clone(),
+        }),
+        (Some(_), None) => Some(Alert {
+            network_id,
+            kind: AlertKind::ChainSplitResolved,
+            timestamp: unix_timestamp(),
+            tips: api::active_tip_groups(&node_data),
+        }),
+        _ => None,
+    };
+    cache.chain_split = split;
+
+    if let Some(alert) = &alert {
+        cache.alerts.push(alert.clone());
+        if cache.alerts.len() > MAX_ALERTS_IN_CACHE {
+            cache.alerts.remove(0);
+        }
+    }
+    alert
+}
+
+// The chain split state after a restart: if the last alert was a chain
+// split, it's still ongoing.
+pub fn chain_split_from_alerts(alerts: &[Alert]) -> Option<Vec<ConsensusTipJson>> {
+    match alerts.last() {
+        Some(alert) if alert.kind == AlertKind::ChainSplit => Some(alert.tips.clone()),
+        _ => None,
+    }
+}
@@ -1,37 +1,1374 @@
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
 use std::convert::Infallible;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
 
-use warp::{sse::Event, Filter};
+use bitcoincore_rpc::bitcoin::consensus::encode::serialize_hex;
+use bitcoincore_rpc::bitcoin::hashes::{sha256, Hash};
+use bitcoincore_rpc::bitcoin::BlockHash;
+use futures_util::stream::{select_all, SelectAll};
+use log::{error, info};
+use petgraph::graph::{DiGraph, NodeIndex};
+use petgraph::Direction;
+use tokio::sync::broadcast;
+use tokio_stream::wrappers::BroadcastStream;
+use warp::http::header::{CONTENT_TYPE, ETAG};
+use warp::http::StatusCode;
+use warp::hyper::body::Bytes;
+use warp::path::FullPath;
+use warp::{reject, sse::Event, Filter, Rejection, Reply};
 
+use crate::compression;
+use crate::config::Privacy;
+use crate::db;
+use crate::error::DbError;
+use crate::headertree;
+use crate::ratelimit::RateLimited;
+use crate::rss;
 use crate::types::{
-    Caches, DataChanged, DataJsonResponse, InfoJsonResponse, NetworkJson, NetworksJsonResponse,
+    network_type_name, unix_timestamp, AlertHistoryJsonResponse, AlertHistoryQuery,
+    BlockJsonResponse, Cache, Caches, ChainTipStatus, Change, ChangeEvent, CompactDataJsonResponse,
+    ConsensusJsonResponse, ConsensusStatus, ConsensusTipJson, DataChanged, DataChangedDetail,
+    DataDeltaJsonResponse, DataJsonBody, DataJsonResponse, DataQuery, DataV2JsonResponse, Db,
+    ForkHistoryJsonResponse, ForkHistoryQuery, ForkJson, Freshness, HeaderInfo, HeaderInfoJson,
+    HeaderStatus, HealthJsonResponse, HeightQuery, InfoJsonResponse, InvalidBlockJson, Lagged,
+    LastPrune, MinerShareJson, MinersQuery, NetworkHealthJson, NetworkJson, NetworksJsonResponse,
+    NodeData, NodeHealthJson, PoolIdQueues, ReadinessJsonResponse, ReorgJson, SearchJsonResponse,
+    SearchQuery, SearchResultJson, SearchTerm, SignallingQuery, SinceHeightQuery, Tree, Trees,
 };
 
+const NOT_FOUND_HTML: &str = r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+  <meta charset="utf-8">
+  <title>Not found - fork-observer</title>
+</head>
+<body>
+  <h1>Not found</h1>
+  <p>This page doesn't exist. <a href="/">Back to fork-observer</a></p>
+</body>
+</html>
+"#;
+
+// One difficulty adjustment period.
+const DEFAULT_SIGNALLING_WINDOW: usize = 2016;
+// The signalling statistics walk the header tree while holding its lock.
+const MAX_SIGNALLING_WINDOW: usize = DEFAULT_SIGNALLING_WINDOW;
+const DEFAULT_MINERS_WINDOW: usize = 100;
+
+// Forks per page of /api/<network id>/forkhistory.json.
+const FORK_HISTORY_PAGE_SIZE: usize = 50;
+// Number of alerts per page of /api/<network id>/alerts.json.
+const ALERT_HISTORY_PAGE_SIZE: usize = 50;
+// Maximum number of blocks returned by /api/search over all networks.
+const MAX_SEARCH_RESULTS: usize = 50;
+
 pub async fn info_response(footer: String) -> Result<impl warp::Reply, Infallible> {
     Ok(warp::reply::json(&InfoJsonResponse { footer }))
 }
 
-pub async fn data_response(network: u32, caches: Caches) -> Result<impl warp::Reply, Infallible> {
+pub async fn data_response(
+    network: u32,
+    query: DataQuery,
+    if_none_match: Option<String>,
+    gzip: bool,
+    caches: Caches,
+    privacy: BTreeMap<u32, Privacy>,
+    hash_prefix_length: usize,
+) -> Result<warp::reply::Response, Infallible> {
+    let privacy = privacy.get(&network).cloned().unwrap_or_default();
+    let (min_height, max_height) = match query.height_range() {
+        Ok(range) => range,
+        Err(e) => {
+            return Ok(warp::reply::with_status(
+                warp::reply::json(&serde_json::json!({ "error": e })),
+                StatusCode::BAD_REQUEST,
+            )
+            .into_response())
+        }
+    };
+    let filtered = min_height.is_some() || max_height.is_some();
+    let mut caches_locked = caches.lock().await;
+    if let Some(since) = query.since {
+        return Ok(warp::reply::json(&data_delta(
+            caches_locked.get_mut(&network),
+            since,
+            &privacy,
+        ))
+        .into_response());
+    }
+    let cache = match caches_locked.get_mut(&network) {
+        Some(cache) => cache,
+        None => {
+            return Ok(warp::reply::json(&DataJsonResponse {
+                header_infos: vec![],
+                nodes: vec![],
+                deployment_disagreements: vec![],
+                block_propagation: None,
+                active_chain_work: None,
+            })
+            .into_response())
+        }
+    };
+    // The poll latencies are only served on nodes.json and data.v2.json.
+    let nodes: Vec<_> = cache
+        .served_node_data(&privacy)
+        .into_values()
+        .map(|node| node.without_poll_latency())
+        .collect();
+    // Compact, height filtered and capped responses aren't cached.
+    if query.is_compact() || filtered || query.max_headers.is_some() {
+        let mut header_infos = cache.header_infos_with_status();
+        if filtered {
+            header_infos = headers_in_range(header_infos, min_height, max_height);
+        }
+        if let Some(max_headers) = query.max_headers {
+            header_infos = cap_headers(header_infos, max_headers);
+        }
+        let body = if query.is_compact() {
+            serde_json::to_vec(&CompactDataJsonResponse::new(
+                &header_infos,
+                nodes,
+                cache.deployment_disagreements.clone(),
+                hash_prefix_length,
+            ))
+        } else {
+            serde_json::to_vec(&DataJsonResponse {
+                header_infos,
+                nodes,
+                deployment_disagreements: cache.deployment_disagreements.clone(),
+                block_propagation: cache.propagation.as_ref().map(|p| p.json()),
+                active_chain_work: cache.active_chain_work.as_ref().map(|w| w.hex()),
+            })
+        };
+        return Ok(match body {
+            Ok(body) => {
+                let body = Arc::new(Bytes::from(body));
+                etag_json_response(&body, &etag(&body), if_none_match.as_deref())
+            }
+            Err(e) => {
+                error!("could not serialize data.json: {}", e);
+                StatusCode::INTERNAL_SERVER_ERROR.into_response()
+            }
+        });
+    }
+    let cached = match cache.data_json.as_mut() {
+        Some(cached) if cached.privacy == privacy => cached,
+        _ => {
+            let body = match serde_json::to_vec(&DataJsonResponse {
+                header_infos: cache.header_infos_with_status(),
+                nodes,
+                deployment_disagreements: cache.deployment_disagreements.clone(),
+                block_propagation: cache.propagation.as_ref().map(|p| p.json()),
+                active_chain_work: cache.active_chain_work.as_ref().map(|w| w.hex()),
+            }) {
+                Ok(body) => Arc::new(Bytes::from(body)),
+                Err(e) => {
+                    error!("could not serialize data.json: {}", e);
+                    return Ok(StatusCode::INTERNAL_SERVER_ERROR.into_response());
+                }
+            };
+            cache.data_json.insert(DataJsonBody {
+                privacy,
+                etag: etag(&body),
+                body,
+                gzip: None,
+            })
+        }
+    };
+    if !gzip {
+        return Ok(etag_json_response(
+            &cached.body,
+            &cached.etag,
+            if_none_match.as_deref(),
+        ));
+    }
+    // The compressed body is cached too, so it isn't compressed again for
+    // every request.
+    let gzip_body = match &cached.gzip {
+        Some(gzip_body) => gzip_body.clone(),
+        None => match compression::gzip(&cached.body) {
+            Ok(gzip_body) => cached.gzip.insert(Arc::new(gzip_body)).clone(),
+            Err(e) => {
+                error!("could not compress data.json: {}", e);
+                return Ok(StatusCode::INTERNAL_SERVER_ERROR.into_response());
+            }
+        },
+    };
+    let response = etag_json_response(&gzip_body, &cached.etag, if_none_match.as_deref());
+    if response.status() == StatusCode::NOT_MODIFIED {
+        return Ok(response);
+    }
+    Ok(compression::gzip_encoded(response))
+}
+
+// The headers between min_height and max_height, both inclusive. The order
+// is kept.
+fn headers_in_range(
+    headers: Vec<HeaderInfoJson>,
+    min_height: Option<u64>,
+    max_height: Option<u64>,
+) -> Vec<HeaderInfoJson> {
+    headers
+        .into_iter()
+        .filter(|header| min_height.is_none_or(|min| header.height >= min))
+        .filter(|header| max_height.is_none_or(|max| header.height <= max))
+        .collect()
+}
+
+// The max_headers highest headers. Starting with the highest header, the
+// ancestors of each header are included until the cap is reached, so the
+// headers stay connected as far as the cap allows. The order is kept.
+fn cap_headers(headers: Vec<HeaderInfoJson>, max_headers: usize) -> Vec<HeaderInfoJson> {
+    if headers.len() <= max_headers {
+        return headers;
+    }
+    let index_by_id: HashMap<usize, usize> = headers
+        .iter()
+        .enumerate()
+        .map(|(index, header)| (header.id, index))
+        .collect();
+    let mut by_height: Vec<usize> = (0..headers.len()).collect();
+    by_height.sort_by(|a, b| headers[*b].height.cmp(&headers[*a].height));
+
+    let mut selected = vec![false; headers.len()];
+    let mut count = 0;
+    for start in by_height {
+        let mut next = Some(start);
+        while let Some(index) = next {
+            if count >= max_headers || selected[index] {
+                break;
+            }
+            selected[index] = true;
+            count += 1;
+            next = index_by_id.get(&headers[index].prev_id).copied();
+        }
+        if count >= max_headers {
+            break;
+        }
+    }
+    headers
+        .into_iter()
+        .zip(selected)
+        .filter(|(_, selected)| *selected)
+        .map(|(header, _)| header)
+        .collect()
+}
+
+// A response with an already serialized JSON body.
+fn json_bytes_response(body: &Arc<Bytes>) -> warp::reply::Response {
+    warp::reply::with_header(
+        warp::reply::Response::new(Bytes::clone(body).into()),
+        CONTENT_TYPE,
+        "application/json",
+    )
+    .into_response()
+}
+
+// A strong ETag of a response body: the first 16 bytes of its SHA256 in hex.
+// Derived from the content, so it stays valid across restarts.
+fn etag(body: &[u8]) -> String {
+    format!(
+        "\"{}\"",
+        hex::encode(&sha256::Hash::hash(body).to_byte_array()[..16])
+    )
+}
+
+// If the If-None-Match header value lists the ETag or is "*". Weak ETags
+// match their strong counterpart, as If-None-Match uses the weak comparison.
+fn etag_matches(if_none_match: &str, etag: &str) -> bool {
+    if_none_match
+        .split(',')
+        .map(|tag| tag.trim())
+        .any(|tag| tag == "*" || tag.strip_prefix("W/").unwrap_or(tag) == etag)
+}
+
+// A response with an already serialized JSON body and its ETag, or an empty
+// 304 Not Modified if the client already has the body.
+fn etag_json_response(
+    body: &Arc<Bytes>,
+    etag: &str,
+    if_none_match: Option<&str>,
+) -> warp::reply::Response {
+    let response = if if_none_match.is_some_and(|header| etag_matches(header, etag)) {
+        StatusCode::NOT_MODIFIED.into_response()
+    } else {
+        json_bytes_response(body)
+    };
+    warp::reply::with_header(response, ETAG, etag).into_response()
+}
+
+// The headers and nodes changed since the generation. Falls back to all
+// headers and nodes if the generation is too old.
+fn data_delta(cache: Option<&mut Cache>, since: u64, privacy: &Privacy) -> DataDeltaJsonResponse {
+    let cache = match cache {
+        Some(cache) => cache,
+        None => {
+            return DataDeltaJsonResponse {
+                generation: 0,
+                full: true,
+                header_infos: vec![],
+                removed_header_infos: vec![],
+                nodes: vec![],
+                deployment_disagreements: vec![],
+            }
+        }
+    };
+    let node_data = cache.served_node_data(privacy);
+    let header_infos = cache.header_infos_with_status();
+    match cache.journal.since(since) {
+        Some(delta) => DataDeltaJsonResponse {
+            generation: cache.journal.generation,
+            full: false,
+            header_infos: header_infos
+                .into_iter()
+                .filter(|h| delta.headers.contains(&h.hash))
+                .collect(),
+            removed_header_infos: delta.removed_headers.iter().cloned().collect(),
+            nodes: node_data
+                .into_values()
+                .filter(|n| delta.nodes.contains(&n.id))
+                .map(|n| n.without_poll_latency())
+                .collect(),
+            deployment_disagreements: cache.deployment_disagreements.clone(),
+        },
+        None => DataDeltaJsonResponse {
+            generation: cache.journal.generation,
+            full: true,
+            header_infos,
+            removed_header_infos: vec![],
+            nodes: node_data
+                .into_values()
+                .map(|n| n.without_poll_latency())
+                .collect(),
+            deployment_disagreements: cache.deployment_disagreements.clone(),
+        },
+    }
+}
+
+pub async fn data_v2_response(
+    network: u32,
+    caches: Caches,
+    privacy: BTreeMap<u32, Privacy>,
+) -> Result<impl warp::Reply, Infallible> {
+    let privacy = privacy.get(&network).cloned().unwrap_or_default();
+    let mut caches_locked = caches.lock().await;
+    match caches_locked.get_mut(&network) {
+        Some(cache) => Ok(warp::reply::json(&DataV2JsonResponse::new(
+            &cache.header_infos_with_status(),
+            &cache
+                .served_node_data(&privacy)
+                .into_values()
+                .collect::<Vec<_>>(),
+            &cache.deployment_disagreements,
+        ))),
+        None => Ok(warp::reply::json(&DataV2JsonResponse::new(&[], &[], &[]))),
+    }
+}
+
+// The node data without the headers. Unknown networks are answered with a
+// 404.
+pub async fn nodes_response(
+    network: u32,
+    caches: Caches,
+    privacy: BTreeMap<u32, Privacy>,
+) -> Result<impl warp::Reply, Infallible> {
+    let privacy = privacy.get(&network).cloned().unwrap_or_default();
+    let caches_locked = caches.lock().await;
+    match caches_locked.get(&network) {
+        Some(cache) => Ok(warp::reply::json(
+            &cache
+                .served_node_data(&privacy)
+                .into_values()
+                .collect::<Vec<_>>(),
+        )
+        .into_response()),
+        None => {
+            Ok(warp::reply::with_status("Unknown network.", StatusCode::NOT_FOUND).into_response())
+        }
+    }
+}
+
+pub async fn signalling_response(
+    network: u32,
+    query: SignallingQuery,
+    caches: Caches,
+    trees: Trees,
+) -> Result<impl warp::Reply, Infallible> {
+    let window = query
+        .window
+        .unwrap_or(DEFAULT_SIGNALLING_WINDOW)
+        .min(MAX_SIGNALLING_WINDOW);
+    // The nodes might disagree on the active tip. Use the highest one.
+    let active_tip: Option<BlockHash> = {
+        let caches_locked = caches.lock().await;
+        caches_locked.get(&network).and_then(|cache| {
+            cache
+                .node_data
+                .values()
+                .filter_map(|node| node.active_tip())
+                .max_by_key(|tip| tip.height)
+                .and_then(|tip| BlockHash::from_str(&tip.hash).ok())
+        })
+    };
+
+    let stats = match (active_tip, trees.get(&network)) {
+        (Some(tip), Some(tree)) => headertree::signalling_stats(tree, &tip, window).await,
+        _ => None,
+    };
+    Ok(warp::reply::json(&stats.unwrap_or_else(|| {
+        headertree::signalling_stats_from_headers(&[])
+    })))
+}
+
+// A header of the tree with the hashes of its parent, if in the tree, and of
+// its children.
+fn tree_block(
+    tree: &DiGraph<HeaderInfo, bool>,
+    idx: NodeIndex,
+) -> (HeaderInfo, Option<String>, Vec<String>) {
+    let parent = tree
+        .neighbors_directed(idx, Direction::Incoming)
+        .next()
+        .map(|parent| tree[parent].header.block_hash().to_string());
+    let mut children: Vec<String> = tree
+        .neighbors_directed(idx, Direction::Outgoing)
+        .map(|child| tree[child].header.block_hash().to_string())
+        .collect();
+    children.sort();
+    (tree[idx].clone(), parent, children)
+}
+
+// Loads the block details that aren't part of the header tree.
+async fn block_json(
+    network: u32,
+    (header_info, parent, children): (HeaderInfo, Option<String>, Vec<String>),
+    validity: &BTreeMap<String, BTreeMap<u32, String>>,
+    db: Db,
+) -> Result<BlockJsonResponse, DbError> {
+    let hash = header_info.header.block_hash();
+    let coinbase_pays_watched = db::watched_payouts(db.clone(), network, &hash).await?;
+    let miner_identified_by = db::miner_identification(db, network, &hash).await?;
+    Ok(BlockJsonResponse::new(
+        &header_info,
+        validity.get(&hash.to_string()).cloned().unwrap_or_default(),
+        coinbase_pays_watched,
+        miner_identified_by,
+        parent,
+        children,
+    ))
+}
+
+async fn blocks_response(
+    network: u32,
+    blocks: Vec<(HeaderInfo, Option<String>, Vec<String>)>,
+    caches: Caches,
+    db: Db,
+) -> Result<Vec<BlockJsonResponse>, warp::reply::Response> {
+    let validity = caches
+        .lock()
+        .await
+        .get(&network)
+        .map(|cache| tip_validity(&cache.node_data))
+        .unwrap_or_default();
+    let mut responses = Vec::with_capacity(blocks.len());
+    for block in blocks {
+        let hash = block.0.header.block_hash();
+        match block_json(network, block, &validity, db.clone()).await {
+            Ok(response) => responses.push(response),
+            Err(e) => {
+                error!(
+                    "Could not load the details of block {} on network {} from the database: {}",
+                    hash, network, e
+                );
+                return Err(warp::reply::with_status(
+                    "Could not load the block.",
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                )
+                .into_response());
+            }
+        }
+    }
+    Ok(responses)
+}
+
+fn unknown_network() -> warp::reply::Response {
+    warp::reply::with_status("Unknown network.", StatusCode::NOT_FOUND).into_response()
+}
+
+fn unknown_block() -> warp::reply::Response {
+    warp::reply::with_status("Unknown block.", StatusCode::NOT_FOUND).into_response()
+}
+
+// The hash can have a .json suffix, i.e. /api/<network id>/block/<hash>.json.
+// Blocks that aren't in the header tree (anymore) are loaded from the
+// database, without a parent and children.
+pub async fn block_response(
+    network: u32,
+    hash: String,
+    caches: Caches,
+    trees: Trees,
+    db: Db,
+) -> Result<impl warp::Reply, Infallible> {
+    let tree = match trees.get(&network) {
+        Some(tree) => tree,
+        None => return Ok(unknown_network()),
+    };
+    let hash = match BlockHash::from_str(hash.strip_suffix(".json").unwrap_or(&hash)) {
+        Ok(hash) => hash,
+        Err(_) => return Ok(unknown_block()),
+    };
+    let block = {
+        let tree_locked = tree.lock().await;
+        let (tree, hash_index_map) = &*tree_locked;
+        hash_index_map.get(&hash).map(|idx| tree_block(tree, *idx))
+    };
+    let block = match block {
+        Some(block) => block,
+        None => match db::header_info_by_hash(db.clone(), network, &hash).await {
+            Ok(Some(header_info)) => (header_info, None, vec![]),
+            Ok(None) => return Ok(unknown_block()),
+            Err(e) => {
+                error!(
+                    "Could not load header {} on network {} from the database: {}",
+                    hash, network, e
+                );
+                return Ok(warp::reply::with_status(
+                    "Could not load the block.",
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                )
+                .into_response());
+            }
+        },
+    };
+    match blocks_response(network, vec![block], caches, db).await {
+        Ok(mut blocks) => Ok(warp::reply::json(&blocks.remove(0)).into_response()),
+        Err(response) => Ok(response),
+    }
+}
+
+/// The blocks at the height in the header tree. More than one if there's a
+/// fork at the height.
+pub async fn blocks_by_height_response(
+    network: u32,
+    height: u64,
+    caches: Caches,
+    trees: Trees,
+    db: Db,
+) -> Result<impl warp::Reply, Infallible> {
+    let tree = match trees.get(&network) {
+        Some(tree) => tree,
+        None => return Ok(unknown_network()),
+    };
+    let blocks: Vec<_> = {
+        let tree_locked = tree.lock().await;
+        let (tree, _) = &*tree_locked;
+        let mut blocks: Vec<_> = tree
+            .node_indices()
+            .filter(|idx| tree[*idx].height == height)
+            .map(|idx| tree_block(tree, idx))
+            .collect();
+        blocks.sort_by_key(|(header_info, _, _)| header_info.header.block_hash().to_string());
+        blocks
+    };
+    if blocks.is_empty() {
+        return Ok(unknown_block());
+    }
+    match blocks_response(network, blocks, caches, db).await {
+        Ok(blocks) => Ok(warp::reply::json(&blocks).into_response()),
+        Err(response) => Ok(response),
+    }
+}
+
+// Compares the tokens in constant time for tokens of the same length.
+fn tokens_match(a: &str, b: &str) -> bool {
+    a.len() == b.len()
+        && a.bytes()
+            .zip(b.bytes())
+            .fold(0u8, |diff, (x, y)| diff | (x ^ y))
+            == 0
+}
+
+// If the Authorization header carries the admin token as bearer token.
+fn is_admin(authorization: Option<&str>, admin_token: &str) -> bool {
+    authorization
+        .and_then(|authorization| authorization.strip_prefix("Bearer "))
+        .is_some_and(|token| tokens_match(token.trim(), admin_token))
+}
+
+// Queues a block for pool identification again, e.g. after the nodes couldn't
+// serve its coinbase. Blocks with an identified miner are skipped by the
+// pool identification. Without a configured admin token, the endpoint
+// doesn't exist.
+pub async fn identify_response(
+    network: u32,
+    hash: String,
+    authorization: Option<String>,
+    admin_token: Option<String>,
+    trees: Trees,
+    pool_id_queues: PoolIdQueues,
+) -> Result<impl warp::Reply, Infallible> {
+    let admin_token = match admin_token {
+        Some(admin_token) => admin_token,
+        None => {
+            return Ok(
+                warp::reply::with_status("Unknown API endpoint.", StatusCode::NOT_FOUND)
+                    .into_response(),
+            )
+        }
+    };
+    if !is_admin(authorization.as_deref(), &admin_token) {
+        return Ok(warp::reply::with_header(
+            warp::reply::with_status("Unauthorized.", StatusCode::UNAUTHORIZED),
+            "www-authenticate",
+            "Bearer",
+        )
+        .into_response());
+    }
+    let (tree, queue) = match (trees.get(&network), pool_id_queues.get(&network)) {
+        (Some(tree), Some(queue)) => (tree, queue),
+        _ => return Ok(unknown_network()),
+    };
+    let hash = match BlockHash::from_str(&hash) {
+        Ok(hash) => hash,
+        Err(_) => return Ok(unknown_block()),
+    };
+    if !tree.lock().await.1.contains_key(&hash) {
+        return Ok(unknown_block());
+    }
+    if !queue.send(hash).await {
+        return Ok(warp::reply::with_status(
+            "The pool identification queue is full.",
+            StatusCode::SERVICE_UNAVAILABLE,
+        )
+        .into_response());
+    }
+    info!(
+        "Queued block {} on network {} for pool identification",
+        hash, network
+    );
+    Ok(warp::reply::with_status(
+        "Queued the block for pool identification.",
+        StatusCode::ACCEPTED,
+    )
+    .into_response())
+}
+
+pub async fn search_response(
+    query: SearchQuery,
+    trees: Trees,
+    caches: Caches,
+) -> Result<impl warp::Reply, Infallible> {
+    let term =
+        match query.q.as_deref().and_then(SearchTerm::parse) {
+            Some(term) => term,
+            None => return Ok(warp::reply::with_status(
+                "Expected a block hash, a block hash prefix of at least 8 characters or a height.",
+                StatusCode::BAD_REQUEST,
+            )
+            .into_response()),
+        };
+
+    let active_tips: BTreeMap<u32, Vec<BlockHash>> = {
+        let caches_locked = caches.lock().await;
+        caches_locked
+            .iter()
+            .map(|(network, cache)| {
+                let tips = cache
+                    .node_data
+                    .values()
+                    .flat_map(|node| node.tips.iter())
+                    .filter(|tip| tip.status == ChainTipStatus::Active)
+                    .filter_map(|tip| BlockHash::from_str(&tip.hash).ok())
+                    .collect();
+                (*network, tips)
+            })
+            .collect()
+    };
+
+    let mut results: Vec<SearchResultJson> = vec![];
+    let mut truncated = false;
+    for (network, tree) in trees.iter() {
+        let limit = MAX_SEARCH_RESULTS - results.len();
+        if limit == 0 {
+            truncated = true;
+            break;
+        }
+        let tips = active_tips.get(network).cloned().unwrap_or_default();
+        let (matches, more) = headertree::search(tree, &term, &tips, limit).await;
+        truncated |= more;
+        results.extend(
+            matches
+                .into_iter()
+                .map(|(header_info, active)| SearchResultJson {
+                    network: *network,
+                    height: header_info.height,
+                    hash: header_info.header.block_hash().to_string(),
+                    miner: header_info.miner,
+                    active,
+                }),
+        );
+    }
+    Ok(warp::reply::json(&SearchJsonResponse { results, truncated }).into_response())
+}
+
+pub async fn node_chaintips_response(
+    network: u32,
+    node: u32,
+    caches: Caches,
+) -> Result<impl warp::Reply, Infallible> {
+    let caches_locked = caches.lock().await;
+    let raw_tips = match caches_locked.get(&network) {
+        Some(cache) => &cache.raw_tips,
+        None => {
+            return Ok(
+                warp::reply::with_status("Unknown network.", StatusCode::NOT_FOUND).into_response(),
+            )
+        }
+    };
+    match raw_tips {
+        Some(raw_tips) => match raw_tips.get(&node) {
+            Some(tips) => Ok(warp::reply::json(tips).into_response()),
+            None => Ok(warp::reply::with_status(
+                "Unknown node or no chain tips fetched from it yet.",
+                StatusCode::NOT_FOUND,
+            )
+            .into_response()),
+        },
+        None => Ok(warp::reply::with_status(
+            "Raw chain tips are not retained for this network. Set retain_raw_tips = true in the network configuration.",
+            StatusCode::NOT_FOUND,
+        )
+        .into_response()),
+    }
+}
+
+pub async fn node_tips_replay_response(
+    network: u32,
+    node: u32,
+    caches: Caches,
+) -> Result<impl warp::Reply, Infallible> {
+    let caches_locked = caches.lock().await;
+    let tips_replay = match caches_locked.get(&network) {
+        Some(cache) => &cache.tips_replay,
+        None => {
+            return Ok(
+                warp::reply::with_status("Unknown network.", StatusCode::NOT_FOUND).into_response(),
+            )
+        }
+    };
+    match tips_replay {
+        Some(tips_replay) => match tips_replay.snapshots(node) {
+            Some(snapshots) => Ok(warp::reply::json(snapshots).into_response()),
+            None => Ok(warp::reply::with_status(
+                "Unknown node or no chain tips fetched from it yet.",
+                StatusCode::NOT_FOUND,
+            )
+            .into_response()),
+        },
+        None => Ok(warp::reply::with_status(
+            "Chain tip snapshots are not retained for this network. Set tips_replay_length in the network configuration.",
+            StatusCode::NOT_FOUND,
+        )
+        .into_response()),
+    }
+}
+
+pub async fn header_hex_response(
+    network: u32,
+    hash: String,
+    trees: Trees,
+    db: Db,
+) -> Result<impl warp::Reply, Infallible> {
+    let hash = match BlockHash::from_str(&hash) {
+        Ok(hash) => hash,
+        Err(_) => {
+            return Ok(
+                warp::reply::with_status("Unknown block.", StatusCode::NOT_FOUND).into_response(),
+            )
+        }
+    };
+
+    let header_info = match trees.get(&network) {
+        Some(tree) => {
+            let tree_locked = tree.lock().await;
+            let (tree, hash_index_map) = &*tree_locked;
+            hash_index_map.get(&hash).map(|idx| tree[*idx].clone())
+        }
+        None => None,
+    };
+    let header_info = match header_info {
+        Some(header_info) => Some(header_info),
+        None => match db::header_info_by_hash(db, network, &hash).await {
+            Ok(header_info) => header_info,
+            Err(e) => {
+                error!(
+                    "Could not load header {} on network {} from the database: {}",
+                    hash, network, e
+                );
+                return Ok(warp::reply::with_status(
+                    "Could not load the header.",
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                )
+                .into_response());
+            }
+        },
+    };
+
+    match header_info {
+        Some(header_info) => Ok(serialize_hex(&header_info.header).into_response()),
+        None => {
+            Ok(warp::reply::with_status("Unknown block.", StatusCode::NOT_FOUND).into_response())
+        }
+    }
+}
+
+pub async fn headers_hex_by_height_response(
+    network: u32,
+    query: HeightQuery,
+    trees: Trees,
+    db: Db,
+) -> Result<impl warp::Reply, Infallible> {
+    let mut header_infos: Vec<HeaderInfo> = match trees.get(&network) {
+        Some(tree) => {
+            let tree_locked = tree.lock().await;
+            tree_locked
+                .0
+                .node_weights()
+                .filter(|h| h.height == query.height)
+                .cloned()
+                .collect()
+        }
+        None => vec![],
+    };
+    if header_infos.is_empty() {
+        header_infos = match db::header_infos_by_height(db, network, query.height).await {
+            Ok(header_infos) => header_infos,
+            Err(e) => {
+                error!(
+                    "Could not load headers at height {} on network {} from the database: {}",
+                    query.height, network, e
+                );
+                return Ok(warp::reply::with_status(
+                    "Could not load the headers.",
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                )
+                .into_response());
+            }
+        };
+    }
+
+    let headers_hex: Vec<String> = header_infos
+        .iter()
+        .map(|h| serialize_hex(&h.header))
+        .collect();
+    Ok(warp::reply::json(&headers_hex).into_response())
+}
+
+pub async fn forks_response(
+    network: u32,
+    query: SinceHeightQuery,
+    if_none_match: Option<String>,
+    caches: Caches,
+    network_infos: Vec<NetworkJson>,
+) -> Result<impl warp::Reply, Infallible> {
+    let mut caches_locked = caches.lock().await;
+    match caches_locked.get_mut(&network) {
+        Some(cache) => {
+            let statuses = cache.header_status_map();
+            let forks: Vec<ForkJson> = cache
+                .forks
+                .iter()
+                .filter(|fork| query.since_height.is_none_or(|h| fork.common.height > h))
+                .map(|fork| ForkJson::new(fork, &statuses))
+                .collect();
+            match serde_json::to_vec(&forks) {
+                Ok(body) => {
+                    let body = Arc::new(Bytes::from(body));
+                    Ok(etag_json_response(
+                        &body,
+                        &etag(&body),
+                        if_none_match.as_deref(),
+                    ))
+                }
+                Err(e) => {
+                    error!("could not serialize forks.json: {}", e);
+                    Ok(StatusCode::INTERNAL_SERVER_ERROR.into_response())
+                }
+            }
+        }
+        None => Ok(rss::response_unknown_network(network_infos).into_response()),
+    }
+}
+
+// Aggregates the last active-chain headers by miner, most blocks first.
+// Blocks without an identified miner are counted as "Unknown".
+fn miner_shares(headers: &[HeaderInfoJson], last: usize) -> Vec<MinerShareJson> {
+    let mut active: Vec<&HeaderInfoJson> = headers
+        .iter()
+        .filter(|header| header.status == HeaderStatus::Active)
+        .collect();
+    active.sort_by_key(|header| Reverse(header.height));
+    active.truncate(last);
+
+    let mut counts: BTreeMap<&str, usize> = BTreeMap::new();
+    for header in active.iter() {
+        let miner = if header.miner.is_empty() {
+            crate::MINER_UNKNOWN
+        } else {
+            header.miner.as_str()
+        };
+        *counts.entry(miner).or_default() += 1;
+    }
+    let mut shares: Vec<MinerShareJson> = counts
+        .into_iter()
+        .map(|(miner, block_count)| MinerShareJson {
+            miner: miner.to_string(),
+            block_count,
+            share: block_count as f64 / active.len() as f64,
+        })
+        .collect();
+    // sort_by_key is stable: miners with the same count stay ordered by name
+    shares.sort_by_key(|share| Reverse(share.block_count));
+    shares
+}
+
+pub async fn miners_response(
+    network: u32,
+    query: MinersQuery,
+    caches: Caches,
+    network_infos: Vec<NetworkJson>,
+) -> Result<impl warp::Reply, Infallible> {
+    let mut caches_locked = caches.lock().await;
+    match caches_locked.get_mut(&network) {
+        Some(cache) => {
+            let mut headers = cache.header_infos_with_status();
+            // The miner of a recent block might not be in the header tree yet.
+            for header in headers.iter_mut().filter(|h| h.miner.is_empty()) {
+                if let Some((miner, _)) = cache.recent_miners.get(&header.hash) {
+                    header.miner = miner.clone();
+                }
+            }
+            let shares = miner_shares(&headers, query.last.unwrap_or(DEFAULT_MINERS_WINDOW));
+            Ok(warp::reply::json(&shares).into_response())
+        }
+        None => Ok(rss::response_unknown_network(network_infos).into_response()),
+    }
+}
+
+pub async fn fork_history_response(
+    network: u32,
+    query: ForkHistoryQuery,
+    caches: Caches,
+    db: Db,
+    network_infos: Vec<NetworkJson>,
+) -> Result<impl warp::Reply, Infallible> {
+    if !caches.lock().await.contains_key(&network) {
+        return Ok(rss::response_unknown_network(network_infos).into_response());
+    }
+    let page = query.page.unwrap_or_default();
+    let history = async {
+        Ok::<_, DbError>(ForkHistoryJsonResponse {
+            page,
+            page_size: FORK_HISTORY_PAGE_SIZE,
+            forks: db::load_forks(db.clone(), network, page, FORK_HISTORY_PAGE_SIZE).await?,
+            total: db::count_forks(db, network).await?,
+        })
+    }
+    .await;
+    match history {
+        Ok(history) => Ok(warp::reply::json(&history).into_response()),
+        Err(e) => {
+            error!(
+                "Could not load the fork history of network {} from the database: {}",
+                network, e
+            );
+            Ok(warp::reply::with_status(
+                "Could not load the fork history.",
+                StatusCode::INTERNAL_SERVER_ERROR,
+            )
+            .into_response())
+        }
+    }
+}
+
+// Most recent alert first.
+pub async fn alert_history_response(
+    network: u32,
+    query: AlertHistoryQuery,
+    caches: Caches,
+    db: Db,
+    network_infos: Vec<NetworkJson>,
+) -> Result<impl warp::Reply, Infallible> {
+    if !caches.lock().await.contains_key(&network) {
+        return Ok(rss::response_unknown_network(network_infos).into_response());
+    }
+    let page = query.page.unwrap_or_default();
+    let history = async {
+        Ok::<_, DbError>(AlertHistoryJsonResponse {
+            page,
+            page_size: ALERT_HISTORY_PAGE_SIZE,
+            alerts: db::load_alert_history(db.clone(), network, page, ALERT_HISTORY_PAGE_SIZE)
+                .await?,
+            total: db::count_alerts(db, network).await?,
+        })
+    }
+    .await;
+    match history {
+        Ok(history) => Ok(warp::reply::json(&history).into_response()),
+        Err(e) => {
+            error!(
+                "Could not load the alert history of network {} from the database: {}",
+                network, e
+            );
+            Ok(warp::reply::with_status(
+                "Could not load the alert history.",
+                StatusCode::INTERNAL_SERVER_ERROR,
+            )
+            .into_response())
+        }
+    }
+}
+
+// Most recent reorg first.
+pub async fn reorgs_response(
+    network: u32,
+    caches: Caches,
+    network_infos: Vec<NetworkJson>,
+) -> Result<impl warp::Reply, Infallible> {
     let caches_locked = caches.lock().await;
     match caches_locked.get(&network) {
-        Some(cache) => Ok(warp::reply::json(&DataJsonResponse {
-            header_infos: cache.header_infos_json.clone(),
-            nodes: cache.node_data.values().cloned().collect(),
-        })),
-        None => Ok(warp::reply::json(&DataJsonResponse {
-            header_infos: vec![],
-            nodes: vec![],
-        })),
+        Some(cache) => {
+            let reorgs: Vec<&ReorgJson> = cache.reorgs.iter().rev().collect();
+            Ok(warp::reply::json(&reorgs).into_response())
+        }
+        None => Ok(rss::response_unknown_network(network_infos).into_response()),
+    }
+}
+
+pub async fn invalid_response(
+    network: u32,
+    query: SinceHeightQuery,
+    caches: Caches,
+    privacy: BTreeMap<u32, Privacy>,
+    network_infos: Vec<NetworkJson>,
+) -> Result<impl warp::Reply, Infallible> {
+    let privacy = privacy.get(&network).cloned().unwrap_or_default();
+    let caches_locked = caches.lock().await;
+    match caches_locked.get(&network) {
+        Some(cache) => {
+            // Shared with the invalid blocks RSS feed.
+            let invalid: Vec<InvalidBlockJson> =
+                rss::invalid_blocks(&cache.served_node_data(&privacy))
+                    .into_iter()
+                    .filter(|(tip, _)| query.since_height.is_none_or(|h| tip.height > h))
+                    .map(|(tip, nodes)| InvalidBlockJson {
+                        hash: tip.hash,
+                        height: tip.height,
+                        nodes: nodes.iter().map(|node| node.id).collect(),
+                        node_names: nodes.iter().map(|node| node.name.clone()).collect(),
+                    })
+                    .collect();
+            Ok(warp::reply::json(&invalid).into_response())
+        }
+        None => Ok(rss::response_unknown_network(network_infos).into_response()),
+    }
+}
+
+pub async fn consensus_response(
+    network: u32,
+    caches: Caches,
+    trees: Trees,
+) -> Result<impl warp::Reply, Infallible> {
+    let node_data = match caches.lock().await.get(&network) {
+        Some(cache) => cache.node_data.clone(),
+        None => {
+            return Ok(
+                warp::reply::with_status("Unknown network.", StatusCode::NOT_FOUND).into_response(),
+            )
+        }
+    };
+    let tree = match trees.get(&network) {
+        Some(tree) => tree,
+        None => {
+            return Ok(
+                warp::reply::with_status("Unknown network.", StatusCode::NOT_FOUND).into_response(),
+            )
+        }
+    };
+    Ok(warp::reply::json(&consensus(&node_data, tree).await).into_response())
+}
+
+// Compares the active tips of the reachable nodes. Nodes more than
+// THREASHOLD_NODE_LAGGING blocks behind the highest tip are lagging and
+// reported separately, as in the lagging nodes feed.
+async fn consensus(node_data: &NodeData, tree: &Tree) -> ConsensusJsonResponse {
+    let groups = active_tip_groups(node_data);
+    let highest_height = groups.first().map(|tip| tip.height).unwrap_or_default();
+    let (tips, lagging): (Vec<ConsensusTipJson>, Vec<ConsensusTipJson>) = groups
+        .into_iter()
+        .partition(|tip| tip.height + rss::THREASHOLD_NODE_LAGGING >= highest_height);
+
+    // The nodes are only on the same chain if all lower tips, including the
+    // lagging ones, are ancestors of the highest tip. As in the chain split
+    // alerts, tips not in the tree yet are skipped.
+    let mut split = false;
+    if let Some(highest) = tips
+        .first()
+        .and_then(|tip| BlockHash::from_str(&tip.hash).ok())
+    {
+        for tip in tips.iter().skip(1).chain(lagging.iter()) {
+            let hash = match BlockHash::from_str(&tip.hash) {
+                Ok(hash) => hash,
+                Err(_) => continue,
+            };
+            match headertree::common_ancestor(tree, &highest, &hash).await {
+                Some(common) if common != hash => {
+                    split = true;
+                    break;
+                }
+                _ => continue,
+            }
+        }
+    }
+
+    if split {
+        return ConsensusJsonResponse {
+            status: ConsensusStatus::Split,
+            hash: None,
+            height: None,
+            tips,
+            lagging,
+        };
+    }
+    if tips.len() <= 1 {
+        return ConsensusJsonResponse {
+            status: ConsensusStatus::Consistent,
+            hash: tips.first().map(|tip| tip.hash.clone()),
+            height: tips.first().map(|tip| tip.height),
+            tips,
+            lagging,
+        };
+    }
+    ConsensusJsonResponse {
+        status: ConsensusStatus::HeightMismatch,
+        hash: None,
+        height: None,
+        tips,
+        lagging,
+    }
+}
+
+// Reachable nodes grouped by their active tip, highest tip first.
+pub fn active_tip_groups(node_data: &NodeData) -> Vec<ConsensusTipJson> {
+    let mut groups: BTreeMap<(u64, String), Vec<u32>> = BTreeMap::new();
+    for node in node_data.values().filter(|node| node.reachable) {
+        if let Some(tip) = node.active_tip() {
+            groups
+                .entry((tip.height, tip.hash.clone()))
+                .or_default()
+                .push(node.id);
+        }
     }
+    groups
+        .into_iter()
+        .rev()
+        .map(|((height, hash), nodes)| ConsensusTipJson {
+            hash,
+            height,
+            nodes,
+        })
+        .collect()
+}
+
+// The validity opinions of the nodes for each chain tip they know about:
+// tip hash → {node id → tip status}. Nodes can disagree, e.g. one node
+// might consider a block invalid while another has it as valid-fork.
+pub fn tip_validity(node_data: &NodeData) -> BTreeMap<String, BTreeMap<u32, String>> {
+    let mut validity: BTreeMap<String, BTreeMap<u32, String>> = BTreeMap::new();
+    for node in node_data.values() {
+        for tip in node.tips.iter() {
+            validity
+                .entry(tip.hash.clone())
+                .or_default()
+                .insert(node.id, tip.status.to_string());
+        }
+    }
+    validity
 }
 
 pub async fn networks_response(
     network_infos: Vec<NetworkJson>,
+    trees: Trees,
+    caches: Caches,
+    freshness: Freshness,
+    show_empty_networks: bool,
 ) -> Result<impl warp::Reply, Infallible> {
+    let now = unix_timestamp();
+    let last_polls = last_poll_timestamps(&caches).await;
+    let (header_counts, with_data) = {
+        let caches_locked = caches.lock().await;
+        let header_counts: BTreeMap<u32, (Option<u64>, usize)> = caches_locked
+            .iter()
+            .map(|(id, cache)| (*id, (cache.headers_total, cache.header_infos_json.len())))
+            .collect();
+        let with_data: BTreeSet<u32> = caches_locked
+            .iter()
+            .filter(|(_, cache)| cache.has_data())
+            .map(|(id, _)| *id)
+            .collect();
+        (header_counts, with_data)
+    };
+    let mut networks = network_infos;
+    if !show_empty_networks {
+        networks.retain(|network| with_data.contains(&network.id));
+    }
+    for network in networks.iter_mut() {
+        if let Some(tree) = trees.get(&network.id) {
+            if let Some(network_type) = headertree::network_type(tree).await {
+                network.network_type = Some(network_type_name(network_type).to_string());
+            }
+        }
+        let (last_poll_ago_seconds, stale) =
+            freshness.network(last_polls.get(&network.id).cloned().flatten(), now);
+        network.last_poll_ago_seconds = last_poll_ago_seconds;
+        network.stale = stale;
+        if let Some((headers_total, headers_in_view)) = header_counts.get(&network.id) {
+            network.headers_total = *headers_total;
+            network.headers_in_view = *headers_in_view;
+        }
+    }
     Ok(warp::reply::json(&NetworksJsonResponse {
-        networks: network_infos,
+        uptime_seconds: freshness.uptime(now),
+        networks,
     }))
 }
 
+// The health of the nodes of each network.
+async fn node_health(caches: &Caches, now: u64) -> BTreeMap<u32, Vec<NodeHealthJson>> {
+    let caches_locked = caches.lock().await;
+    caches_locked
+        .iter()
+        .map(|(id, cache)| {
+            let lagging: BTreeSet<u32> = rss::lagging_nodes(&cache.node_data)
+                .iter()
+                .map(|(node, _)| node.id)
+                .collect();
+            let nodes = cache
+                .node_data
+                .values()
+                .map(|node| NodeHealthJson {
+                    id: node.id,
+                    reachable: node.reachable,
+                    last_changed_ago_seconds: Some(node.last_changed_timestamp)
+                        .filter(|timestamp| *timestamp > 0)
+                        .map(|timestamp| now.saturating_sub(timestamp)),
+                    lagging: lagging.contains(&node.id),
+                })
+                .collect();
+            (*id, nodes)
+        })
+        .collect()
+}
+
+/// Answers with a 503 if a node is unreachable. The body lists the health of
+/// each node either way.
+pub async fn health_response(
+    network_infos: Vec<NetworkJson>,
+    caches: Caches,
+    freshness: Freshness,
+    last_prune: LastPrune,
+    pool_id_queues: PoolIdQueues,
+) -> Result<impl warp::Reply, Infallible> {
+    let now = unix_timestamp();
+    let last_polls = last_poll_timestamps(&caches).await;
+    let mut node_health = node_health(&caches, now).await;
+    let networks: Vec<NetworkHealthJson> = network_infos
+        .iter()
+        .map(|network| {
+            let (last_poll_ago_seconds, stale) =
+                freshness.network(last_polls.get(&network.id).cloned().flatten(), now);
+            NetworkHealthJson {
+                id: network.id,
+                name: network.name.clone(),
+                last_poll_ago_seconds,
+                stale,
+                pool_id_queued: pool_id_queues
+                    .get(&network.id)
+                    .map(|queue| queue.queued())
+                    .unwrap_or_default(),
+                pool_id_dropped: pool_id_queues
+                    .get(&network.id)
+                    .map(|queue| queue.dropped())
+                    .unwrap_or_default(),
+                nodes: node_health.remove(&network.id).unwrap_or_default(),
+            }
+        })
+        .collect();
+    let all_reachable = networks
+        .iter()
+        .all(|network| network.nodes.iter().all(|node| node.reachable));
+    let (status, code) = if all_reachable {
+        ("ok", StatusCode::OK)
+    } else {
+        ("degraded", StatusCode::SERVICE_UNAVAILABLE)
+    };
+    let last_prune = last_prune.lock().await.clone();
+    Ok(warp::reply::with_status(
+        warp::reply::json(&HealthJsonResponse {
+            status,
+            uptime_seconds: freshness.uptime(now),
+            networks,
+            last_prune,
+        }),
+        code,
+    ))
+}
+
+/// Readiness check for container orchestration: 200 once every network has
+/// headers and tips, 503 while a network is still starting up.
+pub async fn readiness_response(
+    network_infos: Vec<NetworkJson>,
+    caches: Caches,
+) -> Result<impl warp::Reply, Infallible> {
+    let caches_locked = caches.lock().await;
+    let pending_networks: Vec<u32> = network_infos
+        .iter()
+        .map(|network| network.id)
+        .filter(|id| !caches_locked.get(id).is_some_and(|cache| cache.has_data()))
+        .collect();
+    let oldest_last_changed_timestamp = caches_locked
+        .values()
+        .flat_map(|cache| cache.node_data.values())
+        .map(|node| node.last_changed_timestamp)
+        .filter(|timestamp| *timestamp > 0)
+        .min();
+    let (status, code) = if pending_networks.is_empty() {
+        ("ok", StatusCode::OK)
+    } else {
+        ("starting", StatusCode::SERVICE_UNAVAILABLE)
+    };
+    Ok(warp::reply::with_status(
+        warp::reply::json(&ReadinessJsonResponse {
+            status,
+            networks: network_infos.len(),
+            pending_networks,
+            oldest_last_changed_timestamp,
+        }),
+        code,
+    ))
+}
+
+async fn last_poll_timestamps(caches: &Caches) -> BTreeMap<u32, Option<u64>> {
+    let caches_locked = caches.lock().await;
+    caches_locked
+        .iter()
+        .map(|(id, cache)| (*id, cache.last_poll_timestamp))
+        .collect()
+}
+
 pub fn data_changed_sse(
     network_id: u32,
 ) -> Result<Event, bitcoincore_rpc::jsonrpc::serde_json::Error> {
@@ -40,16 +1377,2416 @@ pub fn data_changed_sse(
         .json_data(DataChanged { network_id })
 }
 
+pub fn data_changed_detail_sse(
+    network_id: u32,
+    change: Change,
+) -> Result<Event, bitcoincore_rpc::jsonrpc::serde_json::Error> {
+    warp::sse::Event::default()
+        .event(change.kind())
+        .json_data(DataChangedDetail { network_id, change })
+}
+
+pub fn lagged_sse(skipped: u64) -> Result<Event, bitcoincore_rpc::jsonrpc::serde_json::Error> {
+    warp::sse::Event::default()
+        .event("lagged")
+        .json_data(Lagged { skipped })
+}
+
+// Counts an open SSE connection for as long as it's alive.
+pub struct SseConnection {
+    count: Arc<AtomicUsize>,
+}
+
+impl SseConnection {
+    // Returns None if there are already max connections open.
+    pub fn open(count: Arc<AtomicUsize>, max: usize) -> Option<SseConnection> {
+        count
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |open| {
+                if open < max {
+                    Some(open + 1)
+                } else {
+                    None
+                }
+            })
+            .ok()
+            .map(|_| SseConnection { count })
+    }
+}
+
+impl Drop for SseConnection {
+    fn drop(&mut self) {
+        self.count.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+// Merges the change events of all networks into one stream.
+pub fn change_event_stream(
+    senders: &BTreeMap<u32, broadcast::Sender<ChangeEvent>>,
+) -> SelectAll<BroadcastStream<ChangeEvent>> {
+    select_all(
+        senders
+            .values()
+            .map(|sender| BroadcastStream::new(sender.subscribe())),
+    )
+}
+
+pub fn change_event_sse(
+    event: ChangeEvent,
+) -> Result<Event, bitcoincore_rpc::jsonrpc::serde_json::Error> {
+    match event {
+        ChangeEvent::TipChanged { network_id } => data_changed_sse(network_id),
+        ChangeEvent::Changed { network_id, change } => data_changed_detail_sse(network_id, change),
+        ChangeEvent::Alert(alert) => warp::sse::Event::default()
+            .event(alert.kind.to_string())
+            .json_data(alert),
+    }
+}
+
+// Only lets requests for /api/ paths pass.
+pub fn api_path() -> impl Filter<Extract = (), Error = Rejection> + Clone {
+    warp::path::full()
+        .and_then(|path: FullPath| async move {
+            if path.as_str().starts_with("/api/") {
+                Ok(())
+            } else {
+                Err(warp::reject::not_found())
+            }
+        })
+        .untuple_one()
+}
+
+// Answers requests for unknown API paths in plain text. Other rejections,
+// e.g. for invalid query strings, are passed on.
+pub async fn api_rejection(rejection: Rejection) -> Result<warp::reply::Response, Rejection> {
+    if let Some(rate_limited) = rejection.find::<RateLimited>() {
+        Ok(rate_limited_response(rate_limited))
+    } else if rejection.is_not_found() {
+        Ok(
+            warp::reply::with_status("Unknown API endpoint.", StatusCode::NOT_FOUND)
+                .into_response(),
+        )
+    } else {
+        Err(rejection)
+    }
+}
+
+// Answers requests for unknown paths with an HTML page. Other rejections are
+// passed on.
+pub async fn page_rejection(rejection: Rejection) -> Result<warp::reply::Response, Rejection> {
+    if let Some(rate_limited) = rejection.find::<RateLimited>() {
+        Ok(rate_limited_response(rate_limited))
+    } else if rejection.is_not_found() {
+        Ok(
+            warp::reply::with_status(warp::reply::html(NOT_FOUND_HTML), StatusCode::NOT_FOUND)
+                .into_response(),
+        )
+    } else {
+        Err(rejection)
+    }
+}
+
+// The status code warp would answer a rejection with.
+fn rejection_status(rejection: &Rejection) -> StatusCode {
+    if rejection.is_not_found() {
+        StatusCode::NOT_FOUND
+    } else if rejection.find::<reject::MethodNotAllowed>().is_some() {
+        StatusCode::METHOD_NOT_ALLOWED
+    } else if rejection.find::<reject::InvalidQuery>().is_some()
+        || rejection.find::<reject::InvalidHeader>().is_some()
+        || rejection.find::<reject::MissingHeader>().is_some()
+    {
+        StatusCode::BAD_REQUEST
+    } else if rejection.find::<reject::LengthRequired>().is_some() {
+        StatusCode::LENGTH_REQUIRED
+    } else if rejection.find::<reject::PayloadTooLarge>().is_some() {
+        StatusCode::PAYLOAD_TOO_LARGE
+    } else if rejection.find::<reject::UnsupportedMediaType>().is_some() {
+        StatusCode::UNSUPPORTED_MEDIA_TYPE
+    } else {
+        StatusCode::INTERNAL_SERVER_ERROR
+    }
+}
+
+// Answers the rejections the other handlers passed on, so that every
+// request is answered by the routes and can be logged.
+pub async fn final_rejection(rejection: Rejection) -> Result<warp::reply::Response, Infallible> {
+    let status = rejection_status(&rejection);
+    Ok(
+        warp::reply::with_status(status.canonical_reason().unwrap_or_default(), status)
+            .into_response(),
+    )
+}
+
+fn rate_limited_response(rate_limited: &RateLimited) -> warp::reply::Response {
+    warp::reply::with_header(
+        warp::reply::with_status("Too many requests.", StatusCode::TOO_MANY_REQUESTS),
+        "retry-after",
+        rate_limited.retry_after.as_secs().to_string(),
+    )
+    .into_response()
+}
+
 pub fn with_footer(footer: String) -> impl Filter<Extract = (String,), Error = Infallible> + Clone {
     warp::any().map(move || footer.clone())
 }
 
+pub fn with_hash_prefix_length(
+    length: usize,
+) -> impl Filter<Extract = (usize,), Error = Infallible> + Clone {
+    warp::any().map(move || length)
+}
+
 pub fn with_caches(caches: Caches) -> impl Filter<Extract = (Caches,), Error = Infallible> + Clone {
     warp::any().map(move || caches.clone())
 }
 
+pub fn with_trees(trees: Trees) -> impl Filter<Extract = (Trees,), Error = Infallible> + Clone {
+    warp::any().map(move || trees.clone())
+}
+
+pub fn with_db(db: Db) -> impl Filter<Extract = (Db,), Error = Infallible> + Clone {
+    warp::any().map(move || db.clone())
+}
+
+pub fn with_privacy(
+    privacy: BTreeMap<u32, Privacy>,
+) -> impl Filter<Extract = (BTreeMap<u32, Privacy>,), Error = Infallible> + Clone {
+    warp::any().map(move || privacy.clone())
+}
+
+pub fn with_show_empty_networks(
+    show_empty_networks: bool,
+) -> impl Filter<Extract = (bool,), Error = Infallible> + Clone {
+    warp::any().map(move || show_empty_networks)
+}
+
+pub fn with_freshness(
+    freshness: Freshness,
+) -> impl Filter<Extract = (Freshness,), Error = Infallible> + Clone {
+    warp::any().map(move || freshness.clone())
+}
+
+pub fn with_last_prune(
+    last_prune: LastPrune,
+) -> impl Filter<Extract = (LastPrune,), Error = Infallible> + Clone {
+    warp::any().map(move || last_prune.clone())
+}
+
+pub fn with_admin_token(
+    admin_token: Option<String>,
+) -> impl Filter<Extract = (Option<String>,), Error = Infallible> + Clone {
+    warp::any().map(move || admin_token.clone())
+}
+
+pub fn with_pool_id_queues(
+    pool_id_queues: PoolIdQueues,
+) -> impl Filter<Extract = (PoolIdQueues,), Error = Infallible> + Clone {
+    warp::any().map(move || pool_id_queues.clone())
+}
+
 pub fn with_networks(
     networks: Vec<NetworkJson>,
 ) -> impl Filter<Extract = (Vec<NetworkJson>,), Error = Infallible> + Clone {
     warp::any().map(move || networks.clone())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Theme;
+    use crate::test_utils;
+    use crate::types::{
+        Alert, AlertKind, ChainTip, ChainTipStatus, Fork, HeaderInfoJson, PoolIdQueue, PruneRun,
+    };
+    use bitcoincore_rpc::bitcoin::block::Header;
+    use bitcoincore_rpc::bitcoin::consensus::deserialize;
+    use bitcoincore_rpc::bitcoin::hashes::Hash;
+    use flate2::read::GzDecoder;
+    use rusqlite::Connection;
+    use std::collections::HashMap;
+    use std::io::Read;
+    use tokio::sync::Mutex;
+    use warp::http::header::CONTENT_ENCODING;
+
+    async fn body_string(reply: impl Reply) -> String {
+        let body = warp::hyper::body::to_bytes(reply.into_response().into_body())
+            .await
+            .expect("body should be readable");
+        String::from_utf8(body.to_vec()).expect("body should be UTF-8")
+    }
+
+    async fn test_db() -> Db {
+        let db: Db = Arc::new(Mutex::new(
+            Connection::open_in_memory().expect("in-memory database should open"),
+        ));
+        db::setup_db(db.clone())
+            .await
+            .expect("database should be set up");
+        db
+    }
+
+    fn assert_header_hex_roundtrips(header_hex: &str, header_info: &HeaderInfo) {
+        assert_eq!(header_hex.len(), 160);
+        assert_eq!(header_hex, header_hex.to_lowercase());
+        let header: Header =
+            deserialize(&hex::decode(header_hex).expect("valid hex")).expect("valid header");
+        assert_eq!(header.block_hash(), header_info.header.block_hash());
+    }
+
+    #[tokio::test]
+    async fn test_header_hex_roundtrip() {
+        let headers = test_utils::chain(BlockHash::all_zeros(), 1, 3, 0);
+        // The last header is only in the database.
+        let trees: Trees = Arc::new(BTreeMap::from([(0, test_utils::tree(&headers[..2]))]));
+        let db = test_db().await;
+        db::write_to_db(&headers, db.clone(), 0).await.unwrap();
+
+        for header_info in headers.iter() {
+            let reply = header_hex_response(
+                0,
+                header_info.header.block_hash().to_string(),
+                trees.clone(),
+                db.clone(),
+            )
+            .await
+            .unwrap()
+            .into_response();
+            assert_eq!(reply.status(), StatusCode::OK);
+            assert_header_hex_roundtrips(&body_string(reply).await, header_info);
+        }
+
+        let reply = header_hex_response(
+            0,
+            BlockHash::all_zeros().to_string(),
+            trees.clone(),
+            db.clone(),
+        )
+        .await
+        .unwrap()
+        .into_response();
+        assert_eq!(reply.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn test_block_json() {
+        // a fork at height 3
+        let chain = test_utils::chain(BlockHash::all_zeros(), 1, 4, 0);
+        let fork = test_utils::header_info(chain[1].header.block_hash(), 3, 100);
+        let mut headers = chain.clone();
+        headers.push(fork.clone());
+        let trees: Trees = Arc::new(BTreeMap::from([(0, test_utils::tree(&headers))]));
+        let caches: Caches = Arc::new(Mutex::new(BTreeMap::from([(
+            0,
+            test_utils::cache(BTreeMap::new()),
+        )])));
+        let db = test_db().await;
+        let hash = |h: &HeaderInfo| h.header.block_hash().to_string();
+        let block = |network: u32, hash: String| {
+            let (caches, trees, db) = (caches.clone(), trees.clone(), db.clone());
+            async move {
+                block_response(network, hash, caches, trees, db)
+                    .await
+                    .unwrap()
+                    .into_response()
+            }
+        };
+
+        let reply = block(0, hash(&chain[1])).await;
+        assert_eq!(reply.status(), StatusCode::OK);
+        let json: serde_json::Value = serde_json::from_str(&body_string(reply).await).unwrap();
+        assert_eq!(json["height"], 2);
+        assert_eq!(json["parent"], hash(&chain[0]));
+        let mut children = vec![hash(&chain[2]), hash(&fork)];
+        children.sort();
+        assert_eq!(json["children"], serde_json::json!(children));
+
+        // the parent of the lowest header isn't in the tree
+        let json: serde_json::Value =
+            serde_json::from_str(&body_string(block(0, hash(&chain[0])).await).await).unwrap();
+        assert_eq!(json["parent"], serde_json::Value::Null);
+
+        // with a .json suffix
+        let json: serde_json::Value = serde_json::from_str(
+            &body_string(block(0, format!("{}.json", hash(&chain[3]))).await).await,
+        )
+        .unwrap();
+        assert_eq!(json["height"], 4);
+        assert_eq!(json["children"], serde_json::json!([]));
+
+        // blocks only in the database don't have a parent and children
+        let db_only = test_utils::header_info(chain[3].header.block_hash(), 5, 200);
+        db::write_to_db(std::slice::from_ref(&db_only), db.clone(), 0)
+            .await
+            .unwrap();
+        let reply = block(0, format!("{}.json", hash(&db_only))).await;
+        assert_eq!(reply.status(), StatusCode::OK);
+        let json: serde_json::Value = serde_json::from_str(&body_string(reply).await).unwrap();
+        assert_eq!(json["height"], 5);
+        assert_eq!(json["prev_blockhash"], hash(&chain[3]));
+        assert_eq!(json["parent"], serde_json::Value::Null);
+
+        let reply = block(0, BlockHash::all_zeros().to_string()).await;
+        assert_eq!(reply.status(), StatusCode::NOT_FOUND);
+        assert_eq!(body_string(reply).await, "Unknown block.");
+        let reply = block(0, "not a hash".to_string()).await;
+        assert_eq!(reply.status(), StatusCode::NOT_FOUND);
+        let reply = block(1, hash(&chain[1])).await;
+        assert_eq!(reply.status(), StatusCode::NOT_FOUND);
+        assert_eq!(body_string(reply).await, "Unknown network.");
+
+        let by_height = |network: u32, height: u64| {
+            let (caches, trees, db) = (caches.clone(), trees.clone(), db.clone());
+            async move {
+                blocks_by_height_response(network, height, caches, trees, db)
+                    .await
+                    .unwrap()
+                    .into_response()
+            }
+        };
+        let reply = by_height(0, 3).await;
+        assert_eq!(reply.status(), StatusCode::OK);
+        let json: serde_json::Value = serde_json::from_str(&body_string(reply).await).unwrap();
+        let hashes: Vec<&str> = json
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|block| block["hash"].as_str().unwrap())
+            .collect();
+        let mut expected = vec![hash(&chain[2]), hash(&fork)];
+        expected.sort();
+        assert_eq!(hashes, expected);
+        assert_eq!(json[0]["parent"], hash(&chain[1]));
+        assert_eq!(by_height(0, 10).await.status(), StatusCode::NOT_FOUND);
+        let reply = by_height(1, 3).await;
+        assert_eq!(reply.status(), StatusCode::NOT_FOUND);
+        assert_eq!(body_string(reply).await, "Unknown network.");
+    }
+
+    #[tokio::test]
+    async fn test_headers_hex_by_height_roundtrip() {
+        let headers = vec![
+            test_utils::header_info(BlockHash::all_zeros(), 5, 0),
+            test_utils::header_info(BlockHash::all_zeros(), 5, 1),
+            test_utils::header_info(BlockHash::all_zeros(), 6, 2),
+        ];
+        let trees: Trees = Arc::new(BTreeMap::from([(0, test_utils::tree(&headers))]));
+        let db = test_db().await;
+
+        let body = body_string(
+            headers_hex_by_height_response(0, HeightQuery { height: 5 }, trees.clone(), db.clone())
+                .await
+                .unwrap(),
+        )
+        .await;
+        let headers_hex: Vec<String> = serde_json::from_str(&body).unwrap();
+        assert_eq!(headers_hex.len(), 2);
+        let by_hash: HashMap<BlockHash, &HeaderInfo> =
+            headers.iter().map(|h| (h.header.block_hash(), h)).collect();
+        for header_hex in headers_hex.iter() {
+            let header: Header = deserialize(&hex::decode(header_hex).unwrap()).unwrap();
+            assert_header_hex_roundtrips(header_hex, by_hash[&header.block_hash()]);
+        }
+
+        // falls back to the database for heights not in the tree
+        db::write_to_db(&[headers[2].clone()], db.clone(), 1)
+            .await
+            .unwrap();
+        let body = body_string(
+            headers_hex_by_height_response(1, HeightQuery { height: 6 }, trees, db)
+                .await
+                .unwrap(),
+        )
+        .await;
+        let headers_hex: Vec<String> = serde_json::from_str(&body).unwrap();
+        assert_eq!(headers_hex.len(), 1);
+        assert_header_hex_roundtrips(&headers_hex[0], &headers[2]);
+    }
+
+    #[test]
+    fn test_tip_validity_conflicting_opinions() {
+        let active = test_utils::header_info(BlockHash::all_zeros(), 100, 0);
+        let disputed = test_utils::header_info(BlockHash::all_zeros(), 100, 1);
+
+        let mut node_data: NodeData = BTreeMap::new();
+        node_data.insert(
+            0,
+            test_utils::node_data(
+                0,
+                &vec![
+                    test_utils::chain_tip(100, &active.header.block_hash(), ChainTipStatus::Active),
+                    test_utils::chain_tip(
+                        100,
+                        &disputed.header.block_hash(),
+                        ChainTipStatus::Invalid,
+                    ),
+                ],
+            ),
+        );
+        node_data.insert(
+            1,
+            test_utils::node_data(
+                1,
+                &vec![
+                    test_utils::chain_tip(100, &active.header.block_hash(), ChainTipStatus::Active),
+                    test_utils::chain_tip(
+                        100,
+                        &disputed.header.block_hash(),
+                        ChainTipStatus::ValidFork,
+                    ),
+                ],
+            ),
+        );
+        node_data.insert(2, test_utils::node_data(2, &vec![]));
+
+        let validity = tip_validity(&node_data);
+        assert_eq!(validity.len(), 2);
+        assert_eq!(
+            validity.get(&disputed.header.block_hash().to_string()),
+            Some(&BTreeMap::from([
+                (0, "invalid".to_string()),
+                (1, "valid-fork".to_string()),
+            ]))
+        );
+        assert_eq!(
+            validity.get(&active.header.block_hash().to_string()),
+            Some(&BTreeMap::from([
+                (0, "active".to_string()),
+                (1, "active".to_string()),
+            ]))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_consensus_status() {
+        let chain = test_utils::chain(BlockHash::all_zeros(), 1, 5, 0);
+        let fork = test_utils::chain(chain[2].header.block_hash(), 4, 2, 100);
+        let tree = test_utils::tree(&[chain.clone(), fork.clone()].concat());
+        let active = |h: &HeaderInfo| {
+            vec![test_utils::chain_tip(
+                h.height,
+                &h.header.block_hash(),
+                ChainTipStatus::Active,
+            )]
+        };
+        let node_data = |tips: Vec<Vec<ChainTip>>| -> NodeData {
+            tips.iter()
+                .enumerate()
+                .map(|(id, tips)| (id as u32, test_utils::node_data(id as u32, tips)))
+                .collect()
+        };
+
+        // consistent
+        let mut nodes = node_data(vec![active(&chain[4]), active(&chain[4])]);
+        // unreachable nodes are ignored
+        let mut unreachable = test_utils::node_data(2, &active(&fork[1]));
+        unreachable.reachable(false);
+        nodes.insert(2, unreachable);
+        let consensus_result = consensus(&nodes, &tree).await;
+        assert_eq!(consensus_result.status, ConsensusStatus::Consistent);
+        assert_eq!(
+            consensus_result.hash,
+            Some(chain[4].header.block_hash().to_string())
+        );
+        assert_eq!(consensus_result.height, Some(5));
+        assert_eq!(consensus_result.tips.len(), 1);
+        assert_eq!(consensus_result.tips[0].nodes, vec![0, 1]);
+
+        // height mismatch
+        let nodes = node_data(vec![
+            active(&chain[4]),
+            active(&chain[3]),
+            active(&chain[4]),
+        ]);
+        let consensus_result = consensus(&nodes, &tree).await;
+        assert_eq!(consensus_result.status, ConsensusStatus::HeightMismatch);
+        assert_eq!(consensus_result.hash, None);
+        assert_eq!(
+            consensus_result.tips,
+            vec![
+                ConsensusTipJson {
+                    hash: chain[4].header.block_hash().to_string(),
+                    height: 5,
+                    nodes: vec![0, 2],
+                },
+                ConsensusTipJson {
+                    hash: chain[3].header.block_hash().to_string(),
+                    height: 4,
+                    nodes: vec![1],
+                },
+            ]
+        );
+
+        // nodes lagging more than the threshold behind are reported
+        // separately
+        let nodes = node_data(vec![
+            active(&chain[4]),
+            active(&chain[0]),
+            active(&chain[4]),
+        ]);
+        let consensus_result = consensus(&nodes, &tree).await;
+        assert_eq!(consensus_result.status, ConsensusStatus::Consistent);
+        assert_eq!(consensus_result.height, Some(5));
+        assert_eq!(consensus_result.tips.len(), 1);
+        assert_eq!(
+            consensus_result.lagging,
+            vec![ConsensusTipJson {
+                hash: chain[0].header.block_hash().to_string(),
+                height: 1,
+                nodes: vec![1],
+            }]
+        );
+        let lagging_fork = test_utils::header_info(BlockHash::all_zeros(), 1, 200);
+        let nodes = node_data(vec![
+            active(&chain[4]),
+            active(&chain[2]),
+            active(&lagging_fork),
+        ]);
+        let consensus_result = consensus(&nodes, &tree).await;
+        // lagging tips not in the tree yet are skipped
+        assert_eq!(consensus_result.status, ConsensusStatus::HeightMismatch);
+        assert_eq!(consensus_result.lagging[0].nodes, vec![2]);
+
+        // but a lagging node on another branch is a split
+        let long = test_utils::chain(BlockHash::all_zeros(), 1, 7, 0);
+        let side = test_utils::chain(long[0].header.block_hash(), 2, 1, 300);
+        let long_tree = test_utils::tree(&[long.clone(), side.clone()].concat());
+        let nodes = node_data(vec![active(&long[6]), active(&side[0])]);
+        let consensus_result = consensus(&nodes, &long_tree).await;
+        assert_eq!(consensus_result.status, ConsensusStatus::Split);
+        assert_eq!(consensus_result.tips.len(), 1);
+        assert_eq!(consensus_result.lagging[0].nodes, vec![1]);
+
+        // split
+        let nodes = node_data(vec![active(&chain[4]), active(&fork[0]), active(&chain[3])]);
+        let consensus_result = consensus(&nodes, &tree).await;
+        assert_eq!(consensus_result.status, ConsensusStatus::Split);
+        assert_eq!(consensus_result.hash, None);
+        assert_eq!(consensus_result.tips.len(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_search() {
+        let chain = test_utils::chain(BlockHash::all_zeros(), 1, 5, 0);
+        let fork = test_utils::chain(chain[2].header.block_hash(), 4, 2, 100);
+        // network 2 knows the first blocks of the chain of network 1
+        let trees: Trees = Arc::new(BTreeMap::from([
+            (1, test_utils::tree(&[chain.clone(), fork.clone()].concat())),
+            (2, test_utils::tree(&chain[..3])),
+        ]));
+        let active = |id: u32, h: &HeaderInfo| {
+            test_utils::node_data(
+                id,
+                &vec![test_utils::chain_tip(
+                    h.height,
+                    &h.header.block_hash(),
+                    ChainTipStatus::Active,
+                )],
+            )
+        };
+        let caches: Caches = Arc::new(Mutex::new(BTreeMap::from([
+            (
+                1,
+                test_utils::cache(BTreeMap::from([(0, active(0, &chain[4]))])),
+            ),
+            (
+                2,
+                test_utils::cache(BTreeMap::from([(0, active(0, &chain[1]))])),
+            ),
+        ])));
+        let search = |q: &str| {
+            let trees = trees.clone();
+            let caches = caches.clone();
+            let q = q.to_string();
+            async move {
+                let response = search_response(SearchQuery { q: Some(q) }, trees, caches)
+                    .await
+                    .unwrap()
+                    .into_response();
+                let status = response.status();
+                let body = body_string(response).await;
+                (status, body)
+            }
+        };
+        let results = |body: &str| -> Vec<serde_json::Value> {
+            let json: serde_json::Value = serde_json::from_str(body).unwrap();
+            json["results"].as_array().unwrap().clone()
+        };
+
+        // full hash
+        let hash = fork[1].header.block_hash().to_string();
+        let (status, body) = search(&hash).await;
+        assert_eq!(status, StatusCode::OK);
+        let found = results(&body);
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0]["network"], 1);
+        assert_eq!(found[0]["height"], 5);
+        assert_eq!(found[0]["hash"], hash);
+        assert_eq!(found[0]["active"], false);
+
+        // a prefix of a block both networks know
+        let hash = chain[2].header.block_hash().to_string();
+        let (status, body) = search(&hash[..8]).await;
+        assert_eq!(status, StatusCode::OK);
+        let found = results(&body);
+        assert_eq!(found.len(), 2);
+        assert_eq!(found[0]["network"], 1);
+        assert_eq!(found[0]["hash"], hash);
+        assert_eq!(found[0]["active"], true);
+        assert_eq!(found[1]["network"], 2);
+        assert_eq!(found[1]["hash"], hash);
+        // network 2's node is on the parent
+        assert_eq!(found[1]["active"], false);
+
+        // height: the active and the fork block on network 1
+        let (status, body) = search("4").await;
+        assert_eq!(status, StatusCode::OK);
+        let mut found: Vec<(String, bool)> = results(&body)
+            .iter()
+            .map(|r| {
+                assert_eq!(r["network"], 1);
+                assert_eq!(r["height"], 4);
+                (
+                    r["hash"].as_str().unwrap().to_string(),
+                    r["active"].as_bool().unwrap(),
+                )
+            })
+            .collect();
+        found.sort_by_key(|(_, active)| *active);
+        assert_eq!(
+            found,
+            vec![
+                (fork[0].header.block_hash().to_string(), false),
+                (chain[3].header.block_hash().to_string(), true),
+            ]
+        );
+        let (_, body) = search("1000").await;
+        assert!(results(&body).is_empty());
+
+        // neither a hash, a long enough prefix nor a height
+        for q in ["", "abc", "abcdef1", "0000000", "-1", "not a hash"].iter() {
+            let (status, _) = search(q).await;
+            assert_eq!(status, StatusCode::BAD_REQUEST, "{}", q);
+        }
+        let response = search_response(SearchQuery { q: None }, trees.clone(), caches.clone())
+            .await
+            .unwrap()
+            .into_response();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[test]
+    fn test_data_v2_schema() {
+        let headers = test_utils::chain(BlockHash::all_zeros(), 1, 3, 0);
+        let header_infos: Vec<HeaderInfoJson> = headers
+            .iter()
+            .enumerate()
+            .map(|(i, h)| HeaderInfoJson::new(h, i, i.checked_sub(1).unwrap_or(usize::MAX)))
+            .collect();
+        let nodes = vec![test_utils::node_data(0, &vec![])];
+
+        let data = DataV2JsonResponse::new(&header_infos, &nodes, &[]);
+        assert_eq!(data.header_infos[0].parent, None);
+        assert_eq!(data.header_infos[1].parent, Some(0));
+        assert!(!data.header_infos[0].is_tip);
+        assert!(!data.header_infos[1].is_tip);
+        assert!(data.header_infos[2].is_tip);
+
+        let json = serde_json::to_value(&data).unwrap();
+        assert!(json.get("headerInfos").is_some());
+        assert!(json.get("deploymentDisagreements").is_some());
+        assert!(json["headerInfos"][2]["isTip"].as_bool().unwrap());
+        assert!(json["headerInfos"][0]["prevBlockhash"].is_string());
+        assert!(json["nodes"][0]["lastChangedTimestamp"].is_u64());
+    }
+
+    #[tokio::test]
+    async fn test_change_event_flood_on_other_network() {
+        use futures_util::StreamExt;
+
+        let (sender_a, _) = broadcast::channel::<ChangeEvent>(16);
+        let (sender_b, _) = broadcast::channel::<ChangeEvent>(16);
+        let senders = BTreeMap::from([(0, sender_a.clone()), (1, sender_b.clone())]);
+        let mut stream = change_event_stream(&senders);
+
+        // flood network A with far more events than the channel capacity
+        for _ in 0..1000 {
+            sender_a
+                .send(ChangeEvent::TipChanged { network_id: 0 })
+                .unwrap();
+        }
+        sender_b
+            .send(ChangeEvent::TipChanged { network_id: 1 })
+            .unwrap();
+        drop(sender_a);
+        drop(sender_b);
+        drop(senders);
+
+        let mut events_b = 0;
+        let mut lagged = 0;
+        while let Some(event) = stream.next().await {
+            match event {
+                Ok(ChangeEvent::TipChanged { network_id: 1 }) => events_b += 1,
+                Ok(_) => (),
+                Err(_) => lagged += 1,
+            }
+        }
+        assert_eq!(events_b, 1);
+        // only network A's subscription lagged
+        assert_eq!(lagged, 1);
+    }
+
+    #[test]
+    fn test_change_event_sse() {
+        let event = change_event_sse(ChangeEvent::TipChanged { network_id: 1 }).unwrap();
+        assert_eq!(
+            event.to_string(),
+            "event:tip_changed\ndata:{\"network_id\":1}\n\n"
+        );
+        let event = change_event_sse(ChangeEvent::Changed {
+            network_id: 1,
+            change: Change::NodeTips { node_id: 3 },
+        })
+        .unwrap();
+        assert_eq!(
+            event.to_string(),
+            "event:node_tips\ndata:{\"network_id\":1,\"kind\":\"node_tips\",\"node_id\":3}\n\n"
+        );
+        let event = change_event_sse(ChangeEvent::Changed {
+            network_id: 1,
+            change: Change::NewBlock {
+                hash: "00ab".to_string(),
+                height: 7,
+            },
+        })
+        .unwrap();
+        assert_eq!(
+            event.to_string(),
+            "event:new_block\ndata:{\"network_id\":1,\"kind\":\"new_block\",\"hash\":\"00ab\",\"height\":7}\n\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_networks_json_theme() {
+        let network = |id: u32, theme: Option<Theme>| NetworkJson {
+            id,
+            name: format!("network {}", id),
+            description: String::new(),
+            theme,
+            network_type: None,
+            last_poll_ago_seconds: None,
+            stale: false,
+            headers_total: None,
+            headers_in_view: 0,
+        };
+        let theme = Theme {
+            color: Some("#f7931a".to_string()),
+            icon: None,
+            chain: Some("mainnet".to_string()),
+        };
+        let reply = networks_response(
+            vec![network(0, Some(theme)), network(1, None)],
+            Arc::new(BTreeMap::new()),
+            Arc::new(Mutex::new(BTreeMap::new())),
+            freshness(),
+            true,
+        )
+        .await
+        .unwrap();
+        let json: serde_json::Value = serde_json::from_str(&body_string(reply).await).unwrap();
+        assert_eq!(
+            json["networks"][0]["theme"],
+            serde_json::json!({"color": "#f7931a", "icon": null, "chain": "mainnet"})
+        );
+        assert!(json["networks"][1]["theme"].is_null());
+    }
+
+    #[tokio::test]
+    async fn test_networks_json_network_type() {
+        use bitcoincore_rpc::bitcoin::constants::genesis_block;
+        use bitcoincore_rpc::bitcoin::Network;
+
+        let network = |id: u32, network_type: Option<&str>| NetworkJson {
+            id,
+            name: format!("network {}", id),
+            description: String::new(),
+            theme: None,
+            network_type: network_type.map(|t| t.to_string()),
+            last_poll_ago_seconds: None,
+            stale: false,
+            headers_total: None,
+            headers_in_view: 0,
+        };
+        let genesis = genesis_block(Network::Regtest).block_hash();
+        let trees: Trees = Arc::new(BTreeMap::from([
+            (0, test_utils::tree(&test_utils::chain(genesis, 1, 3, 0))),
+            (1, test_utils::tree(&test_utils::chain(genesis, 100, 3, 0))),
+        ]));
+        let reply = networks_response(
+            vec![
+                // the genesis block takes precedence over the config
+                network(0, Some("signet")),
+                // falls back to the config
+                network(1, Some("signet")),
+                network(2, None),
+            ],
+            trees,
+            Arc::new(Mutex::new(BTreeMap::new())),
+            freshness(),
+            true,
+        )
+        .await
+        .unwrap();
+        let json: serde_json::Value = serde_json::from_str(&body_string(reply).await).unwrap();
+        assert_eq!(json["networks"][0]["network_type"], "regtest");
+        assert_eq!(json["networks"][1]["network_type"], "signet");
+        assert!(json["networks"][2]["network_type"].is_null());
+    }
+
+    #[tokio::test]
+    async fn test_signalling_json_window() {
+        let headers = test_utils::chain(BlockHash::all_zeros(), 1, 3, 0);
+        let tip = test_utils::chain_tip(3, &headers[2].header.block_hash(), ChainTipStatus::Active);
+        let caches: Caches = Arc::new(Mutex::new(BTreeMap::from([(
+            0,
+            test_utils::cache(BTreeMap::from([(0, test_utils::node_data(0, &vec![tip]))])),
+        )])));
+        let trees: Trees = Arc::new(BTreeMap::from([(0, test_utils::tree(&headers))]));
+
+        for (window, blocks) in [(Some(2), 2), (None, 3), (Some(usize::MAX), 3)] {
+            let reply =
+                signalling_response(0, SignallingQuery { window }, caches.clone(), trees.clone())
+                    .await
+                    .unwrap();
+            let json: serde_json::Value = serde_json::from_str(&body_string(reply).await).unwrap();
+            assert_eq!(json["tip_height"], 3);
+            assert_eq!(json["blocks"], blocks);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_forks_json() {
+        let chain = test_utils::chain(BlockHash::all_zeros(), 1, 3, 0);
+        let mut branch_a = test_utils::header_info(chain[2].header.block_hash(), 4, 100);
+        branch_a.miner = "Pool A".to_string();
+        let mut branch_b = test_utils::header_info(chain[2].header.block_hash(), 4, 200);
+        branch_b.miner = "Pool B".to_string();
+        // branch_c was stripped from the header tree
+        let branch_c = test_utils::header_info(chain[2].header.block_hash(), 4, 300);
+
+        let tip = test_utils::chain_tip(4, &branch_a.header.block_hash(), ChainTipStatus::Active);
+        let mut cache =
+            test_utils::cache(BTreeMap::from([(0, test_utils::node_data(0, &vec![tip]))]));
+        cache.header_infos_json = vec![
+            HeaderInfoJson::new(&chain[0], 0, usize::MAX),
+            HeaderInfoJson::new(&chain[1], 1, 0),
+            HeaderInfoJson::new(&chain[2], 2, 1),
+            HeaderInfoJson::new(&branch_a, 3, 2),
+            HeaderInfoJson::new(&branch_b, 4, 2),
+        ];
+        cache.forks = vec![Fork {
+            common: chain[2].clone(),
+            children: vec![branch_a.clone(), branch_b.clone(), branch_c.clone()],
+            truncated_children: 1,
+        }];
+        let caches: Caches = Arc::new(Mutex::new(BTreeMap::from([(0, cache)])));
+        let networks = vec![NetworkJson {
+            id: 0,
+            name: "regtest".to_string(),
+            description: String::new(),
+            theme: None,
+            network_type: None,
+            last_poll_ago_seconds: None,
+            stale: false,
+            headers_total: None,
+            headers_in_view: 0,
+        }];
+
+        let all = || SinceHeightQuery { since_height: None };
+        let reply = forks_response(0, all(), None, caches.clone(), networks.clone())
+            .await
+            .unwrap()
+            .into_response();
+        assert_eq!(reply.status(), StatusCode::OK);
+        let json: serde_json::Value = serde_json::from_str(&body_string(reply).await).unwrap();
+        let fork = &json[0];
+        assert_eq!(
+            fork["common"]["hash"],
+            chain[2].header.block_hash().to_string()
+        );
+        assert_eq!(fork["common"]["height"], 3);
+        assert_eq!(fork["common"]["status"], "active");
+        assert_eq!(fork["truncated_children"], 1);
+        let children: Vec<(String, String, serde_json::Value)> = fork["children"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|child| {
+                assert_eq!(child["height"], 4);
+                (
+                    child["hash"].as_str().unwrap().to_string(),
+                    child["miner"].as_str().unwrap().to_string(),
+                    child["status"].clone(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            children,
+            vec![
+                (
+                    branch_a.header.block_hash().to_string(),
+                    "Pool A".to_string(),
+                    serde_json::json!("active")
+                ),
+                (
+                    branch_b.header.block_hash().to_string(),
+                    "Pool B".to_string(),
+                    serde_json::json!("stale")
+                ),
+                (
+                    branch_c.header.block_hash().to_string(),
+                    String::new(),
+                    serde_json::Value::Null
+                ),
+            ]
+        );
+
+        for (since_height, expected) in [(2, 1), (3, 0)] {
+            let query = SinceHeightQuery {
+                since_height: Some(since_height),
+            };
+            let reply = forks_response(0, query, None, caches.clone(), networks.clone())
+                .await
+                .unwrap();
+            let json: serde_json::Value = serde_json::from_str(&body_string(reply).await).unwrap();
+            assert_eq!(json.as_array().unwrap().len(), expected);
+        }
+
+        let reply = forks_response(1, all(), None, caches, networks)
+            .await
+            .unwrap()
+            .into_response();
+        assert_eq!(reply.status(), StatusCode::NOT_FOUND);
+        assert_eq!(
+            body_string(reply).await,
+            "Unknown network. Avaliable networks are: 0 (regtest)."
+        );
+    }
+
+    #[tokio::test]
+    async fn test_fork_history_json() {
+        let chain = test_utils::chain(BlockHash::all_zeros(), 1, 3, 0);
+        let fork = Fork {
+            common: chain[1].clone(),
+            children: vec![
+                test_utils::header_info(chain[1].header.block_hash(), 3, 100),
+                chain[2].clone(),
+            ],
+            truncated_children: 0,
+        };
+        let db = test_db().await;
+        db::write_forks(db.clone(), 0, &[fork], 1234).await.unwrap();
+        let caches: Caches = Arc::new(Mutex::new(BTreeMap::from([(
+            0,
+            test_utils::cache(BTreeMap::new()),
+        )])));
+        let networks = vec![NetworkJson {
+            id: 0,
+            name: "regtest".to_string(),
+            description: String::new(),
+            theme: None,
+            network_type: None,
+            last_poll_ago_seconds: None,
+            stale: false,
+            headers_total: None,
+            headers_in_view: 0,
+        }];
+
+        let page = |page: Option<usize>| ForkHistoryQuery { page };
+        let reply =
+            fork_history_response(0, page(None), caches.clone(), db.clone(), networks.clone())
+                .await
+                .unwrap()
+                .into_response();
+        assert_eq!(reply.status(), StatusCode::OK);
+        let json: serde_json::Value = serde_json::from_str(&body_string(reply).await).unwrap();
+        assert_eq!(json["page"], 0);
+        assert_eq!(json["page_size"], FORK_HISTORY_PAGE_SIZE);
+        assert_eq!(json["total"], 1);
+        assert_eq!(
+            json["forks"][0]["common_hash"],
+            chain[1].header.block_hash().to_string()
+        );
+        assert_eq!(json["forks"][0]["common_height"], 2);
+        assert_eq!(json["forks"][0]["children"].as_array().unwrap().len(), 2);
+        assert_eq!(json["forks"][0]["first_seen"], 1234);
+
+        let reply = fork_history_response(
+            0,
+            page(Some(1)),
+            caches.clone(),
+            db.clone(),
+            networks.clone(),
+        )
+        .await
+        .unwrap()
+        .into_response();
+        let json: serde_json::Value = serde_json::from_str(&body_string(reply).await).unwrap();
+        assert_eq!(json["page"], 1);
+        assert_eq!(json["total"], 1);
+        assert!(json["forks"].as_array().unwrap().is_empty());
+
+        let reply = fork_history_response(1, page(None), caches, db, networks)
+            .await
+            .unwrap()
+            .into_response();
+        assert_eq!(reply.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn test_alert_history_json() {
+        let db = test_db().await;
+        for (kind, timestamp) in [
+            (AlertKind::ChainSplit, 1000),
+            (AlertKind::ChainSplitResolved, 2000),
+        ] {
+            let alert = Alert {
+                network_id: 0,
+                kind,
+                timestamp,
+                tips: vec![],
+            };
+            db::write_alert(&alert, db.clone()).await.unwrap();
+        }
+        let caches: Caches = Arc::new(Mutex::new(BTreeMap::from([(
+            0,
+            test_utils::cache(BTreeMap::new()),
+        )])));
+
+        let page = |page: Option<usize>| AlertHistoryQuery { page };
+        let history = |page: AlertHistoryQuery| {
+            alert_history_response(0, page, caches.clone(), db.clone(), vec![])
+        };
+        let reply = history(page(None)).await.unwrap().into_response();
+        assert_eq!(reply.status(), StatusCode::OK);
+        let json: serde_json::Value = serde_json::from_str(&body_string(reply).await).unwrap();
+        assert_eq!(json["page"], 0);
+        assert_eq!(json["page_size"], ALERT_HISTORY_PAGE_SIZE);
+        assert_eq!(json["total"], 2);
+        // most recent first
+        assert_eq!(json["alerts"][0]["kind"], "chain_split_resolved");
+        assert_eq!(json["alerts"][0]["timestamp"], 2000);
+        assert_eq!(json["alerts"][1]["kind"], "chain_split");
+
+        let reply = history(page(Some(1))).await.unwrap().into_response();
+        let json: serde_json::Value = serde_json::from_str(&body_string(reply).await).unwrap();
+        assert_eq!(json["total"], 2);
+        assert!(json["alerts"].as_array().unwrap().is_empty());
+
+        let reply = alert_history_response(1, page(None), caches.clone(), db.clone(), vec![])
+            .await
+            .unwrap()
+            .into_response();
+        assert_eq!(reply.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn test_reorgs_json() {
+        let chain = test_utils::chain(BlockHash::all_zeros(), 1, 5, 0);
+        let fork = test_utils::chain(chain[2].header.block_hash(), 4, 3, 100);
+        let tip = |header_info: &HeaderInfo| {
+            test_utils::chain_tip(
+                header_info.height,
+                &header_info.header.block_hash(),
+                ChainTipStatus::Active,
+            )
+        };
+        let fork_point = (chain[2].header.block_hash(), chain[2].height);
+        let mut cache = test_utils::cache(BTreeMap::new());
+        cache.reorgs.push_back(ReorgJson::new(
+            0,
+            1000,
+            &tip(&chain[4]),
+            &tip(&fork[2]),
+            fork_point,
+        ));
+        cache.reorgs.push_back(ReorgJson::new(
+            1,
+            2000,
+            &tip(&chain[3]),
+            &tip(&fork[0]),
+            fork_point,
+        ));
+        let caches: Caches = Arc::new(Mutex::new(BTreeMap::from([(0, cache)])));
+        let networks = vec![NetworkJson {
+            id: 0,
+            name: "regtest".to_string(),
+            description: String::new(),
+            theme: None,
+            network_type: None,
+            last_poll_ago_seconds: None,
+            stale: false,
+            headers_total: None,
+            headers_in_view: 0,
+        }];
+
+        let reply = reorgs_response(0, caches.clone(), networks.clone())
+            .await
+            .unwrap()
+            .into_response();
+        assert_eq!(reply.status(), StatusCode::OK);
+        let json: serde_json::Value = serde_json::from_str(&body_string(reply).await).unwrap();
+        // most recent first
+        assert_eq!(json[0]["node_id"], 1);
+        assert_eq!(json[0]["timestamp"], 2000);
+        assert_eq!(json[0]["depth"], 1);
+        assert_eq!(json[1]["node_id"], 0);
+        assert_eq!(json[1]["old_tip_height"], 5);
+        assert_eq!(json[1]["new_tip_height"], 6);
+        assert_eq!(
+            json[1]["fork_point_hash"],
+            chain[2].header.block_hash().to_string()
+        );
+        assert_eq!(json[1]["fork_point_height"], 3);
+        assert_eq!(json[1]["depth"], 2);
+
+        let reply = reorgs_response(1, caches, networks)
+            .await
+            .unwrap()
+            .into_response();
+        assert_eq!(reply.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn test_identify() {
+        let chain = test_utils::chain(BlockHash::all_zeros(), 1, 3, 0);
+        let trees: Trees = Arc::new(BTreeMap::from([(0, test_utils::tree(&chain))]));
+        let (queue, mut rx) = PoolIdQueue::new(1);
+        let queues: PoolIdQueues = Arc::new(BTreeMap::from([(0, queue)]));
+        let token = Some("s3cret".to_string());
+        let identify = |network: u32,
+                        hash: &BlockHash,
+                        authorization: Option<&str>,
+                        admin_token: Option<String>| {
+            identify_response(
+                network,
+                hash.to_string(),
+                authorization.map(|a| a.to_string()),
+                admin_token,
+                trees.clone(),
+                queues.clone(),
+            )
+        };
+        let hash = chain[1].header.block_hash();
+        fn status<R: Reply>(reply: Result<R, Infallible>) -> StatusCode {
+            reply.unwrap().into_response().status()
+        }
+
+        // disabled without a configured token
+        assert_eq!(
+            status(identify(0, &hash, Some("Bearer s3cret"), None).await),
+            StatusCode::NOT_FOUND
+        );
+        for authorization in [
+            None,
+            Some("Bearer wrong"),
+            Some("s3cret"),
+            Some("Basic s3cret"),
+        ] {
+            let reply = identify(0, &hash, authorization, token.clone())
+                .await
+                .unwrap()
+                .into_response();
+            assert_eq!(reply.status(), StatusCode::UNAUTHORIZED);
+            assert_eq!(reply.headers()["www-authenticate"], "Bearer");
+        }
+        assert_eq!(
+            status(identify(1, &hash, Some("Bearer s3cret"), token.clone()).await),
+            StatusCode::NOT_FOUND
+        );
+        assert_eq!(
+            status(
+                identify(
+                    0,
+                    &BlockHash::all_zeros(),
+                    Some("Bearer s3cret"),
+                    token.clone()
+                )
+                .await
+            ),
+            StatusCode::NOT_FOUND
+        );
+
+        assert_eq!(
+            status(identify(0, &hash, Some("Bearer s3cret"), token.clone()).await),
+            StatusCode::ACCEPTED
+        );
+        assert_eq!(rx.try_recv().unwrap(), hash);
+        assert!(rx.try_recv().is_err());
+    }
+
+    #[test]
+    fn test_is_admin() {
+        assert!(is_admin(Some("Bearer s3cret"), "s3cret"));
+        assert!(!is_admin(Some("Bearer s3cre"), "s3cret"));
+        assert!(!is_admin(Some("Bearer s3cret2"), "s3cret"));
+        assert!(!is_admin(Some("bearer s3cret"), "s3cret"));
+        assert!(!is_admin(Some("Bearer "), "s3cret"));
+        assert!(!is_admin(None, "s3cret"));
+    }
+
+    #[test]
+    fn test_miner_shares() {
+        let chain = test_utils::chain(BlockHash::all_zeros(), 0, 6, 0);
+        let stale = test_utils::header_info(chain[4].header.block_hash(), 5, 100);
+        let miners = ["", "Pool A", "Pool B", "Unknown", "Pool A", "Pool B"];
+        let mut headers: Vec<HeaderInfoJson> = chain
+            .iter()
+            .zip(miners.iter())
+            .enumerate()
+            .map(|(i, (h, miner))| {
+                let mut header = HeaderInfoJson::new(h, i, i.wrapping_sub(1));
+                header.miner = miner.to_string();
+                header.status = HeaderStatus::Active;
+                header
+            })
+            .collect();
+        let mut stale = HeaderInfoJson::new(&stale, 6, 4);
+        stale.miner = "Pool C".to_string();
+        stale.status = HeaderStatus::Stale;
+        headers.push(stale);
+
+        let share = |miner: &str, block_count: usize, share: f64| MinerShareJson {
+            miner: miner.to_string(),
+            block_count,
+            share,
+        };
+        assert_eq!(
+            miner_shares(&headers, 100),
+            vec![
+                share("Pool A", 2, 2.0 / 6.0),
+                share("Pool B", 2, 2.0 / 6.0),
+                share("Unknown", 2, 2.0 / 6.0),
+            ]
+        );
+        // the last blocks are counted from the highest active header
+        assert_eq!(
+            miner_shares(&headers, 4),
+            vec![
+                share("Pool B", 2, 0.5),
+                share("Pool A", 1, 0.25),
+                share("Unknown", 1, 0.25),
+            ]
+        );
+        assert!(miner_shares(&headers, 0).is_empty());
+        assert!(miner_shares(&[], 100).is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_invalid_json() {
+        let invalid_a = BlockHash::from_byte_array([1; 32]);
+        let invalid_b = BlockHash::from_byte_array([2; 32]);
+        let cache = test_utils::cache(BTreeMap::from([
+            (
+                0,
+                test_utils::node_data(
+                    0,
+                    &vec![
+                        test_utils::chain_tip(5, &invalid_a, ChainTipStatus::Invalid),
+                        test_utils::chain_tip(8, &invalid_b, ChainTipStatus::Invalid),
+                    ],
+                ),
+            ),
+            (
+                1,
+                test_utils::node_data(
+                    1,
+                    &vec![test_utils::chain_tip(
+                        8,
+                        &invalid_b,
+                        ChainTipStatus::Invalid,
+                    )],
+                ),
+            ),
+        ]));
+        let caches: Caches = Arc::new(Mutex::new(BTreeMap::from([(0, cache)])));
+        let query = |since_height| SinceHeightQuery { since_height };
+
+        let reply = invalid_response(0, query(None), caches.clone(), BTreeMap::new(), vec![])
+            .await
+            .unwrap();
+        let json: serde_json::Value = serde_json::from_str(&body_string(reply).await).unwrap();
+        assert_eq!(
+            json,
+            serde_json::json!([
+                {
+                    "hash": invalid_b.to_string(),
+                    "height": 8,
+                    "nodes": [0, 1],
+                    "node_names": ["node 0", "node 1"],
+                },
+                {
+                    "hash": invalid_a.to_string(),
+                    "height": 5,
+                    "nodes": [0],
+                    "node_names": ["node 0"],
+                },
+            ])
+        );
+
+        // hidden node names
+        let privacy = BTreeMap::from([(
+            0,
+            Privacy {
+                hide_node_names: true,
+                ..Privacy::default()
+            },
+        )]);
+        let reply = invalid_response(0, query(None), caches.clone(), privacy, vec![])
+            .await
+            .unwrap();
+        let json: serde_json::Value = serde_json::from_str(&body_string(reply).await).unwrap();
+        assert_eq!(
+            json[0]["node_names"],
+            serde_json::json!(["Node 0", "Node 1"])
+        );
+
+        let reply = invalid_response(0, query(Some(5)), caches.clone(), BTreeMap::new(), vec![])
+            .await
+            .unwrap();
+        let json: serde_json::Value = serde_json::from_str(&body_string(reply).await).unwrap();
+        assert_eq!(json.as_array().unwrap().len(), 1);
+        assert_eq!(json[0]["height"], 8);
+
+        let reply = invalid_response(1, query(None), caches, BTreeMap::new(), vec![])
+            .await
+            .unwrap()
+            .into_response();
+        assert_eq!(reply.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn test_since_height_query() {
+        let filter = warp::query::<SinceHeightQuery>();
+        let query = warp::test::request()
+            .path("/?since_height=10")
+            .filter(&filter)
+            .await
+            .unwrap();
+        assert_eq!(query.since_height, Some(10));
+        let query = warp::test::request()
+            .path("/")
+            .filter(&filter)
+            .await
+            .unwrap();
+        assert_eq!(query.since_height, None);
+        assert!(warp::test::request()
+            .path("/?since_height=abc")
+            .filter(&filter)
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn test_networks_json_header_counts() {
+        let network = |id: u32| NetworkJson {
+            id,
+            name: format!("network {}", id),
+            description: String::new(),
+            theme: None,
+            network_type: None,
+            last_poll_ago_seconds: None,
+            stale: false,
+            headers_total: None,
+            headers_in_view: 0,
+        };
+        let mut cache = test_utils::cache(BTreeMap::new());
+        cache.header_infos_json = test_utils::chain(BlockHash::all_zeros(), 1, 3, 0)
+            .iter()
+            .enumerate()
+            .map(|(i, h)| HeaderInfoJson::new(h, i, i.checked_sub(1).unwrap_or(usize::MAX)))
+            .collect();
+        cache.headers_total = Some(1000);
+        let caches: Caches = Arc::new(Mutex::new(BTreeMap::from([
+            (0, cache),
+            (1, test_utils::cache(BTreeMap::new())),
+        ])));
+        let reply = networks_response(
+            vec![network(0), network(1)],
+            Arc::new(BTreeMap::new()),
+            caches,
+            freshness(),
+            true,
+        )
+        .await
+        .unwrap();
+        let json: serde_json::Value = serde_json::from_str(&body_string(reply).await).unwrap();
+        assert_eq!(json["networks"][0]["headers_total"], 1000);
+        assert_eq!(json["networks"][0]["headers_in_view"], 3);
+        // not counted yet
+        assert!(json["networks"][1]["headers_total"].is_null());
+        assert_eq!(json["networks"][1]["headers_in_view"], 0);
+    }
+
+    #[tokio::test]
+    async fn test_networks_json_hide_empty() {
+        let network = |id: u32| NetworkJson {
+            id,
+            name: format!("network {}", id),
+            description: String::new(),
+            theme: None,
+            network_type: None,
+            last_poll_ago_seconds: None,
+            stale: false,
+            headers_total: None,
+            headers_in_view: 0,
+        };
+        let headers = test_utils::chain(BlockHash::all_zeros(), 1, 3, 0);
+        let header_infos_json: Vec<HeaderInfoJson> = headers
+            .iter()
+            .enumerate()
+            .map(|(i, h)| HeaderInfoJson::new(h, i, i.checked_sub(1).unwrap_or(usize::MAX)))
+            .collect();
+        let tips = vec![test_utils::chain_tip(
+            headers[2].height,
+            &headers[2].header.block_hash(),
+            ChainTipStatus::Active,
+        )];
+        let mut synced = test_utils::cache(BTreeMap::from([(0, test_utils::node_data(0, &tips))]));
+        synced.header_infos_json = header_infos_json.clone();
+        // headers, but no node reported its tips yet
+        let mut no_tips =
+            test_utils::cache(BTreeMap::from([(0, test_utils::node_data(0, &vec![]))]));
+        no_tips.header_infos_json = header_infos_json;
+        let caches: Caches = Arc::new(Mutex::new(BTreeMap::from([
+            (0, synced),
+            (1, no_tips),
+            (2, test_utils::cache(BTreeMap::new())),
+        ])));
+
+        let ids = |show_empty_networks: bool| {
+            let caches = caches.clone();
+            async move {
+                let reply = networks_response(
+                    vec![network(0), network(1), network(2)],
+                    Arc::new(BTreeMap::new()),
+                    caches,
+                    freshness(),
+                    show_empty_networks,
+                )
+                .await
+                .unwrap();
+                let json: serde_json::Value =
+                    serde_json::from_str(&body_string(reply).await).unwrap();
+                json["networks"]
+                    .as_array()
+                    .unwrap()
+                    .iter()
+                    .map(|n| n["id"].as_u64().unwrap())
+                    .collect::<Vec<u64>>()
+            }
+        };
+        assert_eq!(ids(true).await, vec![0, 1, 2]);
+        assert_eq!(ids(false).await, vec![0]);
+    }
+
+    #[tokio::test]
+    async fn test_readiness_json() {
+        let network = |id: u32| NetworkJson {
+            id,
+            name: format!("network {}", id),
+            description: String::new(),
+            theme: None,
+            network_type: None,
+            last_poll_ago_seconds: None,
+            stale: false,
+            headers_total: None,
+            headers_in_view: 0,
+        };
+        let headers = test_utils::chain(BlockHash::all_zeros(), 1, 3, 0);
+        let tips = vec![test_utils::chain_tip(
+            headers[2].height,
+            &headers[2].header.block_hash(),
+            ChainTipStatus::Active,
+        )];
+        let mut node_a = test_utils::node_data(0, &tips);
+        node_a.last_changed_timestamp = 1700000200;
+        let mut node_b = test_utils::node_data(1, &tips);
+        node_b.last_changed_timestamp = 1700000100;
+        let mut synced = test_utils::cache(BTreeMap::from([(0, node_a), (1, node_b)]));
+        synced.header_infos_json = headers
+            .iter()
+            .enumerate()
+            .map(|(i, h)| HeaderInfoJson::new(h, i, i.checked_sub(1).unwrap_or(usize::MAX)))
+            .collect();
+        let caches: Caches = Arc::new(Mutex::new(BTreeMap::from([
+            (0, synced),
+            // a node that never reported tips
+            (
+                1,
+                test_utils::cache(BTreeMap::from([(0, test_utils::node_data(0, &vec![]))])),
+            ),
+        ])));
+
+        let reply = readiness_response(vec![network(0), network(1), network(2)], caches.clone())
+            .await
+            .unwrap()
+            .into_response();
+        assert_eq!(reply.status(), StatusCode::SERVICE_UNAVAILABLE);
+        let json: serde_json::Value = serde_json::from_str(&body_string(reply).await).unwrap();
+        assert_eq!(
+            json,
+            serde_json::json!({
+                "status": "starting",
+                "networks": 3,
+                "pending_networks": [1, 2],
+                "oldest_last_changed_timestamp": 1700000100,
+            })
+        );
+
+        let reply = readiness_response(vec![network(0)], caches)
+            .await
+            .unwrap()
+            .into_response();
+        assert_eq!(reply.status(), StatusCode::OK);
+        let json: serde_json::Value = serde_json::from_str(&body_string(reply).await).unwrap();
+        assert_eq!(json["status"], "ok");
+        assert_eq!(json["networks"], 1);
+        assert_eq!(json["pending_networks"], serde_json::json!([]));
+    }
+
+    fn freshness() -> Freshness {
+        Freshness {
+            started: unix_timestamp(),
+            stale_after: 60,
+        }
+    }
+
+    #[test]
+    fn test_network_freshness() {
+        let freshness = Freshness {
+            started: 1000,
+            stale_after: 60,
+        };
+        assert_eq!(freshness.uptime(1100), 100);
+        assert_eq!(freshness.network(Some(1090), 1100), (Some(10), false));
+        assert_eq!(freshness.network(Some(1040), 1100), (Some(60), false));
+        assert_eq!(freshness.network(Some(1039), 1100), (Some(61), true));
+        // never polled successfully
+        assert_eq!(freshness.network(None, 1060), (None, false));
+        assert_eq!(freshness.network(None, 1061), (None, true));
+        // clock went backwards
+        assert_eq!(freshness.network(Some(1200), 1100), (Some(0), false));
+    }
+
+    #[tokio::test]
+    async fn test_health_and_networks_json_staleness() {
+        let now = unix_timestamp();
+        let mut fresh = test_utils::cache(BTreeMap::new());
+        fresh.last_poll_timestamp = Some(now - 5);
+        let mut stale = test_utils::cache(BTreeMap::new());
+        stale.last_poll_timestamp = Some(now - 600);
+        let caches: Caches = Arc::new(Mutex::new(BTreeMap::from([
+            (0, fresh),
+            (1, stale),
+            (2, test_utils::cache(BTreeMap::new())),
+        ])));
+        let freshness = Freshness {
+            started: now - 3600,
+            stale_after: 60,
+        };
+        let network_infos: Vec<NetworkJson> = (0..3)
+            .map(|id| NetworkJson {
+                id,
+                name: format!("network {}", id),
+                description: String::new(),
+                theme: None,
+                network_type: None,
+                last_poll_ago_seconds: None,
+                stale: false,
+                headers_total: None,
+                headers_in_view: 0,
+            })
+            .collect();
+
+        let last_prune: LastPrune = Arc::new(Mutex::new(None));
+        let reply = health_response(
+            network_infos.clone(),
+            caches.clone(),
+            freshness.clone(),
+            last_prune.clone(),
+            Arc::new(BTreeMap::new()),
+        )
+        .await
+        .unwrap();
+        let health: serde_json::Value = serde_json::from_str(&body_string(reply).await).unwrap();
+        // the tests might cross a second boundary
+        assert!(health["uptime_seconds"].as_u64().unwrap() >= 3600);
+        assert!(
+            health["networks"][0]["last_poll_ago_seconds"]
+                .as_u64()
+                .unwrap()
+                >= 5
+        );
+        assert_eq!(health["networks"][0]["stale"], false);
+        assert!(
+            health["networks"][1]["last_poll_ago_seconds"]
+                .as_u64()
+                .unwrap()
+                >= 600
+        );
+        assert_eq!(health["networks"][1]["stale"], true);
+        assert!(health["networks"][2]["last_poll_ago_seconds"].is_null());
+        assert_eq!(health["networks"][2]["stale"], true);
+        assert!(health["last_prune"].is_null());
+
+        *last_prune.lock().await = Some(PruneRun {
+            timestamp: now,
+            deleted: BTreeMap::from([("alerts".to_string(), 3)]),
+        });
+        let reply = health_response(
+            network_infos.clone(),
+            caches.clone(),
+            freshness.clone(),
+            last_prune,
+            Arc::new(BTreeMap::new()),
+        )
+        .await
+        .unwrap();
+        let json: serde_json::Value = serde_json::from_str(&body_string(reply).await).unwrap();
+        assert_eq!(json["last_prune"]["timestamp"], now);
+        assert_eq!(json["last_prune"]["deleted"]["alerts"], 3);
+
+        let reply = networks_response(
+            network_infos,
+            Arc::new(BTreeMap::new()),
+            caches.clone(),
+            freshness,
+            true,
+        )
+        .await
+        .unwrap();
+        let networks: serde_json::Value = serde_json::from_str(&body_string(reply).await).unwrap();
+        assert!(networks["uptime_seconds"].as_u64().unwrap() >= 3600);
+        for i in 0..3 {
+            assert_eq!(
+                networks["networks"][i]["stale"],
+                health["networks"][i]["stale"]
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn test_health_json_nodes() {
+        let now = unix_timestamp();
+        let tip = |height: u64| {
+            vec![test_utils::chain_tip(
+                height,
+                &BlockHash::all_zeros(),
+                ChainTipStatus::Active,
+            )]
+        };
+        let mut synced = test_utils::node_data(0, &tip(10));
+        synced.last_changed_timestamp = now - 30;
+        let lagging = test_utils::node_data(1, &tip(6));
+        let mut unreachable = test_utils::node_data(2, &tip(10));
+        unreachable.reachable = false;
+        let caches: Caches = Arc::new(Mutex::new(BTreeMap::from([(
+            0,
+            test_utils::cache(BTreeMap::from([
+                (0, synced),
+                (1, lagging),
+                (2, unreachable),
+            ])),
+        )])));
+        let network_infos = vec![NetworkJson {
+            id: 0,
+            name: "network 0".to_string(),
+            description: String::new(),
+            theme: None,
+            network_type: None,
+            last_poll_ago_seconds: None,
+            stale: false,
+            headers_total: None,
+            headers_in_view: 0,
+        }];
+        let health = || {
+            health_response(
+                network_infos.clone(),
+                caches.clone(),
+                freshness(),
+                Arc::new(Mutex::new(None)),
+                Arc::new(BTreeMap::new()),
+            )
+        };
+
+        let reply = health().await.unwrap().into_response();
+        assert_eq!(reply.status(), StatusCode::SERVICE_UNAVAILABLE);
+        let json: serde_json::Value = serde_json::from_str(&body_string(reply).await).unwrap();
+        assert_eq!(json["status"], "degraded");
+        let nodes = &json["networks"][0]["nodes"];
+        assert_eq!(nodes.as_array().unwrap().len(), 3);
+        assert_eq!(nodes[0]["reachable"], true);
+        assert_eq!(nodes[0]["lagging"], false);
+        // the test might cross a second boundary
+        assert!(nodes[0]["last_changed_ago_seconds"].as_u64().unwrap() >= 30);
+        assert_eq!(nodes[1]["lagging"], true);
+        assert!(nodes[1]["last_changed_ago_seconds"].is_null());
+        assert_eq!(nodes[2]["reachable"], false);
+        assert_eq!(nodes[2]["lagging"], false);
+
+        caches
+            .lock()
+            .await
+            .get_mut(&0)
+            .unwrap()
+            .node_data
+            .get_mut(&2)
+            .unwrap()
+            .reachable = true;
+        let reply = health().await.unwrap().into_response();
+        assert_eq!(reply.status(), StatusCode::OK);
+        let json: serde_json::Value = serde_json::from_str(&body_string(reply).await).unwrap();
+        assert_eq!(json["status"], "ok");
+    }
+
+    #[tokio::test]
+    async fn test_not_found_responses() {
+        let api = api_path().and(
+            warp::get()
+                .and(warp::path!("api" / "info.json"))
+                .map(|| "info")
+                .recover(api_rejection),
+        );
+        let index = warp::get().and(warp::path::end()).map(|| "index");
+        let routes = index
+            .or(api)
+            .recover(page_rejection)
+            .recover(final_rejection);
+
+        let res = warp::test::request().path("/").reply(&routes).await;
+        assert_eq!(res.status(), StatusCode::OK);
+        let res = warp::test::request()
+            .path("/api/info.json")
+            .reply(&routes)
+            .await;
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(res.body(), "info");
+
+        // unknown API paths are answered in plain text
+        let res = warp::test::request()
+            .path("/api/unknown.json")
+            .reply(&routes)
+            .await;
+        assert_eq!(res.status(), StatusCode::NOT_FOUND);
+        assert_eq!(res.body(), "Unknown API endpoint.");
+        assert!(res.headers()[CONTENT_TYPE]
+            .to_str()
+            .unwrap()
+            .starts_with("text/plain"));
+
+        // other unknown paths with an HTML page
+        let res = warp::test::request()
+            .path("/does/not/exist")
+            .reply(&routes)
+            .await;
+        assert_eq!(res.status(), StatusCode::NOT_FOUND);
+        assert!(res.headers()[CONTENT_TYPE]
+            .to_str()
+            .unwrap()
+            .starts_with("text/html"));
+        assert_eq!(res.body(), NOT_FOUND_HTML);
+
+        // other rejections keep their status
+        let res = warp::test::request()
+            .method("POST")
+            .path("/api/info.json")
+            .reply(&routes)
+            .await;
+        assert_eq!(res.status(), StatusCode::METHOD_NOT_ALLOWED);
+        assert_eq!(res.body(), "Method Not Allowed");
+    }
+
+    #[test]
+    fn test_sse_connection_limit() {
+        let count = Arc::new(AtomicUsize::new(0));
+        let first = SseConnection::open(count.clone(), 2).expect("below the limit");
+        let second = SseConnection::open(count.clone(), 2).expect("below the limit");
+        assert_eq!(count.load(Ordering::SeqCst), 2);
+        assert!(SseConnection::open(count.clone(), 2).is_none());
+        assert_eq!(count.load(Ordering::SeqCst), 2);
+
+        drop(first);
+        assert_eq!(count.load(Ordering::SeqCst), 1);
+        let _third = SseConnection::open(count.clone(), 2).expect("a connection was closed");
+        drop(second);
+        assert_eq!(count.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn test_lagged_sse() {
+        let event = lagged_sse(42).unwrap().to_string();
+        assert!(event.contains("event:lagged\n"));
+        assert!(event.contains(r#"data:{"skipped":42}"#));
+        assert!(!event.contains("network_id"));
+    }
+
+    fn full() -> DataQuery {
+        DataQuery {
+            compact: None,
+            since: None,
+            max_headers: None,
+            min_height: None,
+            max_height: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_data_json_max_headers() {
+        // a fork at height 5
+        let mut headers = test_utils::chain(BlockHash::all_zeros(), 1, 10, 0);
+        headers.extend(test_utils::chain(
+            headers[3].header.block_hash(),
+            5,
+            2,
+            1000,
+        ));
+        let header_infos: Vec<HeaderInfoJson> = headers
+            .iter()
+            .enumerate()
+            .map(|(i, h)| {
+                let prev_id = headers
+                    .iter()
+                    .position(|p| p.header.block_hash() == h.header.prev_blockhash)
+                    .unwrap_or(usize::MAX);
+                HeaderInfoJson::new(h, i, prev_id)
+            })
+            .collect();
+        let ids = |headers: Vec<HeaderInfoJson>| -> Vec<usize> {
+            headers.iter().map(|header| header.id).collect()
+        };
+
+        assert_eq!(ids(cap_headers(header_infos.clone(), 100)).len(), 12);
+        // the highest header and its ancestors first
+        assert_eq!(
+            ids(cap_headers(header_infos.clone(), 8)),
+            vec![2, 3, 4, 5, 6, 7, 8, 9]
+        );
+        // the fork tip is included without its parent
+        assert_eq!(
+            ids(cap_headers(header_infos.clone(), 11)),
+            vec![0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 11]
+        );
+        assert!(cap_headers(header_infos.clone(), 0).is_empty());
+
+        let mut cache = test_utils::cache(BTreeMap::from([(0, test_utils::node_data(0, &vec![]))]));
+        cache.header_infos_json = header_infos;
+        let caches: Caches = Arc::new(Mutex::new(BTreeMap::from([(0, cache)])));
+        let query = DataQuery {
+            compact: None,
+            since: None,
+            max_headers: Some(3),
+            min_height: None,
+            max_height: None,
+        };
+        let body = body_string(
+            data_response(0, query, None, false, caches.clone(), BTreeMap::new(), 16)
+                .await
+                .unwrap(),
+        )
+        .await;
+        let json: serde_json::Value = serde_json::from_str(&body).unwrap();
+        let heights: Vec<u64> = json["header_infos"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|header| header["height"].as_u64().unwrap())
+            .collect();
+        assert_eq!(heights, vec![8, 9, 10]);
+
+        // the capped response isn't cached
+        let body = body_string(
+            data_response(0, full(), None, false, caches, BTreeMap::new(), 16)
+                .await
+                .unwrap(),
+        )
+        .await;
+        let json: serde_json::Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(json["header_infos"].as_array().unwrap().len(), 12);
+    }
+
+    #[tokio::test]
+    async fn test_data_json_height_range() {
+        let headers = test_utils::chain(BlockHash::all_zeros(), 1, 10, 0);
+        let header_infos: Vec<HeaderInfoJson> = headers
+            .iter()
+            .enumerate()
+            .map(|(i, h)| HeaderInfoJson::new(h, i, i.wrapping_sub(1)))
+            .collect();
+        let heights = |headers: Vec<HeaderInfoJson>| -> Vec<u64> {
+            headers.iter().map(|header| header.height).collect()
+        };
+
+        assert_eq!(
+            heights(headers_in_range(header_infos.clone(), Some(8), None)),
+            vec![8, 9, 10]
+        );
+        assert_eq!(
+            heights(headers_in_range(header_infos.clone(), None, Some(2))),
+            vec![1, 2]
+        );
+        assert_eq!(
+            heights(headers_in_range(header_infos.clone(), Some(4), Some(4))),
+            vec![4]
+        );
+        assert!(headers_in_range(header_infos.clone(), Some(11), None).is_empty());
+        assert_eq!(headers_in_range(header_infos.clone(), None, None).len(), 10);
+
+        let mut cache = test_utils::cache(BTreeMap::from([(0, test_utils::node_data(0, &vec![]))]));
+        cache.header_infos_json = header_infos;
+        let caches: Caches = Arc::new(Mutex::new(BTreeMap::from([(0, cache)])));
+        let query = |min_height: Option<&str>, max_height: Option<&str>| DataQuery {
+            compact: None,
+            since: None,
+            max_headers: None,
+            min_height: min_height.map(|h| h.to_string()),
+            max_height: max_height.map(|h| h.to_string()),
+        };
+        let response = data_response(
+            0,
+            query(Some("3"), Some("5")),
+            None,
+            false,
+            caches.clone(),
+            BTreeMap::new(),
+            16,
+        )
+        .await
+        .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let json: serde_json::Value = serde_json::from_str(&body_string(response).await).unwrap();
+        let served: Vec<u64> = json["header_infos"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|header| header["height"].as_u64().unwrap())
+            .collect();
+        assert_eq!(served, vec![3, 4, 5]);
+        assert_eq!(json["nodes"].as_array().unwrap().len(), 1);
+
+        for (min_height, max_height) in [
+            (Some("abc"), None),
+            (None, Some("-1")),
+            (Some("6"), Some("5")),
+        ] {
+            let response = data_response(
+                0,
+                query(min_height, max_height),
+                None,
+                false,
+                caches.clone(),
+                BTreeMap::new(),
+                16,
+            )
+            .await
+            .unwrap();
+            assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+            let json: serde_json::Value =
+                serde_json::from_str(&body_string(response).await).unwrap();
+            assert!(json["error"].is_string());
+        }
+
+        // the filtered response isn't cached
+        let response = data_response(0, full(), None, false, caches, BTreeMap::new(), 16)
+            .await
+            .unwrap();
+        let json: serde_json::Value = serde_json::from_str(&body_string(response).await).unwrap();
+        assert_eq!(json["header_infos"].as_array().unwrap().len(), 10);
+    }
+
+    #[tokio::test]
+    async fn test_compact_data_json() {
+        // a fork at height 11
+        let mut headers = test_utils::chain(BlockHash::all_zeros(), 1, 500, 0);
+        headers.extend(test_utils::chain(
+            headers[9].header.block_hash(),
+            11,
+            3,
+            1000,
+        ));
+        let header_infos: Vec<HeaderInfoJson> = headers
+            .iter()
+            .enumerate()
+            .map(|(i, h)| {
+                let prev_id = headers
+                    .iter()
+                    .position(|p| p.header.block_hash() == h.header.prev_blockhash)
+                    .unwrap_or(usize::MAX);
+                HeaderInfoJson::new(h, i, prev_id)
+            })
+            .collect();
+        let mut cache = test_utils::cache(BTreeMap::from([(0, test_utils::node_data(0, &vec![]))]));
+        cache.header_infos_json = header_infos.clone();
+        let caches: Caches = Arc::new(Mutex::new(BTreeMap::from([(0, cache)])));
+
+        let full_body = body_string(
+            data_response(0, full(), None, false, caches.clone(), BTreeMap::new(), 16)
+                .await
+                .unwrap(),
+        )
+        .await;
+        let compact_query = DataQuery {
+            compact: Some("1".to_string()),
+            since: None,
+            max_headers: None,
+            min_height: None,
+            max_height: None,
+        };
+        let compact_body = body_string(
+            data_response(
+                0,
+                compact_query,
+                None,
+                false,
+                caches.clone(),
+                BTreeMap::new(),
+                16,
+            )
+            .await
+            .unwrap(),
+        )
+        .await;
+        // mostly saved by the short keys and the omitted merkle roots and
+        // previous hashes
+        assert!(compact_body.len() * 3 < full_body.len() * 2);
+
+        // decode the compact encoding and compare it to the full one
+        let full: serde_json::Value = serde_json::from_str(&full_body).unwrap();
+        let compact: serde_json::Value = serde_json::from_str(&compact_body).unwrap();
+        let hashes = compact["x"].as_array().unwrap();
+        let compact_headers = compact["h"].as_array().unwrap();
+        assert_eq!(compact_headers.len(), header_infos.len());
+        for (i, c) in compact_headers.iter().enumerate() {
+            let f = &full["header_infos"][i];
+            assert_eq!(c["i"], f["id"]);
+            assert_eq!(c["p"], f["prev_id"]);
+            assert_eq!(c["n"], f["height"]);
+            assert_eq!(hashes[i], f["hash"]);
+            assert!(f["hash"]
+                .as_str()
+                .unwrap()
+                .starts_with(c["x"].as_str().unwrap()));
+            assert_eq!(c["x"].as_str().unwrap().len(), 16);
+            assert_eq!(c["v"], f["version"]);
+            assert_eq!(c["t"], f["time"]);
+            assert_eq!(c["b"], f["bits"]);
+            assert_eq!(c["d"], f["difficulty_int"]);
+            assert_eq!(c["o"], f["nonce"]);
+            assert_eq!(c["m"], f["miner"]);
+            assert!(c.get("merkle_root").is_none());
+            // the previous hash is derivable from the prev_id, except for the root
+            let prev_blockhash = match compact_headers.iter().position(|p| p["i"] == c["p"]) {
+                Some(prev) => hashes[prev].clone(),
+                None => c["r"].clone(),
+            };
+            assert_eq!(prev_blockhash, f["prev_blockhash"]);
+        }
+        assert!(compact_headers[0]["r"].is_string());
+        assert!(compact_headers[1].get("r").is_none());
+        assert_eq!(compact["n"], full["nodes"]);
+        assert_eq!(compact["dd"], full["deployment_disagreements"]);
+    }
+
+    #[tokio::test]
+    async fn test_data_json_etag() {
+        let caches: Caches = Arc::new(Mutex::new(BTreeMap::from([(
+            0,
+            test_utils::cache(BTreeMap::from([(0, test_utils::node_data(0, &vec![]))])),
+        )])));
+        let request = |if_none_match: Option<&str>| {
+            data_response(
+                0,
+                full(),
+                if_none_match.map(|s| s.to_string()),
+                false,
+                caches.clone(),
+                BTreeMap::new(),
+                16,
+            )
+        };
+
+        let reply = request(None).await.unwrap();
+        assert_eq!(reply.status(), StatusCode::OK);
+        let etag = reply.headers()[ETAG].to_str().unwrap().to_string();
+        assert!(etag.starts_with('"') && etag.ends_with('"'));
+        assert_eq!(
+            caches.lock().await[&0].data_json.as_ref().unwrap().etag,
+            etag
+        );
+
+        let reply = request(Some(&etag)).await.unwrap();
+        assert_eq!(reply.status(), StatusCode::NOT_MODIFIED);
+        assert_eq!(reply.headers()[ETAG], etag.as_str());
+        assert!(body_string(reply).await.is_empty());
+        let reply = request(Some(&format!("\"other\", W/{}", etag)))
+            .await
+            .unwrap();
+        assert_eq!(reply.status(), StatusCode::NOT_MODIFIED);
+        let reply = request(Some("\"other\"")).await.unwrap();
+        assert_eq!(reply.status(), StatusCode::OK);
+
+        // a changed cache has a new ETag
+        {
+            let mut locked = caches.lock().await;
+            let cache = locked.get_mut(&0).unwrap();
+            cache.node_data.get_mut(&0).unwrap().name = "renamed".to_string();
+            cache.data_json = None;
+        }
+        let reply = request(Some(&etag)).await.unwrap();
+        assert_eq!(reply.status(), StatusCode::OK);
+        assert_ne!(reply.headers()[ETAG], etag.as_str());
+    }
+
+    #[tokio::test]
+    async fn test_data_json_gzip() {
+        let caches: Caches = Arc::new(Mutex::new(BTreeMap::from([(
+            0,
+            test_utils::cache(BTreeMap::from([(0, test_utils::node_data(0, &vec![]))])),
+        )])));
+        let request = |if_none_match: Option<&str>| {
+            data_response(
+                0,
+                full(),
+                if_none_match.map(|s| s.to_string()),
+                true,
+                caches.clone(),
+                BTreeMap::new(),
+                16,
+            )
+        };
+
+        let reply = request(None).await.unwrap();
+        assert_eq!(reply.status(), StatusCode::OK);
+        assert_eq!(reply.headers()[CONTENT_ENCODING], "gzip");
+        let etag = reply.headers()[ETAG].to_str().unwrap().to_string();
+        let compressed = warp::hyper::body::to_bytes(reply.into_body())
+            .await
+            .unwrap();
+        let mut decompressed = vec![];
+        GzDecoder::new(&compressed[..])
+            .read_to_end(&mut decompressed)
+            .unwrap();
+
+        // the compressed body is cached next to the body
+        let cached = caches.lock().await[&0].data_json.clone().unwrap();
+        assert_eq!(decompressed, cached.body.to_vec());
+        assert_eq!(cached.etag, etag);
+        let cached_gzip = cached.gzip.unwrap();
+        assert_eq!(*cached_gzip, compressed);
+        let reply = request(None).await.unwrap();
+        let body = warp::hyper::body::to_bytes(reply.into_body())
+            .await
+            .unwrap();
+        assert_eq!(body, *cached_gzip);
+        assert!(Arc::ptr_eq(
+            &cached_gzip,
+            caches.lock().await[&0]
+                .data_json
+                .as_ref()
+                .unwrap()
+                .gzip
+                .as_ref()
+                .unwrap()
+        ));
+
+        let reply = request(Some(&etag)).await.unwrap();
+        assert_eq!(reply.status(), StatusCode::NOT_MODIFIED);
+        assert!(reply.headers().get(CONTENT_ENCODING).is_none());
+    }
+
+    #[tokio::test]
+    async fn test_data_json_redaction() {
+        let mut node = test_utils::node_data(3, &vec![]);
+        node.name = "alice's node".to_string();
+        node.description = "in alice's basement".to_string();
+        node.version("/Satoshi:27.1.0/".to_string());
+        node.poll_latency(120);
+        let caches: Caches = Arc::new(Mutex::new(BTreeMap::from([(
+            0,
+            test_utils::cache(BTreeMap::from([(3, node)])),
+        )])));
+
+        let privacy = BTreeMap::from([(
+            0,
+            Privacy {
+                hide_node_names: true,
+                hide_versions: true,
+                hide_descriptions: true,
+                hide_poll_latency: true,
+            },
+        )]);
+        let body = body_string(
+            data_response(0, full(), None, false, caches.clone(), privacy.clone(), 16)
+                .await
+                .unwrap(),
+        )
+        .await;
+        let json: serde_json::Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(json["nodes"][0]["name"], "Node 3");
+        assert_eq!(json["nodes"][0]["description"], "");
+        assert_eq!(json["nodes"][0]["version"], "/Satoshi:27.x/");
+        assert!(json["nodes"][0]["last_poll_latency_ms"].is_null());
+        assert!(json["nodes"][0]["avg_poll_latency_ms"].is_null());
+
+        // the cache keeps the real values
+        let body = body_string(
+            data_response(0, full(), None, false, caches.clone(), BTreeMap::new(), 16)
+                .await
+                .unwrap(),
+        )
+        .await;
+        let json: serde_json::Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(json["nodes"][0]["name"], "alice's node");
+        assert_eq!(json["nodes"][0]["description"], "in alice's basement");
+        assert_eq!(json["nodes"][0]["version"], "/Satoshi:27.1.0/");
+        // the poll latencies are only served on nodes.json
+        assert!(json["nodes"][0]["last_poll_latency_ms"].is_null());
+        let body = body_string(
+            nodes_response(0, caches.clone(), BTreeMap::new())
+                .await
+                .unwrap(),
+        )
+        .await;
+        let json: serde_json::Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(json[0]["last_poll_latency_ms"], 120);
+        let body = body_string(nodes_response(0, caches, privacy).await.unwrap()).await;
+        let json: serde_json::Value = serde_json::from_str(&body).unwrap();
+        assert!(json[0]["last_poll_latency_ms"].is_null());
+        assert!(json[0]["avg_poll_latency_ms"].is_null());
+    }
+
+    #[tokio::test]
+    async fn test_data_json_height_offset() {
+        let headers = test_utils::chain(BlockHash::all_zeros(), 1, 3, 0);
+        let tip = test_utils::chain_tip(3, &headers[2].header.block_hash(), ChainTipStatus::Active);
+        let mut cache =
+            test_utils::cache(BTreeMap::from([(0, test_utils::node_data(0, &vec![tip]))]));
+        cache.header_infos_json = headers
+            .iter()
+            .enumerate()
+            .map(|(i, h)| HeaderInfoJson::new(h, i, i.checked_sub(1).unwrap_or(usize::MAX)))
+            .collect();
+        cache.height_offset = -1;
+        let caches: Caches = Arc::new(Mutex::new(BTreeMap::from([(0, cache)])));
+
+        let body = body_string(
+            data_response(0, full(), None, false, caches.clone(), BTreeMap::new(), 16)
+                .await
+                .unwrap(),
+        )
+        .await;
+        let json: serde_json::Value = serde_json::from_str(&body).unwrap();
+        let heights: Vec<u64> = json["header_infos"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|h| h["height"].as_u64().unwrap())
+            .collect();
+        assert_eq!(heights, vec![0, 1, 2]);
+        assert_eq!(json["nodes"][0]["tips"][0]["height"], 2);
+        // the statuses are derived from the actual heights
+        assert_eq!(json["header_infos"][2]["status"], "active");
+
+        let compact_query = DataQuery {
+            compact: Some("1".to_string()),
+            since: None,
+            max_headers: None,
+            min_height: None,
+            max_height: None,
+        };
+        let body = body_string(
+            data_response(
+                0,
+                compact_query,
+                None,
+                false,
+                caches.clone(),
+                BTreeMap::new(),
+                16,
+            )
+            .await
+            .unwrap(),
+        )
+        .await;
+        let json: serde_json::Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(json["h"][0]["n"], 0);
+
+        // the cache keeps the actual heights
+        let caches_locked = caches.lock().await;
+        assert_eq!(caches_locked[&0].header_infos_json[0].height, 1);
+        assert_eq!(caches_locked[&0].node_data[&0].tips[0].height, 3);
+    }
+
+    #[tokio::test]
+    async fn test_nodes_json() {
+        let tip = test_utils::chain_tip(100, &BlockHash::all_zeros(), ChainTipStatus::Active);
+        let mut node = test_utils::node_data(3, &vec![tip]);
+        node.name = "alice's node".to_string();
+        node.version("/Satoshi:27.1.0/".to_string());
+        node.poll_latency(120);
+        let caches: Caches = Arc::new(Mutex::new(BTreeMap::from([(
+            0,
+            test_utils::cache(BTreeMap::from([(3, node)])),
+        )])));
+        let privacy = BTreeMap::from([(
+            0,
+            Privacy {
+                hide_node_names: true,
+                hide_versions: false,
+                hide_descriptions: false,
+                hide_poll_latency: false,
+            },
+        )]);
+
+        let reply = nodes_response(0, caches.clone(), privacy.clone())
+            .await
+            .unwrap()
+            .into_response();
+        assert_eq!(reply.status(), StatusCode::OK);
+        let json: serde_json::Value = serde_json::from_str(&body_string(reply).await).unwrap();
+        assert_eq!(json.as_array().unwrap().len(), 1);
+        assert_eq!(json[0]["id"], 3);
+        assert_eq!(json[0]["name"], "Node 3");
+        assert_eq!(json[0]["version"], "/Satoshi:27.1.0/");
+        assert_eq!(json[0]["reachable"], true);
+        assert_eq!(json[0]["tips"][0]["height"], 100);
+
+        let reply = nodes_response(1, caches, privacy)
+            .await
+            .unwrap()
+            .into_response();
+        assert_eq!(reply.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[test]
+    fn test_version_redaction() {
+        let privacy = Privacy {
+            hide_node_names: false,
+            hide_versions: true,
+            hide_descriptions: false,
+            hide_poll_latency: false,
+        };
+        let version = |v: &str| {
+            let mut node = test_utils::node_data(0, &vec![]);
+            node.version(v.to_string());
+            node.redacted(&privacy).version
+        };
+        assert_eq!(version("/Satoshi:27.1.0/"), "/Satoshi:27.x/");
+        assert_eq!(version("/Satoshi:0.21.1/"), "/Satoshi:0.21.x/");
+        assert_eq!(version("/Satoshi:28.0.0(comment)/"), "/Satoshi:28.x/");
+        assert_eq!(
+            version("/Satoshi:27.1.0/Knots:20240801/"),
+            "/Satoshi:27.x/Knots:x/"
+        );
+        assert_eq!(version("unknown"), "unknown");
+    }
+}
@@ -0,0 +1,204 @@
+use schemars::gen::SchemaSettings;
+use schemars::schema::Schema;
+use serde_json::{json, Value};
+
+use crate::types::{
+    Alert, DataChanged, DataChangedDetail, DataJsonResponse, InfoJsonResponse, Lagged,
+    NetworksJsonResponse,
+};
+
+fn json_response(description: &str, schema: Schema) -> Value {
+    json!({
+        "description": description,
+        "content": { "application/json": { "schema": schema } },
+    })
+}
+
+fn query_parameter(name: &str, schema: Value, description: &str) -> Value {
+    json!({
+        "name": name,
+        "in": "query",
+        "required": false,
+        "schema": schema,
+        "description": description,
+    })
+}
+
+/// The OpenAPI 3 description of the JSON API, served on /api/openapi.json.
+/// The response schemas are derived from the response types, so they stay
+/// in sync with what the endpoints serve.
+pub fn openapi() -> Value {
+    let mut gen = SchemaSettings::openapi3().into_generator();
+    let info = gen.subschema_for::<InfoJsonResponse>();
+    let networks = gen.subschema_for::<NetworksJsonResponse>();
+    let data = gen.subschema_for::<DataJsonResponse>();
+    let events = vec![
+        gen.subschema_for::<DataChanged>(),
+        gen.subschema_for::<DataChangedDetail>(),
+        gen.subschema_for::<Alert>(),
+        gen.subschema_for::<Lagged>(),
+    ];
+
+    json!({
+        "openapi": "3.0.3",
+        "info": {
+            "title": "fork-observer",
+            "version": env!("CARGO_PKG_VERSION"),
+        },
+        "paths": {
+            "/api/info.json": {
+                "get": {
+                    "summary": "Information about the instance",
+                    "responses": {
+                        "200": json_response("The footer shown by the frontend.", info),
+                    },
+                },
+            },
+            "/api/networks.json": {
+                "get": {
+                    "summary": "The observed networks",
+                    "responses": {
+                        "200": json_response("The networks and the uptime of the instance.", networks),
+                    },
+                },
+            },
+            "/api/{network_id}/data.json": {
+                "get": {
+                    "summary": "The header tree and the nodes of a network",
+                    "description": "Unknown networks are answered with empty lists. The `compact` and `since` parameters change the shape of the response, see the README.",
+                    "parameters": [
+                        {
+                            "name": "network_id",
+                            "in": "path",
+                            "required": true,
+                            "schema": { "type": "integer", "format": "uint32", "minimum": 0 },
+                        },
+                        query_parameter("compact", json!({ "type": "string", "enum": ["1", "true"] }), "Requests the compact encoding."),
+                        query_parameter("since", json!({ "type": "integer", "format": "uint64", "minimum": 0 }), "Only returns the changes since this cache generation."),
+                        query_parameter("max_headers", json!({ "type": "integer", "format": "uint", "minimum": 0 }), "Only returns this many of the highest headers."),
+                        query_parameter("min_height", json!({ "type": "integer", "format": "uint64", "minimum": 0 }), "Only returns the headers at or above this height."),
+                        query_parameter("max_height", json!({ "type": "integer", "format": "uint64", "minimum": 0 }), "Only returns the headers at or below this height."),
+                        {
+                            "name": "If-None-Match",
+                            "in": "header",
+                            "required": false,
+                            "schema": { "type": "string" },
+                        },
+                    ],
+                    "responses": {
+                        "200": json_response("The headers and nodes of the network.", data),
+                        "304": { "description": "The response didn't change since the ETag in If-None-Match." },
+                        "400": {
+                            "description": "An invalid min_height or max_height.",
+                            "content": {
+                                "application/json": {
+                                    "schema": {
+                                        "type": "object",
+                                        "required": ["error"],
+                                        "properties": { "error": { "type": "string" } },
+                                    },
+                                },
+                            },
+                        },
+                    },
+                },
+            },
+            "/api/changes": {
+                "get": {
+                    "summary": "Server-sent events about changes on all networks",
+                    "description": "The event names are tip_changed (DataChanged), node_tips and new_block (DataChangedDetail), chain_split and chain_split_resolved (Alert) and lagged (Lagged).",
+                    "responses": {
+                        "200": {
+                            "description": "The event stream. The data of each event is JSON.",
+                            "content": { "text/event-stream": { "schema": { "oneOf": events } } },
+                        },
+                        "503": { "description": "Too many open event streams." },
+                    },
+                },
+            },
+        },
+        "components": { "schemas": gen.take_definitions() },
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils;
+    use crate::types::{ChainTipStatus, HeaderInfoJson};
+    use bitcoincore_rpc::bitcoin::hashes::Hash;
+    use bitcoincore_rpc::bitcoin::BlockHash;
+
+    // The keys of the serialized object are properties of the schema and the
+    // required properties are all set.
+    fn assert_matches_schema(doc: &Value, name: &str, value: &Value) {
+        let schema = &doc["components"]["schemas"][name];
+        let properties = schema["properties"]
+            .as_object()
+            .unwrap_or_else(|| panic!("{} should have properties", name));
+        let object = value.as_object().unwrap();
+        for key in object.keys() {
+            assert!(
+                properties.contains_key(key),
+                "{}.{} is missing in the schema",
+                name,
+                key
+            );
+        }
+        for required in schema["required"].as_array().into_iter().flatten() {
+            assert!(
+                object.contains_key(required.as_str().unwrap()),
+                "{}.{} is required but not serialized",
+                name,
+                required
+            );
+        }
+    }
+
+    #[test]
+    fn test_openapi() {
+        let doc = openapi();
+        assert_eq!(doc["openapi"], "3.0.3");
+        for path in [
+            "/api/info.json",
+            "/api/networks.json",
+            "/api/{network_id}/data.json",
+            "/api/changes",
+        ] {
+            assert!(doc["paths"][path]["get"].is_object(), "{}", path);
+        }
+        assert_eq!(
+            doc["paths"]["/api/{network_id}/data.json"]["get"]["responses"]["200"]["content"]
+                ["application/json"]["schema"]["$ref"],
+            "#/components/schemas/DataJsonResponse"
+        );
+        assert_eq!(
+            doc["components"]["schemas"]["ChainTipStatus"]["enum"],
+            json!([
+                "active",
+                "invalid",
+                "valid-fork",
+                "headers-only",
+                "valid-headers",
+                "unknown"
+            ])
+        );
+
+        let chain = test_utils::chain(BlockHash::all_zeros(), 1, 2, 0);
+        let tip = test_utils::chain_tip(2, &chain[1].header.block_hash(), ChainTipStatus::Active);
+        let node = test_utils::node_data(0, &vec![tip]);
+        let header_info = HeaderInfoJson::new(&chain[1], 1, 0);
+        let data = DataJsonResponse {
+            header_infos: vec![header_info.clone()],
+            nodes: vec![node.clone()],
+            deployment_disagreements: vec![],
+            block_propagation: None,
+            active_chain_work: Some(format!("{:064x}", 2)),
+        };
+        assert_matches_schema(&doc, "DataJsonResponse", &json!(data));
+        assert_matches_schema(&doc, "HeaderInfoJson", &json!(header_info));
+        assert_matches_schema(&doc, "NodeDataJson", &json!(node));
+        assert_matches_schema(&doc, "TipInfoJson", &json!(node.tips[0]));
+        assert_matches_schema(&doc, "Lagged", &json!(Lagged { skipped: 3 }));
+    }
+}
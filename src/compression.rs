@@ -0,0 +1,166 @@
+use std::io::Write;
+
+use flate2::write::GzEncoder;
+use warp::filters::BoxedFilter;
+use warp::http::header::{CONTENT_ENCODING, CONTENT_LENGTH, VARY};
+use warp::http::StatusCode;
+use warp::hyper::body::{to_bytes, Bytes};
+use warp::reply::Response;
+use warp::{Filter, Rejection, Reply};
+
+// If the Accept-Encoding header value accepts the encoding. Encodings with a
+// quality of zero, e.g. "gzip;q=0", are refused.
+fn accepts_encoding(accept_encoding: &str, encoding: &str) -> bool {
+    accept_encoding.split(',').any(|entry| {
+        let mut params = entry.split(';').map(|param| param.trim());
+        let name = params.next().unwrap_or_default();
+        if !name.eq_ignore_ascii_case(encoding) && name != "*" {
+            return false;
+        }
+        params
+            .filter_map(|param| param.strip_prefix("q="))
+            .all(|q| q.parse::<f32>().is_ok_and(|q| q > 0.0))
+    })
+}
+
+/// Extracts whether the response can be gzip compressed: compression is
+/// enabled and the client accepts gzip.
+pub fn accepts_gzip(enable: bool) -> impl Filter<Extract = (bool,), Error = Rejection> + Clone {
+    warp::header::optional::<String>("accept-encoding").map(
+        move |accept_encoding: Option<String>| {
+            enable && accept_encoding.is_some_and(|a| accepts_encoding(&a, "gzip"))
+        },
+    )
+}
+
+/// Gzip compresses a body.
+pub fn gzip(body: &[u8]) -> std::io::Result<Bytes> {
+    let mut encoder = GzEncoder::new(Vec::new(), flate2::Compression::default());
+    encoder.write_all(body)?;
+    Ok(Bytes::from(encoder.finish()?))
+}
+
+/// Marks a response with a gzip compressed body.
+pub fn gzip_encoded(response: Response) -> Response {
+    warp::reply::with_header(response, CONTENT_ENCODING, "gzip").into_response()
+}
+
+// Gzip compresses the body of the response. Responses that are already
+// encoded, e.g. the cached data.json, are passed as they are.
+async fn compress(response: Response) -> Response {
+    if response.headers().contains_key(CONTENT_ENCODING) {
+        return response;
+    }
+    let (mut parts, body) = response.into_parts();
+    let body = match to_bytes(body).await.map(|body| gzip(&body)) {
+        Ok(Ok(body)) => body,
+        _ => return StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+    };
+    parts.headers.remove(CONTENT_LENGTH);
+    gzip_encoded(Response::from_parts(parts, body.into()))
+}
+
+fn into_response<R: Reply>(reply: R) -> Response {
+    reply.into_response()
+}
+
+/// Gzip compresses the responses of the routes for clients that accept it.
+/// Not meant for streams, e.g. server-sent events, as their body is
+/// compressed at once.
+pub fn wrap<F, R>(routes: F, enable: bool) -> BoxedFilter<(Response,)>
+where
+    F: Filter<Extract = (R,), Error = Rejection> + Clone + Send + Sync + 'static,
+    R: Reply + 'static,
+{
+    let routes = routes.map(into_response::<R>);
+    if !enable {
+        return routes.boxed();
+    }
+    accepts_gzip(enable)
+        .and(routes)
+        .then(|gzip: bool, reply: Response| async move {
+            let reply = if gzip { compress(reply).await } else { reply };
+            warp::reply::with_header(reply, VARY, "accept-encoding").into_response()
+        })
+        .boxed()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::read::GzDecoder;
+    use std::io::Read;
+
+    #[test]
+    fn test_accepts_encoding() {
+        assert!(accepts_encoding("gzip", "gzip"));
+        assert!(accepts_encoding("deflate, GZIP;q=0.5", "gzip"));
+        assert!(accepts_encoding("br;q=1.0, *", "gzip"));
+        assert!(!accepts_encoding("", "gzip"));
+        assert!(!accepts_encoding("identity", "gzip"));
+        assert!(!accepts_encoding("gzip;q=0", "gzip"));
+        assert!(!accepts_encoding("gzip;q=0.0, br", "gzip"));
+        assert!(!accepts_encoding("x-gzip", "gzip"));
+    }
+
+    #[tokio::test]
+    async fn test_wrap() {
+        let route = || warp::path!("data.json").map(|| warp::reply::json(&vec![1u32; 1000]));
+
+        let compressed = wrap(route(), true);
+        let response = warp::test::request()
+            .path("/data.json")
+            .header("accept-encoding", "gzip, deflate")
+            .reply(&compressed)
+            .await;
+        assert_eq!(response.headers()[CONTENT_ENCODING], "gzip");
+        assert_eq!(response.headers()[VARY], "accept-encoding");
+        assert!(response.body().len() < 2000);
+        let mut decompressed = String::new();
+        GzDecoder::new(&response.body()[..])
+            .read_to_string(&mut decompressed)
+            .unwrap();
+        assert_eq!(
+            decompressed,
+            serde_json::to_string(&vec![1u32; 1000]).unwrap()
+        );
+
+        let response = warp::test::request()
+            .path("/data.json")
+            .reply(&compressed)
+            .await;
+        assert!(response.headers().get(CONTENT_ENCODING).is_none());
+        assert_eq!(response.headers()[VARY], "accept-encoding");
+        assert_eq!(response.body().len(), 2001);
+
+        let uncompressed = wrap(route(), false);
+        let response = warp::test::request()
+            .path("/data.json")
+            .header("accept-encoding", "gzip")
+            .reply(&uncompressed)
+            .await;
+        assert!(response.headers().get(CONTENT_ENCODING).is_none());
+        assert_eq!(response.body().len(), 2001);
+
+        // already encoded responses aren't compressed again
+        let encoded = wrap(
+            warp::path!("data.json").map(|| gzip_encoded("encoded".into_response())),
+            true,
+        );
+        let response = warp::test::request()
+            .path("/data.json")
+            .header("accept-encoding", "gzip")
+            .reply(&encoded)
+            .await;
+        assert_eq!(response.headers()[CONTENT_ENCODING], "gzip");
+        assert_eq!(response.body(), "encoded");
+
+        // unknown paths are still rejected
+        let response = warp::test::request()
+            .path("/unknown")
+            .header("accept-encoding", "gzip")
+            .reply(&compressed)
+            .await;
+        assert_eq!(response.status(), 404);
+    }
+}
@@ -1,30 +1,52 @@
+use std::cmp::max;
+use std::collections::BTreeMap;
 use std::hash::Hash;
-use std::net::SocketAddr;
-use std::path::PathBuf;
+use std::net::{IpAddr, SocketAddr};
+use std::path::{Path, PathBuf};
 use std::str::FromStr;
 use std::sync::Arc;
 use std::time::Duration;
 use std::{env, fmt, fs};
 
-use bitcoincore_rpc::bitcoin::Network as BitcoinNetwork;
+use bitcoincore_rpc::bitcoin::secp256k1::SecretKey;
+use bitcoincore_rpc::bitcoin::{Address, Network as BitcoinNetwork};
 use bitcoincore_rpc::Auth;
-use log::{error, info};
-use serde::Deserialize;
+use log::{error, info, warn};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use warp::http::uri::Authority;
 
 use crate::error::ConfigError;
 use crate::node::{BitcoinCoreNode, BtcdNode, Node, NodeInfo};
+use crate::rss::FeedKind;
 
 pub const ENVVAR_CONFIG_FILE: &str = "CONFIG_FILE";
 const DEFAULT_CONFIG: &str = "config.toml";
 const DEFAULT_NODE_IMPL: NodeImplementation = NodeImplementation::BitcoinCore;
 const DEFAULT_USE_REST: bool = true;
+const DEFAULT_MAX_FORK_CHILDREN: usize = 10;
+const DEFAULT_MAX_INITIAL_BACKFILL: u64 = 50_000;
+const DEFAULT_CONTEXT_BLOCKS_BEFORE: u64 = 2;
+const DEFAULT_CONTEXT_BLOCKS_AFTER: u64 = 1;
+const DEFAULT_MAX_SSE_CONNECTIONS: usize = 1000;
+const IN_MEMORY_DATABASE_PATH: &str = ":memory:";
+const DEFAULT_COMPACT_HASH_PREFIX_LENGTH: usize = 16;
+const DEFAULT_MAX_CONCURRENT_INITIAL_SYNCS: usize = 2;
+const DEFAULT_POOL_IDENTIFICATION_QUEUE_CAPACITY: usize = 1000;
+const DEFAULT_SHOW_EMPTY_NETWORKS: bool = true;
+const DEFAULT_COMPRESS_RESPONSES: bool = true;
+const DEFAULT_LOG_STARTUP_SUMMARY: bool = true;
+const DEFAULT_BLOCKS_FEED_ITEMS: usize = 20;
+const DEFAULT_STALLED_AFTER_SECONDS: u64 = 60 * 60;
 
 pub type BoxedSyncSendNode = Arc<dyn Node + Send + Sync>;
 
 #[derive(Clone, Deserialize, Debug)]
 pub enum PoolIdentificationNetwork {
     Mainnet,
+    /// testnet3
     Testnet,
+    Testnet4,
     Signet,
     Regtest,
 }
@@ -34,6 +56,7 @@ impl PoolIdentificationNetwork {
         match self {
             PoolIdentificationNetwork::Mainnet => BitcoinNetwork::Bitcoin,
             PoolIdentificationNetwork::Testnet => BitcoinNetwork::Testnet,
+            PoolIdentificationNetwork::Testnet4 => BitcoinNetwork::Testnet4,
             PoolIdentificationNetwork::Signet => BitcoinNetwork::Signet,
             PoolIdentificationNetwork::Regtest => BitcoinNetwork::Regtest,
         }
@@ -49,23 +72,414 @@ struct TomlConfig {
     query_interval: u64,
     networks: Vec<TomlNetwork>,
     footer_html: String,
+    max_sse_connections: Option<usize>,
+    #[serde(default)]
+    ephemeral: bool,
+    compact_hash_prefix_length: Option<usize>,
+    retention: Option<TomlRetention>,
+    max_concurrent_initial_syncs: Option<usize>,
+    rate_limit_per_minute: Option<u32>,
+    show_empty_networks: Option<bool>,
+    compress_responses: Option<bool>,
+    log_startup_summary: Option<bool>,
+    #[serde(default)]
+    cors_allowed_origins: Vec<String>,
+    admin_token: Option<String>,
+    access_log: Option<TomlAccessLog>,
+    nostr: Option<TomlNostr>,
 }
 
 #[derive(Clone)]
 pub struct Config {
     pub database_path: PathBuf,
+    /// Keep everything in memory and don't persist headers, e.g. for tests.
+    pub ephemeral: bool,
     pub www_path: PathBuf,
     pub query_interval: Duration,
     pub address: SocketAddr,
     pub networks: Vec<Network>,
     pub footer_html: String,
     pub rss_base_url: String,
+    /// Maximum number of concurrent server-sent event connections.
+    pub max_sse_connections: usize,
+    /// Length of the hash prefixes in the compact data.json encoding.
+    pub compact_hash_prefix_length: usize,
+    pub retention: Retention,
+    /// Maximum number of networks doing their initial header sync at the
+    /// same time.
+    pub max_concurrent_initial_syncs: usize,
+    /// Maximum number of API and RSS requests per minute and client IP
+    /// address. None disables the rate limit.
+    pub rate_limit_per_minute: Option<u32>,
+    /// List networks without headers or tips yet in networks.json.
+    pub show_empty_networks: bool,
+    /// Gzip compress the JSON API responses for clients accepting it.
+    pub compress_responses: bool,
+    /// Log a summary of the effective configuration on startup.
+    pub log_startup_summary: bool,
+    /// Origins allowed to fetch the JSON API cross-origin. Empty disables
+    /// CORS.
+    pub cors_allowed_origins: Vec<String>,
+    /// Bearer token of the admin API, e.g. to retry a pool identification.
+    /// None disables the admin API.
+    pub admin_token: Option<String>,
+    pub access_log: AccessLog,
+    /// Where fork and reorg notes are published. None disables publishing.
+    pub nostr: Option<NostrConfig>,
+}
+
+impl Config {
+    /// A summary of the effective configuration for the startup log. Only
+    /// the node infos are listed, so RPC credentials aren't included.
+    pub fn summary(&self) -> String {
+        let mut lines = vec![format!(
+            "Configuration: address={}, database_path={:?}, ephemeral={}, query_interval={}s, rate_limit_per_minute={}, compress_responses={}, access_log={}, nostr={}",
+            self.address,
+            self.database_path,
+            self.ephemeral,
+            self.query_interval.as_secs(),
+            self.rate_limit_per_minute
+                .map_or_else(|| "unlimited".to_string(), |limit| limit.to_string()),
+            self.compress_responses,
+            self.access_log.enable,
+            self.nostr.is_some(),
+        )];
+        for network in self.networks.iter() {
+            let feeds: Vec<&str> = FeedKind::ALL
+                .iter()
+                .filter(|kind| kind.enabled(&network.feeds))
+                .map(|kind| kind.stem())
+                .collect();
+            lines.push(format!(
+                "  Network(id={}, name='{}', nodes={}, pool_identification={}, feeds=[{}])",
+                network.id,
+                network.name,
+                network.nodes.len(),
+                network.pool_identification.enable,
+                feeds.join(", "),
+            ));
+            for node in network.nodes.iter() {
+                lines.push(format!("    {}", node.info()));
+            }
+        }
+        lines.join("\n")
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct TomlNostr {
+    relay: String,
+    secret_key: String,
+}
+
+/// The relay and key for publishing notes on Nostr. Only used if built with
+/// the nostr feature.
+#[derive(Clone)]
+#[cfg_attr(not(feature = "nostr"), allow(dead_code))]
+pub struct NostrConfig {
+    /// A relay URL, e.g. wss://relay.example.com.
+    pub relay: String,
+    pub secret_key: SecretKey,
+}
+
+#[derive(Debug, Deserialize, Default)]
+struct TomlAccessLog {
+    #[serde(default)]
+    enable: bool,
+    #[serde(default)]
+    trusted_proxies: Vec<String>,
+}
+
+/// Configuration of the HTTP access log.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct AccessLog {
+    /// Log a line for each response. Disabled by default.
+    pub enable: bool,
+    /// Proxies whose X-Forwarded-For header is trusted to contain the client
+    /// IP address. Also used by the rate limit, even if the access log is
+    /// disabled.
+    pub trusted_proxies: Vec<IpAddr>,
+}
+
+#[derive(Debug, Deserialize, Default)]
+struct TomlRetention {
+    alerts: Option<String>,
+}
+
+/// How long rows in the database tables with timestamped events are kept.
+/// None keeps them forever.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Retention {
+    pub alerts: Option<Duration>,
+}
+
+impl Retention {
+    /// The tables with a limited retention and their retention duration.
+    pub fn tables(&self) -> Vec<(&'static str, Duration)> {
+        let mut tables = vec![];
+        if let Some(alerts) = self.alerts {
+            tables.push(("alerts", alerts));
+        }
+        tables
+    }
+}
+
+// Parses a retention duration like "90d", "12h", "30m", "45s" or "2w".
+// "unlimited" is None.
+fn parse_retention(retention: &str) -> Result<Option<Duration>, ConfigError> {
+    let retention = retention.trim();
+    if retention == "unlimited" {
+        return Ok(None);
+    }
+    let invalid = || ConfigError::InvalidRetention(retention.to_string());
+    let unit_index = retention
+        .find(|c: char| !c.is_ascii_digit())
+        .ok_or_else(invalid)?;
+    let (value, unit) = retention.split_at(unit_index);
+    let value: u64 = value.parse().map_err(|_| invalid())?;
+    let seconds_per_unit: u64 = match unit {
+        "s" => 1,
+        "m" => 60,
+        "h" => 60 * 60,
+        "d" => 24 * 60 * 60,
+        "w" => 7 * 24 * 60 * 60,
+        _ => return Err(invalid()),
+    };
+    let seconds = value.checked_mul(seconds_per_unit).ok_or_else(invalid)?;
+    if seconds == 0 {
+        return Err(invalid());
+    }
+    Ok(Some(Duration::from_secs(seconds)))
+}
+
+fn parse_toml_access_log(toml_access_log: &TomlAccessLog) -> Result<AccessLog, ConfigError> {
+    let trusted_proxies = toml_access_log
+        .trusted_proxies
+        .iter()
+        .map(|proxy| {
+            IpAddr::from_str(proxy.trim())
+                .map_err(|_| ConfigError::InvalidTrustedProxy(proxy.clone()))
+        })
+        .collect::<Result<Vec<IpAddr>, ConfigError>>()?;
+    Ok(AccessLog {
+        enable: toml_access_log.enable,
+        trusted_proxies,
+    })
+}
+
+fn parse_toml_retention(toml_retention: &TomlRetention) -> Result<Retention, ConfigError> {
+    Ok(Retention {
+        alerts: match &toml_retention.alerts {
+            Some(alerts) => parse_retention(alerts)?,
+            None => None,
+        },
+    })
 }
 
 #[derive(Debug, Deserialize, Clone, Default)]
 pub struct PoolIdentification {
     pub enable: bool,
     pub network: Option<PoolIdentificationNetwork>,
+    /// Coinbase tag substrings that are never trusted for pool
+    /// identification. Coinbase tags can be spoofed by anyone. Blocks with
+    /// a coinbase tag containing one of these are kept as "Unknown".
+    #[serde(default)]
+    pub coinbase_tag_denylist: Vec<String>,
+    /// Maximum number of block hashes queued for pool identification.
+    pub queue_capacity: Option<usize>,
+    /// Identified miner name → canonical miner name. Collapses the variants
+    /// of a pool name into one.
+    #[serde(default)]
+    pub miner_aliases: BTreeMap<String, String>,
+    /// Custom coinbase output templates, checked after the coinbase tags and
+    /// addresses of the pool identification data.
+    #[serde(default)]
+    pub output_templates: Vec<OutputTemplate>,
+}
+
+/// Attributes blocks with a coinbase output script starting with the prefix
+/// to the miner, e.g. for miners not in the pool identification data.
+#[derive(Debug, Deserialize, Clone, PartialEq, Eq)]
+pub struct OutputTemplate {
+    /// Hex encoded script_pubkey prefix.
+    pub script_pubkey_prefix: String,
+    pub miner: String,
+}
+
+impl PoolIdentification {
+    pub fn queue_capacity(&self) -> usize {
+        max(
+            1,
+            self.queue_capacity
+                .unwrap_or(DEFAULT_POOL_IDENTIFICATION_QUEUE_CAPACITY),
+        )
+    }
+
+    fn validate(&self) -> Result<(), ConfigError> {
+        for template in self.output_templates.iter() {
+            match hex::decode(&template.script_pubkey_prefix) {
+                Ok(prefix) if !prefix.is_empty() => (),
+                _ => {
+                    return Err(ConfigError::InvalidOutputTemplate(
+                        template.script_pubkey_prefix.clone(),
+                    ))
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// The canonical name of an identified miner. Names without an alias
+    /// are returned unchanged.
+    pub fn canonical_miner(&self, miner: String) -> String {
+        match self.miner_aliases.get(&miner) {
+            Some(alias) => alias.clone(),
+            None => miner,
+        }
+    }
+}
+
+/// What node details to hide in the API and RSS responses, e.g. on public
+/// instances. Logs always contain the real values.
+#[derive(Debug, Deserialize, Clone, Default, PartialEq, Eq)]
+pub struct Privacy {
+    /// Replace node names with "Node <id>".
+    #[serde(default)]
+    pub hide_node_names: bool,
+    /// Truncate node versions to the major release, e.g. "/Satoshi:27.x/".
+    #[serde(default)]
+    pub hide_versions: bool,
+    /// Replace node descriptions with an empty string.
+    #[serde(default)]
+    pub hide_descriptions: bool,
+    /// Leave out the node poll latencies.
+    #[serde(default)]
+    pub hide_poll_latency: bool,
+}
+
+/// Which RSS feeds are served for a network. Disabled feeds respond with a
+/// 404 and aren't listed in the OPML index.
+#[derive(Debug, Deserialize, Clone, PartialEq, Eq)]
+pub struct Feeds {
+    #[serde(default = "feed_enabled")]
+    pub forks: bool,
+    #[serde(default = "feed_enabled")]
+    pub invalid: bool,
+    #[serde(default = "feed_enabled")]
+    pub lagging: bool,
+    #[serde(default = "feed_enabled")]
+    pub unreachable: bool,
+    #[serde(default = "feed_enabled")]
+    pub alerts: bool,
+    #[serde(default = "feed_enabled")]
+    pub blocks: bool,
+    /// Maximum number of items in the new blocks feed.
+    #[serde(default = "default_blocks_feed_items")]
+    pub blocks_items: usize,
+    #[serde(default = "feed_enabled")]
+    pub stalled: bool,
+    /// Seconds without a change of its tips after which a reachable node is
+    /// listed in the stalled nodes feed.
+    #[serde(default = "default_stalled_after_seconds")]
+    pub stalled_after_seconds: u64,
+}
+
+fn feed_enabled() -> bool {
+    true
+}
+
+fn default_blocks_feed_items() -> usize {
+    DEFAULT_BLOCKS_FEED_ITEMS
+}
+
+fn default_stalled_after_seconds() -> u64 {
+    DEFAULT_STALLED_AFTER_SECONDS
+}
+
+impl Default for Feeds {
+    fn default() -> Self {
+        Feeds {
+            forks: true,
+            invalid: true,
+            lagging: true,
+            unreachable: true,
+            alerts: true,
+            blocks: true,
+            blocks_items: DEFAULT_BLOCKS_FEED_ITEMS,
+            stalled: true,
+            stalled_after_seconds: DEFAULT_STALLED_AFTER_SECONDS,
+        }
+    }
+}
+
+/// Optional per-network display metadata for frontends, e.g. to visually
+/// distinguish network tabs.
+#[derive(Debug, Deserialize, Serialize, JsonSchema, Clone, PartialEq, Eq)]
+pub struct Theme {
+    /// Accent color in the "#rgb" or "#rrggbb" format.
+    pub color: Option<String>,
+    /// URL of an icon for the network.
+    pub icon: Option<String>,
+    /// Short chain label, e.g. "mainnet", "testnet4", "signet" or "custom".
+    pub chain: Option<String>,
+}
+
+impl Theme {
+    fn validate(&self) -> Result<(), ConfigError> {
+        if let Some(color) = &self.color {
+            if !is_hex_color(color) {
+                return Err(ConfigError::InvalidThemeColor(color.clone()));
+            }
+        }
+        Ok(())
+    }
+}
+
+// Checks for the "#rgb" or "#rrggbb" color format.
+fn is_hex_color(color: &str) -> bool {
+    match color.strip_prefix('#') {
+        Some(hex) => {
+            (hex.len() == 3 || hex.len() == 6) && hex.chars().all(|c| c.is_ascii_hexdigit())
+        }
+        None => false,
+    }
+}
+
+// An origin as in the Origin header: "*" or a scheme, a host and an optional
+// port, e.g. "https://example.com:8080", without a path.
+fn is_cors_origin(origin: &str) -> bool {
+    if origin == "*" {
+        return true;
+    }
+    match origin.split_once("://") {
+        Some((scheme, authority)) => {
+            !scheme.is_empty()
+                && scheme.chars().all(|c| c.is_ascii_alphanumeric())
+                && !authority.is_empty()
+                && !authority.contains(['/', '@'])
+                && authority
+                    .parse::<Authority>()
+                    .is_ok_and(|a| a.port().is_some() || a.as_str() == a.host())
+        }
+        None => false,
+    }
+}
+
+/// The number of headers kept below and above the interesting heights
+/// (forks and tips) when stripping the header tree.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ContextBlocks {
+    pub before: u64,
+    pub after: u64,
+}
+
+impl Default for ContextBlocks {
+    fn default() -> Self {
+        ContextBlocks {
+            before: DEFAULT_CONTEXT_BLOCKS_BEFORE,
+            after: DEFAULT_CONTEXT_BLOCKS_AFTER,
+        }
+    }
 }
 
 #[derive(Debug, Deserialize)]
@@ -75,8 +489,31 @@ struct TomlNetwork {
     description: String,
     min_fork_height: u64,
     max_interesting_heights: usize,
+    context_blocks_before: Option<u64>,
+    context_blocks_after: Option<u64>,
+    max_fork_children: Option<usize>,
+    max_initial_backfill: Option<u64>,
+    #[serde(default)]
+    sync_priority: u32,
+    #[serde(default)]
+    retain_raw_tips: bool,
+    #[serde(default)]
+    tips_replay_length: usize,
+    #[serde(default)]
+    verify_pow: bool,
+    #[serde(default)]
+    height_offset: i64,
+    #[serde(default)]
+    track_propagation: bool,
+    #[serde(default)]
+    track_chain_work: bool,
+    #[serde(default)]
+    watch_coinbase_addresses: Vec<String>,
     nodes: Vec<TomlNode>,
     pool_identification: Option<PoolIdentification>,
+    privacy: Option<Privacy>,
+    feeds: Option<Feeds>,
+    theme: Option<Theme>,
 }
 
 #[derive(Clone)]
@@ -86,8 +523,38 @@ pub struct Network {
     pub name: String,
     pub min_fork_height: u64,
     pub max_interesting_heights: usize,
+    pub context_blocks: ContextBlocks,
+    /// Maximum number of children tracked per fork point.
+    pub max_fork_children: usize,
+    /// Maximum number of active-chain headers loaded from a node per poll.
+    /// Larger gaps, e.g. at startup, are backfilled over multiple polls.
+    pub max_initial_backfill: u64,
+    /// Networks with a lower sync priority do their initial header sync
+    /// first.
+    pub sync_priority: u32,
+    /// Keep the unfiltered chain tips of each node for debugging.
+    pub retain_raw_tips: bool,
+    /// Number of chain tip snapshots kept per node for replaying. 0
+    /// disables the replay buffer.
+    pub tips_replay_length: usize,
+    /// Reject new headers with invalid proof-of-work for their bits.
+    pub verify_pow: bool,
+    /// Added to the heights served in data.json and data.v2.json, e.g. for
+    /// chains where the meaningful height differs from the block height.
+    pub height_offset: i64,
+    /// Track when the nodes first see new blocks to report the block
+    /// propagation delay in data.json.
+    pub track_propagation: bool,
+    /// Sum up the work of the active chain to report it in data.json.
+    pub track_chain_work: bool,
+    /// Addresses that blocks paying to them in their coinbase are flagged
+    /// for. Checked during pool identification.
+    pub watch_coinbase_addresses: Vec<String>,
     pub nodes: Vec<BoxedSyncSendNode>,
     pub pool_identification: PoolIdentification,
+    pub privacy: Privacy,
+    pub feeds: Feeds,
+    pub theme: Option<Theme>,
 }
 
 impl fmt::Display for TomlNetwork {
@@ -116,6 +583,12 @@ struct TomlNode {
     rpc_password: Option<String>,
     use_rest: Option<bool>,
     implementation: Option<String>,
+    /// btcd only: subscribe to block notifications via websocket.
+    #[serde(default)]
+    websocket: bool,
+    /// Bitcoin Core only: subscribe to block notifications on this
+    /// zmqpubhashblock endpoint.
+    zmq_pubhashblock: Option<String>,
 }
 
 impl fmt::Display for TomlNode {
@@ -177,6 +650,15 @@ fn parse_rpc_auth(node_config: &TomlNode) -> Result<Auth, ConfigError> {
         node_config.rpc_password.clone(),
     ) {
         return Ok(Auth::UserPass(user, password));
+    } else if node_config.use_rest == Some(true) {
+        // The active chain can be synced via REST alone. Only the active tip
+        // is polled via REST. RPC-only calls, e.g. getnetworkinfo, are
+        // skipped.
+        warn!(
+            "No RPC credentials for {}. Only polling the active tip via REST. The node's forks, version and deployments aren't known.",
+            node_config
+        );
+        return Ok(Auth::None);
     }
     Err(ConfigError::NoBitcoinCoreRpcAuth)
 }
@@ -245,32 +727,191 @@ fn parse_config(config_str: &str) -> Result<Config, ConfigError> {
         return Err(ConfigError::NoNetworks);
     }
 
+    let retention = match &toml_config.retention {
+        Some(toml_retention) => parse_toml_retention(toml_retention)?,
+        None => Retention::default(),
+    };
+
+    let access_log = match &toml_config.access_log {
+        Some(toml_access_log) => parse_toml_access_log(toml_access_log)?,
+        None => AccessLog::default(),
+    };
+
     Ok(Config {
+        ephemeral: toml_config.ephemeral || toml_config.database_path == IN_MEMORY_DATABASE_PATH,
         database_path: PathBuf::from(toml_config.database_path),
-        www_path: PathBuf::from(toml_config.www_path),
+        www_path: resolve_www_path(Path::new(&toml_config.www_path)),
         query_interval: Duration::from_secs(toml_config.query_interval),
         address: SocketAddr::from_str(&toml_config.address)?,
         footer_html: toml_config.footer_html.clone(),
         rss_base_url: toml_config.rss_base_url.unwrap_or_default().clone(),
+        max_sse_connections: toml_config
+            .max_sse_connections
+            .unwrap_or(DEFAULT_MAX_SSE_CONNECTIONS),
+        compact_hash_prefix_length: toml_config
+            .compact_hash_prefix_length
+            .unwrap_or(DEFAULT_COMPACT_HASH_PREFIX_LENGTH),
+        retention,
+        max_concurrent_initial_syncs: max(
+            1,
+            toml_config
+                .max_concurrent_initial_syncs
+                .unwrap_or(DEFAULT_MAX_CONCURRENT_INITIAL_SYNCS),
+        ),
+        rate_limit_per_minute: match toml_config.rate_limit_per_minute {
+            Some(0) => return Err(ConfigError::ZeroRateLimit),
+            rate_limit => rate_limit,
+        },
+        show_empty_networks: toml_config
+            .show_empty_networks
+            .unwrap_or(DEFAULT_SHOW_EMPTY_NETWORKS),
+        compress_responses: toml_config
+            .compress_responses
+            .unwrap_or(DEFAULT_COMPRESS_RESPONSES),
+        log_startup_summary: toml_config
+            .log_startup_summary
+            .unwrap_or(DEFAULT_LOG_STARTUP_SUMMARY),
+        cors_allowed_origins: toml_config
+            .cors_allowed_origins
+            .iter()
+            .map(|origin| {
+                if is_cors_origin(origin) {
+                    Ok(origin.clone())
+                } else {
+                    Err(ConfigError::InvalidCorsOrigin(origin.clone()))
+                }
+            })
+            .collect::<Result<Vec<String>, ConfigError>>()?,
+        admin_token: match toml_config.admin_token {
+            Some(token) if token.trim().is_empty() => return Err(ConfigError::EmptyAdminToken),
+            token => token,
+        },
+        access_log,
+        nostr: match &toml_config.nostr {
+            Some(toml_nostr) => Some(NostrConfig {
+                relay: toml_nostr.relay.clone(),
+                secret_key: SecretKey::from_str(toml_nostr.secret_key.trim())
+                    .map_err(|_| ConfigError::InvalidNostrSecretKey)?,
+            }),
+            None => None,
+        },
         networks,
     })
 }
 
+// A relative www_path that doesn't exist relative to the current working
+// directory is resolved against the directory of the executable. This allows
+// running a system-wide installation from any directory.
+fn resolve_www_path(www_path: &Path) -> PathBuf {
+    let exe_dir = env::current_exe()
+        .ok()
+        .and_then(|exe| exe.parent().map(Path::to_path_buf));
+    resolve_relative_to(www_path, exe_dir.as_deref())
+}
+
+fn resolve_relative_to(path: &Path, dir: Option<&Path>) -> PathBuf {
+    if path.is_absolute() || path.exists() {
+        return path.to_path_buf();
+    }
+    if let Some(dir) = dir {
+        let resolved = dir.join(path);
+        if resolved.exists() {
+            warn!(
+                "The www_path {:?} does not exist relative to the current working directory. Using {:?} relative to the executable instead.",
+                path, resolved
+            );
+            return resolved;
+        }
+    }
+    warn!(
+        "The www_path {:?} does not exist relative to the current working directory or the executable.",
+        path
+    );
+    path.to_path_buf()
+}
+
 fn parse_toml_network(
     toml_network: &TomlNetwork,
     nodes: Vec<BoxedSyncSendNode>,
 ) -> Result<Network, ConfigError> {
+    if let Some(theme) = &toml_network.theme {
+        theme.validate()?;
+    }
+    let max_initial_backfill = toml_network
+        .max_initial_backfill
+        .unwrap_or(DEFAULT_MAX_INITIAL_BACKFILL);
+    if max_initial_backfill == 0 {
+        return Err(ConfigError::ZeroMaxInitialBackfill);
+    }
+    let pool_identification = toml_network.pool_identification.clone().unwrap_or_default();
+    pool_identification.validate()?;
+    let watch_coinbase_addresses = parse_watch_addresses(
+        &toml_network.watch_coinbase_addresses,
+        pool_identification
+            .network
+            .as_ref()
+            .map(|network| network.to_network()),
+    )?;
+    if !watch_coinbase_addresses.is_empty() && !pool_identification.enable {
+        warn!(
+            "The watched coinbase addresses of network {} are only checked with pool identification enabled.",
+            toml_network.id
+        );
+    }
     Ok(Network {
         id: toml_network.id,
         name: toml_network.name.clone(),
         description: toml_network.description.clone(),
         min_fork_height: toml_network.min_fork_height,
         max_interesting_heights: toml_network.max_interesting_heights,
+        context_blocks: ContextBlocks {
+            before: toml_network
+                .context_blocks_before
+                .unwrap_or(DEFAULT_CONTEXT_BLOCKS_BEFORE),
+            after: toml_network
+                .context_blocks_after
+                .unwrap_or(DEFAULT_CONTEXT_BLOCKS_AFTER),
+        },
+        max_fork_children: toml_network
+            .max_fork_children
+            .unwrap_or(DEFAULT_MAX_FORK_CHILDREN),
+        max_initial_backfill,
+        sync_priority: toml_network.sync_priority,
+        retain_raw_tips: toml_network.retain_raw_tips,
+        tips_replay_length: toml_network.tips_replay_length,
+        verify_pow: toml_network.verify_pow,
+        height_offset: toml_network.height_offset,
+        track_propagation: toml_network.track_propagation,
+        track_chain_work: toml_network.track_chain_work,
+        watch_coinbase_addresses,
         nodes,
-        pool_identification: toml_network.pool_identification.clone().unwrap_or_default(),
+        pool_identification,
+        privacy: toml_network.privacy.clone().unwrap_or_default(),
+        feeds: toml_network.feeds.clone().unwrap_or_default(),
+        theme: toml_network.theme.clone(),
     })
 }
 
+// Parses the addresses and brings them into their canonical form, e.g.
+// lowercase bech32. If the network is known, the addresses must be for it.
+fn parse_watch_addresses(
+    addresses: &[String],
+    network: Option<BitcoinNetwork>,
+) -> Result<Vec<String>, ConfigError> {
+    addresses
+        .iter()
+        .map(|address| {
+            let invalid = || ConfigError::InvalidWatchAddress(address.clone());
+            let unchecked = Address::from_str(address.trim()).map_err(|_| invalid())?;
+            let checked = match network {
+                Some(network) => unchecked.require_network(network).map_err(|_| invalid())?,
+                None => unchecked.assume_checked(),
+            };
+            Ok(checked.to_string())
+        })
+        .collect()
+}
+
 fn parse_toml_node(toml_node: &TomlNode) -> Result<BoxedSyncSendNode, ConfigError> {
     let implementation = toml_node
         .implementation
@@ -286,16 +927,31 @@ fn parse_toml_node(toml_node: &TomlNode) -> Result<BoxedSyncSendNode, ConfigErro
     };
 
     let node: BoxedSyncSendNode = match implementation {
-        NodeImplementation::BitcoinCore => Arc::new(BitcoinCoreNode::new(
-            node_info,
-            format!("{}:{}", toml_node.rpc_host, toml_node.rpc_port),
-            parse_rpc_auth(toml_node)?,
-            toml_node.use_rest.unwrap_or(DEFAULT_USE_REST),
-        )),
+        NodeImplementation::BitcoinCore => {
+            if toml_node.websocket {
+                warn!(
+                    "The websocket option is only supported for btcd. Ignoring it for {}",
+                    toml_node
+                );
+            }
+            Arc::new(BitcoinCoreNode::new(
+                node_info,
+                format!("{}:{}", toml_node.rpc_host, toml_node.rpc_port),
+                parse_rpc_auth(toml_node)?,
+                toml_node.use_rest.unwrap_or(DEFAULT_USE_REST),
+                toml_node.zmq_pubhashblock.clone(),
+            ))
+        }
         NodeImplementation::Btcd => {
             if toml_node.rpc_user.is_none() || toml_node.rpc_password.is_none() {
                 return Err(ConfigError::NoBtcdRpcAuth);
             }
+            if toml_node.zmq_pubhashblock.is_some() {
+                warn!(
+                    "The zmq_pubhashblock option is only supported for Bitcoin Core. Ignoring it for {}",
+                    toml_node
+                );
+            }
 
             Arc::new(BtcdNode::new(
                 node_info,
@@ -305,6 +961,7 @@ fn parse_toml_node(toml_node: &TomlNode) -> Result<BoxedSyncSendNode, ConfigErro
                     .rpc_password
                     .clone()
                     .expect("a rpc_password for btcd"),
+                toml_node.websocket,
             ))
         }
     };
@@ -333,6 +990,69 @@ mod tests {
         assert_eq!(cfg.networks[0].pool_identification.enable, true);
     }
 
+    #[test]
+    fn parse_retention_test() {
+        assert_eq!(parse_retention("unlimited").unwrap(), None);
+        assert_eq!(
+            parse_retention("45s").unwrap(),
+            Some(Duration::from_secs(45))
+        );
+        assert_eq!(
+            parse_retention("30m").unwrap(),
+            Some(Duration::from_secs(30 * 60))
+        );
+        assert_eq!(
+            parse_retention("12h").unwrap(),
+            Some(Duration::from_secs(12 * 60 * 60))
+        );
+        assert_eq!(
+            parse_retention("90d").unwrap(),
+            Some(Duration::from_secs(90 * 24 * 60 * 60))
+        );
+        assert_eq!(
+            parse_retention("2w").unwrap(),
+            Some(Duration::from_secs(14 * 24 * 60 * 60))
+        );
+        for invalid in ["", "90", "d", "0d", "-1d", "1.5d", "90 d", "90y", "forever"] {
+            match parse_retention(invalid) {
+                Err(ConfigError::InvalidRetention(_)) => (),
+                other => panic!("expected an error for '{}', got {:?}", invalid, other),
+            }
+        }
+        match parse_retention("99999999999999999999w") {
+            Err(ConfigError::InvalidRetention(_)) => (),
+            other => panic!("expected an error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn parse_access_log_test() {
+        let toml_access_log = |enable: bool, trusted_proxies: &[&str]| TomlAccessLog {
+            enable,
+            trusted_proxies: trusted_proxies.iter().map(|p| p.to_string()).collect(),
+        };
+        // the trusted proxies are kept for the rate limit if the access log
+        // is disabled
+        assert_eq!(
+            parse_toml_access_log(&toml_access_log(false, &["127.0.0.1"])).unwrap(),
+            AccessLog {
+                enable: false,
+                trusted_proxies: vec![IpAddr::from([127, 0, 0, 1])],
+            }
+        );
+        assert_eq!(
+            parse_toml_access_log(&toml_access_log(true, &["127.0.0.1", "::1"])).unwrap(),
+            AccessLog {
+                enable: true,
+                trusted_proxies: vec![IpAddr::from([127, 0, 0, 1]), "::1".parse().unwrap()],
+            }
+        );
+        match parse_toml_access_log(&toml_access_log(true, &["127.0.0.0/8"])) {
+            Err(ConfigError::InvalidTrustedProxy(_)) => (),
+            other => panic!("expected an error, got {:?}", other),
+        }
+    }
+
     #[test]
     fn error_on_duplicate_node_id_test() {
         if let Err(ConfigError::DuplicateNodeId) = parse_config(
@@ -424,4 +1144,365 @@ mod tests {
             panic!("Test did not error!");
         }
     }
+
+    #[test]
+    fn pool_identification_testnet4_test() {
+        let cfg = parse_config(
+            r#"
+            database_path = ""
+            www_path = "./www"
+            query_interval = 15
+            address = "127.0.0.1:2323"
+            rss_base_url = ""
+            footer_html = ""
+
+            [[networks]]
+            id = 1
+            name = "Testnet4"
+            description = ""
+            min_fork_height = 0
+            max_interesting_heights = 0
+                [networks.pool_identification]
+                enable = true
+                network = "Testnet4"
+
+                [[networks.nodes]]
+                id = 0
+                name = "Node A"
+                description = ""
+                rpc_host = "127.0.0.1"
+                rpc_port = 0
+                rpc_user = ""
+                rpc_password = ""
+        "#,
+        )
+        .expect("config with a Testnet4 pool identification network should parse");
+
+        let network = cfg.networks[0]
+            .pool_identification
+            .network
+            .as_ref()
+            .expect("pool identification network should be set");
+        assert_eq!(network.to_network(), BitcoinNetwork::Testnet4);
+    }
+
+    const THEME_TEST_CONFIG: &str = r#"
+            database_path = ""
+            www_path = "./www"
+            query_interval = 15
+            address = "127.0.0.1:2323"
+            rss_base_url = ""
+            footer_html = ""
+
+            [[networks]]
+            id = 1
+            name = "Mainnet"
+            description = ""
+            min_fork_height = 0
+            max_interesting_heights = 0
+            theme = { color = "COLOR", icon = "https://example.com/btc.svg", chain = "mainnet" }
+
+                [[networks.nodes]]
+                id = 0
+                name = "Node A"
+                description = ""
+                rpc_host = "127.0.0.1"
+                rpc_port = 0
+                rpc_user = ""
+                rpc_password = ""
+        "#;
+
+    const REST_AUTH_TEST_CONFIG: &str = r#"
+            database_path = ""
+            www_path = "./www"
+            query_interval = 15
+            address = "127.0.0.1:2323"
+            rss_base_url = ""
+            footer_html = ""
+
+            [[networks]]
+            id = 1
+            name = ""
+            description = ""
+            min_fork_height = 0
+            max_interesting_heights = 0
+
+                [[networks.nodes]]
+                id = 0
+                name = "Node A"
+                description = ""
+                rpc_host = "127.0.0.1"
+                rpc_port = 0
+                USE_REST
+        "#;
+
+    #[test]
+    fn parse_watch_addresses_test() {
+        let addresses = vec![
+            "TB1QW508D6QEJXTDG4Y5R3ZARVARY0C5XW7KXPJZSX".to_string(),
+            " tb1qrp33g0q5c5txsp9arysrx4k6zdkfs4nce4xj0gdcccefvpysxf3q0sl5k7".to_string(),
+        ];
+        assert_eq!(
+            parse_watch_addresses(&addresses, Some(BitcoinNetwork::Signet)).unwrap(),
+            vec![
+                "tb1qw508d6qejxtdg4y5r3zarvary0c5xw7kxpjzsx",
+                "tb1qrp33g0q5c5txsp9arysrx4k6zdkfs4nce4xj0gdcccefvpysxf3q0sl5k7"
+            ]
+        );
+        assert_eq!(parse_watch_addresses(&addresses, None).unwrap().len(), 2);
+        // a testnet address on mainnet
+        match parse_watch_addresses(&addresses, Some(BitcoinNetwork::Bitcoin)) {
+            Err(ConfigError::InvalidWatchAddress(a)) => assert_eq!(a, addresses[0]),
+            other => panic!("expected an error, got {:?}", other),
+        }
+        match parse_watch_addresses(&["not an address".to_string()], None) {
+            Err(ConfigError::InvalidWatchAddress(_)) => (),
+            other => panic!("expected an error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn miner_aliases_test() {
+        let pool_identification: PoolIdentification = toml::from_str(
+            r#"
+            enable = true
+            miner_aliases = { "Foundry" = "Foundry USA", "AntPool" = "AntPool" }
+            "#,
+        )
+        .unwrap();
+        assert_eq!(
+            pool_identification.canonical_miner("Foundry".to_string()),
+            "Foundry USA"
+        );
+        assert_eq!(
+            pool_identification.canonical_miner("Foundry USA".to_string()),
+            "Foundry USA"
+        );
+        assert_eq!(
+            pool_identification.canonical_miner("AntPool".to_string()),
+            "AntPool"
+        );
+        // unknown names pass through unchanged
+        assert_eq!(
+            pool_identification.canonical_miner("ViaBTC".to_string()),
+            "ViaBTC"
+        );
+    }
+
+    #[test]
+    fn output_templates_test() {
+        let pool_identification: PoolIdentification = toml::from_str(
+            r#"
+            enable = true
+            output_templates = [{ script_pubkey_prefix = "0014751e", miner = "Solo Miner" }]
+            "#,
+        )
+        .unwrap();
+        assert_eq!(
+            pool_identification.output_templates,
+            vec![OutputTemplate {
+                script_pubkey_prefix: "0014751e".to_string(),
+                miner: "Solo Miner".to_string(),
+            }]
+        );
+        assert!(pool_identification.validate().is_ok());
+
+        for prefix in ["", "0014z", "001"] {
+            let pool_identification = PoolIdentification {
+                output_templates: vec![OutputTemplate {
+                    script_pubkey_prefix: prefix.to_string(),
+                    miner: "Solo Miner".to_string(),
+                }],
+                ..PoolIdentification::default()
+            };
+            match pool_identification.validate() {
+                Err(ConfigError::InvalidOutputTemplate(p)) => assert_eq!(p, prefix),
+                other => panic!("expected an error, got {:?}", other),
+            }
+        }
+    }
+
+    #[test]
+    fn rest_node_without_rpc_auth_test() {
+        assert!(
+            parse_config(&REST_AUTH_TEST_CONFIG.replace("USE_REST", "use_rest = true")).is_ok()
+        );
+        for use_rest in ["use_rest = false", ""] {
+            match parse_config(&REST_AUTH_TEST_CONFIG.replace("USE_REST", use_rest)) {
+                Err(ConfigError::NoBitcoinCoreRpcAuth) => (),
+                _ => panic!("Test did not error for '{}'!", use_rest),
+            }
+        }
+    }
+
+    #[test]
+    fn network_theme_test() {
+        let cfg = parse_config(&THEME_TEST_CONFIG.replace("COLOR", "#f7931a"))
+            .expect("config with a network theme should parse");
+        assert_eq!(
+            cfg.networks[0].theme,
+            Some(Theme {
+                color: Some("#f7931a".to_string()),
+                icon: Some("https://example.com/btc.svg".to_string()),
+                chain: Some("mainnet".to_string()),
+            })
+        );
+        assert!(parse_config(&THEME_TEST_CONFIG.replace("COLOR", "#FFF")).is_ok());
+    }
+
+    #[test]
+    fn network_type_fallback_test() {
+        use crate::types::NetworkJson;
+
+        let config = THEME_TEST_CONFIG.replace("COLOR", "#f7931a");
+        let cfg = parse_config(&config).expect("config should parse");
+        assert_eq!(
+            NetworkJson::new(&cfg.networks[0]).network_type,
+            Some("unknown".to_string())
+        );
+
+        let cfg = parse_config(&format!(
+            "{}\n[networks.pool_identification]\nenable = false\nnetwork = \"Signet\"\n",
+            config
+        ))
+        .expect("config with a pool identification network should parse");
+        assert_eq!(
+            NetworkJson::new(&cfg.networks[0]).network_type,
+            Some("signet".to_string())
+        );
+    }
+
+    #[test]
+    fn error_on_invalid_theme_color_test() {
+        for color in ["f7931a", "#f7931", "#f7931g", "orange", ""] {
+            match parse_config(&THEME_TEST_CONFIG.replace("COLOR", color)) {
+                Err(ConfigError::InvalidThemeColor(c)) => assert_eq!(c, color),
+                _ => panic!("Test did not error for color '{}'!", color),
+            }
+        }
+    }
+
+    #[test]
+    fn resolve_www_path_test() {
+        let exe_dir = env::temp_dir().join(format!("fork-observer-test-{}", std::process::id()));
+        let www = Path::new("fork-observer-test-www");
+        fs::create_dir_all(exe_dir.join(www)).unwrap();
+
+        // relative to the CWD
+        assert_eq!(
+            resolve_relative_to(Path::new("./www"), Some(&exe_dir)),
+            PathBuf::from("./www")
+        );
+        // relative to the executable
+        assert_eq!(resolve_relative_to(www, Some(&exe_dir)), exe_dir.join(www));
+        // absolute paths are kept as-is, even if they don't exist
+        let absolute = exe_dir.join("does-not-exist");
+        assert_eq!(resolve_relative_to(&absolute, Some(&exe_dir)), absolute);
+        // nothing found
+        let missing = Path::new("does-not-exist");
+        assert_eq!(
+            resolve_relative_to(missing, Some(&exe_dir)),
+            missing.to_path_buf()
+        );
+
+        fs::remove_dir_all(exe_dir).unwrap();
+    }
+
+    #[test]
+    fn ephemeral_test() {
+        let config = |options: &str| {
+            parse_config(
+                &THEME_TEST_CONFIG
+                    .replace("COLOR", "#f7931a")
+                    .replace(r#"database_path = """#, options),
+            )
+            .unwrap()
+        };
+        assert!(!config(r#"database_path = "db""#).ephemeral);
+        assert!(config("database_path = \"db\"\nephemeral = true").ephemeral);
+        assert!(config(r#"database_path = ":memory:""#).ephemeral);
+    }
+
+    #[test]
+    fn cors_allowed_origins_test() {
+        let config = |origins: &str| {
+            parse_config(&THEME_TEST_CONFIG.replace("COLOR", "#f7931a").replace(
+                "footer_html = \"\"",
+                &format!("footer_html = \"\"\ncors_allowed_origins = {}", origins),
+            ))
+        };
+        assert!(config("[]").unwrap().cors_allowed_origins.is_empty());
+        assert_eq!(
+            config(r#"["https://app.example.com", "http://localhost:8080", "*"]"#)
+                .unwrap()
+                .cors_allowed_origins,
+            vec!["https://app.example.com", "http://localhost:8080", "*"]
+        );
+        for origin in [
+            "app.example.com",
+            "https://app.example.com/",
+            "https://",
+            "https://user@app.example.com",
+            "https://app.example.com:port",
+        ] {
+            match config(&format!("[\"{}\"]", origin)) {
+                Err(ConfigError::InvalidCorsOrigin(o)) => assert_eq!(o, origin),
+                _ => panic!("Test did not error for origin '{}'!", origin),
+            }
+        }
+    }
+
+    #[test]
+    fn admin_token_test() {
+        let config = |token: &str| {
+            parse_config(&THEME_TEST_CONFIG.replace("COLOR", "#f7931a").replace(
+                "footer_html = \"\"",
+                &format!("footer_html = \"\"\nadmin_token = \"{}\"", token),
+            ))
+        };
+        assert_eq!(
+            parse_config(&THEME_TEST_CONFIG.replace("COLOR", "#f7931a"))
+                .unwrap()
+                .admin_token,
+            None
+        );
+        assert_eq!(
+            config("s3cret").unwrap().admin_token,
+            Some("s3cret".to_string())
+        );
+        match config(" ") {
+            Err(ConfigError::EmptyAdminToken) => (),
+            _ => panic!("Test did not error for an empty admin_token!"),
+        }
+    }
+
+    #[test]
+    fn startup_summary_test() {
+        let cfg = parse_config(
+            &THEME_TEST_CONFIG
+                .replace("COLOR", "#f7931a")
+                .replace(r#"rpc_password = """#, r#"rpc_password = "hunter2""#)
+                .replace(
+                    "theme =",
+                    "feeds = { lagging = false, stalled = false }\n            theme =",
+                ),
+        )
+        .unwrap();
+        assert!(cfg.log_startup_summary);
+        let summary = cfg.summary();
+        let lines: Vec<&str> = summary.lines().collect();
+        assert_eq!(lines.len(), 3);
+        assert!(lines[0].contains("address=127.0.0.1:2323"));
+        assert!(lines[0].contains("query_interval=15s"));
+        assert!(lines[0].contains("rate_limit_per_minute=unlimited"));
+        assert_eq!(
+            lines[1],
+            "  Network(id=1, name='Mainnet', nodes=1, pool_identification=false, feeds=[forks, invalid, unreachable, alerts, blocks])"
+        );
+        assert_eq!(
+            lines[2],
+            "    Node(id=0, name='Node A', implementation='Bitcoin Core')"
+        );
+        assert!(!summary.contains("hunter2"));
+    }
 }
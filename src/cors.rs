@@ -0,0 +1,96 @@
+use warp::filters::BoxedFilter;
+use warp::reply::Response;
+use warp::{Filter, Reply};
+
+fn into_response<R: Reply>(reply: R) -> Response {
+    reply.into_response()
+}
+
+/// Adds CORS headers to the responses of the routes for requests from the
+/// allowed origins and answers their preflight requests. Requests from other
+/// origins are rejected with a 403. "*" allows any origin. Without allowed
+/// origins, no CORS headers are sent.
+pub fn wrap(
+    routes: BoxedFilter<(Response,)>,
+    allowed_origins: &[String],
+) -> BoxedFilter<(Response,)> {
+    if allowed_origins.is_empty() {
+        return routes;
+    }
+    let cors = if allowed_origins.iter().any(|origin| origin == "*") {
+        warp::cors().allow_any_origin()
+    } else {
+        warp::cors().allow_origins(allowed_origins.iter().map(|origin| origin.as_str()))
+    };
+    routes
+        .with(
+            cors.allow_method("GET")
+                .allow_header("if-none-match")
+                .expose_header("etag"),
+        )
+        .map(into_response)
+        .boxed()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use warp::http::StatusCode;
+
+    fn routes(allowed_origins: &[&str]) -> BoxedFilter<(Response,)> {
+        let allowed_origins: Vec<String> = allowed_origins.iter().map(|o| o.to_string()).collect();
+        wrap(
+            warp::path!("data.json")
+                .map(|| warp::reply::json(&vec![1]).into_response())
+                .boxed(),
+            &allowed_origins,
+        )
+    }
+
+    #[tokio::test]
+    async fn test_wrap() {
+        let request = |origin: &str| {
+            warp::test::request()
+                .path("/data.json")
+                .header("origin", origin)
+        };
+
+        let cors = routes(&["https://app.example.com"]);
+        let response = request("https://app.example.com").reply(&cors).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(
+            response.headers()["access-control-allow-origin"],
+            "https://app.example.com"
+        );
+        let response = request("https://evil.example.com").reply(&cors).await;
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+        // requests without an origin aren't CORS requests
+        let response = warp::test::request().path("/data.json").reply(&cors).await;
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let preflight = request("https://app.example.com")
+            .method("OPTIONS")
+            .header("access-control-request-method", "GET")
+            .reply(&cors)
+            .await;
+        assert_eq!(preflight.status(), StatusCode::OK);
+        assert!(preflight.headers()["access-control-allow-methods"]
+            .to_str()
+            .unwrap()
+            .contains("GET"));
+
+        let any = routes(&["*"]);
+        let response = request("https://evil.example.com").reply(&any).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert!(response
+            .headers()
+            .contains_key("access-control-allow-origin"));
+
+        let disabled = routes(&[]);
+        let response = request("https://app.example.com").reply(&disabled).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert!(!response
+            .headers()
+            .contains_key("access-control-allow-origin"));
+    }
+}
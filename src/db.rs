@@ -1,4 +1,5 @@
 use std::collections::HashMap;
+use std::str::FromStr;
 
 use petgraph::graph::DiGraph;
 use petgraph::graph::NodeIndex;
@@ -7,9 +8,10 @@ use bitcoincore_rpc::bitcoin;
 use bitcoincore_rpc::bitcoin::BlockHash;
 
 use log::{debug, info, warn};
+use rusqlite::Connection;
 
 use crate::error::DbError;
-use crate::types::{Db, HeaderInfo, TreeInfo};
+use crate::types::{Alert, AlertKind, Db, Fork, ForkHistoryJson, HeaderInfo, TreeInfo};
 
 const SELECT_STMT_HEADER_HEIGHT: &str = "
 SELECT
@@ -23,6 +25,35 @@ ORDER BY
     ASC
 ";
 
+const SELECT_STMT_HEADER_BY_HASH: &str = "
+SELECT
+    height, header, miner
+FROM
+    headers
+WHERE
+    network = ?1
+    AND hash = ?2
+";
+
+const SELECT_STMT_HEADERS_BY_HEIGHT: &str = "
+SELECT
+    height, header, miner
+FROM
+    headers
+WHERE
+    network = ?1
+    AND height = ?2
+";
+
+const SELECT_STMT_COUNT_HEADERS: &str = "
+SELECT
+    COUNT(*)
+FROM
+    headers
+WHERE
+    network = ?1
+";
+
 const CREATE_STMT_TABLE_HEADERS: &str = "
 CREATE TABLE IF NOT EXISTS headers (
     height     INT,
@@ -34,6 +65,122 @@ CREATE TABLE IF NOT EXISTS headers (
 )
 ";
 
+const CREATE_STMT_TABLE_ALERTS: &str = "
+CREATE TABLE IF NOT EXISTS alerts (
+    network    INT,
+    timestamp  INT,
+    kind       TEXT,
+    tips       TEXT
+)
+";
+
+const CREATE_STMT_TABLE_WATCHED_PAYOUTS: &str = "
+CREATE TABLE IF NOT EXISTS watched_payouts (
+    network    INT,
+    hash       TEXT,
+    address    TEXT,
+    PRIMARY KEY (network, hash, address)
+)
+";
+
+const SELECT_STMT_WATCHED_PAYOUTS: &str = "
+SELECT
+    address
+FROM
+    watched_payouts
+WHERE
+    network = ?1
+    AND hash = ?2
+ORDER BY
+    address
+";
+
+const CREATE_STMT_TABLE_MINER_IDENTIFICATIONS: &str = "
+CREATE TABLE IF NOT EXISTS miner_identifications (
+    network    INT,
+    hash       TEXT,
+    identifier TEXT,
+    PRIMARY KEY (network, hash)
+)
+";
+
+const SELECT_STMT_MINER_IDENTIFICATION: &str = "
+SELECT
+    identifier
+FROM
+    miner_identifications
+WHERE
+    network = ?1
+    AND hash = ?2
+";
+
+const CREATE_STMT_TABLE_FORKS: &str = "
+CREATE TABLE IF NOT EXISTS forks (
+    network       INT,
+    common_hash   TEXT,
+    common_height INT,
+    children      TEXT,
+    first_seen    INT,
+    PRIMARY KEY (network, common_hash)
+)
+";
+
+// Known forks keep their first_seen timestamp. Their children are updated as
+// a fork point can gain more children.
+const UPSERT_STMT_FORK: &str = "
+INSERT INTO
+    forks (network, common_hash, common_height, children, first_seen)
+    values (?1, ?2, ?3, ?4, ?5)
+ON CONFLICT (network, common_hash) DO UPDATE SET
+    children = excluded.children
+";
+
+const SELECT_STMT_FORKS: &str = "
+SELECT
+    common_hash, common_height, children, first_seen
+FROM
+    forks
+WHERE
+    network = ?1
+ORDER BY
+    common_height DESC,
+    common_hash ASC
+LIMIT ?2
+OFFSET ?3
+";
+
+const SELECT_STMT_COUNT_FORKS: &str = "
+SELECT
+    COUNT(*)
+FROM
+    forks
+WHERE
+    network = ?1
+";
+
+const SELECT_STMT_ALERTS: &str = "
+SELECT
+    timestamp, kind, tips
+FROM
+    alerts
+WHERE
+    network = ?1
+ORDER BY
+    rowid
+    DESC
+LIMIT ?2
+OFFSET ?3
+";
+
+const SELECT_STMT_COUNT_ALERTS: &str = "
+SELECT
+    COUNT(*)
+FROM
+    alerts
+WHERE
+    network = ?1
+";
+
 const UPDATE_STMT_HEADER_MINER: &str = "
 UPDATE
     headers
@@ -43,16 +190,23 @@ WHERE
     hash = ?2;
 ";
 
+// Rows deleted per transaction when pruning. Keeps the database lock short.
+const PRUNE_BATCH_SIZE: usize = 1000;
+
 pub async fn setup_db(db: Db) -> Result<(), DbError> {
     db.lock().await.execute(CREATE_STMT_TABLE_HEADERS, [])?;
+    db.lock().await.execute(CREATE_STMT_TABLE_ALERTS, [])?;
+    db.lock()
+        .await
+        .execute(CREATE_STMT_TABLE_WATCHED_PAYOUTS, [])?;
+    db.lock()
+        .await
+        .execute(CREATE_STMT_TABLE_MINER_IDENTIFICATIONS, [])?;
+    db.lock().await.execute(CREATE_STMT_TABLE_FORKS, [])?;
     Ok(())
 }
 
-pub async fn write_to_db(
-    new_headers: &Vec<HeaderInfo>,
-    db: Db,
-    network: u32,
-) -> Result<(), DbError> {
+pub async fn write_to_db(new_headers: &[HeaderInfo], db: Db, network: u32) -> Result<(), DbError> {
     let mut db_locked = db.lock().await;
     let tx = db_locked.transaction()?;
     debug!(
@@ -92,50 +246,340 @@ pub async fn update_miner(db: Db, hash: &BlockHash, miner: String) -> Result<(),
     Ok(())
 }
 
+// Records the watched addresses the coinbase of a block pays to.
+pub async fn write_watched_payouts(
+    db: Db,
+    network: u32,
+    hash: &BlockHash,
+    addresses: &[String],
+) -> Result<(), DbError> {
+    let mut db_locked = db.lock().await;
+    let tx = db_locked.transaction()?;
+    for address in addresses {
+        tx.execute(
+            "INSERT OR IGNORE INTO watched_payouts (network, hash, address) values (?1, ?2, ?3)",
+            [&network.to_string(), &hash.to_string(), address],
+        )?;
+    }
+    tx.commit()?;
+    Ok(())
+}
+
+pub async fn watched_payouts(
+    db: Db,
+    network: u32,
+    hash: &BlockHash,
+) -> Result<Vec<String>, DbError> {
+    let db_locked = db.lock().await;
+    let mut stmt = db_locked.prepare(SELECT_STMT_WATCHED_PAYOUTS)?;
+    let mut addresses: Vec<String> = vec![];
+    let mut rows = stmt.query([network.to_string(), hash.to_string()])?;
+    while let Some(row) = rows.next()? {
+        addresses.push(row.get(0)?);
+    }
+    Ok(addresses)
+}
+
+// Records the identifier that identified the miner of a block.
+pub async fn write_miner_identification(
+    db: Db,
+    network: u32,
+    hash: &BlockHash,
+    identifier: &str,
+) -> Result<(), DbError> {
+    db.lock().await.execute(
+        "INSERT OR REPLACE INTO miner_identifications (network, hash, identifier) values (?1, ?2, ?3)",
+        [&network.to_string(), &hash.to_string(), identifier],
+    )?;
+    Ok(())
+}
+
+pub async fn miner_identification(
+    db: Db,
+    network: u32,
+    hash: &BlockHash,
+) -> Result<Option<String>, DbError> {
+    let db_locked = db.lock().await;
+    let mut stmt = db_locked.prepare(SELECT_STMT_MINER_IDENTIFICATION)?;
+    let mut rows = stmt.query([network.to_string(), hash.to_string()])?;
+    match rows.next()? {
+        Some(row) => Ok(Some(row.get(0)?)),
+        None => Ok(None),
+    }
+}
+
+pub async fn write_alert(alert: &Alert, db: Db) -> Result<(), DbError> {
+    let db_locked = db.lock().await;
+    db_locked.execute(
+        "INSERT INTO alerts (network, timestamp, kind, tips) values (?1, ?2, ?3, ?4)",
+        [
+            &alert.network_id.to_string(),
+            &alert.timestamp.to_string(),
+            &alert.kind.to_string(),
+            &serde_json::to_string(&alert.tips)?,
+        ],
+    )?;
+    Ok(())
+}
+
+// Loads the most recent alerts of a network. Oldest alert first.
+pub async fn load_alerts(db: Db, network: u32, limit: usize) -> Result<Vec<Alert>, DbError> {
+    let db_locked = db.lock().await;
+    let mut alerts = query_alerts(&db_locked, network, limit, 0)?;
+    alerts.reverse();
+    Ok(alerts)
+}
+
+// Loads a page of the alerts of a network. Most recent alert first.
+pub async fn load_alert_history(
+    db: Db,
+    network: u32,
+    page: usize,
+    page_size: usize,
+) -> Result<Vec<Alert>, DbError> {
+    let db_locked = db.lock().await;
+    query_alerts(
+        &db_locked,
+        network,
+        page_size,
+        page.saturating_mul(page_size),
+    )
+}
+
+pub async fn count_alerts(db: Db, network: u32) -> Result<u64, DbError> {
+    let db_locked = db.lock().await;
+    let count = db_locked.query_row(SELECT_STMT_COUNT_ALERTS, [network.to_string()], |row| {
+        row.get(0)
+    })?;
+    Ok(count)
+}
+
+// Most recent alert first.
+fn query_alerts(
+    db: &Connection,
+    network: u32,
+    limit: usize,
+    offset: usize,
+) -> Result<Vec<Alert>, DbError> {
+    let mut stmt = db.prepare(SELECT_STMT_ALERTS)?;
+    let mut alerts: Vec<Alert> = vec![];
+    let mut rows = stmt.query([network.to_string(), limit.to_string(), offset.to_string()])?;
+    while let Some(row) = rows.next()? {
+        let kind: String = row.get(1)?;
+        let tips: String = row.get(2)?;
+        alerts.push(Alert {
+            network_id: network,
+            timestamp: row.get(0)?,
+            kind: AlertKind::from_str(&kind).map_err(DbError::Data)?,
+            tips: serde_json::from_str(&tips)?,
+        });
+    }
+    Ok(alerts)
+}
+
+// Records the forks of a network. Forks that were recorded before keep their
+// first_seen timestamp.
+pub async fn write_forks(
+    db: Db,
+    network: u32,
+    forks: &[Fork],
+    first_seen: u64,
+) -> Result<(), DbError> {
+    let mut db_locked = db.lock().await;
+    let tx = db_locked.transaction()?;
+    for fork in forks {
+        let children: Vec<String> = fork
+            .children
+            .iter()
+            .map(|child| child.header.block_hash().to_string())
+            .collect();
+        tx.execute(
+            UPSERT_STMT_FORK,
+            [
+                &network.to_string(),
+                &fork.common.header.block_hash().to_string(),
+                &fork.common.height.to_string(),
+                &serde_json::to_string(&children)?,
+                &first_seen.to_string(),
+            ],
+        )?;
+    }
+    tx.commit()?;
+    Ok(())
+}
+
+// Loads a page of the recorded forks of a network. Highest fork first.
+pub async fn load_forks(
+    db: Db,
+    network: u32,
+    page: usize,
+    page_size: usize,
+) -> Result<Vec<ForkHistoryJson>, DbError> {
+    let db_locked = db.lock().await;
+    let mut stmt = db_locked.prepare(SELECT_STMT_FORKS)?;
+    let mut forks: Vec<ForkHistoryJson> = vec![];
+    let mut rows = stmt.query([
+        network.to_string(),
+        page_size.to_string(),
+        page.saturating_mul(page_size).to_string(),
+    ])?;
+    while let Some(row) = rows.next()? {
+        let children: String = row.get(2)?;
+        forks.push(ForkHistoryJson {
+            common_hash: row.get(0)?,
+            common_height: row.get(1)?,
+            children: serde_json::from_str(&children)?,
+            first_seen: row.get(3)?,
+        });
+    }
+    Ok(forks)
+}
+
+pub async fn count_forks(db: Db, network: u32) -> Result<u64, DbError> {
+    let db_locked = db.lock().await;
+    let count = db_locked.query_row(SELECT_STMT_COUNT_FORKS, [network.to_string()], |row| {
+        row.get(0)
+    })?;
+    Ok(count)
+}
+
+// Deletes the rows of a table with a timestamp column that are older than the
+// cutoff UNIX timestamp. Rows are deleted in small transactions. Returns the
+// number of deleted rows.
+pub async fn prune(db: Db, table: &str, cutoff: u64) -> Result<usize, DbError> {
+    let stmt = format!(
+        "DELETE FROM {table} WHERE rowid IN (SELECT rowid FROM {table} WHERE timestamp < ?1 LIMIT ?2)",
+        table = table
+    );
+    let mut deleted: usize = 0;
+    loop {
+        let mut db_locked = db.lock().await;
+        let tx = db_locked.transaction()?;
+        let batch = tx.execute(&stmt, [cutoff.to_string(), PRUNE_BATCH_SIZE.to_string()])?;
+        tx.commit()?;
+        deleted += batch;
+        if batch < PRUNE_BATCH_SIZE {
+            return Ok(deleted);
+        }
+    }
+}
+
 // Loads header and tip information for a specified network from the DB and
 // builds a header-tree from it.
 pub async fn load_treeinfos(db: Db, network: u32) -> Result<TreeInfo, DbError> {
     let header_infos = load_header_infos(db, network).await?;
-
-    let mut tree: DiGraph<HeaderInfo, bool> = DiGraph::new();
-    let mut hash_index_map: HashMap<BlockHash, NodeIndex> = HashMap::new();
     info!("building header tree for network {}..", network);
-    // add headers as nodes
-    for h in header_infos.clone() {
-        let idx = tree.add_node(h.clone());
-        hash_index_map.insert(h.header.block_hash(), idx);
-    }
-    info!(".. added headers from network {}", network);
-    // add prev-current block relationships as edges
-    for current in header_infos {
-        let idx_current = hash_index_map
-            .get(&current.header.block_hash())
-            .expect("current header should be in the map as we just inserted it");
-        match hash_index_map.get(&current.header.prev_blockhash) {
-            Some(idx_prev) => tree.update_edge(*idx_prev, *idx_current, false),
-            None => continue,
-        };
-    }
-    info!(
-        ".. added relationships between headers from network {}",
-        network
-    );
-    let root_nodes = tree.externals(petgraph::Direction::Incoming).count();
+    let (tree, hash_index_map) = build_tree(header_infos);
+    let roots = tree_roots(&tree);
     info!(
         "done building header tree for network {}: roots={}, tips={}",
         network,
-        root_nodes,                                            // root nodes
-        tree.externals(petgraph::Direction::Outgoing).count(), // tip nodes
+        roots.len(),
+        tree.externals(petgraph::Direction::Outgoing).count(),
     );
-    if root_nodes > 1 {
+    if roots.len() > 1 {
+        // Happens when the observation started mid-history from nodes at
+        // different heights and the headers between them were never loaded.
+        let roots: Vec<String> = roots
+            .iter()
+            .map(|idx| {
+                format!(
+                    "{} (height {}, missing parent {})",
+                    tree[*idx].header.block_hash(),
+                    tree[*idx].height,
+                    tree[*idx].header.prev_blockhash
+                )
+            })
+            .collect();
         warn!(
-            "header-tree for network {} has more than one ({}) root!",
-            network, root_nodes
+            "header-tree for network {} has more than one ({}) root: {}",
+            network,
+            roots.len(),
+            roots.join(", ")
         );
     }
     Ok((tree, hash_index_map))
 }
 
+// Builds the header tree. The headers don't need to be ordered: each header
+// is connected to its parent if the parent is among the headers, so roots
+// only remain for headers with an unknown parent.
+fn build_tree(header_infos: Vec<HeaderInfo>) -> TreeInfo {
+    let mut tree: DiGraph<HeaderInfo, bool> = DiGraph::new();
+    let mut hash_index_map: HashMap<BlockHash, NodeIndex> = HashMap::new();
+    for h in header_infos {
+        let hash = h.header.block_hash();
+        if hash_index_map.contains_key(&hash) {
+            continue;
+        }
+        let idx = tree.add_node(h);
+        hash_index_map.insert(hash, idx);
+    }
+    for idx_current in tree.node_indices().collect::<Vec<_>>() {
+        let prev = tree[idx_current].header.prev_blockhash;
+        if let Some(idx_prev) = hash_index_map.get(&prev) {
+            tree.update_edge(*idx_prev, idx_current, false);
+        }
+    }
+    (tree, hash_index_map)
+}
+
+// The headers without a parent in the tree, lowest first.
+fn tree_roots(tree: &DiGraph<HeaderInfo, bool>) -> Vec<NodeIndex> {
+    let mut roots: Vec<NodeIndex> = tree.externals(petgraph::Direction::Incoming).collect();
+    roots.sort_by_key(|idx| tree[*idx].height);
+    roots
+}
+
+pub async fn header_info_by_hash(
+    db: Db,
+    network: u32,
+    hash: &BlockHash,
+) -> Result<Option<HeaderInfo>, DbError> {
+    let db_locked = db.lock().await;
+    let mut stmt = db_locked.prepare(SELECT_STMT_HEADER_BY_HASH)?;
+    let mut rows = stmt.query([network.to_string(), hash.to_string()])?;
+    match rows.next()? {
+        Some(row) => Ok(Some(header_info_from_row(row)?)),
+        None => Ok(None),
+    }
+}
+
+pub async fn header_infos_by_height(
+    db: Db,
+    network: u32,
+    height: u64,
+) -> Result<Vec<HeaderInfo>, DbError> {
+    let db_locked = db.lock().await;
+    let mut stmt = db_locked.prepare(SELECT_STMT_HEADERS_BY_HEIGHT)?;
+    let mut headers: Vec<HeaderInfo> = vec![];
+    let mut rows = stmt.query([network.to_string(), height.to_string()])?;
+    while let Some(row) = rows.next()? {
+        headers.push(header_info_from_row(row)?);
+    }
+    Ok(headers)
+}
+
+pub async fn count_headers(db: Db, network: u32) -> Result<u64, DbError> {
+    let db_locked = db.lock().await;
+    let count = db_locked.query_row(SELECT_STMT_COUNT_HEADERS, [network.to_string()], |row| {
+        row.get(0)
+    })?;
+    Ok(count)
+}
+
+// Expects the row to contain the height, header and miner columns.
+fn header_info_from_row(row: &rusqlite::Row) -> Result<HeaderInfo, DbError> {
+    let header_hex: String = row.get(1)?;
+    let header_bytes = hex::decode(&header_hex)?;
+    let header = bitcoin::consensus::deserialize(&header_bytes)?;
+    Ok(HeaderInfo {
+        height: row.get(0)?,
+        header,
+        miner: row.get(2)?,
+    })
+}
+
 async fn load_header_infos(db: Db, network: u32) -> Result<Vec<HeaderInfo>, DbError> {
     info!("loading headers for network {} from database..", network);
     let db_locked = db.lock().await;
@@ -146,14 +590,7 @@ async fn load_header_infos(db: Db, network: u32) -> Result<Vec<HeaderInfo>, DbEr
 
     let mut rows = stmt.query([network.to_string()])?;
     while let Some(row) = rows.next()? {
-        let header_hex: String = row.get(1)?;
-        let header_bytes = hex::decode(&header_hex)?;
-        let header = bitcoin::consensus::deserialize(&header_bytes)?;
-        headers.push(HeaderInfo {
-            height: row.get(0)?,
-            header,
-            miner: row.get(2)?,
-        });
+        headers.push(header_info_from_row(row)?);
     }
 
     info!(
@@ -164,3 +601,191 @@ async fn load_header_infos(db: Db, network: u32) -> Result<Vec<HeaderInfo>, DbEr
 
     Ok(headers)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::ConsensusTipJson;
+    use rusqlite::Connection;
+    use std::sync::Arc;
+    use tokio::sync::Mutex;
+
+    #[tokio::test]
+    async fn test_prune_alerts() {
+        let db: Db = Arc::new(Mutex::new(Connection::open_in_memory().unwrap()));
+        setup_db(db.clone()).await.unwrap();
+        let alert = |network_id: u32, timestamp: u64| Alert {
+            network_id,
+            timestamp,
+            kind: AlertKind::ChainSplit,
+            tips: Vec::<ConsensusTipJson>::new(),
+        };
+        // more rows than fit into a single batch
+        for timestamp in 0..(PRUNE_BATCH_SIZE as u64 * 2 + 10) {
+            write_alert(&alert(timestamp as u32 % 2, timestamp), db.clone())
+                .await
+                .unwrap();
+        }
+
+        // rows with a timestamp equal to the cutoff are kept
+        assert_eq!(prune(db.clone(), "alerts", 1500).await.unwrap(), 1500);
+        let kept = load_alerts(db.clone(), 0, 10_000).await.unwrap();
+        assert_eq!(kept.first().unwrap().timestamp, 1500);
+        assert!(kept.iter().all(|a| a.timestamp >= 1500));
+        let kept = load_alerts(db.clone(), 1, 10_000).await.unwrap();
+        assert_eq!(kept.first().unwrap().timestamp, 1501);
+
+        // nothing older than the cutoff is left
+        assert_eq!(prune(db.clone(), "alerts", 1500).await.unwrap(), 0);
+        assert_eq!(prune(db.clone(), "alerts", 1501).await.unwrap(), 1);
+        assert_eq!(
+            prune(db.clone(), "alerts", u64::MAX / 2).await.unwrap(),
+            PRUNE_BATCH_SIZE * 2 + 10 - 1501
+        );
+        assert!(load_alerts(db.clone(), 0, 10_000).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_count_headers() {
+        use crate::test_utils;
+        use bitcoincore_rpc::bitcoin::hashes::Hash;
+
+        let db: Db = Arc::new(Mutex::new(Connection::open_in_memory().unwrap()));
+        setup_db(db.clone()).await.unwrap();
+        assert_eq!(count_headers(db.clone(), 0).await.unwrap(), 0);
+
+        let headers = test_utils::chain(BlockHash::all_zeros(), 0, 5, 0);
+        write_to_db(&headers, db.clone(), 0).await.unwrap();
+        write_to_db(&headers[..2], db.clone(), 1).await.unwrap();
+        // duplicates are ignored
+        write_to_db(&headers, db.clone(), 0).await.unwrap();
+        assert_eq!(count_headers(db.clone(), 0).await.unwrap(), 5);
+        assert_eq!(count_headers(db.clone(), 1).await.unwrap(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_load_treeinfos_roots() {
+        use crate::test_utils;
+        use bitcoincore_rpc::bitcoin::hashes::Hash;
+
+        let db: Db = Arc::new(Mutex::new(Connection::open_in_memory().unwrap()));
+        setup_db(db.clone()).await.unwrap();
+
+        // Two nodes started to be observed at different heights. Their
+        // headers were written in separate batches, the later-starting node
+        // first, and overlap, so the tree collapses to one root.
+        let chain = test_utils::chain(BlockHash::all_zeros(), 0, 10, 0);
+        let fork = test_utils::chain(chain[6].header.block_hash(), 7, 2, 1000);
+        let mut later = chain[5..].to_vec();
+        later.extend(fork.clone());
+        write_to_db(&later, db.clone(), 0).await.unwrap();
+        write_to_db(&chain[..7], db.clone(), 0).await.unwrap();
+        let (tree, hash_index_map) = load_treeinfos(db.clone(), 0).await.unwrap();
+        assert_eq!(tree.node_count(), 12);
+        assert_eq!(hash_index_map.len(), 12);
+        let roots = tree_roots(&tree);
+        assert_eq!(roots.len(), 1);
+        assert_eq!(tree[roots[0]].height, 0);
+        assert_eq!(tree.externals(petgraph::Direction::Outgoing).count(), 2);
+
+        // Without the headers in between, the roots can't be connected.
+        write_to_db(&chain[..3], db.clone(), 1).await.unwrap();
+        write_to_db(&chain[6..], db.clone(), 1).await.unwrap();
+        let (tree, _) = load_treeinfos(db.clone(), 1).await.unwrap();
+        let heights: Vec<u64> = tree_roots(&tree)
+            .iter()
+            .map(|idx| tree[*idx].height)
+            .collect();
+        assert_eq!(heights, vec![0, 6]);
+
+        // The order of the headers doesn't matter.
+        let mut reversed = chain.clone();
+        reversed.reverse();
+        let (tree, _) = build_tree(reversed);
+        assert_eq!(tree_roots(&tree).len(), 1);
+        assert_eq!(tree.edge_count(), 9);
+    }
+
+    #[tokio::test]
+    async fn test_forks() {
+        use crate::test_utils;
+        use bitcoincore_rpc::bitcoin::hashes::Hash;
+
+        let db: Db = Arc::new(Mutex::new(Connection::open_in_memory().unwrap()));
+        setup_db(db.clone()).await.unwrap();
+        let chain = test_utils::chain(BlockHash::all_zeros(), 0, 3, 0);
+        let fork = |common: &HeaderInfo, nonces: &[u32]| Fork {
+            common: common.clone(),
+            children: nonces
+                .iter()
+                .map(|nonce| {
+                    test_utils::header_info(common.header.block_hash(), common.height + 1, *nonce)
+                })
+                .collect(),
+            truncated_children: 0,
+        };
+
+        write_forks(db.clone(), 0, &[fork(&chain[0], &[1, 2])], 1000)
+            .await
+            .unwrap();
+        // Repeated writes don't duplicate the fork, keep its first_seen
+        // timestamp and update its children.
+        let forks = vec![fork(&chain[2], &[1, 2]), fork(&chain[0], &[1, 2, 3])];
+        write_forks(db.clone(), 0, &forks, 2000).await.unwrap();
+        write_forks(db.clone(), 0, &forks, 3000).await.unwrap();
+        write_forks(db.clone(), 1, &forks[..1], 4000).await.unwrap();
+        assert_eq!(count_forks(db.clone(), 0).await.unwrap(), 2);
+        assert_eq!(count_forks(db.clone(), 1).await.unwrap(), 1);
+
+        let loaded = load_forks(db.clone(), 0, 0, 10).await.unwrap();
+        assert_eq!(loaded.len(), 2);
+        assert_eq!(loaded[0].common_height, 2);
+        assert_eq!(loaded[0].first_seen, 2000);
+        assert_eq!(
+            loaded[1].common_hash,
+            chain[0].header.block_hash().to_string()
+        );
+        assert_eq!(loaded[1].first_seen, 1000);
+        assert_eq!(
+            loaded[1].children,
+            forks[1]
+                .children
+                .iter()
+                .map(|child| child.header.block_hash().to_string())
+                .collect::<Vec<String>>()
+        );
+
+        // paging
+        assert_eq!(load_forks(db.clone(), 0, 1, 1).await.unwrap(), loaded[1..]);
+        assert!(load_forks(db.clone(), 0, 2, 1).await.unwrap().is_empty());
+        assert!(load_forks(db.clone(), 2, 0, 10).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_miner_identification() {
+        use bitcoincore_rpc::bitcoin::hashes::Hash;
+
+        let db: Db = Arc::new(Mutex::new(Connection::open_in_memory().unwrap()));
+        setup_db(db.clone()).await.unwrap();
+        let hash = BlockHash::all_zeros();
+        assert_eq!(
+            miner_identification(db.clone(), 0, &hash).await.unwrap(),
+            None
+        );
+
+        write_miner_identification(db.clone(), 0, &hash, "tag")
+            .await
+            .unwrap();
+        write_miner_identification(db.clone(), 0, &hash, "template")
+            .await
+            .unwrap();
+        assert_eq!(
+            miner_identification(db.clone(), 0, &hash).await.unwrap(),
+            Some("template".to_string())
+        );
+        assert_eq!(
+            miner_identification(db.clone(), 1, &hash).await.unwrap(),
+            None
+        );
+    }
+}
@@ -4,6 +4,7 @@ use std::{error, io};
 
 use bitcoincore_rpc::bitcoin;
 use bitcoincore_rpc::bitcoin::hashes::hex::parse::HexToArrayError;
+use tokio_tungstenite::tungstenite;
 
 #[derive(Debug)]
 pub enum FetchError {
@@ -64,6 +65,8 @@ pub enum DbError {
     Rusqlite(rusqlite::Error),
     DecodeHex(hex::FromHexError),
     BitcoinDeserialize(bitcoin::consensus::encode::Error),
+    Json(serde_json::Error),
+    Data(String),
 }
 
 impl fmt::Display for DbError {
@@ -72,6 +75,8 @@ impl fmt::Display for DbError {
             DbError::DecodeHex(e) => write!(f, "hex decoding error: {:?}", e),
             DbError::BitcoinDeserialize(e) => write!(f, "Bitcoin deserialization error: {:?}", e),
             DbError::Rusqlite(e) => write!(f, "Rusqlite SQL error: {:?}", e),
+            DbError::Json(e) => write!(f, "JSON error: {:?}", e),
+            DbError::Data(e) => write!(f, "invalid data: {}", e),
         }
     }
 }
@@ -82,10 +87,18 @@ impl error::Error for DbError {
             DbError::DecodeHex(ref e) => Some(e),
             DbError::BitcoinDeserialize(ref e) => Some(e),
             DbError::Rusqlite(ref e) => Some(e),
+            DbError::Json(ref e) => Some(e),
+            DbError::Data(_) => None,
         }
     }
 }
 
+impl From<serde_json::Error> for DbError {
+    fn from(e: serde_json::Error) -> Self {
+        DbError::Json(e)
+    }
+}
+
 impl From<rusqlite::Error> for DbError {
     fn from(e: rusqlite::Error) -> Self {
         DbError::Rusqlite(e)
@@ -113,6 +126,16 @@ pub enum ConfigError {
     UnknownImplementation,
     DuplicateNodeId,
     DuplicateNetworkId,
+    InvalidThemeColor(String),
+    ZeroMaxInitialBackfill,
+    InvalidWatchAddress(String),
+    ZeroRateLimit,
+    InvalidNostrSecretKey,
+    InvalidTrustedProxy(String),
+    InvalidRetention(String),
+    InvalidOutputTemplate(String),
+    InvalidCorsOrigin(String),
+    EmptyAdminToken,
     TomlError(toml::de::Error),
     ReadError(io::Error),
     AddrError(AddrParseError),
@@ -128,6 +151,16 @@ impl fmt::Display for ConfigError {
             ConfigError::UnknownImplementation => write!(f, "the node implementation defined in the config is not supported"),
             ConfigError::DuplicateNodeId => write!(f, "a node id has been used multiple times in the same network"),
             ConfigError::DuplicateNetworkId => write!(f, "a network id has been used multiple times"),
+            ConfigError::InvalidThemeColor(c) => write!(f, "the theme color '{}' is not in the '#rgb' or '#rrggbb' format", c),
+            ConfigError::ZeroMaxInitialBackfill => write!(f, "max_initial_backfill must be larger than 0"),
+            ConfigError::InvalidWatchAddress(a) => write!(f, "the watched coinbase address '{}' is not a valid address for the pool identification network", a),
+            ConfigError::ZeroRateLimit => write!(f, "rate_limit_per_minute must be larger than 0"),
+            ConfigError::InvalidNostrSecretKey => write!(f, "the nostr secret_key must be a 32 byte secret key in hex"),
+            ConfigError::InvalidTrustedProxy(p) => write!(f, "the trusted proxy '{}' of the access log is not an IP address", p),
+            ConfigError::InvalidRetention(r) => write!(f, "the retention '{}' is not 'unlimited' or a number followed by one of the units s, m, h, d or w", r),
+            ConfigError::InvalidOutputTemplate(p) => write!(f, "the script_pubkey_prefix '{}' of an output template is not non-empty hex", p),
+            ConfigError::InvalidCorsOrigin(o) => write!(f, "the CORS origin '{}' is not '*' or a scheme, host and optional port like 'https://example.com'", o),
+            ConfigError::EmptyAdminToken => write!(f, "the admin_token must not be empty"),
             ConfigError::TomlError(e) => write!(f, "the TOML in the configuration file could not be parsed: {}", e),
             ConfigError::ReadError(e) => write!(f, "the configuration file could not be read: {}", e),
             ConfigError::AddrError(e) => write!(f, "the address could not be parsed: {}", e),
@@ -148,6 +181,16 @@ impl error::Error for ConfigError {
             ConfigError::AddrError(ref e) => Some(e),
             ConfigError::DuplicateNodeId => None,
             ConfigError::DuplicateNetworkId => None,
+            ConfigError::InvalidThemeColor(_) => None,
+            ConfigError::ZeroMaxInitialBackfill => None,
+            ConfigError::InvalidWatchAddress(_) => None,
+            ConfigError::ZeroRateLimit => None,
+            ConfigError::InvalidNostrSecretKey => None,
+            ConfigError::InvalidTrustedProxy(_) => None,
+            ConfigError::InvalidRetention(_) => None,
+            ConfigError::InvalidOutputTemplate(_) => None,
+            ConfigError::InvalidCorsOrigin(_) => None,
+            ConfigError::EmptyAdminToken => None,
         }
     }
 }
@@ -175,6 +218,8 @@ pub enum MainError {
     Db(DbError),
     Fetch(FetchError),
     Config(ConfigError),
+    /// The webserver could not listen on the configured address.
+    Bind(warp::Error),
 }
 
 impl fmt::Display for MainError {
@@ -183,6 +228,7 @@ impl fmt::Display for MainError {
             MainError::Db(e) => write!(f, "database error: {:?}", e),
             MainError::Fetch(e) => write!(f, "fetch error: {:?}", e),
             MainError::Config(e) => write!(f, "config error: {:?}", e),
+            MainError::Bind(e) => write!(f, "bind error: {}", e),
         }
     }
 }
@@ -193,6 +239,7 @@ impl error::Error for MainError {
             MainError::Db(ref e) => Some(e),
             MainError::Fetch(ref e) => Some(e),
             MainError::Config(ref e) => Some(e),
+            MainError::Bind(ref e) => Some(e),
         }
     }
 }
@@ -219,8 +266,20 @@ impl From<ConfigError> for MainError {
 pub enum JsonRPCError {
     Http(String),
     JsonRpc(String),
+    /// The requested item (e.g. a block) was not found.
+    NotFound(String),
+    InvalidParameter(String),
+    MethodNotFound(String),
+    /// The response id doesn't match the request id.
+    IdMismatch {
+        expected: u64,
+        got: u64,
+    },
     RpcUnexpectedResponseContents(String),
-    MinReq(minreq::Error),
+    Ureq(Box<ureq::Error>),
+    Websocket(Box<tungstenite::Error>),
+    Io(io::Error),
+    Json(serde_json::Error),
     FromHex(hex::FromHexError),
     BitcoinFromHex(HexToArrayError),
     BitcoinDeserializeError(bitcoin::consensus::encode::Error),
@@ -230,9 +289,22 @@ pub enum JsonRPCError {
 impl fmt::Display for JsonRPCError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
-            JsonRPCError::MinReq(e) => write!(f, "minreq error: {:?}", e),
+            JsonRPCError::Ureq(e) => write!(f, "ureq error: {}", e),
+            JsonRPCError::Websocket(e) => write!(f, "websocket error: {}", e),
+            JsonRPCError::Io(e) => write!(f, "IO error: {}", e),
+            JsonRPCError::Json(e) => write!(f, "JSON error: {}", e),
             JsonRPCError::Http(s) => write!(f, "HTTP error: {}", s),
             JsonRPCError::JsonRpc(s) => write!(f, "json-rpc error: {}", s),
+            JsonRPCError::NotFound(s) => write!(f, "json-rpc not found error: {}", s),
+            JsonRPCError::InvalidParameter(s) => {
+                write!(f, "json-rpc invalid parameter error: {}", s)
+            }
+            JsonRPCError::MethodNotFound(s) => write!(f, "json-rpc method not found error: {}", s),
+            JsonRPCError::IdMismatch { expected, got } => write!(
+                f,
+                "json-rpc response id is {} but expected {}",
+                got, expected
+            ),
             JsonRPCError::RpcUnexpectedResponseContents(s) => {
                 write!(f, "unexpected contents in RPC response: {}", s)
             }
@@ -251,9 +323,16 @@ impl error::Error for JsonRPCError {
         match *self {
             JsonRPCError::Http(_) => None,
             JsonRPCError::JsonRpc(_) => None,
+            JsonRPCError::NotFound(_) => None,
+            JsonRPCError::InvalidParameter(_) => None,
+            JsonRPCError::MethodNotFound(_) => None,
+            JsonRPCError::IdMismatch { .. } => None,
             JsonRPCError::RpcUnexpectedResponseContents(_) => None,
             JsonRPCError::NotImplemented => None,
-            JsonRPCError::MinReq(ref e) => Some(e),
+            JsonRPCError::Ureq(ref e) => Some(e),
+            JsonRPCError::Websocket(ref e) => Some(e),
+            JsonRPCError::Io(ref e) => Some(e),
+            JsonRPCError::Json(ref e) => Some(e),
             JsonRPCError::FromHex(ref e) => Some(e),
             JsonRPCError::BitcoinFromHex(ref e) => Some(e),
             JsonRPCError::BitcoinDeserializeError(ref e) => Some(e),
@@ -261,9 +340,27 @@ impl error::Error for JsonRPCError {
     }
 }
 
-impl From<minreq::Error> for JsonRPCError {
-    fn from(e: minreq::Error) -> Self {
-        JsonRPCError::MinReq(e)
+impl From<ureq::Error> for JsonRPCError {
+    fn from(e: ureq::Error) -> Self {
+        JsonRPCError::Ureq(Box::new(e))
+    }
+}
+
+impl From<tungstenite::Error> for JsonRPCError {
+    fn from(e: tungstenite::Error) -> Self {
+        JsonRPCError::Websocket(Box::new(e))
+    }
+}
+
+impl From<io::Error> for JsonRPCError {
+    fn from(e: io::Error) -> Self {
+        JsonRPCError::Io(e)
+    }
+}
+
+impl From<serde_json::Error> for JsonRPCError {
+    fn from(e: serde_json::Error) -> Self {
+        JsonRPCError::Json(e)
     }
 }
 
@@ -284,3 +381,42 @@ impl From<HexToArrayError> for JsonRPCError {
         JsonRPCError::BitcoinFromHex(e)
     }
 }
+
+#[cfg(feature = "nostr")]
+#[derive(Debug)]
+pub enum NostrError {
+    Websocket(Box<tungstenite::Error>),
+    /// The relay didn't accept the event.
+    Rejected(String),
+    /// The relay closed the connection before accepting the event.
+    Closed,
+}
+
+#[cfg(feature = "nostr")]
+impl fmt::Display for NostrError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            NostrError::Websocket(e) => write!(f, "websocket error: {}", e),
+            NostrError::Rejected(m) => write!(f, "the relay rejected the event: {}", m),
+            NostrError::Closed => write!(f, "the relay closed the connection"),
+        }
+    }
+}
+
+#[cfg(feature = "nostr")]
+impl error::Error for NostrError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match *self {
+            NostrError::Websocket(ref e) => Some(e),
+            NostrError::Rejected(_) => None,
+            NostrError::Closed => None,
+        }
+    }
+}
+
+#[cfg(feature = "nostr")]
+impl From<tungstenite::Error> for NostrError {
+    fn from(e: tungstenite::Error) -> Self {
+        NostrError::Websocket(Box::new(e))
+    }
+}
@@ -1,11 +1,42 @@
+use std::cmp::max;
 use std::collections::BTreeMap;
 use std::collections::BTreeSet;
+use std::collections::{HashMap, HashSet};
 
-use crate::types::{Fork, HeaderInfoJson, Tree};
+use crate::config::ContextBlocks;
+use crate::types::{
+    BitSignallingJson, ChainTipStatus, ChainWork, Fork, HeaderInfo, HeaderInfoJson, HeaderStatus,
+    MinerSignallingJson, NodeData, SearchTerm, SignallingJsonResponse, Tree,
+};
 
+use bitcoincore_rpc::bitcoin::constants::genesis_block;
+use bitcoincore_rpc::bitcoin::hashes::Hash;
+use bitcoincore_rpc::bitcoin::{BlockHash, Network, Work};
 use log::{debug, warn};
-use petgraph::graph::NodeIndex;
+use petgraph::graph::{DiGraph, NodeIndex};
 use petgraph::visit::{Dfs, EdgeRef};
+use petgraph::Direction;
+
+// Bits 0 to 28 of the header version can be used for signalling (BIP9).
+const VERSIONBITS_NUM_BITS: u8 = 29;
+// A version only signals if its top three bits are set to 001 (BIP9).
+const VERSIONBITS_TOP_MASK: u32 = 0xE0000000;
+const VERSIONBITS_TOP_BITS: u32 = 0x20000000;
+
+// 2^53 - 1
+const JS_MAX_SAFE_INTEGER: u64 = (1 << 53) - 1;
+
+// Maximum number of headers walked back from each active tip to find out if
+// search results are on the active path.
+const MAX_ACTIVE_PATH_WALK: usize = 10_000;
+
+const KNOWN_NETWORKS: [Network; 5] = [
+    Network::Bitcoin,
+    Network::Testnet,
+    Network::Testnet4,
+    Network::Signet,
+    Network::Regtest,
+];
 
 pub async fn sorted_interesting_heights(
     tree: &Tree,
@@ -68,11 +99,21 @@ pub async fn sorted_interesting_heights(
     interesting_heights
 }
 
+// The id of a header in the stripped tree. Derived from the block hash, so
+// that it's stable across strip_tree runs. Fits into the 53 bits a JavaScript
+// number can represent exactly.
+pub fn header_id(hash: &BlockHash) -> usize {
+    let mut bytes = [0u8; 8];
+    bytes.copy_from_slice(&hash.to_byte_array()[..8]);
+    (u64::from_le_bytes(bytes) & JS_MAX_SAFE_INTEGER) as usize
+}
+
 // We strip the tree of headers that aren't interesting to us.
 pub async fn strip_tree(
     tree: &Tree,
     max_interesting_heights: usize,
     tip_heights: BTreeSet<u64>,
+    context: ContextBlocks,
 ) -> Vec<HeaderInfoJson> {
     let interesting_heights =
         sorted_interesting_heights(tree, max_interesting_heights, tip_heights).await;
@@ -82,11 +123,15 @@ pub async fn strip_tree(
     // Drop headers from our header tree that aren't 'interesting'.
     let mut striped_tree = tree_locked.0.filter_map(
         |_, header| {
-            // Keep some surrounding headers for the headers we find interesting.
-            for x in -2i64..=1 {
-                if interesting_heights.contains(&((header.height as i64 - x) as u64)) {
-                    return Some(header);
-                }
+            // Keep some surrounding headers for the headers we find interesting:
+            // context.before headers below and context.after headers above.
+            let lowest = header.height.saturating_sub(context.after);
+            let highest = header.height.saturating_add(context.before);
+            if interesting_heights
+                .iter()
+                .any(|h| *h >= lowest && *h <= highest)
+            {
+                return Some(header);
             }
             None
         },
@@ -146,21 +191,21 @@ pub async fn strip_tree(
     let mut headers: Vec<HeaderInfoJson> = Vec::new();
     for idx in striped_tree.node_indices() {
         let prev_nodes = striped_tree.neighbors_directed(idx, petgraph::Direction::Incoming);
-        let prev_node_index: usize;
+        let prev_id: usize;
         match prev_nodes.clone().count() {
-            0 => prev_node_index = usize::MAX, // indicates the start in JavaScript
+            0 => prev_id = usize::MAX, // indicates the start in JavaScript
             1 => {
-                prev_node_index = prev_nodes
+                let prev_node = prev_nodes
                     .last()
-                    .expect("we should have exactly one previous node")
-                    .index()
+                    .expect("we should have exactly one previous node");
+                prev_id = header_id(&striped_tree[prev_node].header.block_hash())
             }
             _ => panic!("got multiple previous nodes. this should not happen."),
         }
         headers.push(HeaderInfoJson::new(
             striped_tree[idx],
-            idx.index(),
-            prev_node_index,
+            header_id(&striped_tree[idx].header.block_hash()),
+            prev_id,
         ));
     }
 
@@ -168,7 +213,7 @@ pub async fn strip_tree(
 }
 
 // get recent forks for rss
-pub async fn recent_forks(tree: &Tree, how_many: usize) -> Vec<Fork> {
+pub async fn recent_forks(tree: &Tree, how_many: usize, max_children: usize) -> Vec<Fork> {
     let tree_locked = tree.lock().await;
     let tree = &tree_locked.0;
 
@@ -182,11 +227,22 @@ pub async fn recent_forks(tree: &Tree, how_many: usize) -> Vec<Fork> {
                 let outgoing_iter = tree.edges_directed(idx, petgraph::Direction::Outgoing);
                 if outgoing_iter.clone().count() > 1 {
                     let common = &tree[idx];
+                    let mut children: Vec<NodeIndex> =
+                        outgoing_iter.map(|edge| edge.target()).collect();
+                    // A fork point could have many competing children. Only
+                    // keep the most-extended ones. The search for the highest
+                    // descendant is only done if we need to truncate.
+                    let mut truncated_children = 0;
+                    if children.len() > max_children {
+                        children.sort_by_key(|child| max_descendant_height(tree, *child));
+                        children.reverse();
+                        truncated_children = children.len() - max_children;
+                        children.truncate(max_children);
+                    }
                     let fork = Fork {
                         common: common.clone(),
-                        children: outgoing_iter
-                            .map(|edge| tree[edge.target()].clone())
-                            .collect(),
+                        children: children.iter().map(|child| tree[*child].clone()).collect(),
+                        truncated_children,
                     };
                     forks.push(fork);
                 }
@@ -196,3 +252,804 @@ pub async fn recent_forks(tree: &Tree, how_many: usize) -> Vec<Fork> {
     forks.sort_by_key(|f| f.common.height);
     forks.iter().rev().take(how_many).cloned().collect()
 }
+
+// Height of the highest header building on-top of (or being) the header at
+// the given index.
+fn max_descendant_height(tree: &DiGraph<HeaderInfo, bool>, idx: NodeIndex) -> u64 {
+    let mut max_height = tree[idx].height;
+    let mut dfs = Dfs::new(tree, idx);
+    while let Some(descendant) = dfs.next(tree) {
+        max_height = max(max_height, tree[descendant].height);
+    }
+    max_height
+}
+
+// The most recent common ancestor of the headers with the hashes a and b.
+// If a is an ancestor of b, a is returned (and vice versa). Returns None if
+// either isn't in the tree or they don't share an ancestor in the tree.
+pub async fn common_ancestor(tree: &Tree, a: &BlockHash, b: &BlockHash) -> Option<BlockHash> {
+    let tree_locked = tree.lock().await;
+    let (tree, hash_index_map) = &*tree_locked;
+
+    let parent = |idx: NodeIndex| tree.neighbors_directed(idx, Direction::Incoming).next();
+    let mut a = *hash_index_map.get(a)?;
+    let mut b = *hash_index_map.get(b)?;
+    while a != b {
+        if tree[a].height >= tree[b].height {
+            a = parent(a)?;
+        } else {
+            b = parent(b)?;
+        }
+    }
+    Some(tree[a].header.block_hash())
+}
+
+// The hash and height of the last block the chains of a node's old and new
+// active tip have in common, if the node reorged, i.e. the new tip doesn't
+// descend from the old tip. None if it does, or if either tip isn't in the
+// tree. Learning about a new stale branch doesn't change the active tip and
+// isn't a reorg.
+pub async fn reorg_fork_point(
+    tree: &Tree,
+    old_tip: &BlockHash,
+    new_tip: &BlockHash,
+) -> Option<(BlockHash, u64)> {
+    let common = common_ancestor(tree, old_tip, new_tip).await?;
+    if common == *old_tip {
+        return None;
+    }
+    let tree_locked = tree.lock().await;
+    let (tree, hash_index_map) = &*tree_locked;
+    let idx = hash_index_map.get(&common)?;
+    Some((common, tree[*idx].height))
+}
+
+// The summed work of the headers on the chain ending in `tip`, down to the
+// lowest header in the tree. If `known` is the work up to an ancestor of the
+// tip, only the headers above it are summed up. Returns None if the tip isn't
+// in the tree.
+pub async fn chain_work(tree: &Tree, tip: &BlockHash, known: Option<ChainWork>) -> Option<Work> {
+    let tree_locked = tree.lock().await;
+    let (tree, hash_index_map) = &*tree_locked;
+
+    let mut idx = *hash_index_map.get(tip)?;
+    let mut work = Work::from_be_bytes([0; 32]);
+    loop {
+        let header = &tree[idx].header;
+        if let Some(known) = known.filter(|known| known.tip == header.block_hash()) {
+            return Some(work + known.work);
+        }
+        work = work + header.work();
+        match tree.neighbors_directed(idx, Direction::Incoming).next() {
+            Some(prev) => idx = prev,
+            None => return Some(work),
+        }
+    }
+}
+
+// Up to `limit` headers matching the search term and if they are on the
+// active path of one of the active tips, i.e. are an active tip or one of its
+// ancestors. Hash prefixes are matched against the known block hashes and
+// heights with a linear scan of the tree. The bool is true if there were more
+// matches.
+pub async fn search(
+    tree: &Tree,
+    term: &SearchTerm,
+    active_tips: &[BlockHash],
+    limit: usize,
+) -> (Vec<(HeaderInfo, Option<bool>)>, bool) {
+    let tree_locked = tree.lock().await;
+    let (tree, hash_index_map) = &*tree_locked;
+
+    let mut matches: Vec<NodeIndex> = match term {
+        SearchTerm::Hash(hash) => hash_index_map.get(hash).cloned().into_iter().collect(),
+        SearchTerm::HashPrefix(prefix) => hash_index_map
+            .iter()
+            .filter(|(hash, _)| has_hex_prefix(hash, prefix))
+            .map(|(_, idx)| *idx)
+            .take(limit + 1)
+            .collect(),
+        SearchTerm::Height(height) => tree
+            .node_indices()
+            .filter(|idx| tree[*idx].height == *height)
+            .take(limit + 1)
+            .collect(),
+    };
+    let truncated = matches.len() > limit;
+    matches.sort();
+    matches.truncate(limit);
+
+    let active = on_active_path(tree, hash_index_map, active_tips, &matches);
+    let results = matches
+        .iter()
+        .map(|idx| (tree[*idx].clone(), active.get(idx).cloned().flatten()))
+        .collect();
+    (results, truncated)
+}
+
+// If the block hash, as it's displayed, starts with the lowercase hex prefix.
+fn has_hex_prefix(hash: &BlockHash, prefix: &str) -> bool {
+    let mut bytes = hash.to_byte_array();
+    bytes.reverse();
+    prefix.len() <= bytes.len() * 2
+        && prefix.chars().enumerate().all(|(i, c)| {
+            let byte = bytes[i / 2];
+            let nibble = if i % 2 == 0 { byte >> 4 } else { byte & 0x0f };
+            c.to_digit(16) == Some(nibble as u32)
+        })
+}
+
+// If the candidates are an active tip or one of its ancestors. Walks back from
+// each active tip until the height of the lowest candidate, but at most
+// MAX_ACTIVE_PATH_WALK headers. None for candidates that aren't on the active
+// path as far as it was walked, but lie below a walk that was cut short.
+fn on_active_path(
+    tree: &DiGraph<HeaderInfo, bool>,
+    hash_index_map: &HashMap<BlockHash, NodeIndex>,
+    active_tips: &[BlockHash],
+    candidates: &[NodeIndex],
+) -> HashMap<NodeIndex, Option<bool>> {
+    let candidates: HashSet<NodeIndex> = candidates.iter().cloned().collect();
+    let min_height = match candidates.iter().map(|idx| tree[*idx].height).min() {
+        Some(height) => height,
+        None => return HashMap::new(),
+    };
+    let mut active = HashSet::new();
+    // candidates at or below this height weren't reached by all walks
+    let mut unchecked_height: Option<u64> = None;
+    let tips: BTreeSet<NodeIndex> = active_tips
+        .iter()
+        .filter_map(|hash| hash_index_map.get(hash).cloned())
+        .collect();
+    for tip in tips {
+        let mut idx = tip;
+        let mut walked = 0;
+        while tree[idx].height >= min_height {
+            if walked == MAX_ACTIVE_PATH_WALK {
+                unchecked_height = unchecked_height.max(Some(tree[idx].height));
+                break;
+            }
+            walked += 1;
+            if candidates.contains(&idx) {
+                active.insert(idx);
+            }
+            match tree.neighbors_directed(idx, Direction::Incoming).next() {
+                Some(prev) => idx = prev,
+                None => break,
+            }
+        }
+    }
+    candidates
+        .into_iter()
+        .map(|idx| {
+            let status = if active.contains(&idx) {
+                Some(true)
+            } else if unchecked_height.is_some_and(|height| tree[idx].height <= height) {
+                None
+            } else {
+                Some(false)
+            };
+            (idx, status)
+        })
+        .collect()
+}
+
+// The status of each header (by hash) derived from the node tips:
+// - active: the header is an ancestor of (or) the active tip of the majority
+//   of the reachable nodes.
+// - invalid: the header is on the branch of a tip a node reported as invalid
+//   and isn't on any node's active chain.
+// - stale: the header lost against the majority active chain, i.e. isn't
+//   active, but the majority active chain reaches its height.
+// - unknown: otherwise, e.g. above the majority active tip.
+// Ancestors are followed via the prev_id of the headers.
+pub fn header_statuses(
+    header_infos: &[HeaderInfoJson],
+    node_data: &NodeData,
+) -> HashMap<String, HeaderStatus> {
+    let by_id: HashMap<usize, &HeaderInfoJson> = header_infos.iter().map(|h| (h.id, h)).collect();
+    let by_hash: HashMap<&str, &HeaderInfoJson> =
+        header_infos.iter().map(|h| (h.hash.as_str(), h)).collect();
+    // the header and its ancestors, starting with the header
+    let ancestors = |hash: &str| -> Vec<&HeaderInfoJson> {
+        let mut ancestors = vec![];
+        let mut current = by_hash.get(hash).cloned();
+        while let Some(h) = current {
+            ancestors.push(h);
+            current = by_id.get(&h.prev_id).cloned();
+        }
+        ancestors
+    };
+
+    let active_tips: Vec<&str> = node_data
+        .values()
+        .filter(|node| node.reachable)
+        .filter_map(|node| node.active_tip())
+        .map(|tip| tip.hash.as_str())
+        .collect();
+    let mut active_counts: HashMap<&str, usize> = HashMap::new();
+    for tip in active_tips.iter() {
+        for h in ancestors(tip) {
+            *active_counts.entry(h.hash.as_str()).or_default() += 1;
+        }
+    }
+    let majority = |count: usize| count * 2 > active_tips.len();
+    let max_active_height = header_infos
+        .iter()
+        .filter(|h| {
+            majority(
+                active_counts
+                    .get(h.hash.as_str())
+                    .cloned()
+                    .unwrap_or_default(),
+            )
+        })
+        .map(|h| h.height)
+        .max();
+
+    let mut invalid: HashSet<&str> = HashSet::new();
+    for tip in node_data
+        .values()
+        .flat_map(|node| node.tips.iter())
+        .filter(|tip| tip.status == ChainTipStatus::Invalid)
+    {
+        for h in ancestors(&tip.hash)
+            .into_iter()
+            .take_while(|h| !active_counts.contains_key(h.hash.as_str()))
+        {
+            invalid.insert(h.hash.as_str());
+        }
+    }
+
+    header_infos
+        .iter()
+        .map(|h| {
+            let status = if majority(
+                active_counts
+                    .get(h.hash.as_str())
+                    .cloned()
+                    .unwrap_or_default(),
+            ) {
+                HeaderStatus::Active
+            } else if invalid.contains(h.hash.as_str()) {
+                HeaderStatus::Invalid
+            } else if max_active_height.is_some_and(|max| h.height <= max) {
+                HeaderStatus::Stale
+            } else {
+                HeaderStatus::Unknown
+            };
+            (h.hash.clone(), status)
+        })
+        .collect()
+}
+
+// Derives the network from the genesis block hash. This only works if the
+// tree contains the genesis block or a block at height 1. All signets share
+// the same genesis block.
+pub async fn network_type(tree: &Tree) -> Option<Network> {
+    let tree_locked = tree.lock().await;
+    let genesis_hashes: BTreeSet<BlockHash> = tree_locked
+        .0
+        .raw_nodes()
+        .iter()
+        .filter_map(|node| match node.weight.height {
+            0 => Some(node.weight.header.block_hash()),
+            1 => Some(node.weight.header.prev_blockhash),
+            _ => None,
+        })
+        .collect();
+    KNOWN_NETWORKS
+        .iter()
+        .find(|network| genesis_hashes.contains(&genesis_block(**network).block_hash()))
+        .copied()
+}
+
+// Returns if the header version signals for the given version bit following
+// the BIP9 top-bits convention.
+fn is_signalling(version: u32, bit: u8) -> bool {
+    (version & VERSIONBITS_TOP_MASK) == VERSIONBITS_TOP_BITS && (version >> bit) & 1 == 1
+}
+
+// Version-bits signalling statistics for the `window` blocks on the chain
+// ending in `tip`. Returns None if the tip isn't in the tree.
+pub async fn signalling_stats(
+    tree: &Tree,
+    tip: &BlockHash,
+    window: usize,
+) -> Option<SignallingJsonResponse> {
+    let tree_locked = tree.lock().await;
+    let (tree, hash_index_map) = &*tree_locked;
+
+    let mut idx = *hash_index_map.get(tip)?;
+    let mut headers: Vec<&HeaderInfo> = Vec::with_capacity(window);
+    while headers.len() < window {
+        headers.push(&tree[idx]);
+        match tree.neighbors_directed(idx, Direction::Incoming).next() {
+            Some(prev) => idx = prev,
+            None => break,
+        }
+    }
+    Some(signalling_stats_from_headers(&headers))
+}
+
+// Version-bits signalling statistics for the headers. The first header is
+// expected to be the tip.
+pub fn signalling_stats_from_headers(headers: &[&HeaderInfo]) -> SignallingJsonResponse {
+    let mut bit_counts = [0usize; VERSIONBITS_NUM_BITS as usize];
+    let mut versions: BTreeMap<String, usize> = BTreeMap::new();
+    let mut miners: BTreeMap<String, MinerSignallingJson> = BTreeMap::new();
+
+    for header_info in headers {
+        let version = header_info.header.version.to_consensus() as u32;
+        *versions.entry(format!("0x{:08x}", version)).or_insert(0) += 1;
+
+        let mut miner_stats = if header_info.miner.is_empty() {
+            None
+        } else {
+            let miner_stats = miners.entry(header_info.miner.clone()).or_default();
+            miner_stats.blocks += 1;
+            Some(miner_stats)
+        };
+
+        for bit in 0..VERSIONBITS_NUM_BITS {
+            if is_signalling(version, bit) {
+                bit_counts[bit as usize] += 1;
+                if let Some(miner_stats) = miner_stats.as_mut() {
+                    *miner_stats.bits.entry(bit).or_insert(0) += 1;
+                }
+            }
+        }
+    }
+
+    let bits = bit_counts
+        .iter()
+        .enumerate()
+        .map(|(bit, count)| BitSignallingJson {
+            bit: bit as u8,
+            count: *count,
+            percentage: if headers.is_empty() {
+                0.0
+            } else {
+                *count as f64 * 100.0 / headers.len() as f64
+            },
+        })
+        .collect();
+
+    SignallingJsonResponse {
+        tip_hash: headers
+            .first()
+            .map(|h| h.header.block_hash().to_string())
+            .unwrap_or_default(),
+        tip_height: headers.first().map(|h| h.height).unwrap_or_default(),
+        blocks: headers.len(),
+        bits,
+        versions,
+        miners,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils;
+    use bitcoincore_rpc::bitcoin::block::Version;
+    use std::str::FromStr;
+
+    #[tokio::test]
+    async fn test_search_active_path_walk() {
+        let headers = test_utils::chain(BlockHash::all_zeros(), 1, MAX_ACTIVE_PATH_WALK + 2, 0);
+        let fork = test_utils::chain(headers[0].header.block_hash(), 2, 1, 100);
+        let tree = test_utils::tree(&[headers.clone(), fork.clone()].concat());
+        let tip = headers.last().unwrap().header.block_hash();
+        let search = |term: SearchTerm| {
+            let tree = tree.clone();
+            async move {
+                search(&tree, &term, &[tip], 10)
+                    .await
+                    .0
+                    .into_iter()
+                    .map(|(h, active)| (h.header.block_hash(), active))
+                    .collect::<Vec<_>>()
+            }
+        };
+
+        assert_eq!(search(SearchTerm::Hash(tip)).await, vec![(tip, Some(true))]);
+        let hash = headers[3].header.block_hash();
+        assert_eq!(
+            search(SearchTerm::HashPrefix(hash.to_string()[..8].to_string())).await,
+            vec![(hash, Some(true))]
+        );
+        // the walk from the tip stops above height 2
+        assert_eq!(
+            search(SearchTerm::Height(2)).await,
+            vec![
+                (headers[1].header.block_hash(), None),
+                (fork[0].header.block_hash(), None),
+            ]
+        );
+        assert!(search(SearchTerm::HashPrefix("ffffffffff".to_string()))
+            .await
+            .is_empty());
+    }
+
+    #[test]
+    fn test_has_hex_prefix() {
+        let hash =
+            BlockHash::from_str("00000000000000000002a7c4c1e48d76c5a37902165a270156b7a8d72728a054")
+                .unwrap();
+        assert!(has_hex_prefix(&hash, "00000000000000000002a7c"));
+        assert!(has_hex_prefix(&hash, &hash.to_string()));
+        assert!(!has_hex_prefix(&hash, "00000000000000000002a7d"));
+        assert!(!has_hex_prefix(&hash, &format!("{}0", hash)));
+    }
+
+    #[tokio::test]
+    async fn test_network_type() {
+        let genesis = genesis_block(Network::Regtest).block_hash();
+        let tree = test_utils::tree(&test_utils::chain(genesis, 1, 5, 0));
+        assert_eq!(network_type(&tree).await, Some(Network::Regtest));
+
+        // the genesis block can't be derived without low headers
+        let tree = test_utils::tree(&test_utils::chain(genesis, 100, 5, 0));
+        assert_eq!(network_type(&tree).await, None);
+
+        let tree = test_utils::tree(&test_utils::chain(BlockHash::all_zeros(), 1, 5, 0));
+        assert_eq!(network_type(&tree).await, None);
+    }
+
+    #[tokio::test]
+    async fn test_chain_work() {
+        let headers = test_utils::chain(BlockHash::all_zeros(), 1, 10, 0);
+        let fork = test_utils::header_info(headers[4].header.block_hash(), 6, 100);
+        let mut all = headers.clone();
+        all.push(fork.clone());
+        let tree = test_utils::tree(&all);
+        let tip = headers[9].header.block_hash();
+        let header_work = headers[0].header.work();
+
+        let work = chain_work(&tree, &tip, None).await.unwrap();
+        // each regtest-difficulty header has a work of 2
+        assert_eq!(
+            ChainWork { tip, work }.hex(),
+            format!("{:064x}", 2 * headers.len())
+        );
+
+        // only the headers above a known ancestor are summed up
+        let known = ChainWork {
+            tip: headers[4].header.block_hash(),
+            work: header_work + header_work + header_work,
+        };
+        let mut expected = known.work;
+        for _ in 5..10 {
+            expected = expected + header_work;
+        }
+        assert_eq!(chain_work(&tree, &tip, Some(known)).await, Some(expected));
+
+        // the known work of another branch isn't used
+        let known = ChainWork {
+            tip: fork.header.block_hash(),
+            work: header_work,
+        };
+        assert_eq!(chain_work(&tree, &tip, Some(known)).await, Some(work));
+
+        assert_eq!(chain_work(&tree, &BlockHash::all_zeros(), None).await, None);
+    }
+
+    #[tokio::test]
+    async fn test_strip_tree_stable_ids() {
+        // a chain with a fork at height 6
+        let mut headers = test_utils::chain(BlockHash::all_zeros(), 1, 20, 0);
+        let fork = test_utils::header_info(headers[4].header.block_hash(), 6, 100);
+        let fork_hash = fork.header.block_hash();
+        headers.push(fork);
+        let tree = test_utils::tree(&headers);
+        let first = strip_tree(&tree, 5, BTreeSet::new(), ContextBlocks::default()).await;
+
+        // growing the fork strips the tree differently, but the headers
+        // around the fork are in both stripped trees and keep their ids
+        let mut grown = headers.clone();
+        grown.push(test_utils::header_info(fork_hash, 7, 101));
+        let tree = test_utils::tree(&grown);
+        let second = strip_tree(&tree, 5, BTreeSet::new(), ContextBlocks::default()).await;
+        assert_eq!(second.len(), first.len() + 2);
+
+        let ids = |headers: &[HeaderInfoJson]| -> BTreeMap<String, (usize, usize)> {
+            headers
+                .iter()
+                .map(|h| (h.hash.clone(), (h.id, h.prev_id)))
+                .collect()
+        };
+        let (first_ids, second_ids) = (ids(&first), ids(&second));
+        for (hash, (id, _)) in first_ids.iter() {
+            assert_eq!(Some(id), second_ids.get(hash).map(|(id, _)| id));
+        }
+        assert!(second_ids.contains_key(&fork_hash.to_string()));
+
+        // the prev_ids link to the ids of lower headers in the stripped tree
+        for h in second.iter().filter(|h| h.prev_id != usize::MAX) {
+            let prev = second
+                .iter()
+                .find(|p| p.id == h.prev_id)
+                .expect("the previous header should be in the stripped tree");
+            assert!(prev.height < h.height);
+        }
+        assert!(second.iter().all(|h| h.id as u64 <= JS_MAX_SAFE_INTEGER));
+    }
+
+    #[tokio::test]
+    async fn test_strip_tree_context_blocks() {
+        let headers = test_utils::chain(BlockHash::all_zeros(), 0, 30, 0);
+        let mut tree_headers = headers.clone();
+        // a fork at height 15
+        tree_headers.push(test_utils::header_info(
+            headers[14].header.block_hash(),
+            15,
+            100,
+        ));
+        let tree = test_utils::tree(&tree_headers);
+        let heights = |headers: Vec<HeaderInfoJson>| -> BTreeSet<u64> {
+            headers.iter().map(|h| h.height).collect()
+        };
+
+        // by default, two headers below and one header above the interesting
+        // heights (the fork and the tip) are kept
+        let stripped = strip_tree(&tree, 10, BTreeSet::new(), ContextBlocks::default()).await;
+        assert_eq!(
+            heights(stripped),
+            BTreeSet::from([13, 14, 15, 16, 27, 28, 29])
+        );
+
+        let wide = ContextBlocks {
+            before: 5,
+            after: 3,
+        };
+        let stripped = strip_tree(&tree, 10, BTreeSet::new(), wide).await;
+        assert_eq!(stripped.len(), 16);
+        assert_eq!(
+            heights(stripped),
+            (10..=18).chain(24..=29).collect::<BTreeSet<u64>>()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_recent_forks_caps_children() {
+        let common = test_utils::header_info(BlockHash::all_zeros(), 100, 0);
+        let mut headers = vec![common.clone()];
+        // 12 competing children. The k-th child is extended by k blocks.
+        for k in 0..12u32 {
+            let child = test_utils::header_info(common.header.block_hash(), 101, 1000 * (k + 1));
+            headers.extend(test_utils::chain(
+                child.header.block_hash(),
+                102,
+                k as usize,
+                1000 * (k + 1) + 1,
+            ));
+            headers.push(child);
+        }
+        let tree = test_utils::tree(&headers);
+
+        let forks = recent_forks(&tree, 10, 10).await;
+        assert_eq!(forks.len(), 1);
+        assert_eq!(forks[0].common, common);
+        assert_eq!(forks[0].children.len(), 10);
+        assert_eq!(forks[0].truncated_children, 2);
+        // the two least-extended children are the ones dropped
+        let kept_nonces: Vec<u32> = forks[0].children.iter().map(|c| c.header.nonce).collect();
+        assert!(!kept_nonces.contains(&1000));
+        assert!(!kept_nonces.contains(&2000));
+        assert!(kept_nonces.contains(&12000));
+
+        let forks = recent_forks(&tree, 10, 20).await;
+        assert_eq!(forks[0].children.len(), 12);
+        assert_eq!(forks[0].truncated_children, 0);
+    }
+
+    #[tokio::test]
+    async fn test_signalling_stats() {
+        let mut headers = test_utils::chain(BlockHash::all_zeros(), 1, 10, 0);
+        for (i, h) in headers.iter_mut().enumerate() {
+            h.header.version = Version::from_consensus(match i {
+                // signalling bit 1 and 2
+                0..=3 => 0x20000006,
+                // signalling bit 2
+                4..=5 => 0x20000004,
+                // bit 2 set, but not following the BIP9 top-bits convention
+                6 => 0x40000004,
+                _ => 0x20000000,
+            });
+            h.miner = match i {
+                0..=1 => "Pool A".to_string(),
+                2..=4 => "Pool B".to_string(),
+                _ => String::new(),
+            };
+        }
+        // The version changes the block hash. Re-link the chain.
+        for i in 1..headers.len() {
+            headers[i].header.prev_blockhash = headers[i - 1].header.block_hash();
+        }
+        let tip = headers.last().unwrap().clone();
+        let tree = test_utils::tree(&headers);
+
+        let stats = signalling_stats(&tree, &tip.header.block_hash(), 2016)
+            .await
+            .unwrap();
+        assert_eq!(stats.tip_hash, tip.header.block_hash().to_string());
+        assert_eq!(stats.tip_height, 10);
+        assert_eq!(stats.blocks, 10);
+        assert_eq!(stats.bits.len(), 29);
+        assert_eq!(stats.bits[0].count, 0);
+        assert_eq!(stats.bits[1].count, 4);
+        assert_eq!(stats.bits[1].percentage, 40.0);
+        assert_eq!(stats.bits[2].count, 6);
+        assert_eq!(stats.bits[2].percentage, 60.0);
+        assert_eq!(stats.versions.get("0x20000006"), Some(&4));
+        assert_eq!(stats.versions.get("0x20000004"), Some(&2));
+        assert_eq!(stats.versions.get("0x40000004"), Some(&1));
+        assert_eq!(stats.versions.get("0x20000000"), Some(&3));
+        assert_eq!(stats.miners.len(), 2);
+        let pool_a = stats.miners.get("Pool A").unwrap();
+        assert_eq!(pool_a.blocks, 2);
+        assert_eq!(pool_a.bits, BTreeMap::from([(1, 2), (2, 2)]));
+        let pool_b = stats.miners.get("Pool B").unwrap();
+        assert_eq!(pool_b.blocks, 3);
+        assert_eq!(pool_b.bits, BTreeMap::from([(1, 2), (2, 3)]));
+
+        // only the last 3 blocks are in the window
+        let stats = signalling_stats(&tree, &tip.header.block_hash(), 3)
+            .await
+            .unwrap();
+        assert_eq!(stats.blocks, 3);
+        assert_eq!(stats.bits[2].count, 0);
+        assert_eq!(stats.versions.get("0x20000000"), Some(&3));
+
+        assert!(signalling_stats(&tree, &BlockHash::all_zeros(), 2016)
+            .await
+            .is_none());
+    }
+
+    #[test]
+    fn test_header_statuses() {
+        let chain = test_utils::chain(BlockHash::all_zeros(), 1, 6, 0);
+        let stale = test_utils::chain(chain[2].header.block_hash(), 4, 2, 100);
+        let invalid = test_utils::chain(chain[3].header.block_hash(), 5, 2, 200);
+        let above = test_utils::header_info(chain[5].header.block_hash(), 7, 300);
+        let headers = [
+            chain.clone(),
+            stale.clone(),
+            invalid.clone(),
+            vec![above.clone()],
+        ]
+        .concat();
+        let header_infos: Vec<HeaderInfoJson> = headers
+            .iter()
+            .map(|h| {
+                let prev_id = if h.height == 1 {
+                    usize::MAX
+                } else {
+                    header_id(&h.header.prev_blockhash)
+                };
+                HeaderInfoJson::new(h, header_id(&h.header.block_hash()), prev_id)
+            })
+            .collect();
+        let tip = |h: &HeaderInfo, status: ChainTipStatus| {
+            test_utils::chain_tip(h.height, &h.header.block_hash(), status)
+        };
+        let node_data: NodeData = BTreeMap::from([
+            (
+                0,
+                test_utils::node_data(0, &vec![tip(&chain[5], ChainTipStatus::Active)]),
+            ),
+            (
+                1,
+                test_utils::node_data(1, &vec![tip(&chain[5], ChainTipStatus::Active)]),
+            ),
+            (
+                2,
+                test_utils::node_data(
+                    2,
+                    &vec![
+                        tip(&chain[4], ChainTipStatus::Active),
+                        tip(&stale[1], ChainTipStatus::ValidFork),
+                        tip(&invalid[1], ChainTipStatus::Invalid),
+                    ],
+                ),
+            ),
+        ]);
+
+        let statuses = header_statuses(&header_infos, &node_data);
+        let status = |h: &HeaderInfo| statuses[&h.header.block_hash().to_string()];
+        for h in chain.iter() {
+            assert_eq!(status(h), HeaderStatus::Active);
+        }
+        for h in stale.iter() {
+            assert_eq!(status(h), HeaderStatus::Stale);
+        }
+        for h in invalid.iter() {
+            assert_eq!(status(h), HeaderStatus::Invalid);
+        }
+        assert_eq!(status(&above), HeaderStatus::Unknown);
+
+        // two more nodes on the stale branch: the tip of nodes 0 and 1 loses
+        // its majority
+        let mut split = node_data.clone();
+        split.insert(
+            3,
+            test_utils::node_data(3, &vec![tip(&stale[1], ChainTipStatus::Active)]),
+        );
+        split.insert(
+            4,
+            test_utils::node_data(4, &vec![tip(&stale[1], ChainTipStatus::Active)]),
+        );
+        let statuses = header_statuses(&header_infos, &split);
+        let status = |h: &HeaderInfo| statuses[&h.header.block_hash().to_string()];
+        assert_eq!(status(&chain[2]), HeaderStatus::Active);
+        // on the active chain of nodes 0, 1 and 2
+        assert_eq!(status(&chain[4]), HeaderStatus::Active);
+        assert_eq!(status(&chain[5]), HeaderStatus::Unknown);
+        assert_eq!(status(&stale[0]), HeaderStatus::Stale);
+        assert_eq!(status(&stale[1]), HeaderStatus::Stale);
+        assert_eq!(status(&invalid[0]), HeaderStatus::Invalid);
+    }
+
+    #[tokio::test]
+    async fn test_common_ancestor() {
+        let chain = test_utils::chain(BlockHash::all_zeros(), 1, 5, 0);
+        let fork = test_utils::chain(chain[2].header.block_hash(), 4, 2, 100);
+        let tree = test_utils::tree(&[chain.clone(), fork.clone()].concat());
+        let hash = |h: &HeaderInfo| h.header.block_hash();
+
+        assert_eq!(
+            common_ancestor(&tree, &hash(&chain[4]), &hash(&fork[1])).await,
+            Some(hash(&chain[2]))
+        );
+        assert_eq!(
+            common_ancestor(&tree, &hash(&fork[0]), &hash(&chain[3])).await,
+            Some(hash(&chain[2]))
+        );
+        // an ancestor is its own common ancestor
+        assert_eq!(
+            common_ancestor(&tree, &hash(&chain[1]), &hash(&chain[4])).await,
+            Some(hash(&chain[1]))
+        );
+        assert_eq!(
+            common_ancestor(&tree, &hash(&chain[4]), &hash(&chain[4])).await,
+            Some(hash(&chain[4]))
+        );
+        assert_eq!(
+            common_ancestor(&tree, &hash(&chain[4]), &BlockHash::all_zeros()).await,
+            None
+        );
+    }
+
+    #[tokio::test]
+    async fn test_reorg_fork_point() {
+        let chain = test_utils::chain(BlockHash::all_zeros(), 1, 5, 0);
+        let fork = test_utils::chain(chain[2].header.block_hash(), 4, 3, 100);
+        let tree = test_utils::tree(&[chain.clone(), fork.clone()].concat());
+        let hash = |h: &HeaderInfo| h.header.block_hash();
+
+        // switching to the other branch
+        assert_eq!(
+            reorg_fork_point(&tree, &hash(&chain[4]), &hash(&fork[2])).await,
+            Some((hash(&chain[2]), 3))
+        );
+        // rolling back to an ancestor, e.g. after invalidateblock
+        assert_eq!(
+            reorg_fork_point(&tree, &hash(&chain[4]), &hash(&chain[1])).await,
+            Some((hash(&chain[1]), 2))
+        );
+        // extending the active chain isn't a reorg
+        assert_eq!(
+            reorg_fork_point(&tree, &hash(&chain[2]), &hash(&chain[4])).await,
+            None
+        );
+        assert_eq!(
+            reorg_fork_point(&tree, &hash(&chain[4]), &hash(&chain[4])).await,
+            None
+        );
+        assert_eq!(
+            reorg_fork_point(&tree, &BlockHash::all_zeros(), &hash(&chain[4])).await,
+            None
+        );
+    }
+}
@@ -0,0 +1,311 @@
+use bitcoin_pool_identification::Pool;
+use bitcoincore_rpc::bitcoin::{Address, BlockHash, Network, Transaction};
+
+use crate::config::OutputTemplate;
+
+/// A block to identify the miner of.
+pub struct BlockInfo<'a> {
+    pub hash: BlockHash,
+    pub height: u64,
+    pub coinbase: &'a Transaction,
+}
+
+/// A method to identify the miner of a block. Identifiers are tried in order
+/// by an [`IdentifierChain`] until one matches.
+pub trait Identifier: Send + Sync {
+    /// Name of the identifier, reported for the blocks it identified.
+    fn name(&self) -> &'static str;
+
+    /// The miner of the block, if the identifier recognizes it.
+    fn identify(&self, block: &BlockInfo) -> Option<String>;
+}
+
+/// The identified miner and the name of the identifier that matched.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Identification {
+    pub miner: String,
+    pub identifier: &'static str,
+}
+
+/// Matches the coinbase tag (the scriptSig of the coinbase input) against the
+/// tags of the pools.
+pub struct TagIdentifier {
+    pools: Vec<Pool>,
+}
+
+impl TagIdentifier {
+    pub fn new(pools: Vec<Pool>) -> Self {
+        TagIdentifier { pools }
+    }
+}
+
+impl Identifier for TagIdentifier {
+    fn name(&self) -> &'static str {
+        "tag"
+    }
+
+    fn identify(&self, block: &BlockInfo) -> Option<String> {
+        let coinbase_tag = coinbase_tag(block.coinbase)?;
+        self.pools
+            .iter()
+            .find(|pool| {
+                pool.tags
+                    .iter()
+                    .any(|tag| !tag.is_empty() && coinbase_tag.contains(tag.as_str()))
+            })
+            .map(|pool| pool.name.clone())
+    }
+}
+
+/// Matches the addresses the coinbase outputs pay to against the addresses
+/// of the pools.
+pub struct AddressIdentifier {
+    network: Network,
+    pools: Vec<Pool>,
+}
+
+impl AddressIdentifier {
+    pub fn new(network: Network, pools: Vec<Pool>) -> Self {
+        AddressIdentifier { network, pools }
+    }
+}
+
+impl Identifier for AddressIdentifier {
+    fn name(&self) -> &'static str {
+        "address"
+    }
+
+    fn identify(&self, block: &BlockInfo) -> Option<String> {
+        let addresses: Vec<String> = block
+            .coinbase
+            .output
+            .iter()
+            .filter_map(|output| Address::from_script(&output.script_pubkey, self.network).ok())
+            .map(|address| address.to_string())
+            .collect();
+        self.pools
+            .iter()
+            .find(|pool| pool.addresses.iter().any(|a| addresses.contains(a)))
+            .map(|pool| pool.name.clone())
+    }
+}
+
+/// Matches the coinbase output scripts against the configured output
+/// templates.
+pub struct TemplateIdentifier {
+    templates: Vec<(Vec<u8>, String)>,
+}
+
+impl TemplateIdentifier {
+    // Templates with a prefix that isn't hex are skipped. They are rejected
+    // when the configuration is loaded.
+    pub fn new(templates: &[OutputTemplate]) -> Self {
+        TemplateIdentifier {
+            templates: templates
+                .iter()
+                .filter_map(|template| {
+                    hex::decode(&template.script_pubkey_prefix)
+                        .ok()
+                        .filter(|prefix| !prefix.is_empty())
+                        .map(|prefix| (prefix, template.miner.clone()))
+                })
+                .collect(),
+        }
+    }
+}
+
+impl Identifier for TemplateIdentifier {
+    fn name(&self) -> &'static str {
+        "template"
+    }
+
+    fn identify(&self, block: &BlockInfo) -> Option<String> {
+        self.templates
+            .iter()
+            .find(|(prefix, _)| {
+                block
+                    .coinbase
+                    .output
+                    .iter()
+                    .any(|output| output.script_pubkey.as_bytes().starts_with(prefix))
+            })
+            .map(|(_, miner)| miner.clone())
+    }
+}
+
+/// Tries the identifiers in order and returns the first match.
+pub struct IdentifierChain {
+    identifiers: Vec<Box<dyn Identifier>>,
+}
+
+impl IdentifierChain {
+    pub fn new(identifiers: Vec<Box<dyn Identifier>>) -> Self {
+        IdentifierChain { identifiers }
+    }
+
+    /// The coinbase tag, then the coinbase output addresses of the pool
+    /// identification data and then the custom output templates.
+    pub fn with_defaults(network: Network, pools: Vec<Pool>, templates: &[OutputTemplate]) -> Self {
+        IdentifierChain::new(vec![
+            Box::new(TagIdentifier::new(pools.clone())),
+            Box::new(AddressIdentifier::new(network, pools)),
+            Box::new(TemplateIdentifier::new(templates)),
+        ])
+    }
+
+    pub fn identify(&self, block: &BlockInfo) -> Option<Identification> {
+        self.identifiers.iter().find_map(|identifier| {
+            identifier.identify(block).map(|miner| Identification {
+                miner,
+                identifier: identifier.name(),
+            })
+        })
+    }
+}
+
+/// The coinbase tag (the scriptSig of the coinbase input) as lossy UTF-8.
+pub fn coinbase_tag(coinbase: &Transaction) -> Option<String> {
+    coinbase
+        .input
+        .first()
+        .map(|input| String::from_utf8_lossy(input.script_sig.as_bytes()).to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils;
+    use bitcoin_pool_identification::default_data;
+    use bitcoincore_rpc::bitcoin::hashes::Hash;
+    use bitcoincore_rpc::bitcoin::{Amount, ScriptBuf, TxOut};
+    use std::str::FromStr;
+
+    // A P2WPKH output script not belonging to a known pool.
+    const UNKNOWN_SCRIPT: &str = "0014751e76e8199196d454941c45d1b3a323f1433bd6";
+
+    fn block(coinbase: &Transaction) -> BlockInfo<'_> {
+        BlockInfo {
+            hash: BlockHash::all_zeros(),
+            height: 1,
+            coinbase,
+        }
+    }
+
+    fn pay_to(coinbase: &mut Transaction, script_pubkey: ScriptBuf) {
+        coinbase.output.push(TxOut {
+            value: Amount::from_sat(5_000_000_000),
+            script_pubkey,
+        });
+    }
+
+    fn template(prefix: &str, miner: &str) -> OutputTemplate {
+        OutputTemplate {
+            script_pubkey_prefix: prefix.to_string(),
+            miner: miner.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_tag_identifier() {
+        let pools = default_data(Network::Bitcoin);
+        let pool = pools
+            .iter()
+            .find(|pool| !pool.tags.is_empty())
+            .expect("a pool with a tag")
+            .clone();
+        let identifier = TagIdentifier::new(pools);
+
+        let coinbase = test_utils::coinbase_with_tag(pool.tags[0].as_bytes());
+        assert_eq!(identifier.identify(&block(&coinbase)), Some(pool.name));
+        let coinbase = test_utils::coinbase_with_tag(b"/not a known pool/");
+        assert_eq!(identifier.identify(&block(&coinbase)), None);
+    }
+
+    #[test]
+    fn test_address_identifier() {
+        let network = Network::Bitcoin;
+        let pools = default_data(network);
+        let (pool, address) = pools
+            .iter()
+            .find_map(|pool| {
+                pool.addresses
+                    .iter()
+                    .find_map(|a| Address::from_str(a).ok()?.require_network(network).ok())
+                    .map(|a| (pool.clone(), a))
+            })
+            .expect("a pool with an address");
+        let identifier = AddressIdentifier::new(network, pools);
+
+        let mut coinbase = test_utils::coinbase_with_tag(b"/not a known pool/");
+        pay_to(&mut coinbase, ScriptBuf::from_hex(UNKNOWN_SCRIPT).unwrap());
+        assert_eq!(identifier.identify(&block(&coinbase)), None);
+        pay_to(&mut coinbase, address.script_pubkey());
+        assert_eq!(identifier.identify(&block(&coinbase)), Some(pool.name));
+    }
+
+    #[test]
+    fn test_template_identifier() {
+        let identifier = TemplateIdentifier::new(&[
+            template("6a24aa21a9ed", "Witness Commitment"),
+            template("0014751e", "Solo Miner"),
+            template("not hex", "Skipped"),
+        ]);
+        assert_eq!(identifier.templates.len(), 2);
+
+        let mut coinbase = test_utils::coinbase_with_tag(b"/not a known pool/");
+        assert_eq!(identifier.identify(&block(&coinbase)), None);
+        pay_to(&mut coinbase, ScriptBuf::from_hex(UNKNOWN_SCRIPT).unwrap());
+        assert_eq!(
+            identifier.identify(&block(&coinbase)),
+            Some("Solo Miner".to_string())
+        );
+        // the first matching template wins
+        pay_to(
+            &mut coinbase,
+            ScriptBuf::from_hex(
+                "6a24aa21a9ed0000000000000000000000000000000000000000000000000000000000000000",
+            )
+            .unwrap(),
+        );
+        assert_eq!(
+            identifier.identify(&block(&coinbase)),
+            Some("Witness Commitment".to_string())
+        );
+    }
+
+    #[test]
+    fn test_identifier_chain() {
+        let network = Network::Bitcoin;
+        let pools = default_data(network);
+        let pool = pools
+            .iter()
+            .find(|pool| !pool.tags.is_empty())
+            .expect("a pool with a tag")
+            .clone();
+        let chain =
+            IdentifierChain::with_defaults(network, pools, &[template("0014751e", "Solo Miner")]);
+
+        let mut coinbase = test_utils::coinbase_with_tag(b"/not a known pool/");
+        assert_eq!(chain.identify(&block(&coinbase)), None);
+        pay_to(&mut coinbase, ScriptBuf::from_hex(UNKNOWN_SCRIPT).unwrap());
+        assert_eq!(
+            chain.identify(&block(&coinbase)),
+            Some(Identification {
+                miner: "Solo Miner".to_string(),
+                identifier: "template",
+            })
+        );
+        // the tag is checked before the templates
+        coinbase.input[0].script_sig = ScriptBuf::from_bytes(pool.tags[0].as_bytes().to_vec());
+        assert_eq!(
+            chain.identify(&block(&coinbase)),
+            Some(Identification {
+                miner: pool.name,
+                identifier: "tag",
+            })
+        );
+
+        let empty = IdentifierChain::new(vec![]);
+        assert_eq!(empty.identify(&block(&coinbase)), None);
+    }
+}
@@ -0,0 +1,210 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use log::info;
+use tokio::sync::Mutex;
+use tokio::time::{Duration, Instant};
+
+/// Limits the number of networks doing their initial header sync at the same
+/// time. Networks waiting for a slot only poll tips. Waiting networks get a
+/// slot in the order of their priority (lower first) and id.
+pub struct InitialSyncs {
+    max: usize,
+    priorities: BTreeMap<u32, u32>,
+    // Networks that didn't ask for a slot for this long, e.g. because their
+    // nodes are unreachable, don't hold back other networks. Running networks
+    // that stall for this long lose their slot.
+    wait_expiry: Duration,
+    state: Mutex<InitialSyncState>,
+}
+
+#[derive(Default)]
+struct InitialSyncState {
+    // (priority, network id) of the networks that asked for a slot, but
+    // didn't get one yet, and when they last asked.
+    waiting: BTreeMap<(u32, u32), Instant>,
+    // The networks with a slot and when they last asked.
+    running: BTreeMap<u32, Instant>,
+    done: BTreeSet<u32>,
+}
+
+impl InitialSyncs {
+    /// Takes the network ids and their priorities.
+    pub fn new(max: usize, priorities: BTreeMap<u32, u32>, wait_expiry: Duration) -> Self {
+        InitialSyncs {
+            max,
+            priorities,
+            wait_expiry,
+            state: Mutex::new(InitialSyncState::default()),
+        }
+    }
+
+    /// Returns true if the network may fetch headers in bulk: either it
+    /// finished its initial sync or it has a sync slot. Otherwise, the
+    /// network waits for a slot and is only allowed to poll tips.
+    pub async fn try_start(&self, network: u32) -> bool {
+        let mut state = self.state.lock().await;
+        let now = Instant::now();
+        if state.done.contains(&network) {
+            return true;
+        }
+        if let Some(last_asked) = state.running.get_mut(&network) {
+            *last_asked = now;
+            return true;
+        }
+        let key = (
+            self.priorities.get(&network).cloned().unwrap_or_default(),
+            network,
+        );
+        let wait_expiry = self.wait_expiry;
+        state.running.retain(|id, last_asked| {
+            let stalled = now.duration_since(*last_asked) >= wait_expiry;
+            if stalled {
+                info!(
+                    "the initial header sync of network {} stalled, freeing its slot",
+                    id
+                );
+            }
+            !stalled
+        });
+        state
+            .waiting
+            .retain(|_, last_asked| now.duration_since(*last_asked) < wait_expiry);
+        state.waiting.insert(key, now);
+        let free_slots = self.max.saturating_sub(state.running.len());
+        let waiting_before = state.waiting.range(..key).count();
+        if waiting_before < free_slots {
+            state.waiting.remove(&key);
+            state.running.insert(network, now);
+            info!("starting the initial header sync of network {}", network);
+            return true;
+        }
+        false
+    }
+
+    /// Marks the initial sync of the network as done and frees its slot.
+    pub async fn finish(&self, network: u32) {
+        let mut state = self.state.lock().await;
+        if state.done.insert(network) {
+            state.running.remove(&network);
+            state.waiting.retain(|(_, id), _| *id != network);
+            info!("finished the initial header sync of network {}", network);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use tokio::time::{sleep, Duration};
+
+    // A network that syncs for `duration` once it gets a slot.
+    async fn mock_sync(
+        syncs: Arc<InitialSyncs>,
+        network: u32,
+        duration: Duration,
+        running: Arc<AtomicUsize>,
+        max_running: Arc<AtomicUsize>,
+        order: Arc<Mutex<Vec<u32>>>,
+    ) {
+        while !syncs.try_start(network).await {
+            sleep(Duration::from_millis(1)).await;
+        }
+        order.lock().await.push(network);
+        let now_running = running.fetch_add(1, Ordering::SeqCst) + 1;
+        max_running.fetch_max(now_running, Ordering::SeqCst);
+        sleep(duration).await;
+        running.fetch_sub(1, Ordering::SeqCst);
+        syncs.finish(network).await;
+    }
+
+    #[tokio::test]
+    async fn test_initial_sync_slots() {
+        let syncs = Arc::new(InitialSyncs::new(
+            2,
+            (0..5).map(|id| (id, 0)).collect(),
+            Duration::from_secs(60),
+        ));
+        let running = Arc::new(AtomicUsize::new(0));
+        let max_running = Arc::new(AtomicUsize::new(0));
+        let order = Arc::new(Mutex::new(vec![]));
+        let tasks: Vec<_> = (0..5)
+            .map(|network| {
+                tokio::spawn(mock_sync(
+                    syncs.clone(),
+                    network,
+                    Duration::from_millis(20),
+                    running.clone(),
+                    max_running.clone(),
+                    order.clone(),
+                ))
+            })
+            .collect();
+        for task in tasks {
+            task.await.unwrap();
+        }
+        assert_eq!(max_running.load(Ordering::SeqCst), 2);
+        assert_eq!(order.lock().await.len(), 5);
+
+        // finished networks may always fetch headers
+        for network in 0..5 {
+            assert!(syncs.try_start(network).await);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_initial_sync_priority() {
+        // network 2 has the highest priority, then 0 and 1
+        let syncs = InitialSyncs::new(
+            1,
+            BTreeMap::from([(0, 1), (1, 1), (2, 0)]),
+            Duration::from_secs(60),
+        );
+        assert!(syncs.try_start(1).await);
+        // all slots are taken
+        assert!(!syncs.try_start(0).await);
+        assert!(!syncs.try_start(2).await);
+        // a running network keeps its slot
+        assert!(syncs.try_start(1).await);
+
+        syncs.finish(1).await;
+        // network 2 waits with a higher priority than network 0
+        assert!(!syncs.try_start(0).await);
+        assert!(syncs.try_start(2).await);
+        syncs.finish(2).await;
+        assert!(syncs.try_start(0).await);
+        // finishing twice doesn't free another slot
+        syncs.finish(2).await;
+        assert!(!syncs.try_start(3).await);
+    }
+
+    #[tokio::test]
+    async fn test_initial_sync_wait_expiry() {
+        let syncs = InitialSyncs::new(1, BTreeMap::new(), Duration::from_millis(50));
+        assert!(syncs.try_start(5).await);
+        // network 1 asks once and then e.g. becomes unreachable
+        assert!(!syncs.try_start(1).await);
+        syncs.finish(5).await;
+        assert!(!syncs.try_start(2).await);
+        sleep(Duration::from_millis(60)).await;
+        // network 1 doesn't hold back network 2 anymore
+        assert!(syncs.try_start(2).await);
+    }
+
+    #[tokio::test]
+    async fn test_initial_sync_stalled() {
+        let syncs = InitialSyncs::new(1, BTreeMap::new(), Duration::from_millis(50));
+        assert!(syncs.try_start(1).await);
+        assert!(!syncs.try_start(2).await);
+        // a running network asking again keeps its slot
+        sleep(Duration::from_millis(30)).await;
+        assert!(syncs.try_start(1).await);
+        sleep(Duration::from_millis(30)).await;
+        assert!(!syncs.try_start(2).await);
+        // network 1 stalls and loses its slot
+        sleep(Duration::from_millis(60)).await;
+        assert!(syncs.try_start(2).await);
+        assert!(!syncs.try_start(1).await);
+    }
+}
@@ -1,5 +1,7 @@
 use std::fmt;
 use std::str::FromStr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
 
 use crate::error::JsonRPCError;
 use crate::types::ChainTip;
@@ -9,16 +11,28 @@ use bitcoincore_rpc::bitcoin::blockdata::block::Header;
 use bitcoincore_rpc::bitcoin::Block;
 
 use base64;
+use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
 
 use log::{debug, warn};
 
 const JSON_RPC_VERSION: &str = "1.0";
-const JSON_RPC_ID: u64 = 45324;
+const REQUEST_TIMEOUT_SECONDS: u64 = 8;
+// btcd RPC error codes
+const RPC_INVALID_ADDRESS_OR_KEY: i32 = -5; // e.g. block not found
+const RPC_INVALID_PARAMETER: i32 = -8;
+const RPC_METHOD_NOT_FOUND: i32 = -32601;
 const BITCOIN_BLOCK_HEADER_HEX_LENGTH: usize = 80 * 2;
 const BITCOIN_BLOCK_HASH_HEX_LENGTH: usize = 32 * 2;
 
+// Each request gets a fresh id so that responses can't be mixed up.
+static NEXT_JSON_RPC_ID: AtomicU64 = AtomicU64::new(1);
+
+fn next_id() -> u64 {
+    NEXT_JSON_RPC_ID.fetch_add(1, Ordering::Relaxed)
+}
+
 #[derive(Serialize, Debug)]
 struct Request {
     jsonrpc: String,
@@ -39,6 +53,22 @@ impl fmt::Display for Error {
     }
 }
 
+impl Error {
+    // Maps the btcd error codes callers might want to handle differently.
+    fn to_jsonrpc_error(&self, req_method: &str) -> JsonRPCError {
+        let message = format!(
+            "JSON RPC response for request '{}' contains error: {}",
+            req_method, self
+        );
+        match self.code {
+            RPC_INVALID_ADDRESS_OR_KEY => JsonRPCError::NotFound(message),
+            RPC_INVALID_PARAMETER => JsonRPCError::InvalidParameter(message),
+            RPC_METHOD_NOT_FOUND => JsonRPCError::MethodNotFound(message),
+            _ => JsonRPCError::JsonRpc(message),
+        }
+    }
+}
+
 #[derive(Deserialize)]
 struct Response<T> {
     jsonrpc: String,
@@ -48,12 +78,12 @@ struct Response<T> {
 }
 
 impl<T> Response<T> {
-    fn check(&self, req_method: &str) -> Option<JsonRPCError> {
-        if self.id != JSON_RPC_ID {
-            warn!(
-                "JSON-RPC response id is {} but expected {}",
-                self.id, JSON_RPC_ID
-            );
+    fn check(&self, req_method: &str, req_id: u64) -> Option<JsonRPCError> {
+        if self.id != req_id {
+            return Some(JsonRPCError::IdMismatch {
+                expected: req_id,
+                got: self.id,
+            });
         }
         if self.jsonrpc != JSON_RPC_VERSION {
             warn!(
@@ -61,172 +91,305 @@ impl<T> Response<T> {
                 self.jsonrpc, JSON_RPC_VERSION
             );
         }
-        if let Some(error) = self.error.clone() {
-            return Some(JsonRPCError::JsonRpc(format!(
-                "JSON RPC response for request '{}' contains error: {}",
-                req_method, error
-            )));
+        if let Some(error) = &self.error {
+            return Some(error.to_jsonrpc_error(req_method));
         }
         None
     }
 }
 
-pub fn btcd_chaintips(
+// A btcd JSON-RPC client. Connections are kept alive and reused between
+// requests.
+#[derive(Clone)]
+pub struct BtcdClient {
     url: String,
     user: String,
-    password: String,
-) -> Result<Vec<ChainTip>, JsonRPCError> {
-    const METHOD: &str = "getchaintips";
+    auth_header: String,
+    agent: ureq::Agent,
+}
 
-    let res = request(METHOD.to_string(), vec![], url, user, password)?;
-    let jsonrpc_response: Response<Vec<ChainTip>> = res.json()?;
-    if let Some(e) = jsonrpc_response.check(METHOD) {
-        return Err(e);
+impl BtcdClient {
+    pub fn new(url: String, user: String, password: String) -> Self {
+        let token = format!("{}:{}", user, password);
+        BtcdClient {
+            url,
+            user,
+            auth_header: format!("Basic {}", base64::encode(&token)),
+            agent: ureq::AgentBuilder::new()
+                .timeout(Duration::from_secs(REQUEST_TIMEOUT_SECONDS))
+                .build(),
+        }
     }
 
-    if let Some(response) = jsonrpc_response.result {
-        return Ok(response);
-    } else {
-        return Err(JsonRPCError::JsonRpc(format!(
-            "JSON RPC response for request '{}' was empty.",
-            METHOD
-        )));
+    pub fn chaintips(&self) -> Result<Vec<ChainTip>, JsonRPCError> {
+        const METHOD: &str = "getchaintips";
+
+        match self.call(METHOD, vec![])? {
+            Some(tips) => Ok(tips),
+            None => Err(JsonRPCError::JsonRpc(format!(
+                "JSON RPC response for request '{}' was empty.",
+                METHOD
+            ))),
+        }
     }
-}
 
-pub fn btcd_blockheader(
-    url: String,
-    user: String,
-    password: String,
-    hash: String,
-) -> Result<Header, JsonRPCError> {
-    const METHOD: &str = "getblockheader";
-    const PARAM_VERBOSE: bool = false;
+    pub fn blockheader(&self, hash: String) -> Result<Header, JsonRPCError> {
+        const METHOD: &str = "getblockheader";
+        const PARAM_VERBOSE: bool = false;
+
+        let header_hex: String = self
+            .call(METHOD, vec![Value::from(hash), Value::from(PARAM_VERBOSE)])?
+            .unwrap_or_default();
+
+        if header_hex.len() != BITCOIN_BLOCK_HEADER_HEX_LENGTH {
+            return Err(JsonRPCError::RpcUnexpectedResponseContents(format!(
+                "JSON RPC response for request '{}' has not the correct length for a Bitcoin block header. Expected {} hex chars but got {} chars. Content: {}",
+                METHOD, BITCOIN_BLOCK_HEADER_HEX_LENGTH, header_hex.len(), header_hex
+            )));
+        }
 
-    let res = request(
-        METHOD.to_string(),
-        vec![Value::from(hash), Value::from(PARAM_VERBOSE)],
-        url,
-        user,
-        password,
-    )?;
-    let jsonrpc_response: Response<String> = res.json()?;
-    if let Some(e) = jsonrpc_response.check(METHOD) {
-        return Err(e);
+        let header_bytes = hex::decode(header_hex)?;
+        let header: Header = bitcoin::consensus::deserialize(&header_bytes)?;
+        Ok(header)
     }
 
-    let header_hex = jsonrpc_response.result.unwrap_or_default();
+    pub fn block(&self, hash: String) -> Result<Block, JsonRPCError> {
+        const METHOD: &str = "getblock";
+        const PARAM_VERBOSE: i8 = 0; // requests the raw block
 
-    if header_hex.len() != BITCOIN_BLOCK_HEADER_HEX_LENGTH {
-        return Err(JsonRPCError::RpcUnexpectedResponseContents(format!(
-            "JSON RPC response for request '{}' has not the correct length for a Bitcoin block header. Expected {} hex chars but got {} chars. Content: {}",
-            METHOD, BITCOIN_BLOCK_HEADER_HEX_LENGTH, header_hex.len(), header_hex
-        )));
+        let block_hex: String = self
+            .call(METHOD, vec![Value::from(hash), Value::from(PARAM_VERBOSE)])?
+            .unwrap_or_default();
+        let block_bytes = hex::decode(block_hex)?;
+        let block: Block = bitcoin::consensus::deserialize(&block_bytes)?;
+        Ok(block)
     }
 
-    let header_bytes = hex::decode(header_hex)?;
+    pub fn blockhash(&self, height: u64) -> Result<bitcoin::BlockHash, JsonRPCError> {
+        const METHOD: &str = "getblockhash";
 
-    let header: Header = bitcoin::consensus::deserialize(&header_bytes)?;
-    return Ok(header);
-}
+        let hash_hex: String = self
+            .call(METHOD, vec![Value::from(height)])?
+            .unwrap_or_default();
 
-pub fn btcd_block(
-    url: String,
-    user: String,
-    password: String,
-    hash: String,
-) -> Result<Block, JsonRPCError> {
-    const METHOD: &str = "getblock";
-    const PARAM_VERBOSE: i8 = 0; // requests the raw block
-
-    let res = request(
-        METHOD.to_string(),
-        vec![Value::from(hash), Value::from(PARAM_VERBOSE)],
-        url,
-        user,
-        password,
-    )?;
-    let jsonrpc_response: Response<String> = res.json()?;
-    if let Some(e) = jsonrpc_response.check(METHOD) {
-        return Err(e);
-    }
-
-    let block_hex = jsonrpc_response.result.unwrap_or_default();
-    let block_bytes = hex::decode(block_hex)?;
-    let block: Block = bitcoin::consensus::deserialize(&block_bytes)?;
-    Ok(block)
+        if hash_hex.len() != BITCOIN_BLOCK_HASH_HEX_LENGTH {
+            return Err(JsonRPCError::RpcUnexpectedResponseContents(format!(
+                "JSON RPC response for request '{}' has not the correct length for a Bitcoin block hash. Expected {} hex chars but got {} chars. Content: {}",
+                METHOD, BITCOIN_BLOCK_HASH_HEX_LENGTH, hash_hex.len(), hash_hex
+            )));
+        }
+
+        Ok(bitcoin::BlockHash::from_str(&hash_hex)?)
+    }
+
+    // Sends the request and returns the checked result of the response.
+    fn call<T: DeserializeOwned>(
+        &self,
+        method: &str,
+        params: Vec<Value>,
+    ) -> Result<Option<T>, JsonRPCError> {
+        let jsonrpc_request = Request {
+            jsonrpc: String::from(JSON_RPC_VERSION),
+            id: next_id(),
+            method: method.to_string(),
+            params,
+        };
+
+        debug!(
+            "JSON-RPC request with user='{}': {:?}",
+            self.user, jsonrpc_request
+        );
+
+        let res = match self
+            .agent
+            .post(&self.url)
+            .set("Authorization", &self.auth_header)
+            .set("content-type", "plain/text")
+            .send_json(&jsonrpc_request)
+        {
+            Ok(res) => res,
+            Err(ureq::Error::Status(code, res)) => {
+                let status_text = res.status_text().to_string();
+                return Err(JsonRPCError::Http(format!(
+                    "HTTP request failed: {} {}: {}",
+                    code,
+                    status_text,
+                    res.into_string().unwrap_or_default()
+                )));
+            }
+            Err(e) => return Err(e.into()),
+        };
+
+        let body = res.into_string()?;
+        debug!("JSON-RPC response for {}: {}", method, body);
+
+        let jsonrpc_response: Response<T> = serde_json::from_str(&body)?;
+        if let Some(e) = jsonrpc_response.check(method, jsonrpc_request.id) {
+            return Err(e);
+        }
+        Ok(jsonrpc_response.result)
+    }
 }
 
-pub fn btcd_blockhash(
-    url: String,
-    user: String,
-    password: String,
-    height: u64,
-) -> Result<bitcoin::BlockHash, JsonRPCError> {
-    const METHOD: &str = "getblockhash";
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::ChainTipStatus;
+    use bitcoincore_rpc::bitcoin::hashes::Hash;
+    use serde_json::json;
+    use std::io::{BufRead, BufReader, Read, Write};
+    use std::net::{TcpListener, TcpStream};
+    use std::sync::atomic::AtomicUsize;
+    use std::sync::Arc;
+    use std::thread;
+
+    // A minimal HTTP/1.1 JSON-RPC server that answers every request with the
+    // given result. Returns its URL and a counter of accepted connections.
+    fn mock_server(result: Value) -> (String, Arc<AtomicUsize>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/", listener.local_addr().unwrap());
+        let connections = Arc::new(AtomicUsize::new(0));
+        let connections_clone = connections.clone();
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                connections_clone.fetch_add(1, Ordering::SeqCst);
+                let result = result.clone();
+                thread::spawn(move || serve(stream.unwrap(), result));
+            }
+        });
+        (url, connections)
+    }
 
-    let res = request(
-        METHOD.to_string(),
-        vec![Value::from(height)],
-        url,
-        user,
-        password,
-    )?;
-    let jsonrpc_response: Response<String> = res.json()?;
-    if let Some(e) = jsonrpc_response.check(METHOD) {
-        return Err(e);
+    fn serve(stream: TcpStream, result: Value) {
+        let mut reader = BufReader::new(stream.try_clone().unwrap());
+        let mut writer = stream;
+        loop {
+            let mut content_length = 0;
+            loop {
+                let mut line = String::new();
+                if reader.read_line(&mut line).unwrap_or(0) == 0 {
+                    return; // connection closed
+                }
+                let line = line.trim_end();
+                if line.is_empty() {
+                    break;
+                }
+                if let Some((name, value)) = line.split_once(':') {
+                    if name.eq_ignore_ascii_case("content-length") {
+                        content_length = value.trim().parse().unwrap();
+                    }
+                }
+            }
+            let mut body = vec![0u8; content_length];
+            reader.read_exact(&mut body).unwrap();
+            let request: Value = serde_json::from_slice(&body).unwrap();
+            let response = json!({
+                "jsonrpc": JSON_RPC_VERSION,
+                "result": result,
+                "error": null,
+                "id": request["id"],
+            })
+            .to_string();
+            write!(
+                writer,
+                "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\r\n{}",
+                response.len(),
+                response
+            )
+            .unwrap();
+        }
     }
 
-    let hash_hex = jsonrpc_response.result.unwrap_or_default();
+    #[test]
+    fn test_client_reuses_connection() {
+        let hash = bitcoin::BlockHash::all_zeros();
+        let (url, connections) = mock_server(json!(hash.to_string()));
+        let client = BtcdClient::new(url, "user".to_string(), "password".to_string());
+        for height in 0..10 {
+            assert_eq!(client.blockhash(height).unwrap(), hash);
+        }
+        assert_eq!(connections.load(Ordering::SeqCst), 1);
+    }
 
-    if hash_hex.len() != BITCOIN_BLOCK_HASH_HEX_LENGTH {
-        return Err(JsonRPCError::RpcUnexpectedResponseContents(format!(
-            "JSON RPC response for request '{}' has not the correct length for a Bitcoin block hash. Expected {} hex chars but got {} chars. Content: {}",
-            METHOD, BITCOIN_BLOCK_HEADER_HEX_LENGTH, hash_hex.len(), hash_hex
-        )));
+    #[test]
+    fn test_client_chaintips() {
+        let hash = bitcoin::BlockHash::all_zeros().to_string();
+        let (url, _) = mock_server(json!([
+            {"height": 100, "hash": hash, "branchlen": 0, "status": "active"},
+            {"height": 99, "hash": hash, "branchlen": 1, "status": "valid-fork"},
+        ]));
+        let client = BtcdClient::new(url, "user".to_string(), "password".to_string());
+        let tips = client.chaintips().unwrap();
+        assert_eq!(tips.len(), 2);
+        assert_eq!(tips[0].height, 100);
+        assert_eq!(tips[1].status, ChainTipStatus::ValidFork);
     }
 
-    Ok(bitcoin::BlockHash::from_str(&hash_hex)?)
-}
+    #[test]
+    fn test_client_rejects_bad_blockhash() {
+        let (url, _) = mock_server(json!("00"));
+        let client = BtcdClient::new(url, "user".to_string(), "password".to_string());
+        assert!(matches!(
+            client.blockhash(1),
+            Err(JsonRPCError::RpcUnexpectedResponseContents(_))
+        ));
+    }
 
-fn request(
-    method: String,
-    params: Vec<Value>,
-    url: String,
-    user: String,
-    password: String,
-) -> Result<minreq::Response, JsonRPCError> {
-    let jsonrpc_request = Request {
-        jsonrpc: String::from(JSON_RPC_VERSION),
-        id: JSON_RPC_ID,
-        method: method.clone(),
-        params,
-    };
-
-    let token = format!("{}:{}", user, password);
-
-    debug!(
-        "JSON-RPC request with user='{}': {:?}",
-        user, jsonrpc_request
-    );
-
-    let res = minreq::post(url.clone())
-        .with_header("Authorization", format!("Basic {}", base64::encode(&token)))
-        .with_header("content-type", "plain/text")
-        .with_json(&jsonrpc_request)?
-        .with_timeout(8)
-        .send()?;
-
-    debug!("JSON-RPC response for {}: {:?}", method, res.as_str());
-
-    if res.status_code != 200 {
-        return Err(JsonRPCError::Http(format!(
-            "HTTP request failed: {} {}: {}",
-            res.status_code,
-            res.reason_phrase,
-            res.as_str()?
-        )));
-    }
-
-    Ok(res)
+    fn check(body: &str, req_id: u64) -> Option<JsonRPCError> {
+        let response: Response<String> =
+            serde_json::from_str(body).expect("canned response should parse");
+        response.check("getblockheader", req_id)
+    }
+
+    #[test]
+    fn test_request_ids_are_unique() {
+        let a = next_id();
+        let b = next_id();
+        assert_ne!(a, b);
+    }
+
+    #[test]
+    fn test_response_ok() {
+        assert!(check(r#"{"jsonrpc":"1.0","result":"00","error":null,"id":7}"#, 7).is_none());
+    }
+
+    #[test]
+    fn test_response_id_mismatch() {
+        match check(
+            r#"{"jsonrpc":"1.0","result":"00","error":null,"id":45324}"#,
+            7,
+        ) {
+            Some(JsonRPCError::IdMismatch { expected, got }) => {
+                assert_eq!(expected, 7);
+                assert_eq!(got, 45324);
+            }
+            e => panic!("expected an id mismatch but got {:?}", e),
+        }
+    }
+
+    #[test]
+    fn test_response_error_codes() {
+        let body = |code: i32| {
+            format!(
+                r#"{{"jsonrpc":"1.0","result":null,"error":{{"code":{},"message":"msg"}},"id":7}}"#,
+                code
+            )
+        };
+        assert!(matches!(
+            check(&body(-5), 7),
+            Some(JsonRPCError::NotFound(_))
+        ));
+        assert!(matches!(
+            check(&body(-8), 7),
+            Some(JsonRPCError::InvalidParameter(_))
+        ));
+        assert!(matches!(
+            check(&body(-32601), 7),
+            Some(JsonRPCError::MethodNotFound(_))
+        ));
+        assert!(matches!(
+            check(&body(-1), 7),
+            Some(JsonRPCError::JsonRpc(_))
+        ));
+    }
 }
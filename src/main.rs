@@ -1,49 +1,131 @@
 #![cfg_attr(feature = "strict", deny(warnings))]
 
-use bitcoin_pool_identification::{default_data, PoolIdentification};
-use bitcoincore_rpc::bitcoin::{BlockHash, Network};
+use bitcoin_pool_identification::default_data;
+use bitcoincore_rpc::bitcoin::consensus::Params;
+use bitcoincore_rpc::bitcoin::{Address, BlockHash, Network, Target, Transaction};
 use bitcoincore_rpc::Error::JsonRpc;
 use env_logger::Env;
 use futures_util::StreamExt;
 use log::{debug, error, info, warn};
 use petgraph::graph::NodeIndex;
 use rusqlite::Connection;
-use std::cmp::max;
-use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet, VecDeque};
 use std::fmt;
+use std::future::Future;
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::atomic::AtomicUsize;
 use std::sync::Arc;
-use tokio::sync::mpsc::unbounded_channel;
 use tokio::sync::{broadcast, Mutex};
 use tokio::task;
 use tokio::time::{interval, interval_at, sleep, Duration, Instant};
-use tokio_stream::wrappers::BroadcastStream;
-use warp::Filter;
+use tokio_stream::wrappers::errors::BroadcastStreamRecvError;
+use warp::http::StatusCode;
+use warp::{Filter, Reply};
 
+mod accesslog;
+mod alert;
 mod api;
+mod api_doc;
+mod compression;
 mod config;
+mod cors;
 mod db;
 mod error;
 mod headertree;
+mod identify;
+mod initialsync;
 mod jsonrpc;
 mod node;
+#[cfg(feature = "nostr")]
+mod nostr;
+mod ratelimit;
 mod rss;
+#[cfg(test)]
+mod test_utils;
 mod types;
+mod websocket;
+mod zmq;
 
 use crate::config::BoxedSyncSendNode;
 use crate::error::{DbError, MainError};
+use crate::identify::{BlockInfo, IdentifierChain};
+use crate::initialsync::InitialSyncs;
+use crate::ratelimit::RateLimiter;
 use types::{
-    Cache, Caches, ChainTip, Db, Fork, HeaderInfo, HeaderInfoJson, NetworkJson, NodeData,
-    NodeDataJson, Tree,
+    unix_timestamp, Alert, AlertHistoryQuery, Cache, CacheJournal, Caches, ChainTip,
+    ChainTipStatus, ChainWork, Change, ChangeEvent, DataQuery, Db, Deployments, Fork,
+    ForkHistoryQuery, Freshness, HeaderInfo, HeaderInfoJson, HeightQuery, LastPrune, MinersQuery,
+    NetworkJson, NodeData, NodeDataJson, PoolIdQueue, PoolIdQueues, Propagation, PruneRun,
+    ReorgJson, SearchQuery, SignallingQuery, SinceHeightQuery, TipsReplay, Tree, Trees,
 };
 
 const VERSION_UNKNOWN: &str = "unknown";
 const MINER_UNKNOWN: &str = "Unknown";
 const MAX_FORKS_IN_CACHE: usize = 50;
+const MAX_REORGS_IN_CACHE: usize = 50;
+const CHANGE_CHANNEL_CAPACITY: usize = 16;
+// Miners of blocks this many blocks below the top of the header tree in the
+// cache are forgotten, if the block isn't part of the header tree.
+const RECENT_MINERS_MAX_HEIGHT_DISTANCE: u64 = 10;
+// Networks waiting for an initial sync slot that didn't poll successfully for
+// this many query intervals don't hold back other networks.
+const INITIAL_SYNC_WAIT_EXPIRY_INTERVALS: u32 = 3;
+// How often rows older than their retention are deleted from the database.
+const PRUNE_INTERVAL: Duration = Duration::from_secs(60 * 60);
+// How often the headers stored per network are counted for networks.json.
+const HEADER_COUNT_INTERVAL: Duration = Duration::from_secs(5 * 60);
+// How often clients that stopped sending requests are removed from the rate
+// limiter.
+const RATE_LIMIT_PRUNE_INTERVAL: Duration = Duration::from_secs(10 * 60);
+// Served on /favicon.ico, relative to the www_path.
+const FAVICON: &str = "img/logo.svg";
+// A network is stale if none of its nodes was polled successfully for this
+// many query intervals.
+const STALE_AFTER_POLL_INTERVALS: u64 = 5;
+// If set, tokio-console instrumentation is enabled in builds with the console
+// feature.
+#[cfg(feature = "console")]
+const ENVVAR_CONSOLE: &str = "TOKIO_CONSOLE";
+
+// Spawns a long-lived task. With the console feature and tokio_unstable, the
+// task is named so that it can be identified in tokio-console.
+#[cfg(all(feature = "console", tokio_unstable))]
+fn spawn_named<F>(name: &str, future: F) -> task::JoinHandle<F::Output>
+where
+    F: Future + Send + 'static,
+    F::Output: Send + 'static,
+{
+    task::Builder::new()
+        .name(name)
+        .spawn(future)
+        .expect("should be able to spawn a task")
+}
+
+#[cfg(not(all(feature = "console", tokio_unstable)))]
+fn spawn_named<F>(_name: &str, future: F) -> task::JoinHandle<F::Output>
+where
+    F: Future + Send + 'static,
+    F::Output: Send + 'static,
+{
+    task::spawn(future)
+}
+
+fn open_database(database_path: &Path, ephemeral: bool) -> Result<Connection, rusqlite::Error> {
+    if ephemeral {
+        Connection::open_in_memory()
+    } else {
+        Connection::open(database_path)
+    }
+}
 
 async fn startup() -> Result<(config::Config, Db, Caches), MainError> {
     let config: config::Config = match config::load_config() {
         Ok(config) => {
             info!("Configuration loaded");
+            if config.log_startup_summary {
+                info!("{}", config.summary());
+            }
             config
         }
         Err(e) => {
@@ -52,9 +134,13 @@ async fn startup() -> Result<(config::Config, Db, Caches), MainError> {
         }
     };
 
-    let connection = match Connection::open(config.database_path.clone()) {
+    let connection = match open_database(&config.database_path, config.ephemeral) {
         Ok(db) => {
-            info!("Opened database: {:?}", config.database_path);
+            if config.ephemeral {
+                info!("Using an ephemeral in-memory database. Headers won't be persisted.");
+            } else {
+                info!("Opened database: {:?}", config.database_path);
+            }
             db
         }
         Err(e) => {
@@ -82,9 +168,21 @@ async fn startup() -> Result<(config::Config, Db, Caches), MainError> {
     Ok((config, db, caches))
 }
 
-async fn populate_cache(network: &config::Network, tree: &Tree, caches: &Caches) {
-    let forks = headertree::recent_forks(&tree, MAX_FORKS_IN_CACHE).await;
-    let hij = headertree::strip_tree(&tree, network.max_interesting_heights, BTreeSet::new()).await;
+async fn populate_cache(
+    network: &config::Network,
+    tree: &Tree,
+    caches: &Caches,
+    alerts: Vec<Alert>,
+) {
+    let forks =
+        headertree::recent_forks(&tree, MAX_FORKS_IN_CACHE, network.max_fork_children).await;
+    let hij = headertree::strip_tree(
+        &tree,
+        network.max_interesting_heights,
+        BTreeSet::new(),
+        network.context_blocks,
+    )
+    .await;
     {
         let mut locked_caches = caches.lock().await;
         let node_data: NodeData = network
@@ -110,7 +208,33 @@ async fn populate_cache(network: &config::Network, tree: &Tree, caches: &Caches)
                 header_infos_json: hij.clone(),
                 node_data,
                 forks,
-                recent_miners: vec![],
+                deployment_disagreements: vec![],
+                raw_tips: if network.retain_raw_tips {
+                    Some(BTreeMap::new())
+                } else {
+                    None
+                },
+                tips_replay: if network.tips_replay_length > 0 {
+                    Some(TipsReplay::new(network.tips_replay_length))
+                } else {
+                    None
+                },
+                chain_split: alert::chain_split_from_alerts(&alerts),
+                alerts,
+                reorgs: VecDeque::new(),
+                recent_miners: HashMap::new(),
+                journal: CacheJournal::default(),
+                data_json: None,
+                last_poll_timestamp: None,
+                headers_total: None,
+                height_offset: network.height_offset,
+                propagation: if network.track_propagation {
+                    Some(Propagation::default())
+                } else {
+                    None
+                },
+                active_chain_work: None,
+                header_statuses: None,
             },
         );
     }
@@ -119,16 +243,53 @@ async fn populate_cache(network: &config::Network, tree: &Tree, caches: &Caches)
 #[tokio::main]
 async fn main() -> Result<(), MainError> {
     env_logger::Builder::from_env(Env::default().default_filter_or("info")).init();
+    #[cfg(feature = "console")]
+    if std::env::var(ENVVAR_CONSOLE).is_ok() {
+        console_subscriber::init();
+        info!("tokio-console instrumentation enabled");
+    }
+    let started = unix_timestamp();
     let (config, db, caches) = startup().await?;
+    #[cfg(not(feature = "nostr"))]
+    if config.nostr.is_some() {
+        warn!("Nostr publishing is configured, but fork-observer was built without the nostr feature. Not publishing any notes.");
+    }
 
-    // A channel to notify about tip changes via ServerSentEvents to clients.
-    let (tipchanges_tx, _) = broadcast::channel(16);
+    // Per-network channels to notify about tip changes via ServerSentEvents
+    // to clients. A burst of changes on one network doesn't cause clients to
+    // miss changes on other networks.
+    let mut change_senders: BTreeMap<u32, broadcast::Sender<ChangeEvent>> = BTreeMap::new();
     let network_infos: Vec<NetworkJson> = config.networks.iter().map(NetworkJson::new).collect();
+    let privacy: BTreeMap<u32, config::Privacy> = config
+        .networks
+        .iter()
+        .map(|network| (network.id, network.privacy.clone()))
+        .collect();
+    let feeds: BTreeMap<u32, config::Feeds> = config
+        .networks
+        .iter()
+        .map(|network| (network.id, network.feeds.clone()))
+        .collect();
     let db_clone = db.clone();
+    let mut trees: BTreeMap<u32, Tree> = BTreeMap::new();
+    let mut pool_id_queues: BTreeMap<u32, PoolIdQueue> = BTreeMap::new();
+    let initial_syncs = Arc::new(InitialSyncs::new(
+        config.max_concurrent_initial_syncs,
+        config
+            .networks
+            .iter()
+            .map(|network| (network.id, network.sync_priority))
+            .collect(),
+        config.query_interval * INITIAL_SYNC_WAIT_EXPIRY_INTERVALS,
+    ));
 
     for network in config.networks.iter().cloned() {
         let network = network.clone();
-        let (pool_id_tx, mut pool_id_rx) = unbounded_channel::<BlockHash>();
+        let (pool_id_queue, mut pool_id_rx) =
+            PoolIdQueue::new(network.pool_identification.queue_capacity());
+        pool_id_queues.insert(network.id, pool_id_queue.clone());
+        let (tipchanges_tx, _) = broadcast::channel::<ChangeEvent>(CHANGE_CHANNEL_CAPACITY);
+        change_senders.insert(network.id, tipchanges_tx.clone());
 
         info!(
             "network '{}' (id={}) has {} nodes",
@@ -150,7 +311,20 @@ async fn main() -> Result<(), MainError> {
             },
         ));
 
-        populate_cache(&network, &tree, &caches).await;
+        let alerts =
+            match db::load_alerts(db_clone.clone(), network.id, alert::MAX_ALERTS_IN_CACHE).await {
+                Ok(alerts) => alerts,
+                Err(e) => {
+                    error!(
+                        "Could not load alerts from the database {:?}: {}",
+                        config.database_path, e
+                    );
+                    return Err(e.into());
+                }
+            };
+
+        populate_cache(&network, &tree, &caches, alerts).await;
+        trees.insert(network.id, tree.clone());
 
         for node in network.nodes.iter().cloned() {
             let network = network.clone();
@@ -164,31 +338,91 @@ async fn main() -> Result<(), MainError> {
                 config.query_interval,
             );
             let db_write = db.clone();
+            let ephemeral = config.ephemeral;
             let tree_clone = tree.clone();
             let caches_clone = caches.clone();
             let tipchanges_tx_cloned = tipchanges_tx.clone();
-            let pool_id_tx_clone = pool_id_tx.clone();
+            let pool_id_queue_clone = pool_id_queue.clone();
+            let initial_syncs = initial_syncs.clone();
+            #[cfg(feature = "nostr")]
+            let nostr_config = config.nostr.clone();
 
             let mut last_tips: Vec<ChainTip> = vec![];
-            task::spawn(async move {
+            // The pow limit of the configured pool identification network.
+            // Otherwise, derived from the genesis block once it's known.
+            let mut pow_limit: Option<Target> = network
+                .pool_identification
+                .network
+                .as_ref()
+                .map(|n| Params::new(n.to_network()).max_attainable_target);
+            let task_name = format!("node-poll {}/{}", network.id, node.info().id);
+            spawn_named(&task_name, async move {
                 // Try to load the node version an update the cache with it.
-                update_cache(
-                    &caches_clone,
-                    network.id,
-                    CacheUpdate::NodeVersion {
-                        node_id: node.info().id,
-                        version: load_node_version(node.clone(), &network.name).await,
-                    },
-                )
-                .await;
+                // REST-only nodes can't report it.
+                if !node.rest_only() {
+                    update_cache(
+                        &caches_clone,
+                        network.id,
+                        CacheUpdate::NodeVersion {
+                            node_id: node.info().id,
+                            version: load_node_version(node.clone(), &network.name).await,
+                        },
+                    )
+                    .await;
+                }
 
+                // The deployments can change with every block. They are
+                // reloaded in the background when the active tip of the node
+                // changed, starting with the first poll.
+                let mut deployments_tip: Option<String> = None;
+                let mut deployments_task: Option<task::JoinHandle<()>> = None;
+
+                // Nodes pushing block notifications kick the loop to poll
+                // immediately. Polling on the interval remains as a fallback.
+                let kick = node.block_notifications();
                 loop {
                     // We specifically wait at the beginning of the loop, as we
                     // are using 'continue' on errors. If we would wait at the end,
                     // we might skip the waiting.
-                    interval.tick().await;
-                    let tips = match node.tips().await {
+                    match kick {
+                        Some(ref kick) => {
+                            tokio::select! {
+                                _ = interval.tick() => (),
+                                _ = kick.notified() => debug!(
+                                    "polling {} on network '{}' after a block notification",
+                                    node.info(),
+                                    network.name
+                                ),
+                            }
+                        }
+                        None => {
+                            interval.tick().await;
+                        }
+                    }
+                    let poll_start = Instant::now();
+                    let tips_result = node.tips().await;
+                    let tips = match tips_result {
                         Ok(tips) => {
+                            // Failed calls don't update the latencies, the
+                            // last values are kept while the node is
+                            // unreachable.
+                            update_cache(
+                                &caches_clone,
+                                network.id,
+                                CacheUpdate::NodePollLatency {
+                                    node_id: node.info().id,
+                                    latency_ms: poll_start.elapsed().as_millis() as u64,
+                                },
+                            )
+                            .await;
+                            update_cache(
+                                &caches_clone,
+                                network.id,
+                                CacheUpdate::NetworkPolled {
+                                    timestamp: unix_timestamp(),
+                                },
+                            )
+                            .await;
                             if !is_node_reachable(&caches_clone, network.id, node.info().id).await {
                                 update_cache(
                                     &caches_clone,
@@ -225,55 +459,207 @@ async fn main() -> Result<(), MainError> {
                         }
                     };
 
+                    let active_tip = tips
+                        .iter()
+                        .find(|tip| tip.status == ChainTipStatus::Active)
+                        .map(|tip| tip.hash.clone());
+                    let deployments_loading =
+                        matches!(&deployments_task, Some(task) if !task.is_finished());
+                    if !node.rest_only()
+                        && active_tip.is_some()
+                        && active_tip != deployments_tip
+                        && !deployments_loading
+                    {
+                        deployments_tip = active_tip;
+                        deployments_task = Some(spawn_named(
+                            &format!("node-deployments {}/{}", network.id, node.info().id),
+                            refresh_node_deployments(
+                                node.clone(),
+                                caches_clone.clone(),
+                                network.id,
+                                network.name.clone(),
+                            ),
+                        ));
+                    }
+
+                    if last_tips != tips && !initial_syncs.try_start(network.id).await {
+                        debug!(
+                            "Network '{}' waits for an initial sync slot. Only updating the tips of {}.",
+                            network.name,
+                            node.info()
+                        );
+                        update_cache(
+                            &caches_clone,
+                            network.id,
+                            CacheUpdate::NodeTips {
+                                node_id: node.info().id,
+                                tips: tips.clone(),
+                            },
+                        )
+                        .await;
+                        send_change(
+                            &tipchanges_tx_cloned,
+                            network.id,
+                            Change::NodeTips {
+                                node_id: node.info().id,
+                            },
+                        );
+                        continue;
+                    }
+
                     if last_tips != tips {
-                        let (new_headers, miners_needed): (Vec<HeaderInfo>, Vec<BlockHash>) =
-                            match node
-                                .new_headers(&tips, &tree_clone, network.min_fork_height)
-                                .await
-                            {
-                                Ok(headers) => headers,
-                                Err(e) => {
-                                    error!(
-                                    "Could not fetch headers from {} on network '{}' (id={}): {}",
+                        let (mut new_headers, mut miners_needed): (
+                            Vec<HeaderInfo>,
+                            Vec<BlockHash>,
+                        ) = match node
+                            .new_headers(
+                                &tips,
+                                &tree_clone,
+                                network.min_fork_height,
+                                network.max_initial_backfill,
+                            )
+                            .await
+                        {
+                            Ok(headers) => headers,
+                            Err(e) => {
+                                error!(
+                                    "Could not fetch headers from {} via {} on network '{}' (id={}): {}",
                                     node.info(),
+                                    node.transport(),
                                     network.name,
                                     network.id,
                                     e
                                 );
-                                    continue;
-                                }
-                            };
+                                continue;
+                            }
+                        };
 
-                        // Identify the miner of the new header(s)
-                        for hash in miners_needed.iter() {
-                            if let Err(e) = pool_id_tx_clone.send(*hash) {
+                        if network.verify_pow {
+                            if pow_limit.is_none() {
+                                pow_limit = headertree::network_type(&tree_clone)
+                                    .await
+                                    .map(|n| Params::new(n).max_attainable_target);
+                            }
+                            let (valid, rejected) = split_invalid_pow(new_headers, pow_limit);
+                            for header in rejected.iter() {
                                 error!(
-                                    "Could not send a block hash into the pool identification channel: {}",
-                                    e
+                                    "Rejecting header {} at height {} from {} on network '{}' (id={}): invalid proof-of-work or descendant of such a header",
+                                    header.header.block_hash(),
+                                    header.height,
+                                    node.info(),
+                                    network.name,
+                                    network.id,
                                 );
                             }
+                            let rejected: HashSet<BlockHash> =
+                                rejected.iter().map(|h| h.header.block_hash()).collect();
+                            miners_needed.retain(|hash| !rejected.contains(hash));
+                            new_headers = valid;
+                        }
+
+                        // Identify the miner of the new header(s)
+                        for hash in miners_needed.iter() {
+                            pool_id_queue_clone.send(*hash).await;
                         }
 
+                        // Historical forks and reorgs loaded on the first poll
+                        // aren't announced.
+                        let previous_active_tip: Option<ChainTip> = last_tips
+                            .iter()
+                            .find(|tip| tip.status == ChainTipStatus::Active)
+                            .cloned();
                         last_tips = tips.clone();
                         let db_write = db_write.clone();
                         // We want to avoid stripping the tree (strip_tree()) if it didn't change.
                         // Keeping tracking of changes:
                         let mut tree_changed = false;
                         if !new_headers.is_empty() {
-                            tree_changed =
-                                insert_new_headers_into_tree(&tree_clone, &new_headers).await;
-
-                            match db::write_to_db(&new_headers, db_write, network.id).await {
-                                Ok(_) => info!(
-                                    "Written {} headers to database for network '{}' by node {}",
-                                    new_headers.len(),
-                                    network.name,
-                                    node.info()
-                                ),
+                            tree_changed = match store_new_headers(
+                                &tree_clone,
+                                db_write.clone(),
+                                network.id,
+                                &new_headers,
+                                ephemeral,
+                            )
+                            .await
+                            {
+                                Ok(tree_changed) => {
+                                    if !ephemeral {
+                                        info!(
+                                            "Written {} headers to database for network '{}' by node {}",
+                                            new_headers.len(),
+                                            network.name,
+                                            node.info()
+                                        );
+                                    }
+                                    tree_changed
+                                }
                                 Err(e) => {
                                     error!("Could not write new headers for network '{}' by node {} to database: {}", network.name, node.info(), e);
                                     return MainError::Db(e);
                                 }
+                            };
+                            // The backfill is bounded by max_initial_backfill.
+                            // Load the next headers on the next poll even if the
+                            // tips didn't change.
+                            if !active_tip_in_tree(&tips, &tree_clone).await {
+                                last_tips.clear();
+                            }
+                        }
+                        if active_tip_in_tree(&tips, &tree_clone).await {
+                            initial_syncs.finish(network.id).await;
+                        }
+
+                        if let (Some(old_tip), Some(new_tip)) = (
+                            previous_active_tip.as_ref(),
+                            tips.iter().find(|tip| tip.status == ChainTipStatus::Active),
+                        ) {
+                            let (old_hash, new_hash) = (old_tip.block_hash(), new_tip.block_hash());
+                            if let Some(fork_point) =
+                                headertree::reorg_fork_point(&tree_clone, &old_hash, &new_hash)
+                                    .await
+                            {
+                                let reorg = ReorgJson::new(
+                                    node.info().id,
+                                    unix_timestamp(),
+                                    old_tip,
+                                    new_tip,
+                                    fork_point,
+                                );
+                                info!(
+                                    "Node {} on network '{}' reorged from {} at height {} to {} at height {} (depth {})",
+                                    node.info(),
+                                    network.name,
+                                    reorg.old_tip_hash,
+                                    reorg.old_tip_height,
+                                    reorg.new_tip_hash,
+                                    reorg.new_tip_height,
+                                    reorg.depth
+                                );
+                                update_cache(
+                                    &caches_clone,
+                                    network.id,
+                                    CacheUpdate::Reorg { reorg },
+                                )
+                                .await;
+
+                                #[cfg(feature = "nostr")]
+                                if let Some(nostr_config) = nostr_config.as_ref() {
+                                    let node_name = if network.privacy.hide_node_names {
+                                        format!("Node {}", node.info().id)
+                                    } else {
+                                        node.info().name
+                                    };
+                                    nostr::publish(
+                                        nostr_config,
+                                        nostr::reorg_note(
+                                            &network.name,
+                                            &node_name,
+                                            (&old_hash, old_tip.height),
+                                            (&new_hash, new_tip.height),
+                                        ),
+                                    );
+                                }
                             }
                         }
 
@@ -287,6 +673,42 @@ async fn main() -> Result<(), MainError> {
                             },
                         )
                         .await;
+                        send_change(
+                            &tipchanges_tx_cloned,
+                            network.id,
+                            Change::NodeTips {
+                                node_id: node.info().id,
+                            },
+                        );
+                        if network.retain_raw_tips || network.tips_replay_length > 0 {
+                            update_cache(
+                                &caches_clone,
+                                network.id,
+                                CacheUpdate::NodeRawTips {
+                                    node_id: node.info().id,
+                                    tips: tips.clone(),
+                                },
+                            )
+                            .await;
+                        }
+
+                        if let Some(alert) =
+                            alert::check_chain_split(network.id, &caches_clone, &tree_clone).await
+                        {
+                            warn!(
+                                "Alert on network '{}' (id={}): {}",
+                                network.name, network.id, alert.kind
+                            );
+                            if let Err(e) = db::write_alert(&alert, db_write.clone()).await {
+                                error!(
+                                    "Could not write alert on network '{}' to database: {}",
+                                    network.name, e
+                                );
+                            }
+                            if let Err(e) = tipchanges_tx_cloned.send(ChangeEvent::Alert(alert)) {
+                                debug!("Could not send alert into the channel: {}", e);
+                            }
+                        }
 
                         if tree_changed {
                             let mut tip_heights: BTreeSet<u64> =
@@ -298,10 +720,40 @@ async fn main() -> Result<(), MainError> {
                                 &tree_clone,
                                 network.max_interesting_heights,
                                 tip_heights,
+                                network.context_blocks,
+                            )
+                            .await;
+                            let forks = headertree::recent_forks(
+                                &tree_clone,
+                                MAX_FORKS_IN_CACHE,
+                                network.max_fork_children,
                             )
                             .await;
-                            let forks =
-                                headertree::recent_forks(&tree_clone, MAX_FORKS_IN_CACHE).await;
+                            if let Err(e) = db::write_forks(
+                                db_write.clone(),
+                                network.id,
+                                &forks,
+                                unix_timestamp(),
+                            )
+                            .await
+                            {
+                                error!(
+                                    "Could not write forks on network '{}' to database: {}",
+                                    network.name, e
+                                );
+                            }
+
+                            #[cfg(feature = "nostr")]
+                            if let (Some(nostr_config), Some(_)) =
+                                (nostr_config.as_ref(), previous_active_tip.as_ref())
+                            {
+                                for fork in nostr::new_forks(&forks, &new_headers) {
+                                    nostr::publish(
+                                        nostr_config,
+                                        nostr::fork_note(&network.name, fork),
+                                    );
+                                }
+                            }
 
                             update_cache(
                                 &caches_clone,
@@ -313,7 +765,9 @@ async fn main() -> Result<(), MainError> {
                             )
                             .await;
 
-                            match tipchanges_tx_cloned.clone().send(network.id) {
+                            match tipchanges_tx_cloned.clone().send(ChangeEvent::TipChanged {
+                                network_id: network.id,
+                            }) {
                                 Ok(_) => debug!("Sent a tip_changed notification."),
                                 Err(e) => {
                                     debug!(
@@ -322,6 +776,13 @@ async fn main() -> Result<(), MainError> {
                                     )
                                 }
                             };
+                            if let Some(change) = new_block_change(&new_headers) {
+                                send_change(&tipchanges_tx_cloned, network.id, change);
+                            }
+                        }
+
+                        if network.track_chain_work {
+                            refresh_active_chain_work(&caches_clone, &tree_clone, network.id).await;
                         }
                     }
                 }
@@ -334,8 +795,8 @@ async fn main() -> Result<(), MainError> {
         let tree_clone = tree.clone();
         let caches_clone = caches.clone();
         let network_clone = network.clone();
-        let pool_id_tx_clone = pool_id_tx.clone();
-        task::spawn(async move {
+        let pool_id_queue_clone = pool_id_queue.clone();
+        spawn_named(&format!("pool-id-backfill {}", network.id), async move {
             sleep(Duration::from_secs(5 * 60)).await;
 
             let tip_heights: BTreeSet<u64> = tip_heights(network_clone.id, &caches_clone).await;
@@ -348,27 +809,36 @@ async fn main() -> Result<(), MainError> {
 
             let tree_locked = tree_clone.lock().await;
 
+            // The backfill has a lower priority than new blocks: headers that
+            // don't fit into the queue are skipped.
+            let mut dropped: usize = 0;
             for header_info in tree_locked
                 .0
                 .raw_nodes()
                 .iter()
                 .filter(|node| node.weight.miner == "" || node.weight.miner == MINER_UNKNOWN)
                 .filter(|node| {
+                    // the headers kept around the interesting heights by strip_tree
                     let h = node.weight.height;
-                    interesting_heights.contains(&h)
-                        || interesting_heights.contains(&(h + 1))
-                        || interesting_heights.contains(&(h + 2))
-                        || interesting_heights.contains(&(max(h, 1) - 1))
+                    let context = network_clone.context_blocks;
+                    let lowest = h.saturating_sub(context.after);
+                    let highest = h.saturating_add(context.before);
+                    interesting_heights
+                        .iter()
+                        .any(|i| *i >= lowest && *i <= highest)
                 })
                 .map(|node| node.weight.clone())
             {
-                if let Err(e) = pool_id_tx_clone.send(header_info.header.block_hash()) {
-                    error!(
-                        "Could not send block hash into the pool identification channel: {}",
-                        e
-                    );
+                if !pool_id_queue_clone.try_send(header_info.header.block_hash()) {
+                    dropped += 1;
                 }
             }
+            if dropped > 0 {
+                warn!(
+                    "Skipped the pool identification of {} blocks on network '{}' (id={}): the queue is full",
+                    dropped, network_clone.name, network_clone.id
+                );
+            }
         });
 
         // A thread that identifies miners for each header send into the pool
@@ -377,12 +847,16 @@ async fn main() -> Result<(), MainError> {
         let db_clone2 = db_clone.clone();
         let caches_clone = caches.clone();
         let network_clone = network.clone();
-        task::spawn(async move {
+        spawn_named(&format!("pool-id-worker {}", network.id), async move {
             let pool_identification_network = match network.pool_identification.network {
                 Some(ref network) => network.to_network(),
                 None => Network::Regtest,
             };
-            let pool_identification_data = default_data(pool_identification_network);
+            let identifier_chain = IdentifierChain::with_defaults(
+                pool_identification_network,
+                default_data(pool_identification_network),
+                &network.pool_identification.output_templates,
+            );
 
             let limit = 100;
             let mut buffer: Vec<BlockHash> = Vec::with_capacity(limit);
@@ -417,14 +891,53 @@ async fn main() -> Result<(), MainError> {
                     }
 
                     let mut miner = MINER_UNKNOWN.to_string();
+                    let mut identified_by: Option<&'static str> = None;
+                    // checked on the first coinbase we get
+                    let mut watched: Option<Vec<String>> = None;
                     for node in network_clone.nodes.iter().cloned() {
                         match node.coinbase(&header_info.header.block_hash()).await {
                             Ok(coinbase) => {
-                                miner = match coinbase.identify_pool(
-                                    pool_identification_network,
-                                    &pool_identification_data,
-                                ) {
-                                    Some(result) => result.pool.name,
+                                if watched.is_none() {
+                                    watched = Some(watched_payouts(
+                                        &coinbase,
+                                        pool_identification_network,
+                                        &network_clone.watch_coinbase_addresses,
+                                    ));
+                                }
+                                let block = BlockInfo {
+                                    hash: header_info.header.block_hash(),
+                                    height: header_info.height,
+                                    coinbase: &coinbase,
+                                };
+                                miner = match identifier_chain.identify(&block) {
+                                    Some(identification) => {
+                                        if is_coinbase_tag_denylisted(
+                                            &coinbase,
+                                            &network_clone
+                                                .pool_identification
+                                                .coinbase_tag_denylist,
+                                        ) {
+                                            info!(
+                                                "Not trusting the denylisted coinbase tag of block {} identified as {} by {}",
+                                                block.hash,
+                                                identification.miner,
+                                                identification.identifier
+                                            );
+                                            MINER_UNKNOWN.to_string()
+                                        } else {
+                                            debug!(
+                                                "Identified the miner of block {} at height {} by {}: {}",
+                                                block.hash,
+                                                block.height,
+                                                identification.identifier,
+                                                identification.miner
+                                            );
+                                            identified_by = Some(identification.identifier);
+                                            network_clone
+                                                .pool_identification
+                                                .canonical_miner(identification.miner)
+                                        }
+                                    }
                                     None => MINER_UNKNOWN.to_string(),
                                 };
                             }
@@ -449,6 +962,46 @@ async fn main() -> Result<(), MainError> {
                     }
                     header_info.update_miner(miner);
 
+                    if let Some(identifier) = identified_by {
+                        if let Err(e) = db::write_miner_identification(
+                            db_clone2.clone(),
+                            network.id,
+                            &header_info.header.block_hash(),
+                            identifier,
+                        )
+                        .await
+                        {
+                            warn!(
+                                "Could not write the miner identification of block {}: {}",
+                                header_info.header.block_hash(),
+                                e
+                            );
+                        }
+                    }
+
+                    if let Some(watched) = watched.filter(|watched| !watched.is_empty()) {
+                        info!(
+                            "Coinbase of block {} at height {} pays to watched addresses: {}",
+                            header_info.header.block_hash(),
+                            header_info.height,
+                            watched.join(", ")
+                        );
+                        if let Err(e) = db::write_watched_payouts(
+                            db_clone2.clone(),
+                            network.id,
+                            &header_info.header.block_hash(),
+                            &watched,
+                        )
+                        .await
+                        {
+                            warn!(
+                                "Could not write the watched payouts of block {}: {}",
+                                header_info.header.block_hash(),
+                                e
+                            );
+                        }
+                    }
+
                     // update in-memory graph
                     {
                         let mut tree_locked = tree_clone.lock().await;
@@ -481,6 +1034,90 @@ async fn main() -> Result<(), MainError> {
         });
     }
 
+    let trees: Trees = Arc::new(trees);
+
+    let last_prune: LastPrune = Arc::new(Mutex::new(None));
+    let retention_tables = config.retention.tables();
+    if !retention_tables.is_empty() {
+        let db_clone = db.clone();
+        let last_prune_clone = last_prune.clone();
+        spawn_named("db-prune", async move {
+            let mut interval = interval(PRUNE_INTERVAL);
+            loop {
+                interval.tick().await;
+                let mut deleted: BTreeMap<String, usize> = BTreeMap::new();
+                for (table, retention) in retention_tables.iter() {
+                    let cutoff = unix_timestamp().saturating_sub(retention.as_secs());
+                    match db::prune(db_clone.clone(), table, cutoff).await {
+                        Ok(count) => {
+                            info!(
+                                "Pruned {} rows older than {}s from the '{}' table",
+                                count,
+                                retention.as_secs(),
+                                table
+                            );
+                            deleted.insert(table.to_string(), count);
+                        }
+                        Err(e) => error!("Could not prune the '{}' table: {}", table, e),
+                    }
+                }
+                *last_prune_clone.lock().await = Some(PruneRun {
+                    timestamp: unix_timestamp(),
+                    deleted,
+                });
+            }
+        });
+    }
+
+    {
+        let db_clone = db.clone();
+        let trees_clone = trees.clone();
+        let caches_clone = caches.clone();
+        let ephemeral = config.ephemeral;
+        spawn_named("header-count", async move {
+            let mut interval = interval(HEADER_COUNT_INTERVAL);
+            loop {
+                interval.tick().await;
+                for (network_id, tree) in trees_clone.iter() {
+                    // Ephemeral headers aren't written to the database, but
+                    // all of them are in the header tree.
+                    let count = if ephemeral {
+                        tree.lock().await.0.node_count() as u64
+                    } else {
+                        match db::count_headers(db_clone.clone(), *network_id).await {
+                            Ok(count) => count,
+                            Err(e) => {
+                                error!(
+                                    "Could not count the headers of network {}: {}",
+                                    network_id, e
+                                );
+                                continue;
+                            }
+                        }
+                    };
+                    update_cache(
+                        &caches_clone,
+                        *network_id,
+                        CacheUpdate::HeadersTotal { count },
+                    )
+                    .await;
+                }
+            }
+        });
+    }
+
+    let rate_limiter = config.rate_limit_per_minute.map(RateLimiter::new);
+    if let Some(rate_limiter) = rate_limiter.clone() {
+        spawn_named("rate-limit-prune", async move {
+            let mut interval = interval(RATE_LIMIT_PRUNE_INTERVAL);
+            loop {
+                interval.tick().await;
+                let pruned = rate_limiter.prune(Instant::now()).await;
+                debug!("Pruned {} idle clients from the rate limiter", pruned);
+            }
+        });
+    }
+
     let www_dir = warp::get()
         .and(warp::path("static"))
         .and(warp::fs::dir(config.www_path.clone()));
@@ -490,80 +1127,402 @@ async fn main() -> Result<(), MainError> {
     let fullscreen_html = warp::get()
         .and(warp::path!("fullscreen"))
         .and(warp::fs::file(config.www_path.join("fullscreen.html")));
+    let favicon = warp::get()
+        .and(warp::path!("favicon.ico"))
+        .and(warp::fs::file(config.www_path.join(FAVICON)));
 
     let info_json = warp::get()
         .and(warp::path!("api" / "info.json"))
         .and(api::with_footer(config.footer_html.clone()))
         .and_then(api::info_response);
 
+    let openapi_doc = Arc::new(api_doc::openapi());
+    let openapi_json = warp::get()
+        .and(warp::path!("api" / "openapi.json"))
+        .map(move || warp::reply::json(&*openapi_doc));
+
     let data_json = warp::get()
         .and(warp::path!("api" / u32 / "data.json"))
+        .and(warp::query::<DataQuery>())
+        .and(warp::header::optional::<String>("if-none-match"))
+        .and(compression::accepts_gzip(config.compress_responses))
         .and(api::with_caches(caches.clone()))
+        .and(api::with_privacy(privacy.clone()))
+        .and(api::with_hash_prefix_length(
+            config.compact_hash_prefix_length,
+        ))
         .and_then(api::data_response);
 
+    let data_v2_json = warp::get()
+        .and(warp::path!("api" / u32 / "data.v2.json"))
+        .and(api::with_caches(caches.clone()))
+        .and(api::with_privacy(privacy.clone()))
+        .and_then(api::data_v2_response);
+
+    let nodes_json = warp::get()
+        .and(warp::path!("api" / u32 / "nodes.json"))
+        .and(api::with_caches(caches.clone()))
+        .and(api::with_privacy(privacy.clone()))
+        .and_then(api::nodes_response);
+
+    let signalling_json = warp::get()
+        .and(warp::path!("api" / u32 / "signalling.json"))
+        .and(warp::query::<SignallingQuery>())
+        .and(api::with_caches(caches.clone()))
+        .and(api::with_trees(trees.clone()))
+        .and_then(api::signalling_response);
+
+    let block_json = warp::get()
+        .and(warp::path!("api" / u32 / "block" / String))
+        .and(api::with_caches(caches.clone()))
+        .and(api::with_trees(trees.clone()))
+        .and(api::with_db(db.clone()))
+        .and_then(api::block_response);
+
+    let blocks_by_height_json = warp::get()
+        .and(warp::path!("api" / u32 / "height" / u64))
+        .and(api::with_caches(caches.clone()))
+        .and(api::with_trees(trees.clone()))
+        .and(api::with_db(db.clone()))
+        .and_then(api::blocks_by_height_response);
+
+    let search_json = warp::get()
+        .and(warp::path!("api" / "search"))
+        .and(warp::query::<SearchQuery>())
+        .and(api::with_trees(trees.clone()))
+        .and(api::with_caches(caches.clone()))
+        .and_then(api::search_response);
+
+    let header_hex = warp::get()
+        .and(warp::path!("api" / u32 / "block" / String / "header.hex"))
+        .and(api::with_trees(trees.clone()))
+        .and(api::with_db(db.clone()))
+        .and_then(api::header_hex_response);
+
+    let headers_hex_by_height = warp::get()
+        .and(warp::path!("api" / u32 / "header.hex"))
+        .and(warp::query::<HeightQuery>())
+        .and(api::with_trees(trees.clone()))
+        .and(api::with_db(db.clone()))
+        .and_then(api::headers_hex_by_height_response);
+
+    let forks_json = warp::get()
+        .and(warp::path!("api" / u32 / "forks.json"))
+        .and(warp::query::<SinceHeightQuery>())
+        .and(warp::header::optional::<String>("if-none-match"))
+        .and(api::with_caches(caches.clone()))
+        .and(api::with_networks(network_infos.clone()))
+        .and_then(api::forks_response);
+
+    let fork_history_json = warp::get()
+        .and(warp::path!("api" / u32 / "forkhistory.json"))
+        .and(warp::query::<ForkHistoryQuery>())
+        .and(api::with_caches(caches.clone()))
+        .and(api::with_db(db.clone()))
+        .and(api::with_networks(network_infos.clone()))
+        .and_then(api::fork_history_response);
+
+    let alert_history_json = warp::get()
+        .and(warp::path!("api" / u32 / "alerts.json"))
+        .and(warp::query::<AlertHistoryQuery>())
+        .and(api::with_caches(caches.clone()))
+        .and(api::with_db(db.clone()))
+        .and(api::with_networks(network_infos.clone()))
+        .and_then(api::alert_history_response);
+
+    let reorgs_json = warp::get()
+        .and(warp::path!("api" / u32 / "reorgs.json"))
+        .and(api::with_caches(caches.clone()))
+        .and(api::with_networks(network_infos.clone()))
+        .and_then(api::reorgs_response);
+
+    let invalid_json = warp::get()
+        .and(warp::path!("api" / u32 / "invalid.json"))
+        .and(warp::query::<SinceHeightQuery>())
+        .and(api::with_caches(caches.clone()))
+        .and(api::with_privacy(privacy.clone()))
+        .and(api::with_networks(network_infos.clone()))
+        .and_then(api::invalid_response);
+
+    let miners_json = warp::get()
+        .and(warp::path!("api" / u32 / "miners.json"))
+        .and(warp::query::<MinersQuery>())
+        .and(api::with_caches(caches.clone()))
+        .and(api::with_networks(network_infos.clone()))
+        .and_then(api::miners_response);
+
+    let consensus_json = warp::get()
+        .and(warp::path!("api" / u32 / "consensus.json"))
+        .and(api::with_caches(caches.clone()))
+        .and(api::with_trees(trees.clone()))
+        .and_then(api::consensus_response);
+
+    let node_chaintips_json = warp::get()
+        .and(warp::path!("api" / u32 / "node" / u32 / "chaintips.json"))
+        .and(api::with_caches(caches.clone()))
+        .and_then(api::node_chaintips_response);
+
+    let node_tips_replay_json = warp::get()
+        .and(warp::path!(
+            "api" / u32 / "node" / u32 / "tips" / "replay.json"
+        ))
+        .and(api::with_caches(caches.clone()))
+        .and_then(api::node_tips_replay_response);
+
     let forks_rss = warp::get()
-        .and(warp::path!("rss" / u32 / "forks.xml"))
+        .and(rss::feed_path(rss::FeedKind::Forks, feeds.clone()))
         .and(api::with_caches(caches.clone()))
         .and(api::with_networks(network_infos.clone()))
         .and(rss::with_rss_base_url(config.rss_base_url.clone()))
         .and_then(rss::forks_response);
 
     let invalid_blocks_rss = warp::get()
-        .and(warp::path!("rss" / u32 / "invalid.xml"))
+        .and(rss::feed_path(rss::FeedKind::Invalid, feeds.clone()))
         .and(api::with_caches(caches.clone()))
+        .and(api::with_trees(trees.clone()))
         .and(api::with_networks(network_infos.clone()))
         .and(rss::with_rss_base_url(config.rss_base_url.clone()))
+        .and(api::with_privacy(privacy.clone()))
         .and_then(rss::invalid_blocks_response);
 
+    let stalled_nodes_rss = warp::get()
+        .and(rss::feed_path(rss::FeedKind::Stalled, feeds.clone()))
+        .and(api::with_caches(caches.clone()))
+        .and(api::with_networks(network_infos.clone()))
+        .and(rss::with_rss_base_url(config.rss_base_url.clone()))
+        .and(api::with_privacy(privacy.clone()))
+        .and(rss::with_feeds(feeds.clone()))
+        .and_then(rss::stalled_nodes_response);
+
     let lagging_nodes_rss = warp::get()
-        .and(warp::path!("rss" / u32 / "lagging.xml"))
+        .and(rss::feed_path(rss::FeedKind::Lagging, feeds.clone()))
         .and(api::with_caches(caches.clone()))
         .and(api::with_networks(network_infos.clone()))
         .and(rss::with_rss_base_url(config.rss_base_url.clone()))
+        .and(api::with_privacy(privacy.clone()))
         .and_then(rss::lagging_nodes_response);
 
     let unreachable_nodes_rss = warp::get()
-        .and(warp::path!("rss" / u32 / "unreachable.xml"))
+        .and(rss::feed_path(rss::FeedKind::Unreachable, feeds.clone()))
         .and(api::with_caches(caches.clone()))
         .and(api::with_networks(network_infos.clone()))
         .and(rss::with_rss_base_url(config.rss_base_url.clone()))
+        .and(api::with_privacy(privacy.clone()))
         .and_then(rss::unreachable_nodes_response);
 
-    let networks_json = warp::get()
-        .and(warp::path!("api" / "networks.json"))
-        .and(api::with_networks(network_infos))
-        .and_then(api::networks_response);
-
-    let change_sse = warp::path!("api" / "changes")
+    let alerts_rss = warp::get()
+        .and(rss::feed_path(rss::FeedKind::Alerts, feeds.clone()))
+        .and(api::with_caches(caches.clone()))
+        .and(api::with_networks(network_infos.clone()))
+        .and(rss::with_rss_base_url(config.rss_base_url.clone()))
+        .and_then(rss::alerts_response);
+
+    let new_blocks_rss = warp::get()
+        .and(rss::feed_path(rss::FeedKind::Blocks, feeds.clone()))
+        .and(api::with_caches(caches.clone()))
+        .and(api::with_networks(network_infos.clone()))
+        .and(rss::with_rss_base_url(config.rss_base_url.clone()))
+        .and(rss::with_feeds(feeds.clone()))
+        .and_then(rss::new_blocks_response);
+
+    let all_forks_rss = warp::get()
+        .and(rss::combined_feed_path(rss::FeedKind::Forks))
+        .and(api::with_caches(caches.clone()))
+        .and(api::with_networks(network_infos.clone()))
+        .and(rss::with_rss_base_url(config.rss_base_url.clone()))
+        .and(rss::with_feeds(feeds.clone()))
+        .and_then(rss::all_forks_response);
+
+    let feeds_opml = warp::get()
+        .and(warp::path!("rss" / "index.opml"))
+        .and(api::with_networks(network_infos.clone()))
+        .and(rss::with_feeds(feeds))
+        .and(rss::with_rss_base_url(config.rss_base_url.clone()))
+        .and_then(rss::opml_response);
+
+    let freshness = Freshness {
+        started,
+        stale_after: config.query_interval.as_secs() * STALE_AFTER_POLL_INTERVALS,
+    };
+
+    let pool_id_queues: PoolIdQueues = Arc::new(pool_id_queues);
+    let health_json = warp::get()
+        .and(warp::path!("api" / "health"))
+        .and(api::with_networks(network_infos.clone()))
+        .and(api::with_caches(caches.clone()))
+        .and(api::with_freshness(freshness.clone()))
+        .and(api::with_last_prune(last_prune))
+        .and(api::with_pool_id_queues(pool_id_queues.clone()))
+        .and_then(api::health_response);
+
+    let identify = warp::post()
+        .and(warp::path!("api" / u32 / "identify" / String))
+        .and(warp::header::optional::<String>("authorization"))
+        .and(api::with_admin_token(config.admin_token.clone()))
+        .and(api::with_trees(trees.clone()))
+        .and(api::with_pool_id_queues(pool_id_queues))
+        .and_then(api::identify_response);
+
+    let readiness_json = warp::get()
+        .and(warp::path!("api" / "health.json"))
+        .and(api::with_networks(network_infos.clone()))
+        .and(api::with_caches(caches.clone()))
+        .and_then(api::readiness_response);
+
+    let networks_json = warp::get()
+        .and(warp::path!("api" / "networks.json"))
+        .and(api::with_networks(network_infos))
+        .and(api::with_trees(trees.clone()))
+        .and(api::with_caches(caches.clone()))
+        .and(api::with_freshness(freshness))
+        .and(api::with_show_empty_networks(config.show_empty_networks))
+        .and_then(api::networks_response);
+
+    let sse_connections = Arc::new(AtomicUsize::new(0));
+    let max_sse_connections = config.max_sse_connections;
+    let access_log = config.access_log.clone();
+    let change_sse = warp::path!("api" / "changes")
         .and(warp::get())
-        .map(move || {
-            let tipchanges_rx = tipchanges_tx.clone().subscribe();
-            let broadcast_stream = BroadcastStream::new(tipchanges_rx);
-            let event_stream = broadcast_stream.map(move |d| match d {
-                Ok(d) => api::data_changed_sse(d),
-                Err(e) => {
-                    error!("Could not SSE notify about tip changed event: {}", e);
-                    api::data_changed_sse(u32::MAX)
+        .and(accesslog::client(access_log.trusted_proxies.clone()))
+        .map(move |client| {
+            let connection =
+                match api::SseConnection::open(sse_connections.clone(), max_sse_connections) {
+                    Some(connection) => connection,
+                    None => {
+                        warn!(
+                            "Rejecting SSE connection: already {} connections open",
+                            max_sse_connections
+                        );
+                        return warp::reply::with_status(
+                            "too many open SSE connections",
+                            StatusCode::SERVICE_UNAVAILABLE,
+                        )
+                        .into_response();
+                    }
+                };
+            // the opening of the stream is logged with the response
+            let session = access_log
+                .enable
+                .then(|| accesslog::SseSession::open(client));
+            let event_stream = api::change_event_stream(&change_senders).map(move |d| {
+                // keeps the connection counted until the stream is dropped
+                let _connection = &connection;
+                let _session = &session;
+                match d {
+                    Ok(d) => api::change_event_sse(d),
+                    Err(BroadcastStreamRecvError::Lagged(skipped)) => {
+                        warn!(
+                            "SSE client lagged behind and missed {} change events",
+                            skipped
+                        );
+                        api::lagged_sse(skipped)
+                    }
                 }
             });
             let stream = warp::sse::keep_alive().stream(event_stream);
-            warp::sse::reply(stream)
+            warp::sse::reply(stream).into_response()
         });
 
+    // Unknown API paths are answered in plain text, all other unknown paths
+    // with an HTML page. The health endpoints and the token-authenticated
+    // admin endpoints aren't rate-limited. The server-sent event stream isn't
+    // compressed, as the encoder would hold back its events and keep-alives.
+    let api_routes = api::api_path().and(
+        health_json
+            .or(readiness_json)
+            .or(identify)
+            .or(ratelimit::limit(
+                rate_limiter.clone(),
+                config.access_log.trusted_proxies.clone(),
+            )
+            .and(
+                cors::wrap(
+                    compression::wrap(
+                        data_json
+                            .or(data_v2_json)
+                            .or(nodes_json)
+                            .or(signalling_json)
+                            .or(forks_json)
+                            .or(fork_history_json)
+                            .or(alert_history_json)
+                            .or(reorgs_json)
+                            .or(invalid_json)
+                            .or(miners_json)
+                            .or(consensus_json)
+                            .or(block_json)
+                            .or(blocks_by_height_json)
+                            .or(search_json)
+                            .or(header_hex)
+                            .or(headers_hex_by_height)
+                            .or(node_chaintips_json)
+                            .or(node_tips_replay_json)
+                            .or(info_json)
+                            .or(openapi_json)
+                            .or(networks_json),
+                        config.compress_responses,
+                    ),
+                    &config.cors_allowed_origins,
+                )
+                .or(change_sse),
+            ))
+            .recover(api::api_rejection),
+    );
+
     let routes = www_dir
         .or(index_html)
         .or(fullscreen_html)
-        .or(data_json)
-        .or(info_json)
-        .or(networks_json)
-        .or(change_sse)
-        .or(forks_rss)
-        .or(lagging_nodes_rss)
-        .or(unreachable_nodes_rss)
-        .or(invalid_blocks_rss);
-
-    warp::serve(routes).run(config.address).await;
-    Ok(())
+        .or(favicon)
+        .or(api_routes)
+        .or(
+            ratelimit::limit(rate_limiter, config.access_log.trusted_proxies.clone()).and(
+                forks_rss
+                    .or(lagging_nodes_rss)
+                    .or(stalled_nodes_rss)
+                    .or(unreachable_nodes_rss)
+                    .or(invalid_blocks_rss)
+                    .or(alerts_rss)
+                    .or(new_blocks_rss)
+                    .or(all_forks_rss)
+                    .or(feeds_opml),
+            ),
+        )
+        .recover(api::page_rejection)
+        .recover(api::final_rejection);
+    let routes = accesslog::wrap(routes, config.access_log.clone());
+
+    match warp::serve(routes).try_bind_ephemeral(config.address) {
+        Ok((address, server)) => {
+            info!("listening on http://{}", address);
+            server.await;
+            Ok(())
+        }
+        Err(e) => {
+            if is_addr_in_use(&e) {
+                error!(
+                    "Could not listen on {}: port {} is already in use. Stop the other process using it or change the address in the configuration.",
+                    config.address,
+                    config.address.port()
+                );
+            } else {
+                error!("Could not listen on {}: {}", config.address, e);
+            }
+            Err(MainError::Bind(e))
+        }
+    }
+}
+
+// If the error, or one of its sources, is an AddrInUse IO error.
+fn is_addr_in_use(e: &(dyn std::error::Error + 'static)) -> bool {
+    let mut source = Some(e);
+    while let Some(e) = source {
+        if let Some(io_error) = e.downcast_ref::<std::io::Error>() {
+            if io_error.kind() == std::io::ErrorKind::AddrInUse {
+                return true;
+            }
+        }
+        source = e.source();
+    }
+    false
 }
 
 // Find out for which heights we have tips for. These are
@@ -601,6 +1560,10 @@ enum CacheUpdate {
         node_id: u32,
         tips: Vec<ChainTip>,
     },
+    NodeRawTips {
+        node_id: u32,
+        tips: Vec<ChainTip>,
+    },
     NodeReachability {
         node_id: u32,
         reachable: bool,
@@ -609,6 +1572,26 @@ enum CacheUpdate {
         node_id: u32,
         version: String,
     },
+    NodeDeployments {
+        node_id: u32,
+        deployments: Deployments,
+    },
+    NodePollLatency {
+        node_id: u32,
+        latency_ms: u64,
+    },
+    NetworkPolled {
+        timestamp: u64,
+    },
+    HeadersTotal {
+        count: u64,
+    },
+    ActiveChainWork {
+        chain_work: ChainWork,
+    },
+    Reorg {
+        reorg: ReorgJson,
+    },
 }
 
 impl fmt::Display for CacheUpdate {
@@ -639,12 +1622,45 @@ impl fmt::Display for CacheUpdate {
             CacheUpdate::NodeTips { node_id, .. } => {
                 write!(f, "Update tips of node={}", node_id,)
             }
+            CacheUpdate::NodeRawTips { node_id, .. } => {
+                write!(f, "Update raw tips of node={}", node_id,)
+            }
             CacheUpdate::NodeVersion { node_id, version } => {
                 write!(f, "Update node={} version={}", node_id, version)
             }
+            CacheUpdate::NodeDeployments { node_id, .. } => {
+                write!(f, "Update deployments of node={}", node_id)
+            }
             CacheUpdate::NodeReachability { node_id, reachable } => {
                 write!(f, "Setting node {} to reachable={}", node_id, reachable)
             }
+            CacheUpdate::NodePollLatency {
+                node_id,
+                latency_ms,
+            } => {
+                write!(f, "Setting node {} poll latency={}ms", node_id, latency_ms)
+            }
+            CacheUpdate::NetworkPolled { timestamp } => {
+                write!(f, "Setting last successful poll to timestamp={}", timestamp)
+            }
+            CacheUpdate::HeadersTotal { count } => {
+                write!(f, "Setting number of stored headers to {}", count)
+            }
+            CacheUpdate::ActiveChainWork { chain_work } => {
+                write!(
+                    f,
+                    "Setting active chain work to {} at tip {}",
+                    chain_work.hex(),
+                    chain_work.tip
+                )
+            }
+            CacheUpdate::Reorg { reorg } => {
+                write!(
+                    f,
+                    "Recording reorg of node {} to {}",
+                    reorg.node_id, reorg.new_tip_hash
+                )
+            }
         }
     }
 }
@@ -660,32 +1676,62 @@ async fn is_node_reachable(caches: &Caches, network_id: u32, node_id: u32) -> bo
         .reachable
 }
 
+// The hashes of the headers that were added or modified and of the headers
+// that were removed.
+fn header_changes(
+    old: &[HeaderInfoJson],
+    new: &HashMap<String, HeaderInfoJson>,
+) -> (Vec<String>, Vec<String>) {
+    let old: HashMap<&str, &HeaderInfoJson> = old.iter().map(|h| (h.hash.as_str(), h)).collect();
+    let changed = new
+        .iter()
+        .filter(|(hash, header)| old.get(hash.as_str()) != Some(header))
+        .map(|(hash, _)| hash.clone())
+        .collect();
+    let removed = old
+        .keys()
+        .filter(|hash| !new.contains_key(**hash))
+        .map(|hash| hash.to_string())
+        .collect();
+    (changed, removed)
+}
+
 async fn update_cache(caches: &Caches, network_id: u32, update: CacheUpdate) {
     debug!("updating cache with: {}", update);
     let mut locked_cache = caches.lock().await;
+    if let Some(cache) = locked_cache.get_mut(&network_id) {
+        // The poll timestamp, header count and poll latencies aren't part of
+        // data.json.
+        if !matches!(
+            update,
+            CacheUpdate::NetworkPolled { .. }
+                | CacheUpdate::HeadersTotal { .. }
+                | CacheUpdate::NodePollLatency { .. }
+        ) {
+            cache.data_json = None;
+        }
+    }
     let network = locked_cache
         .get(&network_id)
         .expect("this network should be in the caches");
     match update {
         CacheUpdate::HeaderMiner { header_info } => {
             let mut old = network.header_infos_json.clone();
-            if let Some(index) = old
-                .iter()
-                .position(|h| h.hash == header_info.header.block_hash().to_string())
-            {
+            let hash = header_info.header.block_hash().to_string();
+            let mut changed_headers = vec![];
+            if let Some(index) = old.iter().position(|h| h.hash == hash) {
                 old[index].update_miner(header_info.miner.clone());
+                changed_headers.push(hash);
             }
 
             locked_cache.entry(network_id).and_modify(|cache| {
                 cache.header_infos_json = old;
+                cache.journal.record(changed_headers, vec![], vec![]);
 
-                cache.recent_miners.push((
+                cache.recent_miners.insert(
                     header_info.header.block_hash().to_string(),
-                    header_info.miner,
-                ));
-                if cache.recent_miners.len() > 5 {
-                    cache.recent_miners.remove(0);
-                }
+                    (header_info.miner, header_info.height),
+                );
             });
         }
         CacheUpdate::HeaderTree {
@@ -698,7 +1744,7 @@ async fn update_cache(caches: &Caches, network_id: u32, update: CacheUpdate) {
                 .collect();
             // we might have new miner infos. Make sure to not overwrite headers
             // that already have a miner.
-            for (hash, miner) in network.recent_miners.iter() {
+            for (hash, (miner, _)) in network.recent_miners.iter() {
                 new_header_infos_map.entry(hash.clone()).and_modify(|new| {
                     new.update_miner(miner.clone());
                     debug!(
@@ -708,12 +1754,27 @@ async fn update_cache(caches: &Caches, network_id: u32, update: CacheUpdate) {
                 });
             }
 
+            // A block might not be in this header tree yet, but in the next
+            // one. Only forget about miners of blocks that are neither in the
+            // header tree nor close to its top.
+            let max_height = header_infos_json
+                .iter()
+                .map(|h| h.height)
+                .max()
+                .unwrap_or_default();
+            let (changed_headers, removed_headers) =
+                header_changes(&network.header_infos_json, &new_header_infos_map);
             locked_cache.entry(network_id).and_modify(|e| {
+                e.recent_miners.retain(|hash, (_, height)| {
+                    new_header_infos_map.contains_key(hash)
+                        || *height + RECENT_MINERS_MAX_HEIGHT_DISTANCE >= max_height
+                });
                 e.header_infos_json = new_header_infos_map
                     .iter()
                     .map(|(_, header)| header.clone())
                     .collect();
                 e.forks = forks;
+                e.journal.record(changed_headers, removed_headers, vec![]);
             });
         }
         CacheUpdate::NodeTips { node_id, tips } => {
@@ -721,9 +1782,15 @@ async fn update_cache(caches: &Caches, network_id: u32, update: CacheUpdate) {
                 Some(header) => header.height,
                 None => 0,
             };
+            // Invalid and valid-fork tips are always relevant, e.g. for the
+            // invalid blocks feed, even if they are below the headers we
+            // show.
             let relevant_tips: Vec<ChainTip> = tips
                 .iter()
-                .filter(|t| t.height >= min_height)
+                .filter(|t| {
+                    t.height >= min_height
+                        || t.status.severity() >= ChainTipStatus::ValidFork.severity()
+                })
                 .cloned()
                 .collect();
 
@@ -732,6 +1799,31 @@ async fn update_cache(caches: &Caches, network_id: u32, update: CacheUpdate) {
                     .node_data
                     .entry(node_id)
                     .and_modify(|e| e.tips(&relevant_tips));
+                network.journal.record(vec![], vec![], vec![node_id]);
+                if let Some(propagation) = network.propagation.as_mut() {
+                    let node_count = network.node_data.len();
+                    if let (Some(tip), Some(node)) = (
+                        tips.iter().find(|t| t.status == ChainTipStatus::Active),
+                        network.node_data.get(&node_id),
+                    ) {
+                        propagation.seen(
+                            &tip.block_hash().to_string(),
+                            node_id,
+                            node.last_changed_timestamp,
+                            node_count,
+                        );
+                    }
+                }
+            });
+        }
+        CacheUpdate::NodeRawTips { node_id, tips } => {
+            locked_cache.entry(network_id).and_modify(|network| {
+                if let Some(replay) = network.tips_replay.as_mut() {
+                    replay.record(node_id, unix_timestamp(), tips.clone());
+                }
+                if let Some(raw_tips) = network.raw_tips.as_mut() {
+                    raw_tips.insert(node_id, tips);
+                }
             });
         }
         CacheUpdate::NodeReachability { node_id, reachable } => {
@@ -740,6 +1832,18 @@ async fn update_cache(caches: &Caches, network_id: u32, update: CacheUpdate) {
                     .node_data
                     .entry(node_id)
                     .and_modify(|e| e.reachable(reachable));
+                network.journal.record(vec![], vec![], vec![node_id]);
+            });
+        }
+        CacheUpdate::NodePollLatency {
+            node_id,
+            latency_ms,
+        } => {
+            locked_cache.entry(network_id).and_modify(|network| {
+                network
+                    .node_data
+                    .entry(node_id)
+                    .and_modify(|e| e.poll_latency(latency_ms));
             });
         }
         CacheUpdate::NodeVersion { node_id, version } => {
@@ -748,11 +1852,75 @@ async fn update_cache(caches: &Caches, network_id: u32, update: CacheUpdate) {
                     .node_data
                     .entry(node_id)
                     .and_modify(|e| e.version(version));
+                network.journal.record(vec![], vec![], vec![node_id]);
+            });
+        }
+        CacheUpdate::NodeDeployments {
+            node_id,
+            deployments,
+        } => {
+            locked_cache.entry(network_id).and_modify(|network| {
+                network
+                    .node_data
+                    .entry(node_id)
+                    .and_modify(|e| e.deployments(deployments));
+                network.journal.record(vec![], vec![], vec![node_id]);
+                let disagreements = deployment_disagreements(&network.node_data);
+                if !disagreements.is_empty() && disagreements != network.deployment_disagreements {
+                    warn!(
+                        "Nodes on network with id={} disagree on the status of deployment(s): {}",
+                        network_id,
+                        disagreements.join(", ")
+                    );
+                }
+                network.deployment_disagreements = disagreements;
+            });
+        }
+        CacheUpdate::NetworkPolled { timestamp } => {
+            locked_cache.entry(network_id).and_modify(|network| {
+                network.last_poll_timestamp = Some(timestamp);
+            });
+        }
+        CacheUpdate::HeadersTotal { count } => {
+            locked_cache.entry(network_id).and_modify(|network| {
+                network.headers_total = Some(count);
+            });
+        }
+        CacheUpdate::ActiveChainWork { chain_work } => {
+            locked_cache.entry(network_id).and_modify(|network| {
+                network.active_chain_work = Some(chain_work);
+            });
+        }
+        CacheUpdate::Reorg { reorg } => {
+            locked_cache.entry(network_id).and_modify(|network| {
+                network.reorgs.push_back(reorg);
+                if network.reorgs.len() > MAX_REORGS_IN_CACHE {
+                    network.reorgs.pop_front();
+                }
             });
         }
     }
 }
 
+// Names of the softfork deployments the nodes report different statuses for.
+// Nodes that can't report deployments, or don't know about a deployment, are
+// not part of the comparison.
+fn deployment_disagreements(node_data: &NodeData) -> Vec<String> {
+    let mut statuses: BTreeMap<&String, BTreeSet<&String>> = BTreeMap::new();
+    for node in node_data.values() {
+        if let Some(deployments) = &node.deployments {
+            for (name, status) in deployments.iter() {
+                statuses.entry(name).or_default().insert(status);
+            }
+        }
+    }
+    statuses
+        .into_iter()
+        .filter(|(_, statuses)| statuses.len() > 1)
+        .map(|(name, _)| name.clone())
+        .collect()
+}
+
 async fn load_node_version(node: BoxedSyncSendNode, network: &str) -> String {
     // The Bitcoin Core version is requested via the getnetworkinfo RPC. This
     // RPC exposes sensitive information to the caller, so it might not be
@@ -791,15 +1959,228 @@ async fn load_node_version(node: BoxedSyncSendNode, network: &str) -> String {
     return VERSION_UNKNOWN.to_string();
 }
 
+async fn load_node_deployments(node: BoxedSyncSendNode, network: &str) -> Option<Deployments> {
+    // Like the version, the deployments are requested multiple times as
+    // the RPC interface might not be up yet. Backends that can't report
+    // deployments are left out of the comparison.
+    let mut interval = interval(Duration::from_secs(10));
+    for _ in 0..5 {
+        match node.deployments().await {
+            Ok(deployments) => return Some(deployments),
+            Err(error::FetchError::BtcdRPC(error::JsonRPCError::NotImplemented)) => return None,
+            Err(error::FetchError::BitcoinCoreRPC(JsonRpc(msg))) => {
+                warn!(
+                    "Could not load deployments from node='{}' on network '{}': {:?}. Retrying...",
+                    node.info().name,
+                    network,
+                    msg
+                );
+            }
+            Err(e) => {
+                warn!(
+                    "Could not load deployments from node='{}' on network='{}': {:?}",
+                    node.info().name,
+                    network,
+                    e
+                );
+                return None;
+            }
+        }
+        interval.tick().await;
+    }
+    warn!(
+        "Could not load deployments from node='{}' on network='{}'. Keeping the last known deployments.",
+        node.info().name,
+        network,
+    );
+    None
+}
+
+// Loads the deployments of the node and updates the cache if they changed.
+// The last known deployments are kept if they can't be loaded.
+async fn refresh_node_deployments(
+    node: BoxedSyncSendNode,
+    caches: Caches,
+    network_id: u32,
+    network: String,
+) {
+    if let Some(deployments) = load_node_deployments(node.clone(), &network).await {
+        let unchanged = caches
+            .lock()
+            .await
+            .get(&network_id)
+            .and_then(|cache| cache.node_data.get(&node.info().id))
+            .is_some_and(|node| node.deployments.as_ref() == Some(&deployments));
+        if unchanged {
+            return;
+        }
+        update_cache(
+            &caches,
+            network_id,
+            CacheUpdate::NodeDeployments {
+                node_id: node.info().id,
+                deployments,
+            },
+        )
+        .await;
+    }
+}
+
+// Coinbase tags can be spoofed by anyone mining a block. As an anti-spoofing
+// guard, blocks with a coinbase tag (the scriptSig of the coinbase input)
+// containing one of the denylisted substrings aren't attributed to a pool.
+fn is_coinbase_tag_denylisted(coinbase: &Transaction, denylist: &[String]) -> bool {
+    let coinbase_tag = match identify::coinbase_tag(coinbase) {
+        Some(coinbase_tag) => coinbase_tag,
+        None => return false,
+    };
+    denylist
+        .iter()
+        .any(|tag| !tag.is_empty() && coinbase_tag.contains(tag.as_str()))
+}
+
+// The watched addresses the outputs of the coinbase transaction pay to.
+fn watched_payouts(coinbase: &Transaction, network: Network, watched: &[String]) -> Vec<String> {
+    let mut paid: Vec<String> = vec![];
+    for output in coinbase.output.iter() {
+        if let Ok(address) = Address::from_script(&output.script_pubkey, network) {
+            let address = address.to_string();
+            if watched.contains(&address) && !paid.contains(&address) {
+                paid.push(address);
+            }
+        }
+    }
+    paid
+}
+
+// Updates the work of the active chain if the highest active tip of the nodes
+// changed. If the chain was extended, only the new headers are summed up.
+async fn refresh_active_chain_work(caches: &Caches, tree: &Tree, network_id: u32) {
+    let (tip, known) = {
+        let locked_caches = caches.lock().await;
+        let cache = match locked_caches.get(&network_id) {
+            Some(cache) => cache,
+            None => return,
+        };
+        let tip = cache
+            .node_data
+            .values()
+            .filter_map(|node| node.active_tip())
+            .max_by_key(|tip| tip.height)
+            .and_then(|tip| BlockHash::from_str(&tip.hash).ok());
+        (tip, cache.active_chain_work)
+    };
+    let tip = match tip {
+        Some(tip) => tip,
+        None => return,
+    };
+    if known.map(|known| known.tip) == Some(tip) {
+        return;
+    }
+    if let Some(work) = headertree::chain_work(tree, &tip, known).await {
+        update_cache(
+            caches,
+            network_id,
+            CacheUpdate::ActiveChainWork {
+                chain_work: ChainWork { tip, work },
+            },
+        )
+        .await;
+    }
+}
+
+// Sends a change event to the SSE clients of the network. Fails if there are
+// no SSE clients, which is fine.
+fn send_change(tx: &broadcast::Sender<ChangeEvent>, network_id: u32, change: Change) {
+    let kind = change.kind();
+    match tx.send(ChangeEvent::Changed { network_id, change }) {
+        Ok(_) => debug!("Sent a {} notification.", kind),
+        Err(e) => debug!("Could not send {} update into the channel: {}", kind, e),
+    }
+}
+
+// The new_block change for the highest of the new headers.
+fn new_block_change(new_headers: &[HeaderInfo]) -> Option<Change> {
+    new_headers
+        .iter()
+        .max_by_key(|h| h.height)
+        .map(|h| Change::NewBlock {
+            hash: h.header.block_hash().to_string(),
+            height: h.height,
+        })
+}
+
+async fn active_tip_in_tree(tips: &[ChainTip], tree: &Tree) -> bool {
+    match tips.iter().find(|tip| tip.status == ChainTipStatus::Active) {
+        Some(tip) => tree.lock().await.1.contains_key(&tip.block_hash()),
+        None => true,
+    }
+}
+
+// Splits the headers into the ones with valid proof-of-work for their bits
+// and the rejected ones. The target of the bits can't exceed the pow limit of
+// the network, if it's known. Descendants of rejected headers are rejected
+// too.
+fn split_invalid_pow(
+    headers: Vec<HeaderInfo>,
+    pow_limit: Option<Target>,
+) -> (Vec<HeaderInfo>, Vec<HeaderInfo>) {
+    let mut sorted = headers;
+    sorted.sort_by_key(|h| h.height);
+    let mut rejected_hashes: HashSet<BlockHash> = HashSet::new();
+    let (rejected, valid): (Vec<HeaderInfo>, Vec<HeaderInfo>) = sorted.into_iter().partition(|h| {
+        let target = h.header.target();
+        let invalid = rejected_hashes.contains(&h.header.prev_blockhash)
+            || pow_limit.is_some_and(|limit| target > limit)
+            || h.header.validate_pow(target).is_err();
+        if invalid {
+            rejected_hashes.insert(h.header.block_hash());
+        }
+        invalid
+    });
+    (valid, rejected)
+}
+
+// Inserts the new headers into the tree and, unless the database is
+// ephemeral, writes them to the database. Returns if the tree changed.
+async fn store_new_headers(
+    tree: &Tree,
+    db: Db,
+    network_id: u32,
+    new_headers: &[HeaderInfo],
+    ephemeral: bool,
+) -> Result<bool, DbError> {
+    let tree_changed = insert_new_headers_into_tree(tree, new_headers).await;
+    if !ephemeral {
+        db::write_to_db(new_headers, db, network_id).await?;
+    }
+    Ok(tree_changed)
+}
+
 async fn insert_new_headers_into_tree(tree: &Tree, new_headers: &[HeaderInfo]) -> bool {
     let mut tree_changed: bool = false;
     let mut tree_locked = tree.lock().await;
     // insert headers to tree
     for h in new_headers {
-        if !tree_locked.1.contains_key(&h.header.block_hash()) {
-            let idx = tree_locked.0.add_node(h.clone());
-            tree_locked.1.insert(h.header.block_hash(), idx);
-            tree_changed = true;
+        match tree_locked.1.get(&h.header.block_hash()) {
+            Some(idx) => {
+                // A valid header can't be at two heights. Keep the header we
+                // already know about.
+                let known_height = tree_locked.0[*idx].height;
+                if known_height != h.height {
+                    error!(
+                        "Header {} was reported at height {}, but is already known at height {}. Keeping the known header.",
+                        h.header.block_hash(),
+                        h.height,
+                        known_height
+                    );
+                }
+            }
+            None => {
+                let idx = tree_locked.0.add_node(h.clone());
+                tree_locked.1.insert(h.header.block_hash(), idx);
+                tree_changed = true;
+            }
         }
     }
     // connect nodes with edges
@@ -829,6 +2210,10 @@ async fn insert_new_headers_into_tree(tree: &Tree, new_headers: &[HeaderInfo]) -
 mod tests {
     use super::*;
     use crate::node::NodeInfo;
+    use crate::types::AlertKind;
+    use bitcoin_pool_identification::PoolIdentification;
+    use bitcoincore_rpc::bitcoin::hashes::Hash;
+    use bitcoincore_rpc::bitcoin::{Amount, ScriptBuf, TxOut};
 
     async fn get_test_node_reachable(caches: &Caches, net_id: u32, node_id: u32) -> bool {
         let locked_caches = caches.lock().await;
@@ -842,58 +2227,373 @@ mod tests {
     }
 
     #[tokio::test]
-    async fn test_node_reachable() {
+    async fn test_data_json_since() {
         let network_id: u32 = 0;
-        let caches: Caches = Arc::new(Mutex::new(BTreeMap::new()));
-        let node = NodeInfo {
-            id: 0,
-            name: "".to_string(),
-            description: "".to_string(),
-            implementation: "".to_string(),
+        let node_data: NodeData = BTreeMap::from([(0, test_utils::node_data(0, &vec![]))]);
+        let caches: Caches = Arc::new(Mutex::new(BTreeMap::from([(
+            network_id,
+            test_utils::cache(node_data),
+        )])));
+        let headers = test_utils::chain(BlockHash::all_zeros(), 1, 3, 0);
+        let header_infos_json: Vec<HeaderInfoJson> = headers
+            .iter()
+            .enumerate()
+            .map(|(i, h)| HeaderInfoJson::new(h, i, i.checked_sub(1).unwrap_or(usize::MAX)))
+            .collect();
+        let since = |generation: u64| {
+            let caches = caches.clone();
+            async move {
+                let query = DataQuery {
+                    compact: None,
+                    since: Some(generation),
+                    max_headers: None,
+                    min_height: None,
+                    max_height: None,
+                };
+                let reply =
+                    api::data_response(network_id, query, None, false, caches, BTreeMap::new(), 16)
+                        .await
+                        .unwrap();
+                let body = warp::hyper::body::to_bytes(reply.into_response().into_body())
+                    .await
+                    .unwrap();
+                serde_json::from_slice::<serde_json::Value>(&body).unwrap()
+            }
         };
-        {
-            // populate data
-            let mut locked_caches = caches.lock().await;
-            let mut node_data: NodeData = BTreeMap::new();
-            node_data.insert(
-                node.id,
-                NodeDataJson::new(node.clone(), &vec![], "".to_string(), 0, true),
-            );
-            locked_caches.insert(
-                network_id,
-                Cache {
-                    header_infos_json: vec![],
-                    node_data,
-                    forks: vec![],
-                    recent_miners: vec![],
-                },
-            );
-        }
-        assert_eq!(
-            get_test_node_reachable(&caches, network_id, node.id).await,
-            true
-        );
 
+        // generation 1
+        update_cache(
+            &caches,
+            network_id,
+            CacheUpdate::HeaderTree {
+                header_infos_json: header_infos_json.clone(),
+                forks: vec![],
+            },
+        )
+        .await;
+        // generation 2
         update_cache(
             &caches,
             network_id,
             CacheUpdate::NodeReachability {
-                node_id: node.id,
+                node_id: 0,
                 reachable: false,
             },
         )
         .await;
-        assert_eq!(
-            get_test_node_reachable(&caches, network_id, node.id).await,
-            false
-        );
+        // generation 3
+        let mut mined = headers[2].clone();
+        mined.miner = "Pool".to_string();
+        update_cache(
+            &caches,
+            network_id,
+            CacheUpdate::HeaderMiner {
+                header_info: mined.clone(),
+            },
+        )
+        .await;
+
+        let delta = since(0).await;
+        assert_eq!(delta["generation"], 3);
+        assert_eq!(delta["full"], false);
+        assert_eq!(delta["header_infos"].as_array().unwrap().len(), 3);
+        assert_eq!(delta["nodes"].as_array().unwrap().len(), 1);
+
+        let delta = since(2).await;
+        assert_eq!(delta["full"], false);
+        assert_eq!(delta["header_infos"].as_array().unwrap().len(), 1);
+        assert_eq!(delta["header_infos"][0]["miner"], "Pool");
+        assert!(delta["nodes"].as_array().unwrap().is_empty());
+
+        let delta = since(3).await;
+        assert!(delta["header_infos"].as_array().unwrap().is_empty());
+        assert!(delta["nodes"].as_array().unwrap().is_empty());
 
+        // removed headers
         update_cache(
             &caches,
             network_id,
-            CacheUpdate::NodeReachability {
-                node_id: node.id,
-                reachable: true,
+            CacheUpdate::HeaderTree {
+                header_infos_json: header_infos_json[1..].to_vec(),
+                forks: vec![],
+            },
+        )
+        .await;
+        let delta = since(3).await;
+        assert_eq!(delta["generation"], 4);
+        assert_eq!(
+            delta["removed_header_infos"],
+            serde_json::json!([header_infos_json[0].hash])
+        );
+
+        // an unknown generation falls back to the full document
+        let delta = since(1000).await;
+        assert_eq!(delta["full"], true);
+        assert_eq!(delta["header_infos"].as_array().unwrap().len(), 2);
+
+        // the journal is bounded and old generations fall back to the full
+        // document
+        for i in 0..1000 {
+            update_cache(
+                &caches,
+                network_id,
+                CacheUpdate::NodeReachability {
+                    node_id: 0,
+                    reachable: i % 2 == 1,
+                },
+            )
+            .await;
+        }
+        let delta = since(3).await;
+        assert_eq!(delta["generation"], 1004);
+        assert_eq!(delta["full"], true);
+        assert_eq!(delta["header_infos"].as_array().unwrap().len(), 2);
+        assert_eq!(delta["nodes"].as_array().unwrap().len(), 1);
+        let delta = since(1003).await;
+        assert_eq!(delta["full"], false);
+        assert_eq!(delta["nodes"].as_array().unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_data_json_body_cache() {
+        let network_id: u32 = 0;
+        let node_data: NodeData = BTreeMap::from([(0, test_utils::node_data(0, &vec![]))]);
+        let caches: Caches = Arc::new(Mutex::new(BTreeMap::from([(
+            network_id,
+            test_utils::cache(node_data),
+        )])));
+        let request = || {
+            let caches = caches.clone();
+            async move {
+                let query = DataQuery {
+                    compact: None,
+                    since: None,
+                    max_headers: None,
+                    min_height: None,
+                    max_height: None,
+                };
+                let reply = api::data_response(
+                    network_id,
+                    query,
+                    None,
+                    false,
+                    caches.clone(),
+                    BTreeMap::new(),
+                    16,
+                )
+                .await
+                .unwrap();
+                let body = warp::hyper::body::to_bytes(reply.into_body())
+                    .await
+                    .unwrap();
+                let cached = caches.lock().await[&network_id]
+                    .data_json
+                    .clone()
+                    .expect("the body should be cached")
+                    .body;
+                (body, cached)
+            }
+        };
+
+        let (body1, cached1) = request().await;
+        let (body2, cached2) = request().await;
+        assert_eq!(body1, body2);
+        assert_eq!(body1, *cached1);
+        // the second request is served from the cached body
+        assert!(Arc::ptr_eq(&cached1, &cached2));
+
+        // node-only updates refresh the body
+        update_cache(
+            &caches,
+            network_id,
+            CacheUpdate::NodeReachability {
+                node_id: 0,
+                reachable: false,
+            },
+        )
+        .await;
+        assert!(caches.lock().await[&network_id].data_json.is_none());
+        let (body3, cached3) = request().await;
+        assert_ne!(body2, body3);
+        assert!(!Arc::ptr_eq(&cached2, &cached3));
+        let json: serde_json::Value = serde_json::from_slice(&body3).unwrap();
+        assert_eq!(json["nodes"][0]["reachable"], false);
+
+        // header updates refresh the body
+        let headers = test_utils::chain(BlockHash::all_zeros(), 1, 2, 0);
+        update_cache(
+            &caches,
+            network_id,
+            CacheUpdate::HeaderTree {
+                header_infos_json: headers
+                    .iter()
+                    .enumerate()
+                    .map(|(i, h)| HeaderInfoJson::new(h, i, i.checked_sub(1).unwrap_or(usize::MAX)))
+                    .collect(),
+                forks: vec![],
+            },
+        )
+        .await;
+        let (body4, _) = request().await;
+        let json: serde_json::Value = serde_json::from_slice(&body4).unwrap();
+        assert_eq!(json["header_infos"].as_array().unwrap().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_node_poll_latency() {
+        let network_id: u32 = 0;
+        let node_data: NodeData = BTreeMap::from([(0, test_utils::node_data(0, &vec![]))]);
+        let caches: Caches = Arc::new(Mutex::new(BTreeMap::from([(
+            network_id,
+            test_utils::cache(node_data),
+        )])));
+        let latency = |caches: Caches| async move {
+            let node = &caches.lock().await[&network_id].node_data[&0];
+            (node.last_poll_latency_ms, node.avg_poll_latency_ms)
+        };
+        assert_eq!(latency(caches.clone()).await, (None, None));
+        let served = |caches: Caches| async move {
+            let query = DataQuery {
+                compact: None,
+                since: None,
+                max_headers: None,
+                min_height: None,
+                max_height: None,
+            };
+            let data = api::data_response(
+                network_id,
+                query,
+                None,
+                false,
+                caches.clone(),
+                BTreeMap::new(),
+                16,
+            )
+            .await
+            .unwrap();
+            let nodes = api::nodes_response(network_id, caches, BTreeMap::new())
+                .await
+                .unwrap();
+            (
+                warp::hyper::body::to_bytes(data.into_body()).await.unwrap(),
+                warp::hyper::body::to_bytes(nodes.into_response().into_body())
+                    .await
+                    .unwrap(),
+            )
+        };
+        let generation = caches.lock().await[&network_id].journal.generation;
+        let mut served_bytes = vec![served(caches.clone()).await];
+
+        // the first latency seeds the average, later ones are weighted by 0.2
+        for (latency_ms, avg) in [(120, 120.0), (20, 100.0), (100, 100.0)] {
+            update_cache(
+                &caches,
+                network_id,
+                CacheUpdate::NodePollLatency {
+                    node_id: 0,
+                    latency_ms,
+                },
+            )
+            .await;
+            let (last, average) = latency(caches.clone()).await;
+            assert_eq!(last, Some(latency_ms));
+            assert!((average.unwrap() - avg).abs() < 1e-9);
+            served_bytes.push(served(caches.clone()).await);
+        }
+        // every latency update changes the served nodes.json, while the
+        // cached data.json leaves them out and no change is recorded
+        for pair in served_bytes.windows(2) {
+            assert_eq!(pair[0].0, pair[1].0);
+            assert_ne!(pair[0].1, pair[1].1);
+        }
+        assert!(caches.lock().await[&network_id].data_json.is_some());
+        assert_eq!(
+            caches.lock().await[&network_id].journal.generation,
+            generation
+        );
+
+        // the latencies survive the node becoming unreachable
+        update_cache(
+            &caches,
+            network_id,
+            CacheUpdate::NodeReachability {
+                node_id: 0,
+                reachable: false,
+            },
+        )
+        .await;
+        let (last, average) = latency(caches.clone()).await;
+        assert_eq!(last, Some(100));
+        assert!((average.unwrap() - 100.0).abs() < 1e-9);
+    }
+
+    #[tokio::test]
+    async fn test_node_reachable() {
+        let network_id: u32 = 0;
+        let caches: Caches = Arc::new(Mutex::new(BTreeMap::new()));
+        let node = NodeInfo {
+            id: 0,
+            name: "".to_string(),
+            description: "".to_string(),
+            implementation: "".to_string(),
+        };
+        {
+            // populate data
+            let mut locked_caches = caches.lock().await;
+            let mut node_data: NodeData = BTreeMap::new();
+            node_data.insert(
+                node.id,
+                NodeDataJson::new(node.clone(), &vec![], "".to_string(), 0, true),
+            );
+            locked_caches.insert(
+                network_id,
+                Cache {
+                    header_infos_json: vec![],
+                    node_data,
+                    forks: vec![],
+                    deployment_disagreements: vec![],
+                    raw_tips: None,
+                    tips_replay: None,
+                    chain_split: None,
+                    alerts: vec![],
+                    reorgs: VecDeque::new(),
+                    recent_miners: HashMap::new(),
+                    journal: CacheJournal::default(),
+                    data_json: None,
+                    last_poll_timestamp: None,
+                    headers_total: None,
+                    height_offset: 0,
+                    propagation: None,
+                    active_chain_work: None,
+                    header_statuses: None,
+                },
+            );
+        }
+        assert_eq!(
+            get_test_node_reachable(&caches, network_id, node.id).await,
+            true
+        );
+
+        update_cache(
+            &caches,
+            network_id,
+            CacheUpdate::NodeReachability {
+                node_id: node.id,
+                reachable: false,
+            },
+        )
+        .await;
+        assert_eq!(
+            get_test_node_reachable(&caches, network_id, node.id).await,
+            false
+        );
+
+        update_cache(
+            &caches,
+            network_id,
+            CacheUpdate::NodeReachability {
+                node_id: node.id,
+                reachable: true,
             },
         )
         .await;
@@ -902,4 +2602,703 @@ mod tests {
             true
         );
     }
+
+    #[test]
+    fn test_coinbase_tag_denylist() {
+        let denylist = vec!["/Spoofed Pool/".to_string()];
+        let coinbase =
+            test_utils::coinbase_with_tag(b"\x03\x01\x02\x03/Spoofed Pool/Mined by someone");
+
+        assert!(is_coinbase_tag_denylisted(&coinbase, &denylist));
+        assert!(!is_coinbase_tag_denylisted(&coinbase, &[]));
+        assert!(!is_coinbase_tag_denylisted(
+            &test_utils::coinbase_with_tag(b"\x03\x01\x02\x03/Honest Pool/"),
+            &denylist
+        ));
+    }
+
+    #[test]
+    fn test_deployment_disagreements() {
+        let mut node_data: NodeData = BTreeMap::new();
+        for id in 0..4 {
+            node_data.insert(id, test_utils::node_data(id, &vec![]));
+        }
+        assert!(deployment_disagreements(&node_data).is_empty());
+
+        let deployments = |taproot: &str, testdummy: &str| -> Deployments {
+            BTreeMap::from([
+                ("segwit".to_string(), "active".to_string()),
+                ("taproot".to_string(), taproot.to_string()),
+                ("testdummy".to_string(), testdummy.to_string()),
+            ])
+        };
+        node_data
+            .get_mut(&0)
+            .unwrap()
+            .deployments(deployments("active", "started"));
+        node_data
+            .get_mut(&1)
+            .unwrap()
+            .deployments(deployments("active", "started"));
+        // node 2 can't report deployments (e.g. btcd) and node 3 doesn't know
+        // about the testdummy deployment.
+        node_data.get_mut(&3).unwrap().deployments(BTreeMap::from([
+            ("segwit".to_string(), "active".to_string()),
+            ("taproot".to_string(), "active".to_string()),
+        ]));
+        assert!(deployment_disagreements(&node_data).is_empty());
+
+        node_data
+            .get_mut(&1)
+            .unwrap()
+            .deployments(deployments("locked_in", "failed"));
+        assert_eq!(
+            deployment_disagreements(&node_data),
+            vec!["taproot".to_string(), "testdummy".to_string()]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_node_deployments_cache_update() {
+        let network_id: u32 = 0;
+        let caches: Caches = Arc::new(Mutex::new(BTreeMap::new()));
+        {
+            let mut locked_caches = caches.lock().await;
+            let mut node_data: NodeData = BTreeMap::new();
+            node_data.insert(0, test_utils::node_data(0, &vec![]));
+            node_data.insert(1, test_utils::node_data(1, &vec![]));
+            locked_caches.insert(
+                network_id,
+                Cache {
+                    header_infos_json: vec![],
+                    node_data,
+                    forks: vec![],
+                    deployment_disagreements: vec![],
+                    raw_tips: None,
+                    tips_replay: None,
+                    chain_split: None,
+                    alerts: vec![],
+                    reorgs: VecDeque::new(),
+                    recent_miners: HashMap::new(),
+                    journal: CacheJournal::default(),
+                    data_json: None,
+                    last_poll_timestamp: None,
+                    headers_total: None,
+                    height_offset: 0,
+                    propagation: None,
+                    active_chain_work: None,
+                    header_statuses: None,
+                },
+            );
+        }
+
+        for (node_id, status) in [(0, "active"), (1, "started")] {
+            update_cache(
+                &caches,
+                network_id,
+                CacheUpdate::NodeDeployments {
+                    node_id,
+                    deployments: BTreeMap::from([("taproot".to_string(), status.to_string())]),
+                },
+            )
+            .await;
+        }
+
+        let locked_caches = caches.lock().await;
+        let cache = locked_caches.get(&network_id).unwrap();
+        assert_eq!(cache.deployment_disagreements, vec!["taproot".to_string()]);
+        assert_eq!(
+            cache.node_data.get(&1).unwrap().deployments,
+            Some(BTreeMap::from([(
+                "taproot".to_string(),
+                "started".to_string()
+            )]))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_recent_miners_lagging_identification() {
+        let network_id: u32 = 0;
+        let caches: Caches = Arc::new(Mutex::new(BTreeMap::new()));
+        {
+            let mut locked_caches = caches.lock().await;
+            locked_caches.insert(
+                network_id,
+                Cache {
+                    header_infos_json: vec![],
+                    node_data: BTreeMap::new(),
+                    forks: vec![],
+                    deployment_disagreements: vec![],
+                    raw_tips: None,
+                    tips_replay: None,
+                    chain_split: None,
+                    alerts: vec![],
+                    reorgs: VecDeque::new(),
+                    recent_miners: HashMap::new(),
+                    journal: CacheJournal::default(),
+                    data_json: None,
+                    last_poll_timestamp: None,
+                    headers_total: None,
+                    height_offset: 0,
+                    propagation: None,
+                    active_chain_work: None,
+                    header_statuses: None,
+                },
+            );
+        }
+
+        let header_infos_json = |headers: &[HeaderInfo]| -> Vec<HeaderInfoJson> {
+            headers
+                .iter()
+                .enumerate()
+                .map(|(i, h)| HeaderInfoJson::new(h, i, i.wrapping_sub(1)))
+                .collect()
+        };
+        let headers = test_utils::chain(BlockHash::all_zeros(), 1, 20, 0);
+
+        // The header tree grows by 8 blocks before any of them is identified.
+        for tip in 12..20 {
+            update_cache(
+                &caches,
+                network_id,
+                CacheUpdate::HeaderTree {
+                    header_infos_json: header_infos_json(&headers[..=tip]),
+                    forks: vec![],
+                },
+            )
+            .await;
+        }
+        for h in headers[12..20].iter() {
+            let mut identified = h.clone();
+            identified.update_miner(format!("Pool {}", h.height));
+            update_cache(
+                &caches,
+                network_id,
+                CacheUpdate::HeaderMiner {
+                    header_info: identified,
+                },
+            )
+            .await;
+        }
+        // A header tree computed before the blocks were identified.
+        update_cache(
+            &caches,
+            network_id,
+            CacheUpdate::HeaderTree {
+                header_infos_json: header_infos_json(&headers),
+                forks: vec![],
+            },
+        )
+        .await;
+
+        {
+            let locked_caches = caches.lock().await;
+            let cache = locked_caches.get(&network_id).unwrap();
+            assert_eq!(cache.recent_miners.len(), 8);
+            for h in headers[12..20].iter() {
+                let header_info_json = cache
+                    .header_infos_json
+                    .iter()
+                    .find(|hij| hij.hash == h.header.block_hash().to_string())
+                    .unwrap();
+                assert_eq!(header_info_json.miner, format!("Pool {}", h.height));
+            }
+        }
+
+        // Once the blocks aren't part of the header tree anymore and far
+        // below its top, the miners are forgotten.
+        let later = test_utils::chain(headers[19].header.block_hash(), 21, 20, 100);
+        update_cache(
+            &caches,
+            network_id,
+            CacheUpdate::HeaderTree {
+                header_infos_json: header_infos_json(&later),
+                forks: vec![],
+            },
+        )
+        .await;
+        let locked_caches = caches.lock().await;
+        assert!(locked_caches
+            .get(&network_id)
+            .unwrap()
+            .recent_miners
+            .is_empty());
+    }
+
+    #[test]
+    fn test_pool_identification_testnet4() {
+        let network = config::PoolIdentificationNetwork::Testnet4.to_network();
+        assert_eq!(network, Network::Testnet4);
+
+        let pool_identification_data = default_data(network);
+        let mut coinbase = test_utils::coinbase_with_tag(b"/not a known pool/");
+        // A P2WPKH output. Coinbase output addresses are decoded with the
+        // testnet4 chain parameters.
+        let script_pubkey = ScriptBuf::from_hex("0014751e76e8199196d454941c45d1b3a323f1433bd6")
+            .expect("valid script hex");
+        coinbase.output.push(TxOut {
+            value: Amount::from_sat(5_000_000_000),
+            script_pubkey: script_pubkey.clone(),
+        });
+        assert!(Address::from_script(&script_pubkey, network)
+            .expect("P2WPKH script should be an address")
+            .to_string()
+            .starts_with("tb1"));
+        assert!(coinbase
+            .identify_pool(network, &pool_identification_data)
+            .is_none());
+    }
+
+    #[test]
+    fn test_watched_payouts() {
+        let network = Network::Signet;
+        let mut coinbase = test_utils::coinbase_with_tag(b"/unknown/");
+        for script_hex in [
+            // P2WPKH
+            "0014751e76e8199196d454941c45d1b3a323f1433bd6",
+            // OP_RETURN witness commitment, not an address
+            "6a24aa21a9ed0000000000000000000000000000000000000000000000000000000000000000",
+            // the P2WPKH output again
+            "0014751e76e8199196d454941c45d1b3a323f1433bd6",
+        ] {
+            coinbase.output.push(TxOut {
+                value: Amount::from_sat(0),
+                script_pubkey: ScriptBuf::from_hex(script_hex).expect("valid script hex"),
+            });
+        }
+        let watched = "tb1qw508d6qejxtdg4y5r3zarvary0c5xw7kxpjzsx".to_string();
+
+        assert_eq!(
+            watched_payouts(&coinbase, network, std::slice::from_ref(&watched)),
+            vec![watched.clone()]
+        );
+        assert!(watched_payouts(
+            &coinbase,
+            network,
+            &["tb1qrp33g0q5c5txsp9arysrx4k6zdkfs4nce4xj0gdcccefvpysxf3q0sl5k7".to_string()]
+        )
+        .is_empty());
+        assert!(watched_payouts(&coinbase, network, &[]).is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_low_invalid_tip_retained() {
+        let network_id: u32 = 0;
+        let caches: Caches = Arc::new(Mutex::new(BTreeMap::new()));
+        let headers = test_utils::chain(BlockHash::all_zeros(), 100, 10, 0);
+        let low_invalid = test_utils::header_info(BlockHash::all_zeros(), 50, 1000);
+        let low_headers_only = test_utils::header_info(BlockHash::all_zeros(), 51, 1001);
+        {
+            let mut locked_caches = caches.lock().await;
+            let mut node_data: NodeData = BTreeMap::new();
+            node_data.insert(0, test_utils::node_data(0, &vec![]));
+            locked_caches.insert(
+                network_id,
+                Cache {
+                    header_infos_json: headers
+                        .iter()
+                        .enumerate()
+                        .map(|(i, h)| HeaderInfoJson::new(h, i, i.wrapping_sub(1)))
+                        .collect(),
+                    node_data,
+                    forks: vec![],
+                    deployment_disagreements: vec![],
+                    raw_tips: None,
+                    tips_replay: None,
+                    chain_split: None,
+                    alerts: vec![],
+                    reorgs: VecDeque::new(),
+                    recent_miners: HashMap::new(),
+                    journal: CacheJournal::default(),
+                    data_json: None,
+                    last_poll_timestamp: None,
+                    headers_total: None,
+                    height_offset: 0,
+                    propagation: None,
+                    active_chain_work: None,
+                    header_statuses: None,
+                },
+            );
+        }
+
+        update_cache(
+            &caches,
+            network_id,
+            CacheUpdate::NodeTips {
+                node_id: 0,
+                tips: vec![
+                    test_utils::chain_tip(
+                        109,
+                        &headers[9].header.block_hash(),
+                        ChainTipStatus::Active,
+                    ),
+                    test_utils::chain_tip(
+                        50,
+                        &low_invalid.header.block_hash(),
+                        ChainTipStatus::Invalid,
+                    ),
+                    test_utils::chain_tip(
+                        51,
+                        &low_headers_only.header.block_hash(),
+                        ChainTipStatus::HeadersOnly,
+                    ),
+                ],
+            },
+        )
+        .await;
+
+        {
+            let locked_caches = caches.lock().await;
+            let tips = &locked_caches
+                .get(&network_id)
+                .unwrap()
+                .node_data
+                .get(&0)
+                .unwrap()
+                .tips;
+            assert_eq!(tips.len(), 2);
+            assert!(!tips
+                .iter()
+                .any(|t| t.hash == low_headers_only.header.block_hash().to_string()));
+        }
+
+        let trees: Trees = Arc::new(BTreeMap::from([(
+            network_id,
+            test_utils::tree(std::slice::from_ref(&low_invalid)),
+        )]));
+        let reply = rss::invalid_blocks_response(
+            network_id,
+            rss::FeedFormat::Rss,
+            caches.clone(),
+            trees,
+            vec![],
+            "https://fork-observer.example.com".to_string(),
+            BTreeMap::new(),
+        )
+        .await
+        .unwrap();
+        let body = warp::hyper::body::to_bytes(warp::Reply::into_response(reply).into_body())
+            .await
+            .unwrap();
+        let body = String::from_utf8(body.to_vec()).unwrap();
+        assert!(body.contains(&low_invalid.header.block_hash().to_string()));
+        // the block time of the known invalid header
+        assert!(body.contains("<pubDate>Sat, 03 Jan 2009 18:31:45 GMT</pubDate>"));
+    }
+
+    #[tokio::test]
+    async fn test_chain_split_alert() {
+        let network_id: u32 = 0;
+        let caches: Caches = Arc::new(Mutex::new(BTreeMap::new()));
+        let chain = test_utils::chain(BlockHash::all_zeros(), 1, 10, 0);
+        let fork = test_utils::chain(chain[6].header.block_hash(), 8, 2, 100);
+        let tree = test_utils::tree(&[chain.clone(), fork.clone()].concat());
+        {
+            let mut locked_caches = caches.lock().await;
+            let mut node_data: NodeData = BTreeMap::new();
+            for id in 0..3 {
+                node_data.insert(id, test_utils::node_data(id, &vec![]));
+            }
+            locked_caches.insert(
+                network_id,
+                Cache {
+                    header_infos_json: vec![],
+                    node_data,
+                    forks: vec![],
+                    deployment_disagreements: vec![],
+                    raw_tips: None,
+                    tips_replay: None,
+                    chain_split: None,
+                    alerts: vec![],
+                    reorgs: VecDeque::new(),
+                    recent_miners: HashMap::new(),
+                    journal: CacheJournal::default(),
+                    data_json: None,
+                    last_poll_timestamp: None,
+                    headers_total: None,
+                    height_offset: 0,
+                    propagation: None,
+                    active_chain_work: None,
+                    header_statuses: None,
+                },
+            );
+        }
+        let set_active_tip = |node_id: u32, h: &HeaderInfo| CacheUpdate::NodeTips {
+            node_id,
+            tips: vec![test_utils::chain_tip(
+                h.height,
+                &h.header.block_hash(),
+                ChainTipStatus::Active,
+            )],
+        };
+
+        // all nodes on the same chain. Node 2 is a bit behind.
+        update_cache(&caches, network_id, set_active_tip(0, &chain[8])).await;
+        update_cache(&caches, network_id, set_active_tip(1, &chain[8])).await;
+        update_cache(&caches, network_id, set_active_tip(2, &chain[7])).await;
+        assert_eq!(
+            alert::check_chain_split(network_id, &caches, &tree).await,
+            None
+        );
+
+        // node 2 switches to a different branch
+        update_cache(&caches, network_id, set_active_tip(2, &fork[1])).await;
+        let alert = alert::check_chain_split(network_id, &caches, &tree)
+            .await
+            .expect("a chain split should be alerted");
+        assert_eq!(alert.kind, AlertKind::ChainSplit);
+        assert_eq!(alert.network_id, network_id);
+        assert_eq!(alert.tips.len(), 2);
+
+        // the ongoing chain split isn't alerted again
+        update_cache(&caches, network_id, set_active_tip(0, &chain[9])).await;
+        assert_eq!(
+            alert::check_chain_split(network_id, &caches, &tree).await,
+            None
+        );
+
+        // node 2 reorgs back to the main chain
+        update_cache(&caches, network_id, set_active_tip(2, &chain[9])).await;
+        let alert = alert::check_chain_split(network_id, &caches, &tree)
+            .await
+            .expect("the chain split resolution should be alerted");
+        assert_eq!(alert.kind, AlertKind::ChainSplitResolved);
+        assert_eq!(
+            alert::check_chain_split(network_id, &caches, &tree).await,
+            None
+        );
+
+        let locked_caches = caches.lock().await;
+        let cache = locked_caches.get(&network_id).unwrap();
+        assert_eq!(cache.chain_split, None);
+        assert_eq!(
+            cache.alerts.iter().map(|a| a.kind).collect::<Vec<_>>(),
+            vec![AlertKind::ChainSplit, AlertKind::ChainSplitResolved]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_ephemeral_cycle() {
+        let path = std::env::temp_dir().join(format!(
+            "fork-observer-ephemeral-test-{}.sqlite",
+            std::process::id()
+        ));
+        let db: Db = Arc::new(Mutex::new(open_database(&path, true).unwrap()));
+        db::setup_db(db.clone()).await.unwrap();
+
+        let network = config::Network {
+            id: 0,
+            description: String::new(),
+            name: "ephemeral".to_string(),
+            min_fork_height: 0,
+            max_interesting_heights: 10,
+            context_blocks: config::ContextBlocks::default(),
+            max_fork_children: 10,
+            max_initial_backfill: 50_000,
+            sync_priority: 0,
+            retain_raw_tips: false,
+            tips_replay_length: 0,
+            verify_pow: false,
+            height_offset: 0,
+            nodes: vec![],
+            pool_identification: config::PoolIdentification::default(),
+            privacy: config::Privacy::default(),
+            feeds: config::Feeds::default(),
+            track_propagation: false,
+            track_chain_work: false,
+            watch_coinbase_addresses: vec![],
+            theme: None,
+        };
+        let tree: Tree = Arc::new(Mutex::new(
+            db::load_treeinfos(db.clone(), network.id).await.unwrap(),
+        ));
+        let alerts = db::load_alerts(db.clone(), network.id, alert::MAX_ALERTS_IN_CACHE)
+            .await
+            .unwrap();
+        let caches: Caches = Arc::new(Mutex::new(BTreeMap::new()));
+        populate_cache(&network, &tree, &caches, alerts).await;
+
+        // new headers from a poll take the same path as in the poll loop
+        let headers = test_utils::chain(BlockHash::all_zeros(), 0, 5, 0);
+        assert!(
+            store_new_headers(&tree, db.clone(), network.id, &headers, true)
+                .await
+                .unwrap()
+        );
+        let header_infos_json = headertree::strip_tree(
+            &tree,
+            network.max_interesting_heights,
+            BTreeSet::from([4]),
+            network.context_blocks,
+        )
+        .await;
+        let forks =
+            headertree::recent_forks(&tree, MAX_FORKS_IN_CACHE, network.max_fork_children).await;
+        update_cache(
+            &caches,
+            network.id,
+            CacheUpdate::HeaderTree {
+                header_infos_json,
+                forks,
+            },
+        )
+        .await;
+
+        assert_eq!(tree.lock().await.0.node_count(), 5);
+        let tip = headers[4].header.block_hash().to_string();
+        assert!(caches.lock().await[&network.id]
+            .header_infos_json
+            .iter()
+            .any(|h| h.hash == tip));
+        // the headers aren't written, not even to the in-memory database
+        assert_eq!(
+            db::load_treeinfos(db.clone(), network.id)
+                .await
+                .unwrap()
+                .0
+                .node_count(),
+            0
+        );
+        // nothing is written to disk
+        assert!(!path.exists());
+
+        // without ephemeral mode, the headers are written
+        assert!(
+            !store_new_headers(&tree, db.clone(), network.id, &headers, false)
+                .await
+                .unwrap()
+        );
+        assert_eq!(
+            db::load_treeinfos(db.clone(), network.id)
+                .await
+                .unwrap()
+                .0
+                .node_count(),
+            5
+        );
+    }
+
+    #[tokio::test]
+    async fn test_bind_addr_in_use() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        let routes = warp::any().map(|| "");
+        match warp::serve(routes).try_bind_ephemeral(address) {
+            Err(e) => assert!(is_addr_in_use(&e)),
+            Ok(_) => panic!("binding to a used address should fail"),
+        }
+        drop(listener);
+        assert!(warp::serve(warp::any().map(|| ""))
+            .try_bind_ephemeral(address)
+            .is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_insert_header_with_conflicting_height() {
+        let headers = test_utils::chain(BlockHash::all_zeros(), 0, 3, 0);
+        let tree: Tree = test_utils::tree(&headers);
+
+        let mut conflicting = headers[2].clone();
+        conflicting.height = 7;
+        let child = test_utils::header_info(conflicting.header.block_hash(), 8, 100);
+        assert!(insert_new_headers_into_tree(&tree, &[conflicting.clone(), child.clone()]).await);
+
+        let tree_locked = tree.lock().await;
+        assert_eq!(tree_locked.0.node_count(), 4);
+        let idx = tree_locked.1[&conflicting.header.block_hash()];
+        assert_eq!(tree_locked.0[idx].height, 2);
+        // the child is still connected to the known header
+        let child_idx = tree_locked.1[&child.header.block_hash()];
+        assert!(tree_locked.0.find_edge(idx, child_idx).is_some());
+        drop(tree_locked);
+
+        // re-inserting only the conflicting header doesn't change the tree
+        assert!(!insert_new_headers_into_tree(&tree, &[conflicting]).await);
+    }
+
+    #[test]
+    fn test_new_block_change() {
+        assert_eq!(new_block_change(&[]), None);
+        let chain = test_utils::chain(BlockHash::all_zeros(), 5, 3, 0);
+        assert_eq!(
+            new_block_change(&chain),
+            Some(Change::NewBlock {
+                hash: chain[2].header.block_hash().to_string(),
+                height: 7,
+            })
+        );
+    }
+
+    #[test]
+    fn test_split_invalid_pow() {
+        use bitcoincore_rpc::bitcoin::constants::genesis_block;
+
+        let valid = HeaderInfo {
+            height: 0,
+            header: genesis_block(bitcoincore_rpc::bitcoin::Network::Regtest).header,
+            miner: String::new(),
+        };
+        // the mainnet genesis header with a different nonce doesn't meet its target
+        let mut invalid_header = genesis_block(bitcoincore_rpc::bitcoin::Network::Bitcoin).header;
+        invalid_header.nonce += 1;
+        let invalid = HeaderInfo {
+            height: 0,
+            header: invalid_header,
+            miner: String::new(),
+        };
+        // a descendant of the invalid header is rejected regardless of its own work
+        let child = test_utils::header_info(invalid_header.block_hash(), 1, 0);
+
+        let (accepted, rejected) =
+            split_invalid_pow(vec![child.clone(), valid.clone(), invalid.clone()], None);
+        assert_eq!(accepted, vec![valid.clone()]);
+        assert_eq!(rejected, vec![invalid, child]);
+
+        // the regtest genesis header meets its target, but the target is
+        // above the mainnet pow limit
+        let pow_limit = |network| Some(Params::new(network).max_attainable_target);
+        let (accepted, rejected) = split_invalid_pow(
+            vec![valid.clone()],
+            pow_limit(bitcoincore_rpc::bitcoin::Network::Regtest),
+        );
+        assert_eq!((accepted, rejected), (vec![valid.clone()], vec![]));
+        let (accepted, rejected) = split_invalid_pow(
+            vec![valid.clone()],
+            pow_limit(bitcoincore_rpc::bitcoin::Network::Bitcoin),
+        );
+        assert_eq!((accepted, rejected), (vec![], vec![valid]));
+    }
+
+    #[tokio::test]
+    async fn test_alerts_db_roundtrip() {
+        let db: Db = Arc::new(Mutex::new(Connection::open_in_memory().unwrap()));
+        db::setup_db(db.clone()).await.unwrap();
+        let split = Alert {
+            network_id: 1,
+            kind: AlertKind::ChainSplit,
+            timestamp: 1000,
+            tips: vec![],
+        };
+        db::write_alert(&split, db.clone()).await.unwrap();
+        assert_eq!(
+            alert::chain_split_from_alerts(&db::load_alerts(db.clone(), 1, 10).await.unwrap()),
+            Some(vec![])
+        );
+
+        let resolved = Alert {
+            network_id: 1,
+            kind: AlertKind::ChainSplitResolved,
+            timestamp: 2000,
+            tips: vec![],
+        };
+        db::write_alert(&resolved, db.clone()).await.unwrap();
+        let alerts = db::load_alerts(db.clone(), 1, 10).await.unwrap();
+        assert_eq!(alerts, vec![split, resolved.clone()]);
+        assert_eq!(alert::chain_split_from_alerts(&alerts), None);
+        assert_eq!(
+            db::load_alerts(db.clone(), 1, 1).await.unwrap(),
+            vec![resolved]
+        );
+        assert!(db::load_alerts(db, 2, 10).await.unwrap().is_empty());
+    }
 }
@@ -1,42 +1,78 @@
 use crate::error::{FetchError, JsonRPCError};
-use crate::types::{ChainTip, ChainTipStatus, HeaderInfo, Tree};
+use crate::jsonrpc::BtcdClient;
+use crate::types::{
+    ChainTip, ChainTipStatus, Deployments, GetDeploymentInfoResult, HeaderInfo, Tree,
+};
+use crate::websocket;
+use crate::zmq;
 use async_trait::async_trait;
 use bitcoincore_rpc::bitcoin;
 use bitcoincore_rpc::bitcoin::blockdata::block::Header;
-use bitcoincore_rpc::bitcoin::{BlockHash, Transaction};
+use bitcoincore_rpc::bitcoin::consensus::Decodable;
+use bitcoincore_rpc::bitcoin::{BlockHash, Transaction, VarInt};
 use bitcoincore_rpc::Auth;
 use bitcoincore_rpc::Client;
 use bitcoincore_rpc::RpcApi;
-use log::{debug, error};
-use std::cmp::max;
+use log::{debug, error, warn};
+use serde::Deserialize;
+use std::cmp::{max, min};
 use std::fmt;
+use std::str::FromStr;
+use std::sync::Arc;
+use tokio::sync::Notify;
 use tokio::task;
 
 const BTCD_USE_REST: bool = false;
+// JSON-RPC error code returned for unknown RPC methods.
+const RPC_METHOD_NOT_FOUND: i32 = -32601;
 const DEFAULT_EMPTY_MINER: &str = "";
 
 #[async_trait]
 pub trait Node: Sync {
     fn info(&self) -> NodeInfo;
     fn use_rest(&self) -> bool;
+    /// The node is only reachable via REST, without RPC credentials. Only
+    /// its active tip is known and RPC-only calls, e.g. for the version or
+    /// the deployments, are skipped.
+    fn rest_only(&self) -> bool {
+        false
+    }
     fn rpc_url(&self) -> String;
+    /// The transport used to load active-chain headers and its endpoint,
+    /// e.g. "REST http://127.0.0.1:8332/rest". Used in log messages.
+    fn transport(&self) -> String {
+        if self.use_rest() {
+            format!("REST http://{}/rest", self.rpc_url())
+        } else {
+            format!("RPC {}", self.rpc_url())
+        }
+    }
+    /// Starts listening for block notifications pushed by the node. The
+    /// returned Notify is kicked on each notification. None if the node
+    /// doesn't push notifications.
+    fn block_notifications(&self) -> Option<Arc<Notify>> {
+        None
+    }
     async fn version(&self) -> Result<String, FetchError>;
     async fn block_header(&self, hash: &BlockHash) -> Result<Header, FetchError>;
     async fn block_hash(&self, height: u64) -> Result<BlockHash, FetchError>;
     async fn tips(&self) -> Result<Vec<ChainTip>, FetchError>;
     async fn coinbase(&self, hash: &BlockHash) -> Result<Transaction, FetchError>;
+    async fn deployments(&self) -> Result<Deployments, FetchError>;
 
     async fn new_headers(
         &self,
         tips: &Vec<ChainTip>,
         tree: &Tree,
         min_fork_height: u64,
+        max_backfill: u64,
     ) -> Result<(Vec<HeaderInfo>, Vec<BlockHash>), FetchError> {
         let mut new_headers: Vec<HeaderInfo> = Vec::new();
         let mut headers_needing_miners: Vec<BlockHash> = Vec::new();
 
-        let mut active_new_headers: Vec<HeaderInfo> =
-            self.new_active_headers(tips, tree, min_fork_height).await?;
+        let mut active_new_headers: Vec<HeaderInfo> = self
+            .new_active_headers(tips, tree, min_fork_height, max_backfill)
+            .await?;
         // We only want miners for active headers if they are (smaller) tip updates.
         if active_new_headers.len() <= 20 {
             for h in active_new_headers.iter() {
@@ -56,11 +92,15 @@ pub trait Node: Sync {
         Ok((new_headers, headers_needing_miners))
     }
 
+    /// Loads the active-chain headers not yet in the tree. At most
+    /// `max_backfill` headers above the highest header in the tree are
+    /// loaded. The remaining headers are loaded on the next calls.
     async fn new_active_headers(
         &self,
         tips: &Vec<ChainTip>,
         tree: &Tree,
         min_fork_height: u64,
+        max_backfill: u64,
     ) -> Result<Vec<HeaderInfo>, FetchError> {
         let mut new_headers: Vec<HeaderInfo> = Vec::new();
 
@@ -77,7 +117,30 @@ pub trait Node: Sync {
             }
         };
         const STEP_SIZE: i64 = 2000;
-        let mut query_height: i64 = active_tip.height as i64;
+        let max_known_height: Option<u64> = {
+            let locked_tree = tree.lock().await;
+            // In the steady state without a new block, the active tip is
+            // already in the tree. Scanning would stop at it anyway, so we
+            // skip the block hash and header roundtrips.
+            if locked_tree.1.contains_key(&active_tip.block_hash()) {
+                return Ok(new_headers);
+            }
+            locked_tree.0.node_weights().map(|h| h.height).max()
+        };
+        let backfill_end: u64 = match max_known_height {
+            Some(height) => height.saturating_add(max_backfill),
+            None => min_fork_height.saturating_add(max_backfill.saturating_sub(1)),
+        };
+        if active_tip.height > backfill_end {
+            debug!(
+                "{} is {} headers ahead, only backfilling up to height {} via {}",
+                self.info(),
+                active_tip.height - max_known_height.unwrap_or(min_fork_height),
+                backfill_end,
+                self.transport(),
+            );
+        }
+        let mut query_height: i64 = min(active_tip.height, backfill_end) as i64;
         loop {
             if self.use_rest() {
                 // We want to either start to query blocks at the `min_fork_height` or
@@ -88,9 +151,12 @@ pub trait Node: Sync {
                 // get the header hash for a header STEP_SIZE away from query_height
                 let header_hash = self.block_hash(rest_query_height as u64).await?;
 
-                // get STEP_SIZE headers
+                // get the up to STEP_SIZE headers up to query_height
                 let headers = self
-                    .active_chain_headers_rest(STEP_SIZE as u64, header_hash)
+                    .active_chain_headers_rest(
+                        (query_height - rest_query_height + 1) as u64,
+                        header_hash,
+                    )
                     .await?;
 
                 // zip heights and headers up and to iterate through them by descending height
@@ -128,6 +194,12 @@ pub trait Node: Sync {
                         break;
                     }
                 }
+                debug!(
+                    "loading active-chain header: hash={}, height={} via {}",
+                    header_hash,
+                    query_height,
+                    self.transport()
+                );
                 let header = self.block_header(&header_hash).await?;
                 new_headers.push(HeaderInfo {
                     height: query_height as u64,
@@ -152,20 +224,42 @@ pub trait Node: Sync {
         min_fork_height: u64,
     ) -> Result<Vec<HeaderInfo>, FetchError> {
         let mut new_headers: Vec<HeaderInfo> = Vec::new();
+        // Non-active branches are loaded with getblockheader.
+        if self.rest_only() {
+            return Ok(new_headers);
+        }
         for inactive_tip in tips
             .iter()
-            .filter(|tip| tip.height - tip.branchlen as u64 > min_fork_height)
             .filter(|tip| tip.status != ChainTipStatus::Active)
         {
+            // A known tip's branch was already loaded. Some backends report
+            // a different branchlen for the same tip between polls, so this
+            // is checked before looking at the branchlen.
+            if tree.lock().await.1.contains_key(&inactive_tip.block_hash()) {
+                continue;
+            }
+            match inactive_tip.fork_height() {
+                Some(fork_height) if fork_height > min_fork_height => (),
+                Some(_) => continue,
+                None => {
+                    warn!(
+                        "skipping chain tip {} of node {} with a branchlen ({}) larger than its height ({})",
+                        inactive_tip.hash,
+                        self.info(),
+                        inactive_tip.branchlen,
+                        inactive_tip.height
+                    );
+                    continue;
+                }
+            }
             let mut next_header = inactive_tip.block_hash();
             for i in 0..=inactive_tip.branchlen {
-                {
-                    let tree_locked = tree.lock().await;
-                    if tree_locked.1.contains_key(&inactive_tip.block_hash()) {
-                        break;
-                    }
+                // The rest of the branch is known.
+                if tree.lock().await.1.contains_key(&next_header) {
+                    break;
                 }
 
+                // Can't underflow: branchlen <= height is checked above.
                 let height = inactive_tip.height - i as u64;
                 debug!(
                     "loading non-active-chain header: hash={}, height={}",
@@ -192,8 +286,11 @@ pub trait Node: Sync {
     ) -> Result<Vec<Header>, FetchError> {
         assert!(self.use_rest());
         debug!(
-            "loading active-chain headers starting from {}",
-            start.to_string()
+            "loading {} active-chain headers starting from {} from {} via {}",
+            count,
+            start,
+            self.info(),
+            self.transport(),
         );
 
         let url = format!(
@@ -234,9 +331,11 @@ pub trait Node: Sync {
         };
 
         debug!(
-            "loaded {} active-chain headers starting from {}",
+            "loaded {} active-chain headers starting from {} from {} via {}",
             headers.len(),
-            start.to_string()
+            start,
+            self.info(),
+            self.transport(),
         );
 
         Ok(headers)
@@ -267,25 +366,82 @@ pub struct BitcoinCoreNode {
     rpc_url: String,
     rpc_auth: Auth,
     use_rest: bool,
+    /// The zmqpubhashblock endpoint to subscribe to block notifications on.
+    zmq_pubhashblock: Option<String>,
 }
 
 impl BitcoinCoreNode {
-    pub fn new(info: NodeInfo, rpc_url: String, rpc_auth: Auth, use_rest: bool) -> Self {
+    pub fn new(
+        info: NodeInfo,
+        rpc_url: String,
+        rpc_auth: Auth,
+        use_rest: bool,
+        zmq_pubhashblock: Option<String>,
+    ) -> Self {
         BitcoinCoreNode {
             info,
             rpc_url,
             rpc_auth,
             use_rest,
+            zmq_pubhashblock,
         }
     }
 
+    async fn block_hash_rest(&self, height: u64) -> Result<BlockHash, FetchError> {
+        let url = format!(
+            "http://{}/rest/blockhashbyheight/{}.hex",
+            self.rpc_url, height
+        );
+        let res = minreq::get(url.clone()).with_timeout(8).send()?;
+
+        if res.status_code != 200 {
+            return Err(FetchError::BitcoinCoreREST(format!(
+                "could not load the block hash from REST URL ({}): {} {}: {:?}",
+                url,
+                res.status_code,
+                res.reason_phrase,
+                res.as_str(),
+            )));
+        }
+        parse_rest_block_hash(res.as_str()?)
+    }
+
+    async fn coinbase_rest(&self, hash: &BlockHash) -> Result<Transaction, FetchError> {
+        let url = format!("http://{}/rest/block/{}.bin", self.rpc_url, hash);
+        let res = minreq::get(url.clone()).with_timeout(8).send()?;
+
+        if res.status_code != 200 {
+            return Err(FetchError::BitcoinCoreREST(format!(
+                "could not load the block from REST URL ({}): {} {}",
+                url, res.status_code, res.reason_phrase,
+            )));
+        }
+        parse_rest_coinbase(res.as_bytes(), hash)
+    }
+
+    // The active tip from /rest/chaininfo.json. REST has no equivalent of
+    // getchaintips.
+    async fn active_tip_rest(&self) -> Result<ChainTip, FetchError> {
+        let url = format!("http://{}/rest/chaininfo.json", self.rpc_url);
+        let res = minreq::get(url.clone()).with_timeout(8).send()?;
+
+        if res.status_code != 200 {
+            return Err(FetchError::BitcoinCoreREST(format!(
+                "could not load the chain info from REST URL ({}): {} {}",
+                url, res.status_code, res.reason_phrase,
+            )));
+        }
+        parse_rest_chaininfo(res.as_str()?)
+    }
+
     fn rpc_client(&self) -> Result<Client, FetchError> {
         match Client::new(&self.rpc_url, self.rpc_auth.clone()) {
             Ok(c) => Ok(c),
             Err(e) => {
                 error!(
-                    "Could not create a RPC client for node {}: {:?}",
+                    "Could not create a RPC client for node {} ({}): {:?}",
                     self.info(),
+                    self.rpc_url,
                     e
                 );
                 Err(FetchError::from(e))
@@ -304,10 +460,24 @@ impl Node for BitcoinCoreNode {
         self.use_rest
     }
 
+    fn rest_only(&self) -> bool {
+        self.use_rest && self.rpc_auth == Auth::None
+    }
+
     fn rpc_url(&self) -> String {
         self.rpc_url.clone()
     }
 
+    fn block_notifications(&self) -> Option<Arc<Notify>> {
+        let endpoint = self.zmq_pubhashblock.clone()?;
+        let kick = Arc::new(Notify::new());
+        task::spawn(zmq::bitcoin_core_block_notifications(
+            endpoint,
+            kick.clone(),
+        ));
+        Some(kick)
+    }
+
     async fn version(&self) -> Result<String, FetchError> {
         let rpc = self.rpc_client()?;
         match task::spawn_blocking(move || rpc.get_network_info()).await {
@@ -320,6 +490,18 @@ impl Node for BitcoinCoreNode {
     }
 
     async fn block_hash(&self, height: u64) -> Result<BlockHash, FetchError> {
+        if self.use_rest {
+            match self.block_hash_rest(height).await {
+                Ok(hash) => return Ok(hash),
+                Err(e) if self.rest_only() => return Err(e),
+                Err(e) => warn!(
+                    "Could not load the block hash at height {} via REST from {}, falling back to RPC: {}",
+                    height,
+                    self.info(),
+                    e
+                ),
+            }
+        }
         let rpc = self.rpc_client()?;
         match task::spawn_blocking(move || rpc.get_block_hash(height)).await {
             Ok(result) => match result {
@@ -343,6 +525,18 @@ impl Node for BitcoinCoreNode {
     }
 
     async fn coinbase(&self, hash: &BlockHash) -> Result<Transaction, FetchError> {
+        if self.use_rest {
+            match self.coinbase_rest(hash).await {
+                Ok(coinbase) => return Ok(coinbase),
+                Err(e) if self.rest_only() => return Err(e),
+                Err(e) => warn!(
+                    "Could not load the coinbase of block {} via REST from {}, falling back to RPC: {}",
+                    hash,
+                    self.info(),
+                    e
+                ),
+            }
+        }
         let rpc = self.rpc_client()?;
         let hash_clone = hash.clone();
         match task::spawn_blocking(move || rpc.get_block(&hash_clone)).await {
@@ -358,7 +552,39 @@ impl Node for BitcoinCoreNode {
         }
     }
 
+    async fn deployments(&self) -> Result<Deployments, FetchError> {
+        let rpc = self.rpc_client()?;
+        match task::spawn_blocking(move || {
+            match rpc.call::<GetDeploymentInfoResult>("getdeploymentinfo", &[]) {
+                // getdeploymentinfo was added in Bitcoin Core v23.0. Older
+                // versions list the deployments in getblockchaininfo.
+                Err(bitcoincore_rpc::Error::JsonRpc(bitcoincore_rpc::jsonrpc::Error::Rpc(e)))
+                    if e.code == RPC_METHOD_NOT_FOUND =>
+                {
+                    rpc.call::<GetDeploymentInfoResult>("getblockchaininfo", &[])
+                }
+                result => result,
+            }
+        })
+        .await
+        {
+            Ok(result) => match result {
+                Ok(result) => Ok(result
+                    .deployments
+                    .iter()
+                    .map(|(name, info)| (name.clone(), info.status()))
+                    .collect()),
+                Err(e) => Err(e.into()),
+            },
+            Err(e) => Err(e.into()),
+        }
+    }
+
     async fn tips(&self) -> Result<Vec<ChainTip>, FetchError> {
+        // Without RPC credentials, only the active tip is known.
+        if self.rest_only() {
+            return Ok(vec![self.active_tip_rest().await?]);
+        }
         let rpc = self.rpc_client()?;
         match task::spawn_blocking(move || rpc.get_chain_tips()).await {
             Ok(tips_result) => match tips_result {
@@ -370,21 +596,100 @@ impl Node for BitcoinCoreNode {
     }
 }
 
-#[derive(Hash, Clone)]
+// Parses the body of a /rest/blockhashbyheight/<height>.hex response.
+fn parse_rest_block_hash(body: &str) -> Result<BlockHash, FetchError> {
+    BlockHash::from_str(body.trim()).map_err(|e| {
+        FetchError::BitcoinCoreREST(format!(
+            "could not parse the REST block hash response {:?}: {}",
+            body, e
+        ))
+    })
+}
+
+#[derive(Deserialize)]
+struct RestChainInfo {
+    blocks: u64,
+    bestblockhash: String,
+}
+
+// Parses the body of a /rest/chaininfo.json response into the active tip.
+fn parse_rest_chaininfo(body: &str) -> Result<ChainTip, FetchError> {
+    let chaininfo: RestChainInfo = serde_json::from_str(body).map_err(|e| {
+        FetchError::BitcoinCoreREST(format!(
+            "could not parse the REST chain info response: {}",
+            e
+        ))
+    })?;
+    let hash = parse_rest_block_hash(&chaininfo.bestblockhash)?;
+    Ok(ChainTip {
+        height: chaininfo.blocks,
+        hash: hash.to_string(),
+        branchlen: 0,
+        status: ChainTipStatus::Active,
+    })
+}
+
+// Parses the body of a /rest/block/<hash>.bin response and returns the
+// coinbase transaction of the block.
+// Only the header, the transaction count and the coinbase are decoded from
+// the REST block response. The remaining transactions are skipped, which
+// avoids decoding large blocks during the miner identification sweeps.
+fn parse_rest_coinbase(body: &[u8], hash: &BlockHash) -> Result<Transaction, FetchError> {
+    let decode_error = |e: bitcoin::consensus::encode::Error| {
+        FetchError::BitcoinCoreREST(format!(
+            "could not deserialize the REST block response: {}",
+            e
+        ))
+    };
+    let mut reader = body;
+    let header = Header::consensus_decode(&mut reader).map_err(decode_error)?;
+    if header.block_hash() != *hash {
+        return Err(FetchError::BitcoinCoreREST(format!(
+            "the REST block response contains block {} instead of {}",
+            header.block_hash(),
+            hash
+        )));
+    }
+    let tx_count = VarInt::consensus_decode(&mut reader).map_err(decode_error)?;
+    if tx_count.0 == 0 {
+        return Err(FetchError::BitcoinCoreREST(format!(
+            "block {} has no coinbase transaction",
+            hash
+        )));
+    }
+    Transaction::consensus_decode(&mut reader).map_err(decode_error)
+}
+
+#[derive(Clone)]
 pub struct BtcdNode {
     info: NodeInfo,
     rpc_url: String,
     rpc_user: String,
     rpc_password: String,
+    client: BtcdClient,
+    /// Subscribe to block notifications via the btcd websocket endpoint.
+    websocket: bool,
 }
 
 impl BtcdNode {
-    pub fn new(info: NodeInfo, rpc_url: String, rpc_user: String, rpc_password: String) -> Self {
+    pub fn new(
+        info: NodeInfo,
+        rpc_url: String,
+        rpc_user: String,
+        rpc_password: String,
+        websocket: bool,
+    ) -> Self {
         BtcdNode {
             info,
+            client: BtcdClient::new(
+                format!("http://{}/", rpc_url),
+                rpc_user.clone(),
+                rpc_password.clone(),
+            ),
             rpc_url,
             rpc_user,
             rpc_password,
+            websocket,
         }
     }
 }
@@ -403,31 +708,37 @@ impl Node for BtcdNode {
         self.rpc_url.clone()
     }
 
+    fn block_notifications(&self) -> Option<Arc<Notify>> {
+        if !self.websocket {
+            return None;
+        }
+        let kick = Arc::new(Notify::new());
+        task::spawn(websocket::btcd_block_notifications(
+            format!("ws://{}/ws", self.rpc_url),
+            self.rpc_user.clone(),
+            self.rpc_password.clone(),
+            kick.clone(),
+        ));
+        Some(kick)
+    }
+
     async fn version(&self) -> Result<String, FetchError> {
         Err(FetchError::BtcdRPC(JsonRPCError::NotImplemented))
     }
 
+    async fn deployments(&self) -> Result<Deployments, FetchError> {
+        Err(FetchError::BtcdRPC(JsonRPCError::NotImplemented))
+    }
+
     async fn block_header(&self, hash: &BlockHash) -> Result<Header, FetchError> {
-        let url = format!("http://{}/", self.rpc_url);
-        match crate::jsonrpc::btcd_blockheader(
-            url,
-            self.rpc_user.clone(),
-            self.rpc_password.clone(),
-            hash.to_string(),
-        ) {
+        match self.client.blockheader(hash.to_string()) {
             Ok(header) => Ok(header),
             Err(error) => Err(FetchError::BtcdRPC(error)),
         }
     }
 
     async fn coinbase(&self, hash: &BlockHash) -> Result<Transaction, FetchError> {
-        let url = format!("http://{}/", self.rpc_url);
-        match crate::jsonrpc::btcd_block(
-            url,
-            self.rpc_user.clone(),
-            self.rpc_password.clone(),
-            hash.to_string(),
-        ) {
+        match self.client.block(hash.to_string()) {
             Ok(block) => Ok(block
                 .txdata
                 .first()
@@ -438,24 +749,430 @@ impl Node for BtcdNode {
     }
 
     async fn block_hash(&self, height: u64) -> Result<BlockHash, FetchError> {
-        let url = format!("http://{}/", self.rpc_url);
-        match crate::jsonrpc::btcd_blockhash(
-            url,
-            self.rpc_user.clone(),
-            self.rpc_password.clone(),
-            height,
-        ) {
+        match self.client.blockhash(height) {
             Ok(tips) => Ok(tips),
             Err(error) => Err(FetchError::BtcdRPC(error)),
         }
     }
 
     async fn tips(&self) -> Result<Vec<ChainTip>, FetchError> {
-        let url = format!("http://{}/", self.rpc_url);
-        match crate::jsonrpc::btcd_chaintips(url, self.rpc_user.clone(), self.rpc_password.clone())
-        {
+        match self.client.chaintips() {
             Ok(tips) => Ok(tips),
             Err(error) => Err(FetchError::BtcdRPC(error)),
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils;
+    use bitcoincore_rpc::bitcoin::constants::genesis_block;
+    use bitcoincore_rpc::bitcoin::hashes::Hash;
+    use bitcoincore_rpc::bitcoin::Network;
+    use std::collections::HashMap;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    // Serves block headers from memory.
+    struct MockNode {
+        headers: HashMap<BlockHash, Header>,
+        active_chain: HashMap<u64, BlockHash>,
+        // Number of block_header and block_hash requests.
+        requests: AtomicUsize,
+    }
+
+    impl MockNode {
+        fn new(active_chain: &[HeaderInfo], other: &[HeaderInfo]) -> Self {
+            MockNode {
+                headers: active_chain
+                    .iter()
+                    .chain(other.iter())
+                    .map(|h| (h.header.block_hash(), h.header))
+                    .collect(),
+                active_chain: active_chain
+                    .iter()
+                    .map(|h| (h.height, h.header.block_hash()))
+                    .collect(),
+                requests: AtomicUsize::new(0),
+            }
+        }
+    }
+
+    // The mock node only serves headers and block hashes.
+    fn unsupported() -> FetchError {
+        FetchError::DataError("not supported by the mock node".to_string())
+    }
+
+    #[async_trait]
+    impl Node for MockNode {
+        fn info(&self) -> NodeInfo {
+            NodeInfo {
+                id: 0,
+                name: "mock".to_string(),
+                description: String::new(),
+                implementation: "mock".to_string(),
+            }
+        }
+        fn use_rest(&self) -> bool {
+            false
+        }
+        fn rpc_url(&self) -> String {
+            String::new()
+        }
+        async fn version(&self) -> Result<String, FetchError> {
+            Err(unsupported())
+        }
+        async fn block_header(&self, hash: &BlockHash) -> Result<Header, FetchError> {
+            self.requests.fetch_add(1, Ordering::SeqCst);
+            self.headers
+                .get(hash)
+                .cloned()
+                .ok_or_else(|| FetchError::DataError(format!("unknown header {}", hash)))
+        }
+        async fn block_hash(&self, height: u64) -> Result<BlockHash, FetchError> {
+            self.requests.fetch_add(1, Ordering::SeqCst);
+            self.active_chain
+                .get(&height)
+                .cloned()
+                .ok_or_else(|| FetchError::DataError(format!("no header at height {}", height)))
+        }
+        async fn tips(&self) -> Result<Vec<ChainTip>, FetchError> {
+            Err(unsupported())
+        }
+        async fn coinbase(&self, _hash: &BlockHash) -> Result<Transaction, FetchError> {
+            Err(unsupported())
+        }
+        async fn deployments(&self) -> Result<Deployments, FetchError> {
+            Err(unsupported())
+        }
+    }
+
+    #[test]
+    fn test_parse_rest_block_hash() {
+        let hash = "000000000019d6689c085ae165831e934ff763ae46a2a6c172b3f1b60a8ce26f";
+        assert_eq!(
+            parse_rest_block_hash(&format!("{}\n", hash)).unwrap(),
+            BlockHash::from_str(hash).unwrap()
+        );
+        assert!(parse_rest_block_hash("").is_err());
+        assert!(parse_rest_block_hash("Block height out of range").is_err());
+        assert!(parse_rest_block_hash(&hash[1..]).is_err());
+    }
+
+    // Answers every HTTP request on a local port with the status and body.
+    fn http_server(status: &'static str, body: Vec<u8>) -> String {
+        use std::io::{Read, Write};
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mut stream = match stream {
+                    Ok(stream) => stream,
+                    Err(_) => break,
+                };
+                let mut buffer = [0u8; 4096];
+                let _ = stream.read(&mut buffer);
+                let _ = write!(
+                    stream,
+                    "HTTP/1.1 {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                    status,
+                    body.len(),
+                );
+                let _ = stream.write_all(&body);
+            }
+        });
+        address
+    }
+
+    fn rest_node(address: String) -> BitcoinCoreNode {
+        rest_node_with_auth(address, Auth::None)
+    }
+
+    fn rest_node_with_auth(address: String, rpc_auth: Auth) -> BitcoinCoreNode {
+        BitcoinCoreNode::new(
+            NodeInfo {
+                id: 0,
+                name: "rest".to_string(),
+                description: String::new(),
+                implementation: "Bitcoin Core".to_string(),
+            },
+            address,
+            rpc_auth,
+            true,
+            None,
+        )
+    }
+
+    fn user_pass() -> Auth {
+        Auth::UserPass("user".to_string(), "password".to_string())
+    }
+
+    #[test]
+    fn test_transport() {
+        let node = rest_node("127.0.0.1:8332".to_string());
+        assert_eq!(node.transport(), "REST http://127.0.0.1:8332/rest");
+        let node = MockNode::new(&[], &[]);
+        assert_eq!(node.transport(), "RPC ");
+    }
+
+    #[tokio::test]
+    async fn test_block_hash_rest() {
+        let hash = "000000000019d6689c085ae165831e934ff763ae46a2a6c172b3f1b60a8ce26f";
+        let node = rest_node(http_server("200 OK", format!("{}\n", hash).into_bytes()));
+        assert_eq!(
+            node.block_hash(0).await.unwrap(),
+            BlockHash::from_str(hash).unwrap()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_block_hash_rest_falls_back_to_rpc() {
+        // The server answers the REST request and the RPC fallback with a 404.
+        let node = rest_node_with_auth(http_server("404 Not Found", vec![]), user_pass());
+        match node.block_hash(0).await {
+            Err(FetchError::BitcoinCoreREST(e)) => panic!("expected a RPC error, got: {}", e),
+            Err(_) => (),
+            Ok(hash) => panic!("expected an error, got {}", hash),
+        }
+
+        // REST-only nodes don't fall back to RPC
+        let node = rest_node(http_server("404 Not Found", vec![]));
+        assert!(matches!(
+            node.block_hash(0).await,
+            Err(FetchError::BitcoinCoreREST(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_tips_rest() {
+        let hash = "000000000019d6689c085ae165831e934ff763ae46a2a6c172b3f1b60a8ce26f";
+        let body = format!(
+            r#"{{"chain":"main","blocks":0,"headers":0,"bestblockhash":"{}"}}"#,
+            hash
+        );
+        let node = rest_node(http_server("200 OK", body.into_bytes()));
+        assert_eq!(
+            node.tips().await.unwrap(),
+            vec![ChainTip {
+                height: 0,
+                hash: hash.to_string(),
+                branchlen: 0,
+                status: ChainTipStatus::Active,
+            }]
+        );
+
+        assert!(parse_rest_chaininfo("").is_err());
+        assert!(parse_rest_chaininfo(r#"{"blocks":0,"bestblockhash":"00"}"#).is_err());
+        let node = rest_node(http_server("404 Not Found", vec![]));
+        assert!(node.tips().await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_coinbase_rest() {
+        let genesis = genesis_block(Network::Bitcoin);
+        let node = rest_node(http_server(
+            "200 OK",
+            bitcoin::consensus::serialize(&genesis),
+        ));
+        assert_eq!(
+            node.coinbase(&genesis.block_hash()).await.unwrap(),
+            genesis.txdata[0]
+        );
+
+        // a block with a different hash is rejected
+        let other = genesis_block(Network::Signet).block_hash();
+        assert!(parse_rest_coinbase(&bitcoin::consensus::serialize(&genesis), &other).is_err());
+        assert!(parse_rest_coinbase(&[0, 1, 2], &genesis.block_hash()).is_err());
+
+        // only the coinbase is decoded, the transactions after it aren't
+        let mut body = bitcoin::consensus::serialize(&genesis.header);
+        body.extend(bitcoin::consensus::serialize(&VarInt(2)));
+        body.extend(bitcoin::consensus::serialize(&genesis.txdata[0]));
+        body.extend([0xff; 16]);
+        assert_eq!(
+            parse_rest_coinbase(&body, &genesis.block_hash()).unwrap(),
+            genesis.txdata[0]
+        );
+
+        // a block without transactions has no coinbase
+        let mut body = bitcoin::consensus::serialize(&genesis.header);
+        body.extend(bitcoin::consensus::serialize(&VarInt(0)));
+        assert!(parse_rest_coinbase(&body, &genesis.block_hash()).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_coinbase_rest_falls_back_to_rpc() {
+        // The server answers the REST request and the RPC fallback with a 404.
+        let node = rest_node_with_auth(http_server("404 Not Found", vec![]), user_pass());
+        let hash = genesis_block(Network::Bitcoin).block_hash();
+        match node.coinbase(&hash).await {
+            Err(FetchError::BitcoinCoreREST(e)) => panic!("expected a RPC error, got: {}", e),
+            Err(_) => (),
+            Ok(coinbase) => panic!("expected an error, got {:?}", coinbase),
+        }
+
+        // REST-only nodes don't fall back to RPC
+        let node = rest_node(http_server("404 Not Found", vec![]));
+        assert!(matches!(
+            node.coinbase(&hash).await,
+            Err(FetchError::BitcoinCoreREST(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_nonactive_headers_branchlen_larger_than_height() {
+        let active = test_utils::chain(BlockHash::all_zeros(), 1, 3, 0);
+        let fork = test_utils::header_info(active[1].header.block_hash(), 3, 1);
+        let node = MockNode::new(&active, std::slice::from_ref(&fork));
+        let mut broken_tip =
+            test_utils::chain_tip(2, &active[1].header.block_hash(), ChainTipStatus::ValidFork);
+        broken_tip.branchlen = 5;
+        let mut fork_tip =
+            test_utils::chain_tip(3, &fork.header.block_hash(), ChainTipStatus::ValidFork);
+        fork_tip.branchlen = 1;
+        let tips = vec![
+            test_utils::chain_tip(3, &active[2].header.block_hash(), ChainTipStatus::Active),
+            broken_tip,
+            fork_tip,
+        ];
+
+        let headers = node
+            .new_nonactive_headers(&tips, &test_utils::tree(&[]), 0)
+            .await
+            .unwrap();
+        // Only the consistent fork tip is loaded, the broken tip is skipped.
+        assert_eq!(
+            headers
+                .iter()
+                .map(|h| (h.height, h.header.block_hash()))
+                .collect::<Vec<_>>(),
+            vec![
+                (3, fork.header.block_hash()),
+                (2, active[1].header.block_hash()),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_active_headers_backfill_over_multiple_polls() {
+        let min_fork_height = 10;
+        let active = test_utils::chain(BlockHash::all_zeros(), 0, 100, 0);
+        let node = MockNode::new(&active, &[]);
+        let tips = vec![test_utils::chain_tip(
+            99,
+            &active[99].header.block_hash(),
+            ChainTipStatus::Active,
+        )];
+
+        let mut loaded: Vec<HeaderInfo> = vec![];
+        let mut polls = 0;
+        loop {
+            let tree = test_utils::tree(&loaded);
+            if tree.lock().await.1.contains_key(&tips[0].block_hash()) {
+                break;
+            }
+            let new_headers = node
+                .new_active_headers(&tips, &tree, min_fork_height, 40)
+                .await
+                .unwrap();
+            assert!(new_headers.len() <= 40);
+            loaded.extend(new_headers);
+            polls += 1;
+        }
+        // 10..=49, 50..=89 and 90..=99
+        assert_eq!(polls, 3);
+        assert_eq!(loaded, active[10..].to_vec());
+
+        // without a gap, all new headers are loaded at once
+        let tree = test_utils::tree(&active[..90]);
+        let new_headers = node
+            .new_active_headers(&tips, &tree, min_fork_height, 40)
+            .await
+            .unwrap();
+        assert_eq!(new_headers, active[90..].to_vec());
+    }
+
+    #[tokio::test]
+    async fn test_active_headers_steady_state() {
+        let active = test_utils::chain(BlockHash::all_zeros(), 0, 10, 0);
+        let node = MockNode::new(&active, &[]);
+        let tips = vec![test_utils::chain_tip(
+            9,
+            &active[9].header.block_hash(),
+            ChainTipStatus::Active,
+        )];
+
+        // the active tip is known: nothing is requested from the node
+        let tree = test_utils::tree(&active);
+        let new_headers = node.new_active_headers(&tips, &tree, 0, 100).await.unwrap();
+        assert!(new_headers.is_empty());
+        assert_eq!(node.requests.load(Ordering::SeqCst), 0);
+
+        // a new block: its hash, its header and the known parent's hash
+        let tree = test_utils::tree(&active[..9]);
+        let new_headers = node.new_active_headers(&tips, &tree, 0, 100).await.unwrap();
+        assert_eq!(new_headers, active[9..].to_vec());
+        assert_eq!(node.requests.load(Ordering::SeqCst), 3);
+    }
+
+    #[tokio::test]
+    async fn test_nonactive_headers_flapping_branchlen() {
+        let active = test_utils::chain(BlockHash::all_zeros(), 1, 10, 0);
+        let fork = test_utils::chain(active[4].header.block_hash(), 6, 3, 100);
+        let node = MockNode::new(&active, &fork);
+        let mut all = active.clone();
+        all.extend(fork.clone());
+        let tree = test_utils::tree(&all);
+
+        // the known fork tip isn't walked again, whatever its branchlen
+        for branchlen in [3, 1, 7, 20] {
+            let mut fork_tip =
+                test_utils::chain_tip(8, &fork[2].header.block_hash(), ChainTipStatus::ValidFork);
+            fork_tip.branchlen = branchlen;
+            let headers = node
+                .new_nonactive_headers(&vec![fork_tip], &tree, 0)
+                .await
+                .unwrap();
+            assert!(headers.is_empty());
+        }
+        assert_eq!(node.requests.load(Ordering::SeqCst), 0);
+
+        // a new block on the fork: only its header is loaded
+        let new = test_utils::header_info(fork[2].header.block_hash(), 9, 200);
+        let node = MockNode::new(&active, &[fork.clone(), vec![new.clone()]].concat());
+        let mut fork_tip =
+            test_utils::chain_tip(9, &new.header.block_hash(), ChainTipStatus::ValidFork);
+        fork_tip.branchlen = 4;
+        let headers = node
+            .new_nonactive_headers(&vec![fork_tip], &tree, 0)
+            .await
+            .unwrap();
+        assert_eq!(headers, vec![new]);
+        assert_eq!(node.requests.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn test_nonactive_headers_rest_only() {
+        let active = test_utils::chain(BlockHash::all_zeros(), 1, 5, 0);
+        let tree = test_utils::tree(&active);
+        let fork = test_utils::header_info(active[3].header.block_hash(), 5, 100);
+        let fork_tip =
+            test_utils::chain_tip(5, &fork.header.block_hash(), ChainTipStatus::ValidFork);
+
+        // the unreachable RPC interface isn't asked for the fork headers
+        let node = rest_node("127.0.0.1:1".to_string());
+        assert!(node.rest_only());
+        let headers = node
+            .new_nonactive_headers(&vec![fork_tip.clone()], &tree, 0)
+            .await
+            .unwrap();
+        assert!(headers.is_empty());
+
+        let node = rest_node_with_auth("127.0.0.1:1".to_string(), user_pass());
+        assert!(!node.rest_only());
+        assert!(node
+            .new_nonactive_headers(&vec![fork_tip], &tree, 0)
+            .await
+            .is_err());
+    }
+}
@@ -0,0 +1,219 @@
+use std::collections::HashSet;
+
+use bitcoincore_rpc::bitcoin::hashes::{sha256, Hash};
+use bitcoincore_rpc::bitcoin::secp256k1::{Keypair, Message as SecpMessage, Secp256k1, SecretKey};
+use bitcoincore_rpc::bitcoin::BlockHash;
+use futures_util::{SinkExt, StreamExt};
+use log::{info, warn};
+use serde::Serialize;
+use serde_json::{json, Value};
+use tokio::time::{timeout, Duration};
+use tokio_tungstenite::connect_async;
+use tokio_tungstenite::tungstenite::Message;
+
+use crate::config::NostrConfig;
+use crate::error::NostrError;
+use crate::types::{unix_timestamp, Fork, HeaderInfo};
+
+// NIP-01 short text note.
+const KIND_TEXT_NOTE: u32 = 1;
+// How long we wait for the relay to accept an event.
+const PUBLISH_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// A signed NIP-01 event.
+#[derive(Serialize, Debug)]
+pub struct Event {
+    pub id: String,
+    pub pubkey: String,
+    pub created_at: u64,
+    pub kind: u32,
+    pub tags: Vec<Vec<String>>,
+    pub content: String,
+    pub sig: String,
+}
+
+// The event id is the SHA256 of the serialized
+// [0, pubkey, created_at, kind, tags, content] array.
+fn event_id(
+    pubkey: &str,
+    created_at: u64,
+    kind: u32,
+    tags: &[Vec<String>],
+    content: &str,
+) -> [u8; 32] {
+    let serialized = json!([0, pubkey, created_at, kind, tags, content]).to_string();
+    sha256::Hash::hash(serialized.as_bytes()).to_byte_array()
+}
+
+/// Builds a text note and signs it with the secret key.
+pub fn text_note(secret_key: &SecretKey, created_at: u64, content: String) -> Event {
+    let secp = Secp256k1::signing_only();
+    let keypair = Keypair::from_secret_key(&secp, secret_key);
+    let pubkey = keypair.x_only_public_key().0.to_string();
+    let tags = vec![vec!["t".to_string(), "fork-observer".to_string()]];
+    let id = event_id(&pubkey, created_at, KIND_TEXT_NOTE, &tags, &content);
+    let sig = secp.sign_schnorr_no_aux_rand(&SecpMessage::from_digest(id), &keypair);
+    Event {
+        id: hex::encode(id),
+        pubkey,
+        created_at,
+        kind: KIND_TEXT_NOTE,
+        tags,
+        content,
+        sig: sig.to_string(),
+    }
+}
+
+// Sends the event to the relay and waits for the relay to accept it.
+async fn send(relay: &str, event: &Event) -> Result<(), NostrError> {
+    let (mut ws, _) = connect_async(relay).await?;
+    ws.send(Message::Text(json!(["EVENT", event]).to_string()))
+        .await?;
+    let result = loop {
+        match ws.next().await {
+            Some(Ok(Message::Text(text))) => {
+                // ["OK", <event id>, <accepted>, <message>]
+                let ok: Value = match serde_json::from_str(&text) {
+                    Ok(ok) => ok,
+                    Err(_) => continue,
+                };
+                if ok[0] != "OK" || ok[1] != event.id.as_str() {
+                    continue;
+                }
+                if ok[2] == true {
+                    break Ok(());
+                }
+                break Err(NostrError::Rejected(
+                    ok[3].as_str().unwrap_or_default().to_string(),
+                ));
+            }
+            Some(Ok(Message::Close(_))) | None => break Err(NostrError::Closed),
+            Some(Ok(_)) => continue,
+            Some(Err(e)) => break Err(e.into()),
+        }
+    };
+    let _ = ws.close(None).await;
+    result
+}
+
+/// Signs the content as a text note and publishes it to the relay in the
+/// background. Errors are only logged.
+pub fn publish(config: &NostrConfig, content: String) {
+    let event = text_note(&config.secret_key, unix_timestamp(), content);
+    let relay = config.relay.clone();
+    crate::spawn_named("nostr-publish", async move {
+        match timeout(PUBLISH_TIMEOUT, send(&relay, &event)).await {
+            Ok(Ok(())) => info!("Published nostr event {} to {}", event.id, relay),
+            Ok(Err(e)) => warn!(
+                "Could not publish nostr event {} to {}: {}",
+                event.id, relay, e
+            ),
+            Err(_) => warn!(
+                "Could not publish nostr event {} to {}: timed out",
+                event.id, relay
+            ),
+        }
+    });
+}
+
+/// The forks with a child among the new headers.
+pub fn new_forks<'a>(forks: &'a [Fork], new_headers: &[HeaderInfo]) -> Vec<&'a Fork> {
+    let new: HashSet<BlockHash> = new_headers
+        .iter()
+        .map(|header| header.header.block_hash())
+        .collect();
+    forks
+        .iter()
+        .filter(|fork| {
+            fork.children
+                .iter()
+                .any(|child| new.contains(&child.header.block_hash()))
+        })
+        .collect()
+}
+
+pub fn fork_note(network: &str, fork: &Fork) -> String {
+    let children: Vec<String> = fork
+        .children
+        .iter()
+        .map(|child| format!("{} ({})", child.header.block_hash(), child.miner))
+        .collect();
+    format!(
+        "Fork on {} at height {}. The block {} has {} children: {}",
+        network,
+        fork.common.height + 1,
+        fork.common.header.block_hash(),
+        fork.children.len(),
+        children.join(", ")
+    )
+}
+
+pub fn reorg_note(
+    network: &str,
+    node: &str,
+    old_tip: (&BlockHash, u64),
+    new_tip: (&BlockHash, u64),
+) -> String {
+    format!(
+        "Reorg on {}: {} switched from block {} at height {} to block {} at height {}",
+        network, node, old_tip.0, old_tip.1, new_tip.0, new_tip.1
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils;
+    use bitcoincore_rpc::bitcoin::secp256k1::schnorr::Signature;
+    use bitcoincore_rpc::bitcoin::secp256k1::XOnlyPublicKey;
+    use std::str::FromStr;
+
+    #[test]
+    fn test_text_note() {
+        let secret_key =
+            SecretKey::from_str("0000000000000000000000000000000000000000000000000000000000000003")
+                .unwrap();
+        let event = text_note(&secret_key, 1700000000, "Fork on \"Mainnet\"\n".to_string());
+
+        // BIP340 test vector 0 public key
+        assert_eq!(
+            event.pubkey,
+            "f9308a019258c31049344f85f89d5229b531c845836f99b08601f113bce036f9"
+        );
+        assert_eq!(event.kind, 1);
+        let expected_id = sha256::Hash::hash(
+            format!(
+                r#"[0,"{}",1700000000,1,[["t","fork-observer"]],"Fork on \"Mainnet\"\n"]"#,
+                event.pubkey
+            )
+            .as_bytes(),
+        )
+        .to_byte_array();
+        assert_eq!(event.id, hex::encode(expected_id));
+
+        let secp = Secp256k1::verification_only();
+        let sig = Signature::from_str(&event.sig).unwrap();
+        let pubkey = XOnlyPublicKey::from_str(&event.pubkey).unwrap();
+        assert!(secp
+            .verify_schnorr(&sig, &SecpMessage::from_digest(expected_id), &pubkey)
+            .is_ok());
+    }
+
+    #[test]
+    fn test_new_forks() {
+        let chain = test_utils::chain(BlockHash::all_zeros(), 0, 3, 0);
+        let branch_a = test_utils::header_info(chain[2].header.block_hash(), 3, 100);
+        let branch_b = test_utils::header_info(chain[2].header.block_hash(), 3, 200);
+        let fork = |children: Vec<_>| Fork {
+            common: chain[2].clone(),
+            children,
+            truncated_children: 0,
+        };
+
+        let forks = vec![fork(vec![branch_a.clone(), branch_b.clone()])];
+        assert!(new_forks(&forks, &[]).is_empty());
+        assert!(new_forks(&forks, &chain).is_empty());
+        assert_eq!(new_forks(&forks, std::slice::from_ref(&branch_b)).len(), 1);
+        assert_eq!(new_forks(&forks, &[branch_a, branch_b]).len(), 1);
+    }
+}
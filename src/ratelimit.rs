@@ -0,0 +1,230 @@
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::Arc;
+
+use tokio::sync::Mutex;
+use tokio::time::{Duration, Instant};
+use warp::{Filter, Rejection};
+
+use crate::accesslog;
+
+/// Rejection for clients that exceeded their request rate.
+#[derive(Debug)]
+pub struct RateLimited {
+    /// How long the client has to wait for its next request at most.
+    pub retry_after: Duration,
+}
+
+impl warp::reject::Reject for RateLimited {}
+
+// A token bucket. Holds up to `per_minute` tokens and is refilled with
+// `per_minute` tokens per minute. Each request takes a token.
+struct Bucket {
+    tokens: f64,
+    last_refill: Instant,
+}
+
+/// Limits the requests per minute of each client IP address.
+#[derive(Clone)]
+pub struct RateLimiter {
+    per_minute: u32,
+    buckets: Arc<Mutex<HashMap<IpAddr, Bucket>>>,
+}
+
+impl RateLimiter {
+    pub fn new(per_minute: u32) -> Self {
+        RateLimiter {
+            per_minute,
+            buckets: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    fn refill(&self, bucket: &mut Bucket, now: Instant) {
+        if now <= bucket.last_refill {
+            return;
+        }
+        let elapsed = now - bucket.last_refill;
+        bucket.tokens = (bucket.tokens + elapsed.as_secs_f64() * self.per_minute as f64 / 60.0)
+            .min(self.per_minute as f64);
+        bucket.last_refill = now;
+    }
+
+    /// Takes a token from the bucket of the IP address. Returns false if the
+    /// bucket is empty.
+    async fn allow(&self, ip: IpAddr, now: Instant) -> bool {
+        let mut buckets = self.buckets.lock().await;
+        let bucket = buckets.entry(ip).or_insert(Bucket {
+            tokens: self.per_minute as f64,
+            last_refill: now,
+        });
+        self.refill(bucket, now);
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Forgets the IP addresses with a full bucket. They are treated like
+    /// new clients on their next request.
+    pub async fn prune(&self, now: Instant) -> usize {
+        let mut buckets = self.buckets.lock().await;
+        let before = buckets.len();
+        buckets.retain(|_, bucket| {
+            self.refill(bucket, now);
+            bucket.tokens < self.per_minute as f64
+        });
+        before - buckets.len()
+    }
+
+    // How long a client has to wait for a new token at most.
+    fn retry_after(&self) -> Duration {
+        Duration::from_secs(60u64.div_ceil(self.per_minute as u64))
+    }
+}
+
+/// Rejects requests with RateLimited if the client exceeded its request
+/// rate. Clients are identified like in the access log, honoring the
+/// X-Forwarded-For headers set by trusted proxies. Passes all requests if no
+/// limiter is set or the client address is unknown.
+pub fn limit(
+    limiter: Option<RateLimiter>,
+    trusted_proxies: Vec<IpAddr>,
+) -> impl Filter<Extract = (), Error = Rejection> + Clone {
+    accesslog::client(trusted_proxies)
+        .and_then(move |client: Option<IpAddr>| {
+            let limiter = limiter.clone();
+            async move {
+                match (limiter, client) {
+                    (Some(limiter), Some(client)) => {
+                        if limiter.allow(client, Instant::now()).await {
+                            Ok(())
+                        } else {
+                            Err(warp::reject::custom(RateLimited {
+                                retry_after: limiter.retry_after(),
+                            }))
+                        }
+                    }
+                    _ => Ok(()),
+                }
+            }
+        })
+        .untuple_one()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api;
+    use crate::config::AccessLog;
+    use std::net::SocketAddr;
+    use warp::http::StatusCode;
+
+    #[tokio::test]
+    async fn test_rate_limit() {
+        let limiter = RateLimiter::new(2);
+        let routes = limit(Some(limiter.clone()), vec![])
+            .and(warp::path!("api" / "info.json"))
+            .map(|| "info")
+            .recover(api::api_rejection);
+        let request = |ip: [u8; 4]| {
+            warp::test::request()
+                .path("/api/info.json")
+                .remote_addr(SocketAddr::from((ip, 1234)))
+        };
+
+        for _ in 0..2 {
+            let res = request([10, 0, 0, 1]).reply(&routes).await;
+            assert_eq!(res.status(), StatusCode::OK);
+        }
+        let res = request([10, 0, 0, 1]).reply(&routes).await;
+        assert_eq!(res.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(res.headers()["retry-after"], "30");
+        // other clients have their own bucket
+        let res = request([10, 0, 0, 2]).reply(&routes).await;
+        assert_eq!(res.status(), StatusCode::OK);
+
+        // a token is refilled every 30 seconds
+        let ip = IpAddr::from([10, 0, 0, 1]);
+        let now = Instant::now();
+        assert!(!limiter.allow(ip, now).await);
+        assert!(limiter.allow(ip, now + Duration::from_secs(30)).await);
+        assert!(!limiter.allow(ip, now + Duration::from_secs(30)).await);
+
+        // buckets refilled to the brim are pruned
+        assert_eq!(limiter.prune(now + Duration::from_secs(10)).await, 0);
+        assert_eq!(limiter.prune(now + Duration::from_secs(120)).await, 2);
+    }
+
+    #[tokio::test]
+    async fn test_rate_limit_behind_proxy() {
+        let routes = limit(
+            Some(RateLimiter::new(1)),
+            vec![IpAddr::from([127, 0, 0, 1])],
+        )
+        .map(|| "ok")
+        .recover(api::api_rejection);
+        let request = |remote: [u8; 4], forwarded_for: &str| {
+            warp::test::request()
+                .remote_addr(SocketAddr::from((remote, 1234)))
+                .header("x-forwarded-for", forwarded_for)
+        };
+
+        let res = request([127, 0, 0, 1], "10.0.0.1").reply(&routes).await;
+        assert_eq!(res.status(), StatusCode::OK);
+        // clients behind the proxy have their own bucket
+        let res = request([127, 0, 0, 1], "10.0.0.2").reply(&routes).await;
+        assert_eq!(res.status(), StatusCode::OK);
+        let res = request([127, 0, 0, 1], "10.0.0.1").reply(&routes).await;
+        assert_eq!(res.status(), StatusCode::TOO_MANY_REQUESTS);
+        // the header of untrusted peers is ignored
+        let res = request([10, 0, 0, 3], "10.0.0.4").reply(&routes).await;
+        assert_eq!(res.status(), StatusCode::OK);
+        let res = request([10, 0, 0, 3], "10.0.0.5").reply(&routes).await;
+        assert_eq!(res.status(), StatusCode::TOO_MANY_REQUESTS);
+    }
+
+    #[tokio::test]
+    async fn test_rate_limit_behind_proxy_without_access_log() {
+        let access_log = AccessLog {
+            enable: false,
+            trusted_proxies: vec![IpAddr::from([127, 0, 0, 1])],
+        };
+        let routes = accesslog::wrap(
+            limit(
+                Some(RateLimiter::new(1)),
+                access_log.trusted_proxies.clone(),
+            )
+            .map(|| "ok")
+            .recover(api::api_rejection)
+            .recover(api::final_rejection),
+            access_log,
+        );
+        let request = |forwarded_for: &str| {
+            warp::test::request()
+                .remote_addr(SocketAddr::from(([127, 0, 0, 1], 1234)))
+                .header("x-forwarded-for", forwarded_for)
+        };
+
+        let res = request("10.0.0.1").reply(&routes).await;
+        assert_eq!(res.status(), StatusCode::OK);
+        // the clients behind the proxy aren't limited as one
+        let res = request("10.0.0.2").reply(&routes).await;
+        assert_eq!(res.status(), StatusCode::OK);
+        let res = request("10.0.0.1").reply(&routes).await;
+        assert_eq!(res.status(), StatusCode::TOO_MANY_REQUESTS);
+    }
+
+    #[tokio::test]
+    async fn test_rate_limit_disabled() {
+        let routes = limit(None, vec![]).map(|| "ok");
+        for _ in 0..100 {
+            let res = warp::test::request()
+                .remote_addr(SocketAddr::from(([10, 0, 0, 1], 1234)))
+                .reply(&routes)
+                .await;
+            assert_eq!(res.status(), StatusCode::OK);
+        }
+    }
+}
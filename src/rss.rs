@@ -1,13 +1,21 @@
 use std::fmt;
 use warp::http::Response;
-use warp::Filter;
+use warp::{Filter, Rejection};
 
-use std::collections::HashMap;
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, HashMap};
 use std::convert::Infallible;
+use std::str::FromStr;
 
-use crate::types::{Caches, ChainTipStatus, Fork, NetworkJson, NodeDataJson, TipInfoJson};
+use bitcoincore_rpc::bitcoin::BlockHash;
 
-const THREASHOLD_NODE_LAGGING: u64 = 3; // blocks
+use crate::config::{Feeds, Privacy};
+use crate::types::{
+    redact_node_data, unix_timestamp, Alert, AlertKind, Cache, Caches, ChainTipStatus, Fork,
+    HeaderInfoJson, HeaderStatus, NetworkJson, NodeData, NodeDataJson, TipInfoJson, Trees,
+};
+
+pub const THREASHOLD_NODE_LAGGING: u64 = 3; // blocks
 
 pub fn with_rss_base_url(
     base_url: String,
@@ -15,11 +23,277 @@ pub fn with_rss_base_url(
     warp::any().map(move || base_url.clone())
 }
 
+pub fn with_feeds(
+    feeds: BTreeMap<u32, Feeds>,
+) -> impl Filter<Extract = (BTreeMap<u32, Feeds>,), Error = Infallible> + Clone {
+    warp::any().map(move || feeds.clone())
+}
+
+/// The per-network RSS feeds.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FeedKind {
+    Forks,
+    Invalid,
+    Lagging,
+    Unreachable,
+    Alerts,
+    Blocks,
+    Stalled,
+}
+
+impl FeedKind {
+    pub const ALL: [FeedKind; 7] = [
+        FeedKind::Forks,
+        FeedKind::Invalid,
+        FeedKind::Lagging,
+        FeedKind::Unreachable,
+        FeedKind::Alerts,
+        FeedKind::Blocks,
+        FeedKind::Stalled,
+    ];
+
+    pub fn stem(&self) -> &'static str {
+        match self {
+            FeedKind::Forks => "forks",
+            FeedKind::Invalid => "invalid",
+            FeedKind::Lagging => "lagging",
+            FeedKind::Unreachable => "unreachable",
+            FeedKind::Alerts => "alerts",
+            FeedKind::Blocks => "blocks",
+            FeedKind::Stalled => "stalled",
+        }
+    }
+
+    pub fn file_name(&self, format: FeedFormat) -> String {
+        format!("{}.{}", self.stem(), format.extension())
+    }
+
+    fn title(&self) -> &'static str {
+        match self {
+            FeedKind::Forks => "Recent Forks",
+            FeedKind::Invalid => "Invalid Blocks",
+            FeedKind::Lagging => "Lagging nodes",
+            FeedKind::Unreachable => "Unreachable nodes",
+            FeedKind::Alerts => "Alerts",
+            FeedKind::Blocks => "New blocks",
+            FeedKind::Stalled => "Stalled nodes",
+        }
+    }
+
+    pub fn enabled(&self, feeds: &Feeds) -> bool {
+        match self {
+            FeedKind::Forks => feeds.forks,
+            FeedKind::Invalid => feeds.invalid,
+            FeedKind::Lagging => feeds.lagging,
+            FeedKind::Unreachable => feeds.unreachable,
+            FeedKind::Alerts => feeds.alerts,
+            FeedKind::Blocks => feeds.blocks,
+            FeedKind::Stalled => feeds.stalled,
+        }
+    }
+}
+
+/// The formats the feeds are served in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FeedFormat {
+    /// RSS 2.0, served as <feed>.xml.
+    Rss,
+    /// JSON Feed 1.1, served as <feed>.json.
+    JsonFeed,
+}
+
+impl FeedFormat {
+    const ALL: [FeedFormat; 2] = [FeedFormat::Rss, FeedFormat::JsonFeed];
+
+    fn extension(&self) -> &'static str {
+        match self {
+            FeedFormat::Rss => "xml",
+            FeedFormat::JsonFeed => "json",
+        }
+    }
+}
+
+/// Matches /rss/<network id>/<feed file name> and extracts the network id
+/// and the format of the file name. Feeds disabled for the network are
+/// rejected as not found. Unknown networks are passed on to the feed handler.
+pub fn feed_path(
+    kind: FeedKind,
+    feeds: BTreeMap<u32, Feeds>,
+) -> impl Filter<Extract = (u32, FeedFormat), Error = Rejection> + Clone {
+    warp::path("rss")
+        .and(warp::path::param::<u32>())
+        .and(warp::path::param::<String>())
+        .and(warp::path::end())
+        .and_then(move |network_id: u32, file_name: String| {
+            let format = FeedFormat::ALL
+                .iter()
+                .copied()
+                .find(|format| kind.file_name(*format) == file_name);
+            let enabled = feeds
+                .get(&network_id)
+                .map(|feeds| kind.enabled(feeds))
+                .unwrap_or(true);
+            async move {
+                match format {
+                    Some(format) if enabled => Ok((network_id, format)),
+                    _ => Err(warp::reject::not_found()),
+                }
+            }
+        })
+        .untuple_one()
+}
+
+/// Matches /rss/<feed file name> of a feed combining all networks and
+/// extracts the format of the file name.
+pub fn combined_feed_path(
+    kind: FeedKind,
+) -> impl Filter<Extract = (FeedFormat,), Error = Rejection> + Clone {
+    warp::path("rss")
+        .and(warp::path::param::<String>())
+        .and(warp::path::end())
+        .and_then(move |file_name: String| async move {
+            FeedFormat::ALL
+                .iter()
+                .copied()
+                .find(|format| kind.file_name(*format) == file_name)
+                .ok_or_else(warp::reject::not_found)
+        })
+}
+
+fn xml_escape(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// An OPML outline of the enabled feeds grouped by network.
+fn opml(network_infos: &[NetworkJson], feeds: &BTreeMap<u32, Feeds>, base_url: &str) -> String {
+    let base_url = base_url.trim_end_matches('/');
+    let mut outlines = String::new();
+    for network in network_infos.iter() {
+        let network_feeds = feeds.get(&network.id).cloned().unwrap_or_default();
+        let name = xml_escape(&network.name);
+        outlines.push_str(&format!(
+            "\n    <outline text=\"{}\" title=\"{}\">",
+            name, name
+        ));
+        for kind in FeedKind::ALL.iter().filter(|k| k.enabled(&network_feeds)) {
+            let title = format!("{} - {}", kind.title(), name);
+            outlines.push_str(&format!(
+                "\n      <outline type=\"rss\" text=\"{}\" title=\"{}\" xmlUrl=\"{}\" htmlUrl=\"{}\" />",
+                title,
+                title,
+                xml_escape(&format!(
+                    "{}/rss/{}/{}",
+                    base_url,
+                    network.id,
+                    kind.file_name(FeedFormat::Rss)
+                )),
+                xml_escape(&format!("{}/?network={}", base_url, network.id)),
+            ));
+        }
+        outlines.push_str("\n    </outline>");
+    }
+    format!(
+        r#"<?xml version="1.0" encoding="UTF-8" ?>
+<opml version="2.0">
+  <head>
+    <title>fork-observer feeds</title>
+  </head>
+  <body>{}
+  </body>
+</opml>
+"#,
+        outlines
+    )
+}
+
+pub async fn opml_response(
+    network_infos: Vec<NetworkJson>,
+    feeds: BTreeMap<u32, Feeds>,
+    base_url: String,
+) -> Result<impl warp::Reply, Infallible> {
+    Ok(Response::builder()
+        .header("content-type", "text/x-opml")
+        .body(opml(&network_infos, &feeds, &base_url)))
+}
+
+const WEEKDAYS: [&str; 7] = ["Thu", "Fri", "Sat", "Sun", "Mon", "Tue", "Wed"];
+const MONTHS: [&str; 12] = [
+    "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+];
+
+// The UTC date and time of a UNIX timestamp as (year, month, day, hour,
+// minute, second) and the days since 1970-01-01.
+fn civil_date_time(timestamp: u64) -> ((u64, u64, u64, u64, u64, u64), u64) {
+    let days = timestamp / 86400;
+    let seconds = timestamp % 86400;
+    // civil date from days since 1970-01-01, see
+    // https://howardhinnant.github.io/date_algorithms.html#civil_from_days
+    let z = days + 719468;
+    let era = z / 146097;
+    let doe = z % 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    (
+        (
+            year,
+            month,
+            day,
+            seconds / 3600,
+            seconds % 3600 / 60,
+            seconds % 60,
+        ),
+        days,
+    )
+}
+
+// Formats a UNIX timestamp as RFC 822 date in UTC, e.g.
+// "Sat, 03 Jan 2009 18:15:05 GMT", as used in the RSS pubDate.
+fn rfc822_date(timestamp: u64) -> String {
+    let ((year, month, day, hour, minute, second), days) = civil_date_time(timestamp);
+    format!(
+        "{}, {:02} {} {} {:02}:{:02}:{:02} GMT",
+        WEEKDAYS[(days % 7) as usize],
+        day,
+        MONTHS[(month - 1) as usize],
+        year,
+        hour,
+        minute,
+        second,
+    )
+}
+
+// Formats a UNIX timestamp as RFC 3339 date in UTC, e.g.
+// "2009-01-03T18:15:05Z", as used in the JSON Feed date_published.
+fn rfc3339_date(timestamp: u64) -> String {
+    let ((year, month, day, hour, minute, second), _) = civil_date_time(timestamp);
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        year, month, day, hour, minute, second
+    )
+}
+
 // A RSS item.
 struct Item {
     title: String,
     description: String,
     guid: String,
+    /// UNIX timestamp of the item. The date is omitted if None.
+    published: Option<u64>,
 }
 
 impl fmt::Display for Item {
@@ -30,9 +304,15 @@ impl fmt::Display for Item {
   <item>
 	<title>{}</title>
 	<description>{}</description>
-	<guid isPermaLink="false">{}</guid>
+	<guid isPermaLink="false">{}</guid>{}
   </item>"#,
-            self.title, self.description, self.guid,
+            self.title,
+            self.description,
+            self.guid,
+            match self.published {
+                Some(published) => format!("\n\t<pubDate>{}</pubDate>", rfc822_date(published)),
+                None => String::new(),
+            },
         )
     }
 }
@@ -66,11 +346,54 @@ impl fmt::Display for Channel {
     }
 }
 
+impl Channel {
+    // The channel as JSON Feed 1.1, see https://www.jsonfeed.org/version/1.1/
+    fn json_feed(&self) -> serde_json::Value {
+        let items: Vec<serde_json::Value> = self
+            .items
+            .iter()
+            .map(|item| {
+                let mut json = serde_json::json!({
+                    "id": item.guid,
+                    "title": item.title,
+                    "content_text": item.description,
+                });
+                if let Some(published) = item.published {
+                    json["date_published"] = rfc3339_date(published).into();
+                }
+                json
+            })
+            .collect();
+        serde_json::json!({
+            "version": "https://jsonfeed.org/version/1.1",
+            "title": self.title,
+            "description": self.description,
+            "home_page_url": self.link,
+            "feed_url": self.href,
+            "items": items,
+        })
+    }
+}
+
 // An RSS feed.
 struct Feed {
     channel: Channel,
 }
 
+impl Feed {
+    // The feed in the requested format.
+    fn reply(&self, format: FeedFormat) -> Result<Response<String>, warp::http::Error> {
+        match format {
+            FeedFormat::Rss => Response::builder()
+                .header("content-type", "application/rss+xml")
+                .body(self.to_string()),
+            FeedFormat::JsonFeed => Response::builder()
+                .header("content-type", "application/feed+json")
+                .body(self.channel.json_feed().to_string()),
+        }
+    }
+}
+
 impl fmt::Display for Feed {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(
@@ -90,7 +413,7 @@ impl From<Fork> for Item {
         Item {
             title: format!(
                 "{} at height {}",
-                if fork.children.len() <= 2 {
+                if fork.children.len() + fork.truncated_children <= 2 {
                     "Fork"
                 } else {
                     "Multi-fork"
@@ -99,16 +422,26 @@ impl From<Fork> for Item {
             ),
             description: format!(
                 "There are {} blocks building on-top of block {}.",
-                fork.children.len(),
+                fork.children.len() + fork.truncated_children,
                 fork.common.header.block_hash().to_string()
             ),
             guid: fork.common.header.block_hash().to_string(),
+            // the fork exists since the latest child was mined
+            published: Some(
+                fork.children
+                    .iter()
+                    .map(|child| child.header.time)
+                    .max()
+                    .unwrap_or(fork.common.header.time) as u64,
+            ),
         }
     }
 }
 
-impl From<(&TipInfoJson, &Vec<NodeDataJson>)> for Item {
-    fn from(invalid_block: (&TipInfoJson, &Vec<NodeDataJson>)) -> Self {
+// An invalid block, the nodes reporting it and the block time if the header
+// is known.
+impl From<(&TipInfoJson, &Vec<NodeDataJson>, Option<u32>)> for Item {
+    fn from(invalid_block: (&TipInfoJson, &Vec<NodeDataJson>, Option<u32>)) -> Self {
         let mut nodes = invalid_block.1.clone();
         nodes.sort_by(|a, b| a.id.cmp(&b.id));
 
@@ -126,12 +459,14 @@ impl From<(&TipInfoJson, &Vec<NodeDataJson>)> for Item {
                     .join(", "),
             ),
             guid: invalid_block.0.hash.clone(),
+            published: invalid_block.2.map(|time| time as u64),
         }
     }
 }
 
 pub async fn forks_response(
     network_id: u32,
+    format: FeedFormat,
     caches: Caches,
     network_infos: Vec<NetworkJson>,
     base_url: String,
@@ -158,19 +493,76 @@ pub async fn forks_response(
                     )
                     .to_string(),
                     link: format!("{}?network={}?src=forks-rss", base_url.clone(), network_id),
-                    href: format!("{}/rss/{}/forks.xml", base_url, network_id),
+                    href: format!(
+                        "{}/rss/{}/{}",
+                        base_url,
+                        network_id,
+                        FeedKind::Forks.file_name(format)
+                    ),
                     items: cache.forks.iter().map(|f| f.clone().into()).collect(),
                 },
             };
 
-            Ok(Response::builder()
-                .header("content-type", "application/rss+xml")
-                .body(feed.to_string()))
+            Ok(feed.reply(format))
         }
         None => Ok(Ok(response_unknown_network(network_infos))),
     }
 }
 
+// The recent forks of all networks with the forks feed enabled, highest
+// first. The item titles are prefixed with the network name.
+fn all_forks(
+    caches: &BTreeMap<u32, Cache>,
+    network_infos: &[NetworkJson],
+    feeds: &BTreeMap<u32, Feeds>,
+) -> Vec<Item> {
+    let mut forks: Vec<(u64, u32, Item)> = vec![];
+    for (network_id, cache) in caches.iter() {
+        if !feeds
+            .get(network_id)
+            .map(|feeds| feeds.forks)
+            .unwrap_or(true)
+        {
+            continue;
+        }
+        let network_name = network_infos
+            .iter()
+            .find(|net| net.id == *network_id)
+            .map(|net| net.name.clone())
+            .unwrap_or_else(|| network_id.to_string());
+        for fork in cache.forks.iter() {
+            let mut item: Item = fork.clone().into();
+            item.title = format!("{}: {}", network_name, item.title);
+            // the same block can fork on multiple networks, e.g. signets
+            // sharing their first blocks
+            item.guid = format!("{}-{}", network_id, item.guid);
+            forks.push((fork.common.height, *network_id, item));
+        }
+    }
+    forks.sort_by(|a, b| b.0.cmp(&a.0).then(a.1.cmp(&b.1)));
+    forks.into_iter().map(|(_, _, item)| item).collect()
+}
+
+pub async fn all_forks_response(
+    format: FeedFormat,
+    caches: Caches,
+    network_infos: Vec<NetworkJson>,
+    base_url: String,
+    feeds: BTreeMap<u32, Feeds>,
+) -> Result<impl warp::Reply, Infallible> {
+    let caches_locked = caches.lock().await;
+    let feed = Feed {
+        channel: Channel {
+            title: "Recent Forks - All networks".to_string(),
+            description: "Recent forks that occured on all networks".to_string(),
+            link: format!("{}?src=forks-rss", base_url.clone()),
+            href: format!("{}/rss/{}", base_url, FeedKind::Forks.file_name(format)),
+            items: all_forks(&caches_locked, &network_infos, &feeds),
+        },
+    };
+    Ok(feed.reply(format))
+}
+
 impl Item {
     pub fn lagging_node_item(node: &NodeDataJson, height: u64) -> Item {
         Item {
@@ -181,6 +573,87 @@ impl Item {
                 THREASHOLD_NODE_LAGGING,
             ),
             guid: format!("lagging-node-{}-on-{}", node.name, height),
+            published: Some(node.last_changed_timestamp).filter(|timestamp| *timestamp > 0),
+        }
+    }
+
+    pub fn alert_item(alert: &Alert) -> Item {
+        let tips = alert
+            .tips
+            .iter()
+            .map(|tip| {
+                format!(
+                    "{} at height {} (nodes: {})",
+                    tip.hash,
+                    tip.height,
+                    tip.nodes
+                        .iter()
+                        .map(|id| id.to_string())
+                        .collect::<Vec<String>>()
+                        .join(", ")
+                )
+            })
+            .collect::<Vec<String>>()
+            .join("; ");
+        match alert.kind {
+            AlertKind::ChainSplit => Item {
+                title: "Chain split detected".to_string(),
+                description: format!(
+                    "Reachable nodes that aren't lagging behind have active tips on different branches. Active tips: {}.",
+                    tips
+                ),
+                guid: format!("alert-{}-{}-{}", alert.kind, alert.network_id, alert.timestamp),
+                published: Some(alert.timestamp),
+            },
+            AlertKind::ChainSplitResolved => Item {
+                title: "Chain split resolved".to_string(),
+                description: format!(
+                    "The nodes' active tips are on the same chain again. Active tips: {}.",
+                    tips
+                ),
+                guid: format!("alert-{}-{}-{}", alert.kind, alert.network_id, alert.timestamp),
+                published: Some(alert.timestamp),
+            },
+        }
+    }
+
+    pub fn new_block_item(header: &HeaderInfoJson, miner: &str) -> Item {
+        Item {
+            title: format!("New block at height {}", header.height),
+            description: format!(
+                "Block {} at height {} was mined by {}.",
+                header.hash,
+                header.height,
+                if miner.is_empty() {
+                    "an unknown miner"
+                } else {
+                    miner
+                },
+            ),
+            guid: header.hash.clone(),
+            published: Some(header.time as u64),
+        }
+    }
+
+    pub fn stalled_node_item(node: &NodeDataJson, stalled_after_seconds: u64) -> Item {
+        let active_tip = node
+            .tips
+            .iter()
+            .find(|tip| tip.status == ChainTipStatus::Active)
+            .map(|tip| format!("{} at height {}", tip.hash, tip.height))
+            .unwrap_or_else(|| "unknown".to_string());
+        Item {
+            title: format!("Node '{}' (id={}) is stalled", node.name, node.id),
+            description: format!(
+                "The tips of this node haven't changed for more than {} seconds. The node might be stuck, or there wasn't a new block in the meantime. Its active tip is {}.",
+                stalled_after_seconds, active_tip,
+            ),
+            guid: format!(
+                "stalled-node-{}-last-{}",
+                node.id, node.last_changed_timestamp
+            ),
+            // the node is stalled since the threshold passed
+            published: Some(node.last_changed_timestamp + stalled_after_seconds),
         }
     }
 
@@ -192,16 +665,52 @@ impl Item {
                 node.last_changed_timestamp,
             ),
             guid: format!("unreachable-node-{}-last-{}", node.id, node.last_changed_timestamp),
+            // we don't know when the node became unreachable
+            published: None,
         }
     }
 }
 
+/// The nodes with an active tip more than THREASHOLD_NODE_LAGGING blocks
+/// below the highest active tip of the nodes, with the height of their active
+/// tip. Nodes without an active tip are at height 0.
+pub fn lagging_nodes(node_data: &NodeData) -> Vec<(&NodeDataJson, u64)> {
+    if node_data.len() <= 1 {
+        return vec![];
+    }
+    let nodes_with_active_height: Vec<(&NodeDataJson, u64)> = node_data
+        .values()
+        .map(|node| {
+            (
+                node,
+                node.tips
+                    .iter()
+                    .filter(|tip| tip.status == ChainTipStatus::Active)
+                    .last()
+                    .map_or(0, |tip| tip.height),
+            )
+        })
+        .collect();
+    let max_height: u64 = *nodes_with_active_height
+        .iter()
+        .map(|(_, height)| height)
+        .max()
+        .unwrap_or(&0);
+    nodes_with_active_height
+        .into_iter()
+        .filter(|(_, height)| height + THREASHOLD_NODE_LAGGING < max_height)
+        .collect()
+}
+
 pub async fn lagging_nodes_response(
     network_id: u32,
+    format: FeedFormat,
     caches: Caches,
     network_infos: Vec<NetworkJson>,
     base_url: String,
+    privacy: BTreeMap<u32, Privacy>,
 ) -> Result<impl warp::Reply, Infallible> {
+    let privacy = privacy.get(&network_id).cloned().unwrap_or_default();
     let caches_locked = caches.lock().await;
     match caches_locked.get(&network_id) {
         Some(cache) => {
@@ -215,38 +724,11 @@ pub async fn lagging_nodes_response(
                 network_name = &network.name;
             }
 
-            let mut lagging_nodes: Vec<Item> = vec![];
-            if cache.node_data.len() > 1 {
-                let nodes_with_active_height: Vec<(&NodeDataJson, u64)> = cache
-                    .node_data
-                    .iter()
-                    .map(|(_, node)| {
-                        (
-                            node,
-                            node.tips
-                                .iter()
-                                .filter(|tip| tip.status == "active".to_string())
-                                .last()
-                                .unwrap_or(&TipInfoJson {
-                                    height: 0,
-                                    status: "active".to_string(),
-                                    hash: "dummy".to_string(),
-                                })
-                                .height,
-                        )
-                    })
-                    .collect();
-                let max_height: u64 = *nodes_with_active_height
-                    .iter()
-                    .map(|(_, height)| height)
-                    .max()
-                    .unwrap_or(&0);
-                for (node, height) in nodes_with_active_height.iter() {
-                    if height + THREASHOLD_NODE_LAGGING < max_height {
-                        lagging_nodes.push(Item::lagging_node_item(node, *height));
-                    }
-                }
-            }
+            let node_data = redact_node_data(&cache.node_data, &privacy);
+            let lagging_items: Vec<Item> = lagging_nodes(&node_data)
+                .iter()
+                .map(|(node, height)| Item::lagging_node_item(node, *height))
+                .collect();
 
             let feed = Feed {
                 channel: Channel {
@@ -257,25 +739,75 @@ pub async fn lagging_nodes_response(
                     )
                     .to_string(),
                     link: format!("{}?network={}?src=lagging-rss", base_url.clone(), network_id),
-                    href: format!("{}/rss/{}/lagging.xml", base_url, network_id),
-                    items: lagging_nodes,
+                    href: format!(
+                        "{}/rss/{}/{}",
+                        base_url,
+                        network_id,
+                        FeedKind::Lagging.file_name(format)
+                    ),
+                    items: lagging_items,
                 },
             };
 
-            Ok(Response::builder()
-                .header("content-type", "application/rss+xml")
-                .body(feed.to_string()))
+            Ok(feed.reply(format))
         }
         None => Ok(Ok(response_unknown_network(network_infos))),
     }
 }
 
+// The invalid tips of the nodes and the nodes reporting them, highest first.
+// Tips are grouped by hash, a node might report an invalid block at a wrong
+// height. The height most nodes report is used.
+pub fn invalid_blocks(node_data: &NodeData) -> Vec<(TipInfoJson, Vec<NodeDataJson>)> {
+    let mut by_hash: HashMap<&str, Vec<(&TipInfoJson, &NodeDataJson)>> = HashMap::new();
+    for node in node_data.values() {
+        for tip in node.tips.iter() {
+            if tip.status == ChainTipStatus::Invalid {
+                by_hash
+                    .entry(tip.hash.as_str())
+                    .or_default()
+                    .push((tip, node));
+            }
+        }
+    }
+
+    let mut invalid_blocks: Vec<(TipInfoJson, Vec<NodeDataJson>)> = by_hash
+        .into_values()
+        .map(|reports| {
+            let mut height_counts: BTreeMap<u64, usize> = BTreeMap::new();
+            for (tip, _) in reports.iter() {
+                *height_counts.entry(tip.height).or_default() += 1;
+            }
+            // ties are resolved in favor of the higher height
+            let (height, _) = height_counts
+                .iter()
+                .max_by_key(|(height, count)| (**count, **height))
+                .expect("there is at least one report");
+            let mut tip = reports[0].0.clone();
+            tip.height = *height;
+            let mut nodes: Vec<NodeDataJson> = vec![];
+            for (_, node) in reports {
+                if !nodes.iter().any(|n| n.id == node.id) {
+                    nodes.push(node.clone());
+                }
+            }
+            (tip, nodes)
+        })
+        .collect();
+    invalid_blocks.sort_by(|a, b| b.0.height.cmp(&a.0.height).then(a.0.hash.cmp(&b.0.hash)));
+    invalid_blocks
+}
+
 pub async fn invalid_blocks_response(
     network_id: u32,
+    format: FeedFormat,
     caches: Caches,
+    trees: Trees,
     network_infos: Vec<NetworkJson>,
     base_url: String,
+    privacy: BTreeMap<u32, Privacy>,
 ) -> Result<impl warp::Reply, Infallible> {
+    let privacy = privacy.get(&network_id).cloned().unwrap_or_default();
     let caches_locked = caches.lock().await;
 
     match caches_locked.get(&network_id) {
@@ -290,22 +822,23 @@ pub async fn invalid_blocks_response(
                 network_name = &network.name;
             }
 
-            let mut invalid_blocks_to_node_id: HashMap<TipInfoJson, Vec<NodeDataJson>> =
-                HashMap::new();
-            for node in cache.node_data.values() {
-                for tip in node.tips.iter() {
-                    if tip.status == ChainTipStatus::Invalid.to_string() {
-                        invalid_blocks_to_node_id
-                            .entry(tip.clone())
-                            .and_modify(|k| k.push(node.clone()))
-                            .or_insert(vec![node.clone()]);
-                    }
+            let invalid_blocks = invalid_blocks(&redact_node_data(&cache.node_data, &privacy));
+            let block_times: Vec<Option<u32>> = match trees.get(&network_id) {
+                Some(tree) => {
+                    let tree_locked = tree.lock().await;
+                    let (tree, hash_index_map) = &*tree_locked;
+                    invalid_blocks
+                        .iter()
+                        .map(|(tip, _)| {
+                            BlockHash::from_str(&tip.hash)
+                                .ok()
+                                .and_then(|hash| hash_index_map.get(&hash))
+                                .map(|idx| tree[*idx].header.time)
+                        })
+                        .collect()
                 }
-            }
-
-            let mut invalid_blocks: Vec<(&TipInfoJson, &Vec<NodeDataJson>)> =
-                invalid_blocks_to_node_id.iter().collect();
-            invalid_blocks.sort_by(|a, b| b.0.height.cmp(&a.0.height));
+                None => vec![None; invalid_blocks.len()],
+            };
             let feed = Feed {
                 channel: Channel {
                     title: format!("Invalid Blocks - {}", network_name),
@@ -318,17 +851,21 @@ pub async fn invalid_blocks_response(
                         base_url.clone(),
                         network_id
                     ),
-                    href: format!("{}/rss/{}/invalid.xml", base_url, network_id),
+                    href: format!(
+                        "{}/rss/{}/{}",
+                        base_url,
+                        network_id,
+                        FeedKind::Invalid.file_name(format)
+                    ),
                     items: invalid_blocks
                         .iter()
-                        .map(|(tipinfo, nodes)| (*tipinfo, *nodes).into())
+                        .zip(block_times)
+                        .map(|((tipinfo, nodes), time)| (tipinfo, nodes, time).into())
                         .collect::<Vec<Item>>(),
                 },
             };
 
-            return Ok(Response::builder()
-                .header("content-type", "application/rss+xml")
-                .body(feed.to_string()));
+            Ok(feed.reply(format))
         }
         None => Ok(Ok(response_unknown_network(network_infos))),
     }
@@ -336,10 +873,13 @@ pub async fn invalid_blocks_response(
 
 pub async fn unreachable_nodes_response(
     network_id: u32,
+    format: FeedFormat,
     caches: Caches,
     network_infos: Vec<NetworkJson>,
     base_url: String,
+    privacy: BTreeMap<u32, Privacy>,
 ) -> Result<impl warp::Reply, Infallible> {
+    let privacy = privacy.get(&network_id).cloned().unwrap_or_default();
     let caches_locked = caches.lock().await;
 
     match caches_locked.get(&network_id) {
@@ -354,8 +894,7 @@ pub async fn unreachable_nodes_response(
                 network_name = &network.name;
             }
 
-            let unreachable_node_items: Vec<Item> = cache
-                .node_data
+            let unreachable_node_items: Vec<Item> = redact_node_data(&cache.node_data, &privacy)
                 .values()
                 .filter(|node| !node.reachable)
                 .map(|node| Item::unreachable_node_item(node))
@@ -372,14 +911,222 @@ pub async fn unreachable_nodes_response(
                         base_url.clone(),
                         network_id
                     ),
-                    href: format!("{}/rss/{}/unreachable.xml", base_url, network_id),
+                    href: format!(
+                        "{}/rss/{}/{}",
+                        base_url,
+                        network_id,
+                        FeedKind::Unreachable.file_name(format)
+                    ),
                     items: unreachable_node_items,
                 },
             };
 
-            return Ok(Response::builder()
-                .header("content-type", "application/rss+xml")
-                .body(feed.to_string()));
+            Ok(feed.reply(format))
+        }
+        None => Ok(Ok(response_unknown_network(network_infos))),
+    }
+}
+
+// The reachable nodes whose tips haven't changed for more than
+// stalled_after_seconds. Nodes that never reported tips are skipped.
+fn stalled_nodes(node_data: &NodeData, now: u64, stalled_after_seconds: u64) -> Vec<&NodeDataJson> {
+    node_data
+        .values()
+        .filter(|node| {
+            node.reachable
+                && node.last_changed_timestamp > 0
+                && now.saturating_sub(node.last_changed_timestamp) > stalled_after_seconds
+        })
+        .collect()
+}
+
+pub async fn stalled_nodes_response(
+    network_id: u32,
+    format: FeedFormat,
+    caches: Caches,
+    network_infos: Vec<NetworkJson>,
+    base_url: String,
+    privacy: BTreeMap<u32, Privacy>,
+    feeds: BTreeMap<u32, Feeds>,
+) -> Result<impl warp::Reply, Infallible> {
+    let privacy = privacy.get(&network_id).cloned().unwrap_or_default();
+    let stalled_after_seconds = feeds
+        .get(&network_id)
+        .cloned()
+        .unwrap_or_default()
+        .stalled_after_seconds;
+    let caches_locked = caches.lock().await;
+
+    match caches_locked.get(&network_id) {
+        Some(cache) => {
+            let mut network_name = "";
+            if let Some(network) = network_infos
+                .iter()
+                .filter(|net| net.id == network_id)
+                .collect::<Vec<&NetworkJson>>()
+                .first()
+            {
+                network_name = &network.name;
+            }
+
+            let node_data = redact_node_data(&cache.node_data, &privacy);
+            let stalled_node_items: Vec<Item> =
+                stalled_nodes(&node_data, unix_timestamp(), stalled_after_seconds)
+                    .into_iter()
+                    .map(|node| Item::stalled_node_item(node, stalled_after_seconds))
+                    .collect();
+            let feed = Feed {
+                channel: Channel {
+                    title: format!("Stalled nodes - {}", network_name),
+                    description: format!(
+                        "Nodes on the {} network whose tips haven't changed in a while",
+                        network_name
+                    ),
+                    link: format!(
+                        "{}?network={}?src=stalled-rss",
+                        base_url.clone(),
+                        network_id
+                    ),
+                    href: format!(
+                        "{}/rss/{}/{}",
+                        base_url,
+                        network_id,
+                        FeedKind::Stalled.file_name(format)
+                    ),
+                    items: stalled_node_items,
+                },
+            };
+
+            Ok(feed.reply(format))
+        }
+        None => Ok(Ok(response_unknown_network(network_infos))),
+    }
+}
+
+// The most recent active-chain headers in the header view, highest first.
+// The miner is taken from the recent miners if the header doesn't have one
+// yet.
+fn new_blocks(cache: &mut Cache, max_items: usize) -> Vec<(HeaderInfoJson, String)> {
+    let mut active: Vec<HeaderInfoJson> = cache
+        .header_infos_with_status()
+        .into_iter()
+        .filter(|h| h.status == HeaderStatus::Active)
+        .collect();
+    active.sort_by_key(|h| Reverse(h.height));
+    active.truncate(max_items);
+    active
+        .into_iter()
+        .map(|header| {
+            let miner = if header.miner.is_empty() {
+                cache
+                    .recent_miners
+                    .get(&header.hash)
+                    .map(|(miner, _)| miner.clone())
+                    .unwrap_or_default()
+            } else {
+                header.miner.clone()
+            };
+            (header, miner)
+        })
+        .collect()
+}
+
+pub async fn new_blocks_response(
+    network_id: u32,
+    format: FeedFormat,
+    caches: Caches,
+    network_infos: Vec<NetworkJson>,
+    base_url: String,
+    feeds: BTreeMap<u32, Feeds>,
+) -> Result<impl warp::Reply, Infallible> {
+    let max_items = feeds
+        .get(&network_id)
+        .cloned()
+        .unwrap_or_default()
+        .blocks_items;
+    let mut caches_locked = caches.lock().await;
+
+    match caches_locked.get_mut(&network_id) {
+        Some(cache) => {
+            let mut network_name = "";
+            if let Some(network) = network_infos
+                .iter()
+                .filter(|net| net.id == network_id)
+                .collect::<Vec<&NetworkJson>>()
+                .first()
+            {
+                network_name = &network.name;
+            }
+
+            let block_items: Vec<Item> = new_blocks(cache, max_items)
+                .iter()
+                .map(|(header, miner)| Item::new_block_item(header, miner))
+                .collect();
+            let feed = Feed {
+                channel: Channel {
+                    title: format!("New blocks - {}", network_name),
+                    description: format!(
+                        "Recent blocks on the active chain of the {} network",
+                        network_name
+                    ),
+                    link: format!("{}?network={}?src=blocks-rss", base_url.clone(), network_id),
+                    href: format!(
+                        "{}/rss/{}/{}",
+                        base_url,
+                        network_id,
+                        FeedKind::Blocks.file_name(format)
+                    ),
+                    items: block_items,
+                },
+            };
+
+            Ok(feed.reply(format))
+        }
+        None => Ok(Ok(response_unknown_network(network_infos))),
+    }
+}
+
+pub async fn alerts_response(
+    network_id: u32,
+    format: FeedFormat,
+    caches: Caches,
+    network_infos: Vec<NetworkJson>,
+    base_url: String,
+) -> Result<impl warp::Reply, Infallible> {
+    let caches_locked = caches.lock().await;
+
+    match caches_locked.get(&network_id) {
+        Some(cache) => {
+            let mut network_name = "";
+            if let Some(network) = network_infos
+                .iter()
+                .filter(|net| net.id == network_id)
+                .collect::<Vec<&NetworkJson>>()
+                .first()
+            {
+                network_name = &network.name;
+            }
+
+            let alert_items: Vec<Item> = cache.alerts.iter().rev().map(Item::alert_item).collect();
+            let feed = Feed {
+                channel: Channel {
+                    title: format!("Alerts - {}", network_name),
+                    description: format!(
+                        "Chain splits between the nodes on the {} network",
+                        network_name
+                    ),
+                    link: format!("{}?network={}?src=alerts", base_url.clone(), network_id),
+                    href: format!(
+                        "{}/rss/{}/{}",
+                        base_url,
+                        network_id,
+                        FeedKind::Alerts.file_name(format)
+                    ),
+                    items: alert_items,
+                },
+            };
+
+            Ok(feed.reply(format))
         }
         None => Ok(Ok(response_unknown_network(network_infos))),
     }
@@ -400,3 +1147,507 @@ pub fn response_unknown_network(network_infos: Vec<NetworkJson>) -> Response<Str
         ))
         .unwrap()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils;
+    use bitcoincore_rpc::bitcoin::hashes::Hash;
+    use std::sync::Arc;
+    use tokio::sync::Mutex;
+
+    #[tokio::test]
+    async fn test_lagging_feed_redaction() {
+        let headers = test_utils::chain(BlockHash::all_zeros(), 1, 10, 0);
+        let tip = |height: usize| {
+            vec![test_utils::chain_tip(
+                headers[height - 1].height,
+                &headers[height - 1].header.block_hash(),
+                ChainTipStatus::Active,
+            )]
+        };
+        let mut lagging = test_utils::node_data(1, &tip(2));
+        lagging.name = "alice-home-node".to_string();
+        let node_data = BTreeMap::from([(0, test_utils::node_data(0, &tip(10))), (1, lagging)]);
+        let caches: Caches = Arc::new(Mutex::new(BTreeMap::from([(
+            0,
+            test_utils::cache(node_data),
+        )])));
+
+        let feed = |privacy: BTreeMap<u32, Privacy>| {
+            let caches = caches.clone();
+            async move {
+                let reply = lagging_nodes_response(
+                    0,
+                    FeedFormat::Rss,
+                    caches,
+                    vec![],
+                    String::new(),
+                    privacy,
+                )
+                .await
+                .unwrap();
+                let body =
+                    warp::hyper::body::to_bytes(warp::Reply::into_response(reply).into_body())
+                        .await
+                        .unwrap();
+                String::from_utf8(body.to_vec()).unwrap()
+            }
+        };
+
+        let body = feed(BTreeMap::from([(
+            0,
+            Privacy {
+                hide_node_names: true,
+                hide_versions: true,
+                hide_descriptions: true,
+                hide_poll_latency: true,
+            },
+        )]))
+        .await;
+        assert!(body.contains("Node 'Node 1' is lagging behind"));
+        assert!(!body.contains("alice"));
+
+        let body = feed(BTreeMap::new()).await;
+        assert!(body.contains("alice"));
+    }
+
+    #[test]
+    fn test_invalid_blocks_deduplicated_by_hash() {
+        let invalid = test_utils::header_info(BlockHash::all_zeros(), 100, 0);
+        let other = test_utils::header_info(BlockHash::all_zeros(), 90, 1);
+        let tip = |height: u64, hash: &BlockHash| {
+            test_utils::chain_tip(height, hash, ChainTipStatus::Invalid)
+        };
+        let hash = invalid.header.block_hash();
+        let node_data = BTreeMap::from([
+            (0, test_utils::node_data(0, &vec![tip(100, &hash)])),
+            // a buggy node reporting the block at the wrong height
+            (1, test_utils::node_data(1, &vec![tip(101, &hash)])),
+            (
+                2,
+                test_utils::node_data(
+                    2,
+                    &vec![tip(100, &hash), tip(90, &other.header.block_hash())],
+                ),
+            ),
+        ]);
+
+        let invalid_blocks = invalid_blocks(&node_data);
+        assert_eq!(invalid_blocks.len(), 2);
+        let (tip, nodes) = &invalid_blocks[0];
+        assert_eq!(tip.hash, hash.to_string());
+        // the height most nodes report
+        assert_eq!(tip.height, 100);
+        assert_eq!(
+            nodes.iter().map(|n| n.id).collect::<Vec<u32>>(),
+            vec![0, 1, 2]
+        );
+        let (tip, nodes) = &invalid_blocks[1];
+        assert_eq!(tip.hash, other.header.block_hash().to_string());
+        assert_eq!(nodes.len(), 1);
+    }
+
+    fn network_json(id: u32, name: &str) -> NetworkJson {
+        NetworkJson {
+            id,
+            name: name.to_string(),
+            description: String::new(),
+            theme: None,
+            network_type: None,
+            last_poll_ago_seconds: None,
+            stale: false,
+            headers_total: None,
+            headers_in_view: 0,
+        }
+    }
+
+    #[test]
+    fn test_rfc822_date() {
+        assert_eq!(rfc822_date(0), "Thu, 01 Jan 1970 00:00:00 GMT");
+        assert_eq!(rfc822_date(1231006505), "Sat, 03 Jan 2009 18:15:05 GMT");
+        assert_eq!(rfc822_date(951782400), "Tue, 29 Feb 2000 00:00:00 GMT");
+        assert_eq!(rfc822_date(1735689599), "Tue, 31 Dec 2024 23:59:59 GMT");
+    }
+
+    #[tokio::test]
+    async fn test_all_forks_feed() {
+        let fork_at = |height: u64, nonce: u32| {
+            let common = test_utils::header_info(BlockHash::all_zeros(), height, nonce);
+            let children = vec![
+                test_utils::header_info(common.header.block_hash(), height + 1, nonce + 1),
+                test_utils::header_info(common.header.block_hash(), height + 1, nonce + 2),
+            ];
+            Fork {
+                common,
+                children,
+                truncated_children: 0,
+            }
+        };
+        let cache = |forks: Vec<Fork>| {
+            let mut cache = test_utils::cache(BTreeMap::new());
+            cache.forks = forks;
+            cache
+        };
+        let network_infos = vec![
+            network_json(1, "Signet A"),
+            network_json(2, "Signet B"),
+            network_json(3, "Signet C"),
+        ];
+        // the forks feed is disabled on network 3
+        let feeds = BTreeMap::from([(
+            3,
+            Feeds {
+                forks: false,
+                ..Feeds::default()
+            },
+        )]);
+        let caches: Caches = Arc::new(Mutex::new(BTreeMap::from([
+            (1, cache(vec![fork_at(10, 0), fork_at(30, 10)])),
+            (2, cache(vec![fork_at(20, 20)])),
+            (3, cache(vec![fork_at(40, 30)])),
+        ])));
+
+        let feed = |caches: Caches| {
+            let network_infos = network_infos.clone();
+            let feeds = feeds.clone();
+            async move {
+                let reply = all_forks_response(
+                    FeedFormat::Rss,
+                    caches,
+                    network_infos,
+                    "https://example.com".to_string(),
+                    feeds,
+                )
+                .await
+                .unwrap();
+                let body =
+                    warp::hyper::body::to_bytes(warp::Reply::into_response(reply).into_body())
+                        .await
+                        .unwrap();
+                String::from_utf8(body.to_vec()).unwrap()
+            }
+        };
+
+        let body = feed(caches).await;
+        assert_eq!(body.matches("<item>").count(), 3);
+        assert!(!body.contains("Signet C"));
+        let titles: Vec<usize> = [
+            "Signet A: Fork at height 30",
+            "Signet B: Fork at height 20",
+            "Signet A: Fork at height 10",
+        ]
+        .iter()
+        .map(|title| body.find(&format!("<title>{}</title>", title)).unwrap())
+        .collect();
+        assert!(titles.windows(2).all(|w| w[0] < w[1]));
+        assert!(body.contains(&format!(
+            "<guid isPermaLink=\"false\">2-{}</guid>",
+            fork_at(20, 20).common.header.block_hash()
+        )));
+        assert!(body.contains("https://example.com/rss/forks.xml"));
+
+        // no networks
+        let body = feed(Arc::new(Mutex::new(BTreeMap::new()))).await;
+        assert!(body.contains("<title>Recent Forks - All networks</title>"));
+        assert!(!body.contains("<item>"));
+
+        let path = combined_feed_path(FeedKind::Forks);
+        assert_eq!(
+            warp::test::request()
+                .path("/rss/forks.json")
+                .filter(&path)
+                .await
+                .unwrap(),
+            FeedFormat::JsonFeed
+        );
+        assert!(
+            !warp::test::request()
+                .path("/rss/index.opml")
+                .matches(&path)
+                .await
+        );
+    }
+
+    #[test]
+    fn test_rfc3339_date() {
+        assert_eq!(rfc3339_date(0), "1970-01-01T00:00:00Z");
+        assert_eq!(rfc3339_date(1231006505), "2009-01-03T18:15:05Z");
+        assert_eq!(rfc3339_date(951782400), "2000-02-29T00:00:00Z");
+    }
+
+    #[tokio::test]
+    async fn test_json_feed() {
+        let headers = test_utils::chain(BlockHash::all_zeros(), 1, 3, 0);
+        let mut cache = test_utils::cache(BTreeMap::new());
+        cache.forks = vec![Fork {
+            common: headers[0].clone(),
+            children: vec![headers[1].clone(), headers[2].clone()],
+            truncated_children: 0,
+        }];
+        let caches: Caches = Arc::new(Mutex::new(BTreeMap::from([(0, cache)])));
+
+        let reply = forks_response(
+            0,
+            FeedFormat::JsonFeed,
+            caches.clone(),
+            vec![network_json(0, "Mainnet")],
+            "https://example.com".to_string(),
+        )
+        .await
+        .unwrap();
+        let response = warp::Reply::into_response(reply);
+        assert_eq!(response.headers()["content-type"], "application/feed+json");
+        let body = warp::hyper::body::to_bytes(response.into_body())
+            .await
+            .unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(json["version"], "https://jsonfeed.org/version/1.1");
+        assert_eq!(json["title"], "Recent Forks - Mainnet");
+        assert_eq!(json["feed_url"], "https://example.com/rss/0/forks.json");
+        assert_eq!(
+            json["items"],
+            serde_json::json!([{
+                "id": headers[0].header.block_hash().to_string(),
+                "title": "Fork at height 1",
+                "content_text": format!(
+                    "There are 2 blocks building on-top of block {}.",
+                    headers[0].header.block_hash()
+                ),
+                "date_published": "2009-01-03T18:15:07Z",
+            }])
+        );
+
+        let reply = forks_response(0, FeedFormat::Rss, caches, vec![], String::new())
+            .await
+            .unwrap();
+        let response = warp::Reply::into_response(reply);
+        assert_eq!(response.headers()["content-type"], "application/rss+xml");
+    }
+
+    #[test]
+    fn test_item_pub_date() {
+        let headers = test_utils::chain(BlockHash::all_zeros(), 1, 3, 0);
+        let fork = Fork {
+            common: headers[0].clone(),
+            children: vec![headers[1].clone(), headers[2].clone()],
+            truncated_children: 0,
+        };
+        // the time of the latest child
+        let item: Item = fork.into();
+        assert!(item
+            .to_string()
+            .contains("<pubDate>Sat, 03 Jan 2009 18:15:07 GMT</pubDate>"));
+
+        let node = test_utils::node_data(0, &vec![]);
+        let item = Item::unreachable_node_item(&node);
+        assert_eq!(item.published, None);
+        assert!(!item.to_string().contains("pubDate"));
+    }
+
+    #[tokio::test]
+    async fn test_new_blocks_feed() {
+        let headers = test_utils::chain(BlockHash::all_zeros(), 1, 5, 0);
+        let fork = test_utils::header_info(headers[3].header.block_hash(), 5, 100);
+        let tip = test_utils::chain_tip(5, &headers[4].header.block_hash(), ChainTipStatus::Active);
+        let mut cache =
+            test_utils::cache(BTreeMap::from([(0, test_utils::node_data(0, &vec![tip]))]));
+        cache.header_infos_json = headers
+            .iter()
+            .enumerate()
+            .map(|(i, h)| HeaderInfoJson::new(h, i, i.checked_sub(1).unwrap_or(usize::MAX)))
+            .collect();
+        cache
+            .header_infos_json
+            .push(HeaderInfoJson::new(&fork, 5, 3));
+        cache.header_infos_json[3].miner = "Pool A".to_string();
+        cache.recent_miners.insert(
+            headers[4].header.block_hash().to_string(),
+            ("Pool B".to_string(), 5),
+        );
+        let caches: Caches = Arc::new(Mutex::new(BTreeMap::from([(0, cache)])));
+        let feeds = BTreeMap::from([(
+            0,
+            Feeds {
+                blocks_items: 3,
+                ..Feeds::default()
+            },
+        )]);
+
+        let reply = new_blocks_response(0, FeedFormat::Rss, caches, vec![], String::new(), feeds)
+            .await
+            .unwrap();
+        let body = warp::hyper::body::to_bytes(warp::Reply::into_response(reply).into_body())
+            .await
+            .unwrap();
+        let body = String::from_utf8(body.to_vec()).unwrap();
+
+        // the three highest active blocks, the fork block isn't included
+        assert_eq!(body.matches("<item>").count(), 3);
+        assert!(!body.contains(&fork.header.block_hash().to_string()));
+        assert!(!body.contains(&headers[1].header.block_hash().to_string()));
+        let tip_item = body.find("New block at height 5").unwrap();
+        let next_item = body.find("New block at height 4").unwrap();
+        assert!(tip_item < next_item);
+        assert!(body.contains(&format!(
+            "Block {} at height 5 was mined by Pool B.",
+            headers[4].header.block_hash()
+        )));
+        assert!(body.contains(&format!(
+            "Block {} at height 4 was mined by Pool A.",
+            headers[3].header.block_hash()
+        )));
+        assert!(body.contains(&format!(
+            "Block {} at height 3 was mined by an unknown miner.",
+            headers[2].header.block_hash()
+        )));
+    }
+
+    #[tokio::test]
+    async fn test_stalled_feed() {
+        let now = unix_timestamp();
+        let headers = test_utils::chain(BlockHash::all_zeros(), 1, 3, 0);
+        let tip = vec![test_utils::chain_tip(
+            3,
+            &headers[2].header.block_hash(),
+            ChainTipStatus::Active,
+        )];
+        let node = |id: u32, last_changed_timestamp: u64, reachable: bool| {
+            let mut node = test_utils::node_data(id, &tip);
+            node.last_changed_timestamp = last_changed_timestamp;
+            node.reachable = reachable;
+            node
+        };
+        let node_data = BTreeMap::from([
+            (0, node(0, now - 60, true)),
+            (1, node(1, now - 7200, true)),
+            // unreachable nodes are in the unreachable feed
+            (2, node(2, now - 7200, false)),
+            // never reported tips
+            (3, node(3, 0, true)),
+        ]);
+
+        let stalled: Vec<u32> = stalled_nodes(&node_data, now, 3600)
+            .iter()
+            .map(|node| node.id)
+            .collect();
+        assert_eq!(stalled, vec![1]);
+        let stalled: Vec<u32> = stalled_nodes(&node_data, now, 30)
+            .iter()
+            .map(|node| node.id)
+            .collect();
+        assert_eq!(stalled, vec![0, 1]);
+
+        let caches: Caches = Arc::new(Mutex::new(BTreeMap::from([(
+            0,
+            test_utils::cache(node_data),
+        )])));
+        let reply = stalled_nodes_response(
+            0,
+            FeedFormat::Rss,
+            caches,
+            vec![],
+            String::new(),
+            BTreeMap::new(),
+            BTreeMap::new(),
+        )
+        .await
+        .unwrap();
+        let body = warp::hyper::body::to_bytes(warp::Reply::into_response(reply).into_body())
+            .await
+            .unwrap();
+        let body = String::from_utf8(body.to_vec()).unwrap();
+        assert_eq!(body.matches("<item>").count(), 1);
+        assert!(body.contains("Node 'node 1' (id=1) is stalled"));
+        assert!(body.contains(&format!(
+            "Its active tip is {} at height 3.",
+            headers[2].header.block_hash()
+        )));
+        assert!(body.contains(&format!(
+            "<pubDate>{}</pubDate>",
+            rfc822_date(now - 7200 + 3600)
+        )));
+    }
+
+    #[test]
+    fn test_opml_index() {
+        let network_infos = vec![network_json(1, "Mainnet"), network_json(2, "A & B <test>")];
+        let feeds = BTreeMap::from([(
+            2,
+            Feeds {
+                lagging: false,
+                alerts: false,
+                ..Feeds::default()
+            },
+        )]);
+        let opml = opml(&network_infos, &feeds, "https://example.com/");
+
+        assert_eq!(opml.matches(r#"type="rss""#).count(), 7 + 5);
+        for kind in FeedKind::ALL.iter() {
+            assert!(opml.contains(&format!(
+                r#"xmlUrl="https://example.com/rss/1/{}""#,
+                kind.file_name(FeedFormat::Rss)
+            )));
+        }
+        assert!(opml.contains(r#"xmlUrl="https://example.com/rss/2/forks.xml""#));
+        assert!(opml.contains(r#"xmlUrl="https://example.com/rss/2/invalid.xml""#));
+        assert!(opml.contains(r#"xmlUrl="https://example.com/rss/2/unreachable.xml""#));
+        assert!(!opml.contains("/rss/2/lagging.xml"));
+        assert!(!opml.contains("/rss/2/alerts.xml"));
+
+        assert!(opml.contains(r#"<outline text="A &amp; B &lt;test&gt;""#));
+        assert!(!opml.contains("A & B"));
+    }
+
+    #[tokio::test]
+    async fn test_disabled_feed_path() {
+        let feeds = BTreeMap::from([(
+            2,
+            Feeds {
+                lagging: false,
+                ..Feeds::default()
+            },
+        )]);
+        let lagging = feed_path(FeedKind::Lagging, feeds.clone());
+        assert_eq!(
+            warp::test::request()
+                .path("/rss/1/lagging.xml")
+                .filter(&lagging)
+                .await
+                .unwrap(),
+            (1, FeedFormat::Rss)
+        );
+        assert_eq!(
+            warp::test::request()
+                .path("/rss/1/lagging.json")
+                .filter(&lagging)
+                .await
+                .unwrap(),
+            (1, FeedFormat::JsonFeed)
+        );
+        assert!(
+            !warp::test::request()
+                .path("/rss/1/lagging.txt")
+                .matches(&lagging)
+                .await
+        );
+        assert!(
+            !warp::test::request()
+                .path("/rss/2/lagging.json")
+                .matches(&lagging)
+                .await
+        );
+        assert!(
+            !warp::test::request()
+                .path("/rss/2/lagging.xml")
+                .matches(&lagging)
+                .await
+        );
+        assert!(
+            warp::test::request()
+                .path("/rss/2/forks.xml")
+                .matches(&feed_path(FeedKind::Forks, feeds))
+                .await
+        );
+    }
+}
@@ -0,0 +1,135 @@
+// Helpers to build synthetic headers and header-trees in tests.
+
+use std::collections::{HashMap, VecDeque};
+use std::sync::Arc;
+
+use bitcoincore_rpc::bitcoin::block::{Header, Version};
+use bitcoincore_rpc::bitcoin::hashes::Hash;
+use bitcoincore_rpc::bitcoin::{
+    absolute, transaction, BlockHash, CompactTarget, OutPoint, ScriptBuf, Sequence, Transaction,
+    TxIn, TxMerkleNode, Witness,
+};
+use petgraph::graph::{DiGraph, NodeIndex};
+use tokio::sync::Mutex;
+
+use crate::node::NodeInfo;
+use crate::types::{
+    Cache, CacheJournal, ChainTip, ChainTipStatus, HeaderInfo, NodeData, NodeDataJson, Tree,
+};
+
+// A regtest-difficulty header. The nonce is used to make headers with the
+// same prev_blockhash unique.
+pub fn header(prev_blockhash: BlockHash, nonce: u32) -> Header {
+    Header {
+        version: Version::from_consensus(0x20000000),
+        prev_blockhash,
+        merkle_root: TxMerkleNode::all_zeros(),
+        time: 1231006505 + nonce,
+        bits: CompactTarget::from_consensus(0x207fffff),
+        nonce,
+    }
+}
+
+pub fn header_info(prev_blockhash: BlockHash, height: u64, nonce: u32) -> HeaderInfo {
+    HeaderInfo {
+        height,
+        header: header(prev_blockhash, nonce),
+        miner: String::new(),
+    }
+}
+
+// Builds a chain of `length` headers on-top of `prev_blockhash`. The first
+// header in the chain has the height `height`. Nonces start at `nonce`.
+pub fn chain(prev_blockhash: BlockHash, height: u64, length: usize, nonce: u32) -> Vec<HeaderInfo> {
+    let mut headers: Vec<HeaderInfo> = Vec::with_capacity(length);
+    let mut prev = prev_blockhash;
+    for i in 0..length {
+        let h = header_info(prev, height + i as u64, nonce + i as u32);
+        prev = h.header.block_hash();
+        headers.push(h);
+    }
+    headers
+}
+
+// Builds a header-tree from the headers. The headers don't need to be sorted.
+pub fn tree(headers: &[HeaderInfo]) -> Tree {
+    let mut graph: DiGraph<HeaderInfo, bool> = DiGraph::new();
+    let mut hash_index_map: HashMap<BlockHash, NodeIndex> = HashMap::new();
+    for h in headers {
+        let idx = graph.add_node(h.clone());
+        hash_index_map.insert(h.header.block_hash(), idx);
+    }
+    for h in headers {
+        if let (Some(prev), Some(current)) = (
+            hash_index_map.get(&h.header.prev_blockhash),
+            hash_index_map.get(&h.header.block_hash()),
+        ) {
+            graph.update_edge(*prev, *current, false);
+        }
+    }
+    Arc::new(Mutex::new((graph, hash_index_map)))
+}
+
+pub fn chain_tip(height: u64, hash: &BlockHash, status: ChainTipStatus) -> ChainTip {
+    ChainTip {
+        height,
+        hash: hash.to_string(),
+        branchlen: 0,
+        status,
+    }
+}
+
+// A coinbase transaction with the tag as scriptSig and without outputs.
+pub fn coinbase_with_tag(tag: &[u8]) -> Transaction {
+    Transaction {
+        version: transaction::Version::ONE,
+        lock_time: absolute::LockTime::ZERO,
+        input: vec![TxIn {
+            previous_output: OutPoint::null(),
+            script_sig: ScriptBuf::from_bytes(tag.to_vec()),
+            sequence: Sequence::MAX,
+            witness: Witness::new(),
+        }],
+        output: vec![],
+    }
+}
+
+// A reachable node with the given chain tips.
+pub fn node_data(id: u32, tips: &Vec<ChainTip>) -> NodeDataJson {
+    NodeDataJson::new(
+        NodeInfo {
+            id,
+            name: format!("node {}", id),
+            description: String::new(),
+            implementation: "Bitcoin Core".to_string(),
+        },
+        tips,
+        "unknown".to_string(),
+        0,
+        true,
+    )
+}
+
+// A cache with the given nodes and otherwise empty.
+pub fn cache(node_data: NodeData) -> Cache {
+    Cache {
+        header_infos_json: vec![],
+        node_data,
+        forks: vec![],
+        deployment_disagreements: vec![],
+        raw_tips: None,
+        tips_replay: None,
+        chain_split: None,
+        alerts: vec![],
+        reorgs: VecDeque::new(),
+        recent_miners: HashMap::new(),
+        journal: CacheJournal::default(),
+        data_json: None,
+        last_poll_timestamp: None,
+        headers_total: None,
+        height_offset: 0,
+        propagation: None,
+        active_chain_work: None,
+        header_statuses: None,
+    }
+}
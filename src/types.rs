@@ -1,38 +1,233 @@
-use std::collections::{BTreeMap, HashMap};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet, VecDeque};
 use std::fmt;
 use std::str::FromStr;
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::Arc;
-use std::time::SystemTime;
+use std::time::{Duration, SystemTime};
 
-use crate::config::Network;
+use crate::config::{Network, Privacy, Theme};
+use crate::headertree;
 use crate::node::NodeInfo;
 
 use bitcoincore_rpc::bitcoin::blockdata::block::Header;
 use bitcoincore_rpc::bitcoin::BlockHash;
+use bitcoincore_rpc::bitcoin::Network as BitcoinNetwork;
+use bitcoincore_rpc::bitcoin::Work;
 use bitcoincore_rpc::json::{GetChainTipsResultStatus, GetChainTipsResultTip};
 use log::warn;
 use petgraph::graph::DiGraph;
 use petgraph::graph::NodeIndex;
 use rusqlite::Connection;
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
-use tokio::sync::Mutex;
+use tokio::sync::{mpsc, Mutex};
+use warp::hyper::body::Bytes;
 
 #[derive(Clone)]
 pub struct Cache {
     pub header_infos_json: Vec<HeaderInfoJson>,
     pub node_data: NodeData,
     pub forks: Vec<Fork>,
+    /// Names of the softfork deployments the nodes disagree on the status of.
+    pub deployment_disagreements: Vec<String>,
+    /// The last unfiltered getchaintips result per node id. Only retained
+    /// if enabled for the network, for debugging.
+    pub raw_tips: Option<BTreeMap<u32, Vec<ChainTip>>>,
+    /// The last unfiltered getchaintips results per node, for replaying
+    /// the tip observations e.g. around a reorg. Only retained if enabled
+    /// for the network.
+    pub tips_replay: Option<TipsReplay>,
+    /// The active tips of the nodes while a chain split is ongoing. Used to
+    /// only alert once per chain split.
+    pub chain_split: Option<Vec<ConsensusTipJson>>,
+    /// Recent alerts, oldest first.
+    pub alerts: Vec<Alert>,
+    /// Recent reorgs of the nodes, oldest first.
+    pub reorgs: VecDeque<ReorgJson>,
     /// Since strip_tree and identifying miners runs in parallel,
     /// the strip_tree result might not contain a miner yet. Keeping
-    /// recent miners here and use + manage them when updating the cache.
-    pub recent_miners: Vec<(String, String)>,
+    /// recent miners (block hash → (miner, height)) here and use + manage
+    /// them when updating the cache. A miner is kept as long as its block
+    /// is part of the header tree in the cache or close to its top.
+    pub recent_miners: HashMap<String, (String, u64)>,
+    /// Recent changes to the headers and nodes, for data.json?since=.
+    pub journal: CacheJournal,
+    /// The serialized data.json body. Reset on each cache update and rebuilt
+    /// on the next request.
+    pub data_json: Option<DataJsonBody>,
+    /// UNIX timestamp of the last successful getchaintips of any node in the
+    /// network.
+    pub last_poll_timestamp: Option<u64>,
+    /// Number of headers of the network in the database. Refreshed
+    /// periodically. None until the headers were first counted.
+    pub headers_total: Option<u64>,
+    /// Added to the header and tip heights served in data.json and
+    /// data.v2.json. Only changes the presentation, the header tree and the
+    /// fork logic use the actual heights.
+    pub height_offset: i64,
+    /// When the nodes first had recent blocks as active tip. Only tracked if
+    /// enabled for the network.
+    pub propagation: Option<Propagation>,
+    /// The work of the active chain. Only tracked if enabled for the
+    /// network. None until first computed.
+    pub active_chain_work: Option<ChainWork>,
+    /// The header statuses (block hash → status) and the journal generation
+    /// they were derived at.
+    pub header_statuses: Option<(u64, Arc<HashMap<String, HeaderStatus>>)>,
+}
+
+/// A serialized data.json body as cached in the Cache.
+#[derive(Clone, Debug)]
+pub struct DataJsonBody {
+    /// The privacy settings the body was redacted with.
+    pub privacy: Privacy,
+    pub body: Arc<Bytes>,
+    pub etag: String,
+    /// The gzip compressed body. Compressed on the first request accepting
+    /// gzip.
+    pub gzip: Option<Arc<Bytes>>,
+}
+
+impl Cache {
+    /// The network has headers and at least one node reported its tips.
+    pub fn has_data(&self) -> bool {
+        !self.header_infos_json.is_empty()
+            && self.node_data.values().any(|node| !node.tips.is_empty())
+    }
+
+    /// The node data as served by the data endpoints: redacted according to
+    /// the privacy settings and with the height offset applied.
+    pub fn served_node_data(&self, privacy: &Privacy) -> NodeData {
+        self.node_data
+            .iter()
+            .map(|(id, node)| {
+                (
+                    *id,
+                    node.redacted(privacy)
+                        .with_height_offset(self.height_offset),
+                )
+            })
+            .collect()
+    }
+
+    /// The header statuses by block hash. The statuses are derived once per
+    /// journal generation.
+    pub fn header_status_map(&mut self) -> Arc<HashMap<String, HeaderStatus>> {
+        match &self.header_statuses {
+            Some((generation, statuses)) if *generation == self.journal.generation => {
+                statuses.clone()
+            }
+            _ => {
+                let statuses = Arc::new(headertree::header_statuses(
+                    &self.header_infos_json,
+                    &self.node_data,
+                ));
+                self.header_statuses = Some((self.journal.generation, statuses.clone()));
+                statuses
+            }
+        }
+    }
+
+    /// The headers with their status and the height offset applied.
+    pub fn header_infos_with_status(&mut self) -> Vec<HeaderInfoJson> {
+        let statuses = self.header_status_map();
+        self.header_infos_json
+            .iter()
+            .map(|h| {
+                let mut h = h.clone();
+                h.status = statuses
+                    .get(&h.hash)
+                    .cloned()
+                    .unwrap_or(HeaderStatus::Unknown);
+                h.height = h.height.saturating_add_signed(self.height_offset);
+                h
+            })
+            .collect()
+    }
+}
+
+// Number of changes kept in a CacheJournal.
+const MAX_JOURNAL_CHANGES: usize = 256;
+
+/// A bounded history of the changes to the headers and nodes in a Cache.
+/// Each change increments the generation.
+#[derive(Debug, Clone, Default)]
+pub struct CacheJournal {
+    pub generation: u64,
+    changes: VecDeque<CacheChange>,
+}
+
+#[derive(Debug, Clone)]
+struct CacheChange {
+    generation: u64,
+    /// Hashes of added or modified headers.
+    headers: Vec<String>,
+    /// Hashes of removed headers.
+    removed_headers: Vec<String>,
+    nodes: Vec<u32>,
+}
+
+/// The headers and nodes changed since a generation.
+#[derive(Debug, Default, PartialEq)]
+pub struct CacheDelta {
+    pub headers: BTreeSet<String>,
+    pub removed_headers: BTreeSet<String>,
+    pub nodes: BTreeSet<u32>,
+}
+
+impl CacheJournal {
+    pub fn record(&mut self, headers: Vec<String>, removed_headers: Vec<String>, nodes: Vec<u32>) {
+        if headers.is_empty() && removed_headers.is_empty() && nodes.is_empty() {
+            return;
+        }
+        self.generation += 1;
+        self.changes.push_back(CacheChange {
+            generation: self.generation,
+            headers,
+            removed_headers,
+            nodes,
+        });
+        if self.changes.len() > MAX_JOURNAL_CHANGES {
+            self.changes.pop_front();
+        }
+    }
+
+    /// The changes since the generation. None if the journal doesn't reach
+    /// back that far or the generation is unknown.
+    pub fn since(&self, generation: u64) -> Option<CacheDelta> {
+        if generation > self.generation {
+            return None;
+        }
+        if generation < self.generation {
+            match self.changes.front() {
+                Some(oldest) if oldest.generation <= generation + 1 => (),
+                _ => return None,
+            }
+        }
+        let mut delta = CacheDelta::default();
+        for change in self.changes.iter().filter(|c| c.generation > generation) {
+            for hash in change.headers.iter() {
+                delta.removed_headers.remove(hash);
+                delta.headers.insert(hash.clone());
+            }
+            for hash in change.removed_headers.iter() {
+                delta.headers.remove(hash);
+                delta.removed_headers.insert(hash.clone());
+            }
+            delta.nodes.extend(change.nodes.iter());
+        }
+        Some(delta)
+    }
 }
 
 pub type NodeData = BTreeMap<u32, NodeDataJson>;
 pub type Caches = Arc<Mutex<BTreeMap<u32, Cache>>>;
 pub type TreeInfo = (DiGraph<HeaderInfo, bool>, HashMap<BlockHash, NodeIndex>);
 pub type Tree = Arc<Mutex<TreeInfo>>;
+pub type Trees = Arc<BTreeMap<u32, Tree>>;
 pub type Db = Arc<Mutex<Connection>>;
+/// Softfork deployment name → deployment status.
+pub type Deployments = BTreeMap<String, String>;
 
 #[derive(Debug, Eq, PartialEq, Clone)]
 pub struct HeaderInfo {
@@ -47,11 +242,25 @@ impl HeaderInfo {
     }
 }
 
-#[derive(Serialize, Clone)]
+#[derive(Serialize, JsonSchema, Clone)]
 pub struct NetworkJson {
     pub id: u32,
     pub name: String,
     pub description: String,
+    pub theme: Option<Theme>,
+    /// The network type, e.g. "mainnet" or "signet". Derived from the
+    /// genesis block if possible, "unknown" otherwise.
+    pub network_type: Option<String>,
+    /// Seconds since a node of the network was last polled successfully.
+    /// None if no node has been polled successfully yet.
+    pub last_poll_ago_seconds: Option<u64>,
+    /// No node of the network has been polled successfully for a while.
+    pub stale: bool,
+    /// Number of headers stored for the network. None until the headers
+    /// were first counted.
+    pub headers_total: Option<u64>,
+    /// Number of headers in the stripped header tree served by the API.
+    pub headers_in_view: usize,
 }
 
 impl NetworkJson {
@@ -60,16 +269,216 @@ impl NetworkJson {
             id: network.id,
             name: network.name.clone(),
             description: network.description.clone(),
+            theme: network.theme.clone(),
+            // Until the genesis block is known, fall back to the configured
+            // pool identification network.
+            network_type: Some(
+                network
+                    .pool_identification
+                    .network
+                    .as_ref()
+                    .map(|n| network_type_name(n.to_network()))
+                    .unwrap_or(UNKNOWN_NETWORK_TYPE)
+                    .to_string(),
+            ),
+            last_poll_ago_seconds: None,
+            stale: false,
+            headers_total: None,
+            headers_in_view: 0,
         }
     }
 }
 
-#[derive(Serialize)]
+pub const UNKNOWN_NETWORK_TYPE: &str = "unknown";
+
+pub fn network_type_name(network: BitcoinNetwork) -> &'static str {
+    match network {
+        BitcoinNetwork::Bitcoin => "mainnet",
+        BitcoinNetwork::Testnet => "testnet",
+        BitcoinNetwork::Testnet4 => "testnet4",
+        BitcoinNetwork::Signet => "signet",
+        BitcoinNetwork::Regtest => "regtest",
+    }
+}
+
+#[derive(Serialize, JsonSchema)]
 pub struct NetworksJsonResponse {
+    pub uptime_seconds: u64,
     pub networks: Vec<NetworkJson>,
 }
 
-#[derive(Debug, Eq, PartialEq, Clone, Serialize)]
+#[derive(Serialize)]
+pub struct HealthJsonResponse {
+    /// "ok" if all nodes are reachable, "degraded" otherwise.
+    pub status: &'static str,
+    pub uptime_seconds: u64,
+    pub networks: Vec<NetworkHealthJson>,
+    /// None if the database hasn't been pruned yet.
+    pub last_prune: Option<PruneRun>,
+}
+
+/// The readiness of the instance in /api/health.json.
+#[derive(Serialize, Debug, PartialEq)]
+pub struct ReadinessJsonResponse {
+    /// "ok" if all networks have data, "starting" otherwise.
+    pub status: &'static str,
+    /// Number of configured networks.
+    pub networks: usize,
+    /// Ids of the networks without headers or tips yet.
+    pub pending_networks: Vec<u32>,
+    /// The oldest UNIX timestamp any node's tips last changed at. None if
+    /// no node reported its tips yet.
+    pub oldest_last_changed_timestamp: Option<u64>,
+}
+
+/// A run of the database pruning.
+#[derive(Serialize, Clone, Debug)]
+pub struct PruneRun {
+    /// UNIX timestamp of the end of the run.
+    pub timestamp: u64,
+    /// Number of deleted rows per table.
+    pub deleted: BTreeMap<String, usize>,
+}
+
+pub type LastPrune = Arc<Mutex<Option<PruneRun>>>;
+
+#[derive(Serialize)]
+pub struct NetworkHealthJson {
+    pub id: u32,
+    pub name: String,
+    pub last_poll_ago_seconds: Option<u64>,
+    pub stale: bool,
+    /// Block hashes queued for pool identification.
+    pub pool_id_queued: usize,
+    /// Block hashes dropped because the pool identification queue was full.
+    pub pool_id_dropped: u64,
+    pub nodes: Vec<NodeHealthJson>,
+}
+
+#[derive(Serialize, Debug, PartialEq)]
+pub struct NodeHealthJson {
+    pub id: u32,
+    pub reachable: bool,
+    /// Seconds since the tips of the node last changed. None if the node
+    /// didn't report its tips yet.
+    pub last_changed_ago_seconds: Option<u64>,
+    /// The active tip of the node is more than a few blocks below the
+    /// highest active tip of the network, as in the lagging nodes feed.
+    pub lagging: bool,
+}
+
+/// The process start time and when to consider the data of a network stale.
+#[derive(Clone)]
+pub struct Freshness {
+    /// UNIX timestamp of the process start.
+    pub started: u64,
+    /// A network is stale if no node was polled successfully for this many
+    /// seconds.
+    pub stale_after: u64,
+}
+
+impl Freshness {
+    pub fn uptime(&self, now: u64) -> u64 {
+        now.saturating_sub(self.started)
+    }
+
+    /// The seconds since the last successful poll and if the network is
+    /// stale. Networks that were never polled successfully are stale once
+    /// the process has been running for longer than stale_after.
+    pub fn network(&self, last_poll: Option<u64>, now: u64) -> (Option<u64>, bool) {
+        match last_poll {
+            Some(last_poll) => {
+                let ago = now.saturating_sub(last_poll);
+                (Some(ago), ago > self.stale_after)
+            }
+            None => (None, self.uptime(now) > self.stale_after),
+        }
+    }
+}
+
+/// A bounded queue of the block hashes to identify the pool of. Hashes of new
+/// blocks wait briefly for space in a full queue. Backfilled hashes are
+/// dropped if the queue is full.
+#[derive(Clone)]
+pub struct PoolIdQueue {
+    tx: mpsc::Sender<BlockHash>,
+    dropped: Arc<AtomicU64>,
+}
+
+// How long hashes of new blocks wait for space in a full PoolIdQueue.
+const POOL_ID_QUEUE_SEND_TIMEOUT: Duration = Duration::from_secs(1);
+
+impl PoolIdQueue {
+    pub fn new(capacity: usize) -> (Self, mpsc::Receiver<BlockHash>) {
+        let (tx, rx) = mpsc::channel(capacity);
+        (
+            PoolIdQueue {
+                tx,
+                dropped: Arc::new(AtomicU64::new(0)),
+            },
+            rx,
+        )
+    }
+
+    /// Enqueues the hash of a new block. Returns false if the hash was
+    /// dropped.
+    pub async fn send(&self, hash: BlockHash) -> bool {
+        self.send_with_timeout(hash, POOL_ID_QUEUE_SEND_TIMEOUT)
+            .await
+    }
+
+    async fn send_with_timeout(&self, hash: BlockHash, timeout: Duration) -> bool {
+        match self.tx.send_timeout(hash, timeout).await {
+            Ok(()) => true,
+            Err(e) => {
+                warn!(
+                    "Dropping block {} from the pool identification queue: {}",
+                    hash, e
+                );
+                self.dropped.fetch_add(1, Ordering::Relaxed);
+                false
+            }
+        }
+    }
+
+    /// Enqueues the hash of a backfilled block if there is space. Returns
+    /// false if the hash was dropped.
+    pub fn try_send(&self, hash: BlockHash) -> bool {
+        match self.tx.try_send(hash) {
+            Ok(()) => true,
+            Err(_) => {
+                self.dropped.fetch_add(1, Ordering::Relaxed);
+                false
+            }
+        }
+    }
+
+    /// Number of queued hashes.
+    pub fn queued(&self) -> usize {
+        self.tx.max_capacity() - self.tx.capacity()
+    }
+
+    /// Number of hashes dropped because the queue was full.
+    pub fn dropped(&self) -> u64 {
+        self.dropped.load(Ordering::Relaxed)
+    }
+}
+
+/// The pool identification queues by network id.
+pub type PoolIdQueues = Arc<BTreeMap<u32, PoolIdQueue>>;
+
+/// The current UNIX timestamp in seconds.
+pub fn unix_timestamp() -> u64 {
+    match SystemTime::now().duration_since(SystemTime::UNIX_EPOCH) {
+        Ok(n) => n.as_secs(),
+        Err(_) => {
+            warn!("SystemTime is before UNIX_EPOCH time. Using timestamp 0.");
+            0u64
+        }
+    }
+}
+
+#[derive(Debug, Eq, PartialEq, Clone, Serialize, JsonSchema)]
 pub struct HeaderInfoJson {
     pub id: usize,
     pub prev_id: usize,
@@ -86,6 +495,21 @@ pub struct HeaderInfoJson {
     pub difficulty_int: u64,
     pub nonce: u32,
     pub miner: String,
+    /// Derived from the node tips when building the data responses.
+    pub status: HeaderStatus,
+}
+
+/// The chain status of a header as seen by the nodes of a network.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum HeaderStatus {
+    /// An ancestor of (or) the active tip of the majority of the nodes.
+    Active,
+    /// On a branch that lost against the majority active chain.
+    Stale,
+    /// On a branch of a tip a node reported as invalid.
+    Invalid,
+    Unknown,
 }
 
 impl HeaderInfoJson {
@@ -103,6 +527,7 @@ impl HeaderInfoJson {
             difficulty_int: hi.header.difficulty_float() as u64,
             nonce: hi.header.nonce,
             miner: hi.miner.clone(),
+            status: HeaderStatus::Unknown,
         }
     }
 
@@ -111,41 +536,555 @@ impl HeaderInfoJson {
     }
 }
 
-#[derive(Serialize)]
+#[derive(Serialize, JsonSchema)]
 pub struct InfoJsonResponse {
     pub footer: String,
 }
 
-#[derive(Serialize)]
+#[derive(Serialize, JsonSchema)]
 pub struct DataJsonResponse {
     pub header_infos: Vec<HeaderInfoJson>,
     pub nodes: Vec<NodeDataJson>,
+    pub deployment_disagreements: Vec<String>,
+    /// Only present if propagation tracking is enabled for the network.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub block_propagation: Option<BlockPropagationJson>,
+    /// The summed work of the active chain as 64 hex characters, like the
+    /// chainwork in Bitcoin Core. Only present if tracking the chain work is
+    /// enabled for the network.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub active_chain_work: Option<String>,
+}
+
+/// The summed work of the headers in the header tree on the chain ending in
+/// the tip.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ChainWork {
+    pub tip: BlockHash,
+    pub work: Work,
+}
+
+impl ChainWork {
+    pub fn hex(&self) -> String {
+        hex::encode(self.work.to_be_bytes())
+    }
+}
+
+#[derive(Serialize, JsonSchema, Debug, Clone, PartialEq)]
+pub struct BlockPropagationJson {
+    /// Average seconds between the first and the last node having a block
+    /// as active tip. None until a block was seen by more than one node.
+    pub average_delay_seconds: Option<f64>,
+    /// Number of recent blocks the average is calculated over.
+    pub blocks: usize,
+}
+
+// Number of blocks we track the first-seen times of at the same time.
+// Blocks that are not seen by all nodes, e.g. because a node is
+// unreachable, are finished once they are pushed out.
+const PROPAGATION_TRACKED_BLOCKS: usize = 20;
+// Number of recent propagation delays the average is calculated over.
+const PROPAGATION_WINDOW: usize = 100;
+
+/// Tracks when each node first had a block as active tip. The propagation
+/// delay of a block is the time between the first and the last node seeing
+/// it.
+#[derive(Debug, Default, Clone)]
+pub struct Propagation {
+    // block hash → node id → first seen UNIX timestamp, oldest block first
+    first_seen: VecDeque<(String, BTreeMap<u32, u64>)>,
+    // the last reported active tip of each node
+    active_tips: BTreeMap<u32, String>,
+    // recently finished blocks, oldest first
+    finished: VecDeque<String>,
+    // recent delays in seconds, oldest first
+    delays: VecDeque<u64>,
 }
 
-#[derive(Serialize, Clone, Eq, Hash, PartialEq, Debug)]
+impl Propagation {
+    /// Records that the node has the block as active tip since the
+    /// timestamp. Only a change of the node's active tip counts: the first
+    /// reported active tip of a node was likely found before we polled it.
+    /// Finished blocks aren't tracked again. The block is finished once all
+    /// `node_count` nodes have seen it.
+    pub fn seen(&mut self, hash: &str, node_id: u32, timestamp: u64, node_count: usize) {
+        let previous = self.active_tips.insert(node_id, hash.to_string());
+        if previous.is_none_or(|previous| previous == hash)
+            || self.finished.iter().any(|h| h == hash)
+        {
+            return;
+        }
+        let index = match self.first_seen.iter().position(|(h, _)| h == hash) {
+            Some(index) => index,
+            None => {
+                self.first_seen
+                    .push_back((hash.to_string(), BTreeMap::new()));
+                self.first_seen.len() - 1
+            }
+        };
+        let seen_by = &mut self.first_seen[index].1;
+        seen_by.entry(node_id).or_insert(timestamp);
+        if seen_by.len() >= node_count {
+            if let Some((hash, seen_by)) = self.first_seen.remove(index) {
+                self.finish(hash, &seen_by);
+            }
+        }
+        while self.first_seen.len() > PROPAGATION_TRACKED_BLOCKS {
+            if let Some((hash, seen_by)) = self.first_seen.pop_front() {
+                self.finish(hash, &seen_by);
+            }
+        }
+    }
+
+    fn finish(&mut self, hash: String, seen_by: &BTreeMap<u32, u64>) {
+        self.finished.push_back(hash);
+        while self.finished.len() > PROPAGATION_TRACKED_BLOCKS {
+            self.finished.pop_front();
+        }
+        if seen_by.len() < 2 {
+            return;
+        }
+        let first = seen_by.values().min().cloned().unwrap_or_default();
+        let last = seen_by.values().max().cloned().unwrap_or_default();
+        self.delays.push_back(last - first);
+        while self.delays.len() > PROPAGATION_WINDOW {
+            self.delays.pop_front();
+        }
+    }
+
+    pub fn json(&self) -> BlockPropagationJson {
+        BlockPropagationJson {
+            average_delay_seconds: if self.delays.is_empty() {
+                None
+            } else {
+                Some(self.delays.iter().sum::<u64>() as f64 / self.delays.len() as f64)
+            },
+            blocks: self.delays.len(),
+        }
+    }
+}
+
+/// A getchaintips result and when it was fetched.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct TipsSnapshot {
+    pub timestamp: u64,
+    pub tips: Vec<ChainTip>,
+}
+
+/// The last getchaintips results per node, oldest first. Bounded to
+/// `length` snapshots per node.
+#[derive(Debug, Clone)]
+pub struct TipsReplay {
+    length: usize,
+    snapshots: BTreeMap<u32, VecDeque<TipsSnapshot>>,
+}
+
+impl TipsReplay {
+    pub fn new(length: usize) -> Self {
+        TipsReplay {
+            length,
+            snapshots: BTreeMap::new(),
+        }
+    }
+
+    pub fn record(&mut self, node_id: u32, timestamp: u64, tips: Vec<ChainTip>) {
+        let snapshots = self.snapshots.entry(node_id).or_default();
+        snapshots.push_back(TipsSnapshot { timestamp, tips });
+        while snapshots.len() > self.length {
+            snapshots.pop_front();
+        }
+    }
+
+    /// The snapshots of the node, oldest first. None if nothing was
+    /// recorded for the node yet.
+    pub fn snapshots(&self, node_id: u32) -> Option<&VecDeque<TipsSnapshot>> {
+        self.snapshots.get(&node_id)
+    }
+}
+
+#[derive(Deserialize)]
+pub struct DataQuery {
+    /// "1" or "true" requests the compact encoding.
+    pub compact: Option<String>,
+    /// Only return the changes since this cache generation.
+    pub since: Option<u64>,
+    /// Only return this many of the highest headers.
+    pub max_headers: Option<usize>,
+    /// Only return headers at or above this height. Kept as string to answer
+    /// invalid heights with a JSON error, see height_range().
+    pub min_height: Option<String>,
+    /// Only return headers at or below this height.
+    pub max_height: Option<String>,
+}
+
+impl DataQuery {
+    pub fn is_compact(&self) -> bool {
+        matches!(self.compact.as_deref(), Some("1") | Some("true"))
+    }
+
+    /// The parsed min_height and max_height. Errors if a height isn't a
+    /// number or min_height is above max_height.
+    pub fn height_range(&self) -> Result<(Option<u64>, Option<u64>), String> {
+        let parse = |name: &str, height: &Option<String>| match height {
+            Some(height) => height
+                .parse::<u64>()
+                .map(Some)
+                .map_err(|_| format!("{} '{}' is not a non-negative integer", name, height)),
+            None => Ok(None),
+        };
+        let min_height = parse("min_height", &self.min_height)?;
+        let max_height = parse("max_height", &self.max_height)?;
+        if let (Some(min), Some(max)) = (min_height, max_height) {
+            if min > max {
+                return Err(format!("min_height {} is above max_height {}", min, max));
+            }
+        }
+        Ok((min_height, max_height))
+    }
+}
+
+/// The headers and nodes that changed since a cache generation. If the
+/// generation is too old, all headers and nodes are included and `full` is
+/// set. Served on data.json?since=<generation>.
+#[derive(Serialize, Debug)]
+pub struct DataDeltaJsonResponse {
+    pub generation: u64,
+    pub full: bool,
+    /// Added or modified headers.
+    pub header_infos: Vec<HeaderInfoJson>,
+    /// Hashes of the headers that were removed.
+    pub removed_header_infos: Vec<String>,
+    /// Changed nodes.
+    pub nodes: Vec<NodeDataJson>,
+    pub deployment_disagreements: Vec<String>,
+}
+
+/// A compact encoding of DataJsonResponse with short field keys, served on
+/// data.json?compact=1. Headers only contain a prefix of their hash. The full
+/// hashes are listed in `x` in the same order as the headers.
+#[derive(Serialize, Debug)]
+pub struct CompactDataJsonResponse {
+    #[serde(rename = "h")]
+    pub header_infos: Vec<CompactHeaderInfoJson>,
+    #[serde(rename = "x")]
+    pub hashes: Vec<String>,
+    #[serde(rename = "n")]
+    pub nodes: Vec<NodeDataJson>,
+    #[serde(rename = "dd")]
+    pub deployment_disagreements: Vec<String>,
+}
+
+impl CompactDataJsonResponse {
+    pub fn new(
+        header_infos: &[HeaderInfoJson],
+        nodes: Vec<NodeDataJson>,
+        deployment_disagreements: Vec<String>,
+        hash_prefix_length: usize,
+    ) -> Self {
+        let ids: HashSet<usize> = header_infos.iter().map(|h| h.id).collect();
+        CompactDataJsonResponse {
+            header_infos: header_infos
+                .iter()
+                .map(|h| CompactHeaderInfoJson::new(h, &ids, hash_prefix_length))
+                .collect(),
+            hashes: header_infos.iter().map(|h| h.hash.clone()).collect(),
+            nodes,
+            deployment_disagreements,
+        }
+    }
+}
+
+/// A HeaderInfoJson without the merkle root. The previous block hash is only
+/// included if it can't be derived via the prev_id.
+#[derive(Serialize, Debug)]
+pub struct CompactHeaderInfoJson {
+    #[serde(rename = "i")]
+    pub id: usize,
+    #[serde(rename = "p")]
+    pub prev_id: usize,
+    #[serde(rename = "n")]
+    pub height: u64,
+    #[serde(rename = "x")]
+    pub hash_prefix: String,
+    #[serde(rename = "v")]
+    pub version: u32,
+    #[serde(rename = "r", skip_serializing_if = "Option::is_none")]
+    pub prev_blockhash: Option<String>,
+    #[serde(rename = "t")]
+    pub time: u32,
+    #[serde(rename = "b")]
+    pub bits: u32,
+    #[serde(rename = "d")]
+    pub difficulty_int: u64,
+    #[serde(rename = "o")]
+    pub nonce: u32,
+    #[serde(rename = "m")]
+    pub miner: String,
+}
+
+impl CompactHeaderInfoJson {
+    fn new(h: &HeaderInfoJson, ids: &HashSet<usize>, hash_prefix_length: usize) -> Self {
+        CompactHeaderInfoJson {
+            id: h.id,
+            prev_id: h.prev_id,
+            height: h.height,
+            hash_prefix: h.hash.chars().take(hash_prefix_length).collect(),
+            version: h.version,
+            prev_blockhash: if ids.contains(&h.prev_id) {
+                None
+            } else {
+                Some(h.prev_blockhash.clone())
+            },
+            time: h.time,
+            bits: h.bits,
+            difficulty_int: h.difficulty_int,
+            nonce: h.nonce,
+            miner: h.miner.clone(),
+        }
+    }
+}
+
+/// An alternative to the data.json schema with camelCase field names, an
+/// optional `parent` instead of the `prev_id` sentinel and an explicit
+/// `isTip` flag. Served as data.v2.json.
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct DataV2JsonResponse {
+    pub header_infos: Vec<HeaderInfoV2Json>,
+    pub nodes: Vec<NodeDataV2Json>,
+    pub deployment_disagreements: Vec<String>,
+}
+
+impl DataV2JsonResponse {
+    pub fn new(
+        header_infos: &[HeaderInfoJson],
+        nodes: &[NodeDataJson],
+        deployment_disagreements: &[String],
+    ) -> Self {
+        let parents: HashSet<usize> = header_infos.iter().map(|h| h.prev_id).collect();
+        DataV2JsonResponse {
+            header_infos: header_infos
+                .iter()
+                .map(|h| HeaderInfoV2Json::new(h, !parents.contains(&h.id)))
+                .collect(),
+            nodes: nodes.iter().map(NodeDataV2Json::from).collect(),
+            deployment_disagreements: deployment_disagreements.to_vec(),
+        }
+    }
+}
+
+#[derive(Serialize, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct HeaderInfoV2Json {
+    pub id: usize,
+    /// The id of the parent header. None for the root header.
+    pub parent: Option<usize>,
+    pub height: u64,
+    pub hash: String,
+    pub version: u32,
+    pub prev_blockhash: String,
+    pub merkle_root: String,
+    pub time: u32,
+    pub bits: u32,
+    pub difficulty_int: u64,
+    pub nonce: u32,
+    pub miner: String,
+    pub status: HeaderStatus,
+    /// If no other header builds on-top of this header.
+    pub is_tip: bool,
+}
+
+impl HeaderInfoV2Json {
+    fn new(h: &HeaderInfoJson, is_tip: bool) -> Self {
+        HeaderInfoV2Json {
+            id: h.id,
+            parent: if h.prev_id == usize::MAX {
+                None
+            } else {
+                Some(h.prev_id)
+            },
+            height: h.height,
+            hash: h.hash.clone(),
+            version: h.version,
+            prev_blockhash: h.prev_blockhash.clone(),
+            merkle_root: h.merkle_root.clone(),
+            time: h.time,
+            bits: h.bits,
+            difficulty_int: h.difficulty_int,
+            nonce: h.nonce,
+            miner: h.miner.clone(),
+            status: h.status,
+            is_tip,
+        }
+    }
+}
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct NodeDataV2Json {
+    pub id: u32,
+    pub name: String,
+    pub description: String,
+    pub implementation: String,
+    pub tips: Vec<TipInfoJson>,
+    pub last_changed_timestamp: u64,
+    pub version: String,
+    pub reachable: bool,
+    pub deployments: Option<Deployments>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub last_poll_latency_ms: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub avg_poll_latency_ms: Option<f64>,
+}
+
+impl From<&NodeDataJson> for NodeDataV2Json {
+    fn from(n: &NodeDataJson) -> Self {
+        NodeDataV2Json {
+            id: n.id,
+            name: n.name.clone(),
+            description: n.description.clone(),
+            implementation: n.implementation.clone(),
+            tips: n.tips.clone(),
+            last_changed_timestamp: n.last_changed_timestamp,
+            version: n.version.clone(),
+            reachable: n.reachable,
+            deployments: n.deployments.clone(),
+            last_poll_latency_ms: n.last_poll_latency_ms,
+            avg_poll_latency_ms: n.avg_poll_latency_ms,
+        }
+    }
+}
+
+#[derive(Serialize, JsonSchema, Clone, Eq, Hash, PartialEq, Debug)]
 pub struct TipInfoJson {
     pub hash: String,
-    pub status: String,
+    pub status: ChainTipStatus,
     pub height: u64,
+    /// Length of the branch connecting the tip to the active chain. 0 for
+    /// the active tip.
+    pub branchlen: usize,
 }
 
 #[derive(Debug, Clone)]
 pub struct Fork {
     pub common: HeaderInfo,
     pub children: Vec<HeaderInfo>,
+    /// Number of children that were dropped from `children` as the fork
+    /// point had more children than the configured maximum.
+    pub truncated_children: usize,
+}
+
+/// A node switching its active tip to a block that doesn't descend from its
+/// previous active tip. Listed in /api/<network id>/reorgs.json.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct ReorgJson {
+    pub node_id: u32,
+    /// UNIX timestamp of when the reorg was observed.
+    pub timestamp: u64,
+    pub old_tip_hash: String,
+    pub old_tip_height: u64,
+    pub new_tip_hash: String,
+    pub new_tip_height: u64,
+    /// The last block the old and the new active chain have in common.
+    pub fork_point_hash: String,
+    pub fork_point_height: u64,
+    /// Number of blocks of the old active chain that were disconnected.
+    pub depth: u64,
+}
+
+impl ReorgJson {
+    pub fn new(
+        node_id: u32,
+        timestamp: u64,
+        old_tip: &ChainTip,
+        new_tip: &ChainTip,
+        fork_point: (BlockHash, u64),
+    ) -> Self {
+        ReorgJson {
+            node_id,
+            timestamp,
+            old_tip_hash: old_tip.hash.clone(),
+            old_tip_height: old_tip.height,
+            new_tip_hash: new_tip.hash.clone(),
+            new_tip_height: new_tip.height,
+            fork_point_hash: fork_point.0.to_string(),
+            fork_point_height: fork_point.1,
+            depth: old_tip.height.saturating_sub(fork_point.1),
+        }
+    }
+}
+
+/// A block of a fork in /api/<network id>/forks.json.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct ForkBlockJson {
+    pub hash: String,
+    pub height: u64,
+    pub miner: String,
+    /// None if the header isn't in the header tree (anymore).
+    pub status: Option<HeaderStatus>,
+}
+
+impl ForkBlockJson {
+    fn new(header_info: &HeaderInfo, statuses: &HashMap<String, HeaderStatus>) -> Self {
+        let hash = header_info.header.block_hash().to_string();
+        ForkBlockJson {
+            status: statuses.get(&hash).cloned(),
+            hash,
+            height: header_info.height,
+            miner: header_info.miner.clone(),
+        }
+    }
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct ForkJson {
+    /// The last block both branches have in common.
+    pub common: ForkBlockJson,
+    pub children: Vec<ForkBlockJson>,
+    pub truncated_children: usize,
+}
+
+impl ForkJson {
+    pub fn new(fork: &Fork, statuses: &HashMap<String, HeaderStatus>) -> Self {
+        ForkJson {
+            common: ForkBlockJson::new(&fork.common, statuses),
+            children: fork
+                .children
+                .iter()
+                .map(|child| ForkBlockJson::new(child, statuses))
+                .collect(),
+            truncated_children: fork.truncated_children,
+        }
+    }
+}
+
+/// An invalid block in /api/<network id>/invalid.json.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct InvalidBlockJson {
+    pub hash: String,
+    pub height: u64,
+    /// Ids of the nodes reporting the block as invalid.
+    pub nodes: Vec<u32>,
+    /// Names of the nodes reporting the block as invalid, in the order of
+    /// `nodes`. "Node <id>" if the network hides node names.
+    pub node_names: Vec<String>,
 }
 
 impl TipInfoJson {
     pub fn new(tip: &ChainTip) -> Self {
         TipInfoJson {
             hash: tip.hash.clone(),
-            status: tip.status.to_string(),
+            status: tip.status.clone(),
             height: tip.height,
+            branchlen: tip.branchlen,
         }
     }
 }
 
-#[derive(Serialize, Clone, Debug)]
+// The weight of a new getchaintips latency in the moving average.
+const POLL_LATENCY_EWMA_ALPHA: f64 = 0.2;
+
+#[derive(Serialize, JsonSchema, Clone, Debug)]
 pub struct NodeDataJson {
     pub id: u32,
     pub name: String,
@@ -159,6 +1098,30 @@ pub struct NodeDataJson {
     pub version: String,
     /// If the last getchaintips RPC reached the node.
     pub reachable: bool,
+    /// The softfork deployment status as reported by the node. None if the
+    /// node can't report them.
+    pub deployments: Option<Deployments>,
+    /// How long the last successful getchaintips RPC to the node took. None
+    /// until the node has been polled.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub last_poll_latency_ms: Option<u64>,
+    /// The exponentially weighted moving average of the getchaintips RPC
+    /// latencies. Kept while the node is unreachable.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub avg_poll_latency_ms: Option<f64>,
+}
+
+// The most severe tips come first, tips with the same status are ordered by
+// descending height.
+fn tips_by_severity(tips: &[ChainTip]) -> Vec<TipInfoJson> {
+    let mut tips: Vec<&ChainTip> = tips.iter().collect();
+    tips.sort_by(|a, b| {
+        b.status
+            .severity()
+            .cmp(&a.status.severity())
+            .then(b.height.cmp(&a.height))
+    });
+    tips.into_iter().map(TipInfoJson::new).collect()
 }
 
 impl NodeDataJson {
@@ -174,11 +1137,59 @@ impl NodeDataJson {
             name: info.name,
             description: info.description,
             implementation: info.implementation,
-            tips: tips.iter().map(TipInfoJson::new).collect(),
+            tips: tips_by_severity(tips),
             last_changed_timestamp,
             version,
             reachable,
+            deployments: None,
+            last_poll_latency_ms: None,
+            avg_poll_latency_ms: None,
+        }
+    }
+
+    /// The tip the node considers to be its active chain tip, if any.
+    pub fn active_tip(&self) -> Option<&TipInfoJson> {
+        self.tips
+            .iter()
+            .find(|tip| tip.status == ChainTipStatus::Active)
+    }
+
+    /// A copy of the node data with the node details hidden according to
+    /// the privacy settings.
+    pub fn redacted(&self, privacy: &Privacy) -> NodeDataJson {
+        let mut node = self.clone();
+        if privacy.hide_node_names {
+            node.name = format!("Node {}", node.id);
+        }
+        if privacy.hide_descriptions {
+            node.description = String::new();
+        }
+        if privacy.hide_versions {
+            node.version = major_version(&node.version);
+        }
+        if privacy.hide_poll_latency {
+            node.last_poll_latency_ms = None;
+            node.avg_poll_latency_ms = None;
+        }
+        node
+    }
+
+    /// A copy of the node data without the poll latencies. They change on
+    /// every poll and are left out of the cached data.json.
+    pub fn without_poll_latency(&self) -> NodeDataJson {
+        let mut node = self.clone();
+        node.last_poll_latency_ms = None;
+        node.avg_poll_latency_ms = None;
+        node
+    }
+
+    /// A copy of the node data with the offset added to the tip heights.
+    pub fn with_height_offset(&self, offset: i64) -> NodeDataJson {
+        let mut node = self.clone();
+        for tip in node.tips.iter_mut() {
+            tip.height = tip.height.saturating_add_signed(offset);
         }
+        node
     }
 
     pub fn reachable(&mut self, r: bool) {
@@ -189,8 +1200,21 @@ impl NodeDataJson {
         self.version = v;
     }
 
+    pub fn deployments(&mut self, d: Deployments) {
+        self.deployments = Some(d);
+    }
+
+    pub fn poll_latency(&mut self, latency_ms: u64) {
+        self.last_poll_latency_ms = Some(latency_ms);
+        let latency_ms = latency_ms as f64;
+        self.avg_poll_latency_ms = Some(match self.avg_poll_latency_ms {
+            Some(avg) => avg + POLL_LATENCY_EWMA_ALPHA * (latency_ms - avg),
+            None => latency_ms,
+        });
+    }
+
     pub fn tips(&mut self, tips: &[ChainTip]) {
-        self.tips = tips.iter().map(TipInfoJson::new).collect();
+        self.tips = tips_by_severity(tips);
         self.last_changed_timestamp = match SystemTime::now().duration_since(SystemTime::UNIX_EPOCH)
         {
             Ok(n) => n.as_secs(),
@@ -202,12 +1226,421 @@ impl NodeDataJson {
     }
 }
 
-#[derive(Serialize, Clone)]
+#[derive(Deserialize)]
+pub struct SearchQuery {
+    pub q: Option<String>,
+}
+
+// Minimum length of a block hash prefix to search for.
+const MIN_SEARCH_PREFIX_LENGTH: usize = 8;
+// Longer decimal queries are treated as hash prefixes.
+const MAX_SEARCH_HEIGHT_DIGITS: usize = 7;
+
+/// What /api/search looks for.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SearchTerm {
+    Hash(BlockHash),
+    /// A lowercase hex prefix of a block hash.
+    HashPrefix(String),
+    Height(u64),
+}
+
+impl SearchTerm {
+    /// Parses a full block hash, a hash prefix of at least 8 hex characters
+    /// or a decimal height. Heights don't have leading zeros and are shorter
+    /// than hash prefixes, so e.g. "00000000" is a hash prefix. Returns None
+    /// for anything else.
+    pub fn parse(query: &str) -> Option<SearchTerm> {
+        let query = query.trim();
+        if !query.is_empty()
+            && query.len() <= MAX_SEARCH_HEIGHT_DIGITS
+            && query.chars().all(|c| c.is_ascii_digit())
+            && (query == "0" || !query.starts_with('0'))
+        {
+            return query.parse().ok().map(SearchTerm::Height);
+        }
+        if !query.chars().all(|c| c.is_ascii_hexdigit()) {
+            return None;
+        }
+        if query.len() == 64 {
+            return BlockHash::from_str(query).ok().map(SearchTerm::Hash);
+        }
+        if query.len() >= MIN_SEARCH_PREFIX_LENGTH && query.len() < 64 {
+            return Some(SearchTerm::HashPrefix(query.to_lowercase()));
+        }
+        None
+    }
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct SearchResultJson {
+    pub network: u32,
+    pub height: u64,
+    pub hash: String,
+    pub miner: String,
+    /// If the block is an active tip of a node or one of its ancestors. None
+    /// if the block is too far below the active tips to find out.
+    pub active: Option<bool>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct SearchJsonResponse {
+    pub results: Vec<SearchResultJson>,
+    /// True if there are more matches than returned.
+    pub truncated: bool,
+}
+
+#[derive(Serialize, Debug)]
+pub struct BlockJsonResponse {
+    pub hash: String,
+    pub height: u64,
+    pub version: u32,
+    pub prev_blockhash: String,
+    pub merkle_root: String,
+    pub time: u32,
+    pub bits: u32,
+    pub nonce: u32,
+    pub miner: String,
+    /// The opinions of the nodes that have this block as chain tip about
+    /// its validity: node id → tip status.
+    pub validity: BTreeMap<u32, String>,
+    /// The watched addresses the coinbase of the block pays to.
+    pub coinbase_pays_watched: Vec<String>,
+    /// The identifier that identified the miner: "tag", "address" or
+    /// "template". None if the miner wasn't identified.
+    pub miner_identified_by: Option<String>,
+    /// Hash of the parent block. None if the parent isn't in the header tree.
+    pub parent: Option<String>,
+    /// Hashes of the child blocks in the header tree.
+    pub children: Vec<String>,
+}
+
+impl BlockJsonResponse {
+    pub fn new(
+        hi: &HeaderInfo,
+        validity: BTreeMap<u32, String>,
+        coinbase_pays_watched: Vec<String>,
+        miner_identified_by: Option<String>,
+        parent: Option<String>,
+        children: Vec<String>,
+    ) -> Self {
+        BlockJsonResponse {
+            hash: hi.header.block_hash().to_string(),
+            height: hi.height,
+            version: hi.header.version.to_consensus() as u32,
+            prev_blockhash: hi.header.prev_blockhash.to_string(),
+            merkle_root: hi.header.merkle_root.to_string(),
+            time: hi.header.time,
+            bits: hi.header.bits.to_consensus(),
+            nonce: hi.header.nonce,
+            miner: hi.miner.clone(),
+            validity,
+            coinbase_pays_watched,
+            miner_identified_by,
+            parent,
+            children,
+        }
+    }
+}
+
+#[derive(Serialize, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum ConsensusStatus {
+    /// All nodes have the same active tip.
+    Consistent,
+    /// All active tips are on the same chain, but at different heights.
+    HeightMismatch,
+    /// The active tips are on different branches.
+    Split,
+}
+
+#[derive(Serialize, Debug)]
+pub struct ConsensusJsonResponse {
+    pub status: ConsensusStatus,
+    /// The agreed-on active tip hash. Only set if the status is consistent.
+    pub hash: Option<String>,
+    /// The agreed-on active tip height. Only set if the status is consistent.
+    pub height: Option<u64>,
+    /// Reachable nodes grouped by their active tip, highest tip first.
+    /// Lagging nodes aren't included.
+    pub tips: Vec<ConsensusTipJson>,
+    /// Reachable nodes more than the lagging threshold behind the highest
+    /// active tip, grouped like the tips. They don't count for the status.
+    pub lagging: Vec<ConsensusTipJson>,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Debug, PartialEq)]
+pub struct ConsensusTipJson {
+    pub hash: String,
+    pub height: u64,
+    pub nodes: Vec<u32>,
+}
+
+#[derive(Deserialize)]
+pub struct HeightQuery {
+    pub height: u64,
+}
+
+#[derive(Deserialize)]
+pub struct SinceHeightQuery {
+    /// Only list items above this height.
+    pub since_height: Option<u64>,
+}
+
+#[derive(Deserialize)]
+pub struct ForkHistoryQuery {
+    /// Zero-based page of the fork history.
+    pub page: Option<usize>,
+}
+
+/// A recorded fork in /api/<network id>/forkhistory.json.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct ForkHistoryJson {
+    pub common_hash: String,
+    pub common_height: u64,
+    /// Hashes of the children of the common block.
+    pub children: Vec<String>,
+    /// UNIX timestamp of when the fork was first recorded.
+    pub first_seen: u64,
+}
+
+#[derive(Serialize, Debug, PartialEq)]
+pub struct ForkHistoryJsonResponse {
+    pub page: usize,
+    pub page_size: usize,
+    /// Number of recorded forks on all pages.
+    pub total: u64,
+    pub forks: Vec<ForkHistoryJson>,
+}
+
+#[derive(Deserialize)]
+pub struct AlertHistoryQuery {
+    /// Zero-based page of the alert history.
+    pub page: Option<usize>,
+}
+
+#[derive(Serialize, Debug, PartialEq)]
+pub struct AlertHistoryJsonResponse {
+    pub page: usize,
+    pub page_size: usize,
+    /// Number of recorded alerts on all pages.
+    pub total: u64,
+    pub alerts: Vec<Alert>,
+}
+
+#[derive(Deserialize)]
+pub struct MinersQuery {
+    /// Number of active-chain blocks, counting back from the highest one, to
+    /// aggregate.
+    pub last: Option<usize>,
+}
+
+/// A miner in /api/<network id>/miners.json.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct MinerShareJson {
+    pub miner: String,
+    pub block_count: usize,
+    /// Share of the aggregated blocks, between 0 and 1.
+    pub share: f64,
+}
+
+#[derive(Deserialize)]
+pub struct SignallingQuery {
+    /// Number of blocks, counting back from the active tip, to look at. At
+    /// most one difficulty adjustment period.
+    pub window: Option<usize>,
+}
+
+#[derive(Serialize, Debug, PartialEq)]
+pub struct SignallingJsonResponse {
+    pub tip_hash: String,
+    pub tip_height: u64,
+    /// Number of blocks the statistics are based on. Can be lower than the
+    /// requested window if we don't know about enough headers.
+    pub blocks: usize,
+    pub bits: Vec<BitSignallingJson>,
+    /// Histogram of the raw (hex encoded) header versions.
+    pub versions: BTreeMap<String, usize>,
+    /// Per-miner breakdown. Blocks with a not (yet) identified miner are
+    /// not included here.
+    pub miners: BTreeMap<String, MinerSignallingJson>,
+}
+
+#[derive(Serialize, Debug, PartialEq)]
+pub struct BitSignallingJson {
+    pub bit: u8,
+    pub count: usize,
+    pub percentage: f64,
+}
+
+#[derive(Serialize, Debug, PartialEq, Default)]
+pub struct MinerSignallingJson {
+    pub blocks: usize,
+    /// Number of blocks signalling per bit. Bits without signalling blocks
+    /// are omitted.
+    pub bits: BTreeMap<u8, usize>,
+}
+
+// The deployments as listed by Bitcoin Core's getdeploymentinfo RPC. Older
+// versions list them in the softforks field of getblockchaininfo.
+#[derive(Deserialize, Debug)]
+pub struct GetDeploymentInfoResult {
+    #[serde(alias = "softforks")]
+    pub deployments: HashMap<String, DeploymentInfo>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct DeploymentInfo {
+    pub active: bool,
+    pub bip9: Option<DeploymentBip9Info>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct DeploymentBip9Info {
+    pub status: String,
+}
+
+impl DeploymentInfo {
+    /// The BIP9 status (e.g. "started" or "locked_in") for BIP9 deployments
+    /// and "active" or "inactive" for buried deployments.
+    pub fn status(&self) -> String {
+        match &self.bip9 {
+            Some(bip9) => bip9.status.clone(),
+            None if self.active => "active".to_string(),
+            None => "inactive".to_string(),
+        }
+    }
+}
+
+/// Events sent to the SSE clients.
+#[derive(Clone, Debug)]
+pub enum ChangeEvent {
+    TipChanged { network_id: u32 },
+    Changed { network_id: u32, change: Change },
+    Alert(Alert),
+}
+
+/// What changed on a network. Sent to the SSE clients as a named event next
+/// to tip_changed, so clients can skip refreshing data.json for changes they
+/// aren't interested in.
+#[derive(Serialize, JsonSchema, Clone, Debug, PartialEq)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Change {
+    /// The tips of a node changed.
+    NodeTips { node_id: u32 },
+    /// New headers were added to the header tree. Only the highest of the
+    /// headers added at once is reported.
+    NewBlock { hash: String, height: u64 },
+}
+
+impl Change {
+    /// The SSE event name.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Change::NodeTips { .. } => "node_tips",
+            Change::NewBlock { .. } => "new_block",
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum AlertKind {
+    /// Reachable, non-lagging nodes have active tips on different branches.
+    ChainSplit,
+    /// A previous chain split was resolved.
+    ChainSplitResolved,
+}
+
+impl fmt::Display for AlertKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            AlertKind::ChainSplit => write!(f, "chain_split"),
+            AlertKind::ChainSplitResolved => write!(f, "chain_split_resolved"),
+        }
+    }
+}
+
+impl FromStr for AlertKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "chain_split" => Ok(AlertKind::ChainSplit),
+            "chain_split_resolved" => Ok(AlertKind::ChainSplitResolved),
+            _ => Err(format!("unknown alert kind '{}'", s)),
+        }
+    }
+}
+
+#[derive(Serialize, JsonSchema, Clone, Debug, PartialEq)]
+pub struct Alert {
+    pub network_id: u32,
+    pub kind: AlertKind,
+    /// UTC timestamp of when the alert was raised.
+    pub timestamp: u64,
+    /// The active tips of the nodes when the alert was raised.
+    pub tips: Vec<ConsensusTipJson>,
+}
+
+pub fn redact_node_data(node_data: &NodeData, privacy: &Privacy) -> NodeData {
+    node_data
+        .iter()
+        .map(|(id, node)| (*id, node.redacted(privacy)))
+        .collect()
+}
+
+// Truncates the versions in a subversion string to the major release, e.g.
+// "/Satoshi:27.1.0/" to "/Satoshi:27.x/" and "/Satoshi:0.21.1/" to
+// "/Satoshi:0.21.x/". Versions without a major release (e.g. dates) are
+// fully hidden. Comments are removed. Strings not looking like a
+// subversion (e.g. "unknown") are returned unchanged.
+fn major_version(subversion: &str) -> String {
+    if !subversion.starts_with('/') {
+        return subversion.to_string();
+    }
+    let agents: Vec<String> = subversion
+        .split('/')
+        .filter(|agent| !agent.is_empty())
+        .map(|agent| {
+            let (name, version) = match agent.split_once(':') {
+                Some((name, version)) => (name, version),
+                None => return agent.to_string(),
+            };
+            // remove comments like in "27.0.0(comment)"
+            let version = version.split('(').next().unwrap_or_default();
+            let parts: Vec<&str> = version.split('.').collect();
+            match parts.len() {
+                // e.g. a date based version without a major release
+                1 => format!("{}:x", name),
+                _ if parts[0] == "0" => format!("{}:0.{}.x", name, parts[1]),
+                _ => format!("{}:{}.x", name, parts[0]),
+            }
+        })
+        .collect();
+    format!("/{}/", agents.join("/"))
+}
+
+#[derive(Serialize, JsonSchema, Clone)]
 pub struct DataChanged {
     pub network_id: u32,
 }
 
-#[derive(Deserialize, Clone, Debug, PartialEq, Eq)]
+#[derive(Serialize, JsonSchema, Clone)]
+pub struct DataChangedDetail {
+    pub network_id: u32,
+    #[serde(flatten)]
+    pub change: Change,
+}
+
+// Sent to a SSE client that fell behind and missed change events. The client
+// can't know which networks changed and should refresh all of them.
+#[derive(Serialize, JsonSchema, Clone)]
+pub struct Lagged {
+    pub skipped: u64,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Debug, PartialEq, Eq, Hash)]
 pub enum ChainTipStatus {
     #[serde(rename = "active")]
     Active,
@@ -219,6 +1652,7 @@ pub enum ChainTipStatus {
     HeadersOnly,
     #[serde(rename = "valid-headers")]
     ValidHeaders,
+    #[serde(rename = "unknown")]
     Unknown,
 }
 
@@ -235,6 +1669,23 @@ impl From<String> for ChainTipStatus {
     }
 }
 
+impl ChainTipStatus {
+    /// How much attention a tip with this status needs. Higher is more
+    /// severe: invalid, valid-fork, headers-only, valid-headers, active and
+    /// unknown. Use this instead of the declaration order when prioritizing
+    /// tips.
+    pub fn severity(&self) -> u8 {
+        match self {
+            ChainTipStatus::Invalid => 5,
+            ChainTipStatus::ValidFork => 4,
+            ChainTipStatus::HeadersOnly => 3,
+            ChainTipStatus::ValidHeaders => 2,
+            ChainTipStatus::Active => 1,
+            ChainTipStatus::Unknown => 0,
+        }
+    }
+}
+
 impl From<GetChainTipsResultStatus> for ChainTipStatus {
     fn from(s: GetChainTipsResultStatus) -> Self {
         match s {
@@ -260,7 +1711,7 @@ impl fmt::Display for ChainTipStatus {
     }
 }
 
-#[derive(Deserialize, Debug, Clone, PartialEq, Eq)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
 pub struct ChainTip {
     pub height: u64,
     pub hash: String,
@@ -283,4 +1734,212 @@ impl ChainTip {
     pub fn block_hash(&self) -> BlockHash {
         BlockHash::from_str(&self.hash).unwrap()
     }
+
+    /// Height at which the branch of this tip forks off the active chain.
+    /// None if the backend reported a branchlen larger than the tip height.
+    pub fn fork_height(&self) -> Option<u64> {
+        self.height.checked_sub(self.branchlen as u64)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils;
+    use bitcoincore_rpc::bitcoin::hashes::Hash;
+    use tokio::time::sleep;
+
+    fn hash(n: u8) -> BlockHash {
+        BlockHash::from_byte_array([n; 32])
+    }
+
+    #[test]
+    fn test_chain_tip_status_severity() {
+        let by_severity = [
+            ChainTipStatus::Invalid,
+            ChainTipStatus::ValidFork,
+            ChainTipStatus::HeadersOnly,
+            ChainTipStatus::ValidHeaders,
+            ChainTipStatus::Active,
+            ChainTipStatus::Unknown,
+        ];
+        for pair in by_severity.windows(2) {
+            assert!(
+                pair[0].severity() > pair[1].severity(),
+                "{} should be more severe than {}",
+                pair[0],
+                pair[1]
+            );
+        }
+
+        let tip = |height: u64, status: ChainTipStatus| ChainTip {
+            height,
+            hash: format!("{:064x}", height),
+            branchlen: 0,
+            status,
+        };
+        let mut node = test_utils::node_data(0, &vec![]);
+        node.tips(&[
+            tip(100, ChainTipStatus::Active),
+            tip(99, ChainTipStatus::ValidHeaders),
+            tip(90, ChainTipStatus::Invalid),
+            tip(95, ChainTipStatus::ValidFork),
+            tip(98, ChainTipStatus::Invalid),
+        ]);
+        let order: Vec<(u64, ChainTipStatus)> = node
+            .tips
+            .iter()
+            .map(|tip| (tip.height, tip.status.clone()))
+            .collect();
+        assert_eq!(
+            order,
+            vec![
+                (98, ChainTipStatus::Invalid),
+                (90, ChainTipStatus::Invalid),
+                (95, ChainTipStatus::ValidFork),
+                (99, ChainTipStatus::ValidHeaders),
+                (100, ChainTipStatus::Active),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_tip_info_json() {
+        let tip = ChainTip {
+            height: 100,
+            hash: format!("{:064x}", 100),
+            branchlen: 3,
+            status: ChainTipStatus::ValidFork,
+        };
+        assert_eq!(
+            serde_json::to_value(TipInfoJson::new(&tip)).unwrap(),
+            serde_json::json!({
+                "hash": tip.hash,
+                "status": "valid-fork",
+                "height": 100,
+                "branchlen": 3,
+            })
+        );
+
+        let active = ChainTip {
+            branchlen: 0,
+            status: ChainTipStatus::Active,
+            ..tip
+        };
+        let json = serde_json::to_value(TipInfoJson::new(&active)).unwrap();
+        assert_eq!(json["status"], "active");
+        assert_eq!(json["branchlen"], 0);
+    }
+
+    #[test]
+    fn test_tips_replay() {
+        let tips = |height: u64| {
+            vec![ChainTip {
+                height,
+                hash: format!("{:064x}", height),
+                branchlen: 0,
+                status: ChainTipStatus::Active,
+            }]
+        };
+        let mut replay = TipsReplay::new(3);
+        assert_eq!(replay.snapshots(0), None);
+
+        for height in 1..=5 {
+            replay.record(0, 1000 + height, tips(height));
+        }
+        replay.record(1, 2000, tips(10));
+
+        // only the last three snapshots are kept, oldest first
+        let recorded: Vec<(u64, u64)> = replay
+            .snapshots(0)
+            .unwrap()
+            .iter()
+            .map(|snapshot| (snapshot.timestamp, snapshot.tips[0].height))
+            .collect();
+        assert_eq!(recorded, vec![(1003, 3), (1004, 4), (1005, 5)]);
+        assert_eq!(replay.snapshots(1).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_propagation() {
+        let mut propagation = Propagation::default();
+        assert_eq!(propagation.json().average_delay_seconds, None);
+
+        // the first reported active tips don't count
+        for node in 0..3 {
+            propagation.seen("genesis", node, 50, 3);
+        }
+        assert_eq!(propagation.json().blocks, 0);
+
+        // seen by all three nodes
+        propagation.seen("a", 0, 100, 3);
+        propagation.seen("a", 1, 102, 3);
+        // a node reporting a block again keeps its first-seen time
+        propagation.seen("a", 1, 110, 3);
+        assert_eq!(propagation.json().blocks, 0);
+        propagation.seen("a", 2, 106, 3);
+        assert_eq!(
+            propagation.json(),
+            BlockPropagationJson {
+                average_delay_seconds: Some(6.0),
+                blocks: 1,
+            }
+        );
+        // a node going back to a finished block doesn't track it again
+        propagation.seen("x", 0, 150, 3);
+        propagation.seen("a", 0, 160, 3);
+        assert!(!propagation.first_seen.iter().any(|(h, _)| h == "a"));
+
+        // node 2 never sees block b: it's finished once pushed out
+        propagation.seen("b", 0, 200, 3);
+        propagation.seen("b", 1, 202, 3);
+        for i in 0..PROPAGATION_TRACKED_BLOCKS {
+            propagation.seen(&i.to_string(), 0, 300, 3);
+        }
+        assert_eq!(
+            propagation.json(),
+            BlockPropagationJson {
+                average_delay_seconds: Some(4.0),
+                blocks: 2,
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn test_pool_id_queue_priority() {
+        let (queue, mut rx) = PoolIdQueue::new(2);
+
+        // backfilled blocks are dropped if the queue is full
+        assert!(queue.try_send(hash(1)));
+        assert!(queue.try_send(hash(2)));
+        assert!(!queue.try_send(hash(3)));
+        assert_eq!(queue.queued(), 2);
+        assert_eq!(queue.dropped(), 1);
+
+        // new blocks wait for space in a full queue
+        let worker = tokio::spawn(async move {
+            sleep(Duration::from_millis(50)).await;
+            let mut received = vec![];
+            while let Some(hash) = rx.recv().await {
+                received.push(hash);
+            }
+            received
+        });
+        assert!(queue.send(hash(4)).await);
+        assert_eq!(queue.dropped(), 1);
+
+        // and are dropped if the queue stays full
+        let (blocked, _rx) = PoolIdQueue::new(1);
+        assert!(blocked.try_send(hash(5)));
+        assert!(
+            !blocked
+                .send_with_timeout(hash(6), Duration::from_millis(10))
+                .await
+        );
+        assert_eq!(blocked.dropped(), 1);
+        assert_eq!(blocked.queued(), 1);
+
+        drop(queue);
+        assert_eq!(worker.await.unwrap(), vec![hash(1), hash(2), hash(4)]);
+    }
 }
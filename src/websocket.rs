@@ -0,0 +1,109 @@
+use std::sync::Arc;
+
+use futures_util::{SinkExt, StreamExt};
+use log::{debug, info, warn};
+use serde_json::Value;
+use tokio::sync::Notify;
+use tokio::time::{sleep, Duration};
+use tokio_tungstenite::connect_async;
+use tokio_tungstenite::tungstenite::client::IntoClientRequest;
+use tokio_tungstenite::tungstenite::http::HeaderValue;
+use tokio_tungstenite::tungstenite::Message;
+
+use crate::error::JsonRPCError;
+
+const RECONNECT_DELAY: Duration = Duration::from_secs(5);
+// Notifications btcd sends after subscribing with notifyblocks.
+const BLOCK_NOTIFICATIONS: [&str; 4] = [
+    "blockconnected",
+    "blockdisconnected",
+    "filteredblockconnected",
+    "filteredblockdisconnected",
+];
+
+// Subscribes to block notifications on the btcd websocket endpoint and kicks
+// the notify on each notification. Reconnects if the connection is lost.
+// Never returns.
+pub async fn btcd_block_notifications(
+    url: String,
+    user: String,
+    password: String,
+    kick: Arc<Notify>,
+) {
+    loop {
+        match subscribe(&url, &user, &password, &kick).await {
+            Ok(()) => warn!("btcd websocket connection to {} was closed", url),
+            Err(e) => warn!("btcd websocket connection to {} failed: {}", url, e),
+        }
+        sleep(RECONNECT_DELAY).await;
+    }
+}
+
+async fn subscribe(
+    url: &str,
+    user: &str,
+    password: &str,
+    kick: &Notify,
+) -> Result<(), JsonRPCError> {
+    let mut request = url.into_client_request()?;
+    let token = format!("{}:{}", user, password);
+    request.headers_mut().insert(
+        "Authorization",
+        HeaderValue::from_str(&format!("Basic {}", base64::encode(&token)))
+            .expect("base64 should be a valid header value"),
+    );
+
+    let (ws, _) = connect_async(request).await?;
+    let (mut write, mut read) = ws.split();
+    write
+        .send(Message::Text(
+            r#"{"jsonrpc":"1.0","id":0,"method":"notifyblocks","params":[]}"#.to_string(),
+        ))
+        .await?;
+    info!("subscribed to block notifications on {}", url);
+
+    while let Some(message) = read.next().await {
+        match message? {
+            Message::Text(text) if is_block_notification(&text) => {
+                debug!("block notification from {}: {}", url, text);
+                kick.notify_one();
+            }
+            Message::Close(_) => return Ok(()),
+            _ => (),
+        }
+    }
+    Ok(())
+}
+
+fn is_block_notification(message: &str) -> bool {
+    match serde_json::from_str::<Value>(message) {
+        Ok(value) => match value["method"].as_str() {
+            Some(method) => BLOCK_NOTIFICATIONS.contains(&method),
+            None => false,
+        },
+        Err(_) => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_block_notification() {
+        assert!(is_block_notification(
+            r#"{"jsonrpc":"1.0","method":"blockconnected","params":["00",1,1],"id":null}"#
+        ));
+        assert!(is_block_notification(
+            r#"{"jsonrpc":"1.0","method":"blockdisconnected","params":["00",1,1],"id":null}"#
+        ));
+        // the response to notifyblocks
+        assert!(!is_block_notification(
+            r#"{"jsonrpc":"1.0","result":null,"error":null,"id":0}"#
+        ));
+        assert!(!is_block_notification(
+            r#"{"jsonrpc":"1.0","method":"txaccepted","params":[],"id":null}"#
+        ));
+        assert!(!is_block_notification("not json"));
+    }
+}
@@ -0,0 +1,97 @@
+use std::sync::Arc;
+
+use log::{debug, info, warn};
+use tokio::sync::Notify;
+use tokio::time::{sleep, Duration};
+use zeromq::{Socket, SocketRecv, SubSocket, ZmqError};
+
+const RECONNECT_DELAY: Duration = Duration::from_secs(5);
+const HASHBLOCK_TOPIC: &str = "hashblock";
+
+// Subscribes to the hashblock topic on the Bitcoin Core zmqpubhashblock
+// endpoint and kicks the notify on each notification. Reconnects if the
+// connection is lost. Never returns.
+pub async fn bitcoin_core_block_notifications(endpoint: String, kick: Arc<Notify>) {
+    loop {
+        match subscribe(&endpoint, &kick).await {
+            Ok(()) => warn!("ZMQ connection to {} was closed", endpoint),
+            Err(e) => warn!("ZMQ connection to {} failed: {}", endpoint, e),
+        }
+        sleep(RECONNECT_DELAY).await;
+    }
+}
+
+async fn subscribe(endpoint: &str, kick: &Notify) -> Result<(), ZmqError> {
+    let mut socket = SubSocket::new();
+    socket.connect(endpoint).await?;
+    socket.subscribe(HASHBLOCK_TOPIC).await?;
+    info!("subscribed to hashblock notifications on {}", endpoint);
+
+    loop {
+        let message = socket.recv().await?;
+        if let (Some(topic), Some(body)) = (message.get(0), message.get(1)) {
+            if let Some(hash) = notified_block_hash(topic, body) {
+                debug!("hashblock notification from {}: {}", endpoint, hash);
+                kick.notify_one();
+            }
+        }
+    }
+}
+
+// The hex encoded block hash of a hashblock notification. Bitcoin Core sends
+// the hash in the byte order it's displayed in. None for other messages.
+fn notified_block_hash(topic: &[u8], body: &[u8]) -> Option<String> {
+    if topic != HASHBLOCK_TOPIC.as_bytes() || body.len() != 32 {
+        return None;
+    }
+    Some(hex::encode(body))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::time::timeout;
+    use warp::hyper::body::Bytes;
+    use zeromq::{PubSocket, SocketSend, ZmqMessage};
+
+    #[test]
+    fn test_notified_block_hash() {
+        let mut hash = [0u8; 32];
+        hash[31] = 0xab;
+        assert_eq!(
+            notified_block_hash(b"hashblock", &hash),
+            Some(format!("{:064x}", 0xab))
+        );
+        assert_eq!(notified_block_hash(b"hashtx", &hash), None);
+        assert_eq!(notified_block_hash(b"hashblock", &hash[1..]), None);
+        assert_eq!(notified_block_hash(b"", &[]), None);
+    }
+
+    #[tokio::test]
+    async fn test_bitcoin_core_block_notifications() {
+        let mut publisher = PubSocket::new();
+        let endpoint = publisher.bind("tcp://127.0.0.1:0").await.unwrap();
+        let kick = Arc::new(Notify::new());
+        tokio::spawn(bitcoin_core_block_notifications(
+            endpoint.to_string(),
+            kick.clone(),
+        ));
+
+        // publish until the subscription is established
+        let notified = kick.notified();
+        tokio::pin!(notified);
+        for _ in 0..100 {
+            let mut message = ZmqMessage::from(HASHBLOCK_TOPIC);
+            message.push_back(Bytes::from(vec![0u8; 32]));
+            message.push_back(Bytes::from(vec![0u8; 4]));
+            publisher.send(message).await.unwrap();
+            if timeout(Duration::from_millis(50), &mut notified)
+                .await
+                .is_ok()
+            {
+                return;
+            }
+        }
+        panic!("the hashblock notification should kick the notify");
+    }
+}
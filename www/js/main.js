@@ -142,5 +142,12 @@ changeSSE.addEventListener("tip_changed", (e) => {
   }
 })
 
+changeSSE.addEventListener("lagged", (e) => {
+  let data = JSON.parse(e.data)
+  console.debug("server side event: missed change events, refreshing: ", data)
+  // we don't know which networks changed, so refresh the displayed one
+  update()
+})
+
 
 run()